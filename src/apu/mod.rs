@@ -0,0 +1,587 @@
+pub mod wav;
+mod test;
+
+// The audio processing unit: two pulse channels, a triangle, a noise generator and the delta
+// modulation channel, mixed into 16 bits mono samples with the sound chip of the cartridge. The
+// frame IRQ and the DMC IRQ are not emulated.
+// https://www.nesdev.org/wiki/APU
+
+pub const CPU_FREQUENCY: f64 = 1_789_773.0;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+pub const APU_REGISTERS_START: u16 = 0x4000;
+pub const APU_REGISTERS_END: u16 = 0x4013;
+pub const APU_STATUS_REGISTER: u16 = 0x4015;
+pub const APU_FRAME_COUNTER_REGISTER: u16 = 0x4017;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+// In CPU cycles, NTSC
+const NOISE_PERIODS: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const DMC_RATES: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+
+// Frame counter steps in CPU cycles, a quarter frame clocks the envelopes and the linear counter,
+// a half frame also clocks the length counters and the sweeps
+const FOUR_STEP_SEQUENCE: [(usize, bool); 4] = [(7457, false), (14913, true), (22371, false), (29829, true)];
+const FOUR_STEP_LENGTH: usize = 29830;
+const FIVE_STEP_SEQUENCE: [(usize, bool); 4] = [(7457, false), (14913, true), (22371, false), (37281, true)];
+const FIVE_STEP_LENGTH: usize = 37282;
+
+// The output is high-passed around 90 Hz like the console does, removing the DC offset
+const HIGH_PASS_FREQUENCY: f64 = 90.0;
+
+#[derive(Debug, Clone, Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    // Bits 0-5 of $4000, $4004 and $400C
+    fn write(&mut self, value: u8) {
+        self.looping = value & 0x20 != 0;
+        self.constant = value & 0x10 != 0;
+        self.volume = value & 0x0f;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant { self.volume } else { self.decay }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    value: u8,
+}
+
+impl LengthCounter {
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[(index >> 3) as usize];
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.halt && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Pulse {
+    // The first pulse negates with one's complement in its sweep
+    first: bool,
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register & 3 {
+            0 => {
+                self.duty = value >> 6;
+                self.length.halt = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = value & 0x80 != 0;
+                self.sweep_period = (value >> 4) & 7;
+                self.sweep_negate = value & 0x08 != 0;
+                self.sweep_shift = value & 7;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0x700) | value as u16,
+            _ => {
+                self.period = (self.period & 0xff) | ((value as u16 & 7) << 8);
+                self.length.load(value);
+                self.step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change: u16 = self.period >> self.sweep_shift;
+        match (self.sweep_negate, self.first) {
+            (false, _) => self.period + change,
+            (true, true) => self.period.saturating_sub(change + 1),
+            (true, false) => self.period.saturating_sub(change),
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x7ff
+    }
+
+    // Every other CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        match self.muted() || self.length.value == 0 || DUTY_SEQUENCES[self.duty as usize][self.step as usize] == 0 {
+            true => 0,
+            false => self.envelope.output(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Triangle {
+    step: u8,
+    period: u16,
+    timer: u16,
+    length: LengthCounter,
+    // Also the length counter halt
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+}
+
+impl Triangle {
+    fn write(&mut self, register: u16, value: u8) {
+        match register & 3 {
+            0 => {
+                self.control = value & 0x80 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = value & 0x7f;
+            }
+            1 => (),
+            2 => self.period = (self.period & 0x700) | value as u16,
+            _ => {
+                self.period = (self.period & 0xff) | ((value as u16 & 7) << 8);
+                self.length.load(value);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    // Every CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.value > 0 && self.linear_counter > 0 {
+                self.step = (self.step + 1) & 31;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear_counter(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        // Ultrasonic periods are silenced instead of popping
+        match self.period < 2 {
+            true => 7,
+            false => TRIANGLE_SEQUENCE[self.step as usize],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Noise {
+    short_mode: bool,
+    period: u16,
+    timer: u16,
+    shift: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Noise {
+    fn new() -> Noise {
+        Noise { short_mode: false, period: NOISE_PERIODS[0], timer: 0, shift: 1, envelope: Envelope::default(), length: LengthCounter::default() }
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        match register & 3 {
+            0 => {
+                self.length.halt = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => (),
+            2 => {
+                self.short_mode = value & 0x80 != 0;
+                self.period = NOISE_PERIODS[(value & 0x0f) as usize];
+            }
+            _ => {
+                self.length.load(value);
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    // Every CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period - 1;
+            let tap: u16 = if self.short_mode { 6 } else { 1 };
+            let feedback: u16 = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        match self.shift & 1 != 0 || self.length.value == 0 {
+            true => 0,
+            false => self.envelope.output(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Dmc {
+    looping: bool,
+    period: u16,
+    timer: u16,
+    level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Dmc {
+    fn new() -> Dmc {
+        Dmc {
+            looping: false,
+            period: DMC_RATES[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        match register & 3 {
+            0 => {
+                self.looping = value & 0x40 != 0;
+                self.period = DMC_RATES[(value & 0x0f) as usize];
+            }
+            1 => self.level = value & 0x7f,
+            2 => self.sample_address = 0xc000 | (value as u16) << 6,
+            _ => self.sample_length = (value as u16) << 4 | 1,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    // Every CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period - 1;
+        if !self.silence {
+            match self.shift & 1 {
+                1 if self.level <= 125 => self.level += 2,
+                0 if self.level >= 2 => self.level -= 2,
+                _ => (),
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(byte) => {
+                    self.silence = false;
+                    self.shift = byte;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    // The address of the next sample byte, when the buffer needs one
+    fn fetch_address(&self) -> Option<u16> {
+        (self.buffer.is_none() && self.bytes_remaining > 0).then_some(self.current_address)
+    }
+
+    fn fill(&mut self, value: u8) {
+        self.buffer = Some(value);
+        self.current_address = match self.current_address {
+            0xffff => 0x8000,
+            address => address + 1,
+        };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 && self.looping {
+            self.restart();
+        }
+    }
+}
+
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    // Output of the sound chip of the cartridge (the FDS wavetable), added to the mix
+    expansion: f64,
+    five_step: bool,
+    frame_cycle: usize,
+    odd_cycle: bool,
+    // Resampling: the mixed output is averaged over the CPU cycles of every sample
+    cycles_per_sample: f64,
+    sample_clock: f64,
+    sum: f64,
+    count: usize,
+    high_pass: f64,
+    previous_input: f64,
+    previous_output: f64,
+    pub sample_rate: u32,
+    // Produced since the last take_samples
+    pub samples: Vec<i16>,
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Apu {
+        let rc: f64 = 1.0 / (2.0 * std::f64::consts::PI * HIGH_PASS_FREQUENCY);
+        let dt: f64 = 1.0 / sample_rate as f64;
+        Apu {
+            pulse1: Pulse { first: true, ..Pulse::default() },
+            pulse2: Pulse::default(),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            expansion: 0.0,
+            five_step: false,
+            frame_cycle: 0,
+            odd_cycle: false,
+            cycles_per_sample: CPU_FREQUENCY / sample_rate as f64,
+            sample_clock: 0.0,
+            sum: 0.0,
+            count: 0,
+            high_pass: rc / (rc + dt),
+            previous_input: 0.0,
+            previous_output: 0.0,
+            sample_rate,
+            samples: vec![],
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr, value),
+            0x4004..=0x4007 => self.pulse2.write(addr, value),
+            0x4008..=0x400b => self.triangle.write(addr, value),
+            0x400c..=0x400f => self.noise.write(addr, value),
+            0x4010..=0x4013 => self.dmc.write(addr, value),
+            APU_STATUS_REGISTER => {
+                self.pulse1.length.set_enabled(value & 0x01 != 0);
+                self.pulse2.length.set_enabled(value & 0x02 != 0);
+                self.triangle.length.set_enabled(value & 0x04 != 0);
+                self.noise.length.set_enabled(value & 0x08 != 0);
+                match value & 0x10 != 0 {
+                    true if self.dmc.bytes_remaining == 0 => self.dmc.restart(),
+                    true => (),
+                    false => self.dmc.bytes_remaining = 0,
+                }
+            }
+            APU_FRAME_COUNTER_REGISTER => {
+                self.five_step = value & 0x80 != 0;
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // $4015: which channels are still playing
+    pub fn read_status(&self) -> u8 {
+        (self.pulse1.length.value > 0) as u8
+            | ((self.pulse2.length.value > 0) as u8) << 1
+            | ((self.triangle.length.value > 0) as u8) << 2
+            | ((self.noise.length.value > 0) as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear_counter();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        let (sequence, length): (&[(usize, bool); 4], usize) = match self.five_step {
+            false => (&FOUR_STEP_SEQUENCE, FOUR_STEP_LENGTH),
+            true => (&FIVE_STEP_SEQUENCE, FIVE_STEP_LENGTH),
+        };
+        if let Some((_, half)) = sequence.iter().find(|(cycle, _)| *cycle == self.frame_cycle) {
+            self.clock_quarter_frame();
+            if *half {
+                self.clock_half_frame();
+            }
+        }
+        if self.frame_cycle >= length {
+            self.frame_cycle = 0;
+        }
+    }
+
+    // https://www.nesdev.org/wiki/APU_Mixer, between 0 and 1
+    fn mix(&self) -> f64 {
+        let pulses: f64 = (self.pulse1.output() + self.pulse2.output()) as f64;
+        let pulse_out: f64 = if pulses == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulses + 100.0) };
+        let tnd: f64 = self.triangle.output() as f64 / 8227.0 + self.noise.output() as f64 / 12241.0 + self.dmc.level as f64 / 22638.0;
+        let tnd_out: f64 = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+        pulse_out + tnd_out + self.expansion
+    }
+
+    // Held until the next call, the cartridge sets it before every tick
+    pub fn set_expansion_output(&mut self, level: f64) {
+        self.expansion = level;
+    }
+
+    // One CPU cycle. The DMC asks for its sample bytes through dmc_fetch_address and dmc_fill
+    pub fn tick(&mut self) {
+        self.clock_frame_counter();
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        self.sum += self.mix();
+        self.count += 1;
+        self.sample_clock += 1.0;
+        if self.sample_clock >= self.cycles_per_sample {
+            self.sample_clock -= self.cycles_per_sample;
+            let input: f64 = self.sum / self.count as f64;
+            let output: f64 = self.high_pass * (self.previous_output + input - self.previous_input);
+            (self.previous_input, self.previous_output) = (input, output);
+            self.samples.push((output * i16::MAX as f64).clamp(i16::MIN as f64, i16::MAX as f64) as i16);
+            (self.sum, self.count) = (0.0, 0);
+        }
+    }
+
+    pub fn dmc_fetch_address(&self) -> Option<u16> {
+        self.dmc.fetch_address()
+    }
+
+    pub fn dmc_fill(&mut self, value: u8) {
+        self.dmc.fill(value);
+    }
+
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+}
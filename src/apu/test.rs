@@ -0,0 +1,94 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use super::super::wav::to_wav;
+    use super::super::*;
+
+    fn run(apu: &mut Apu, cycles: usize) {
+        for _ in 0..cycles {
+            apu.tick();
+        }
+    }
+
+    #[test]
+    fn test_silence() {
+        let mut apu: Apu = Apu::new(DEFAULT_SAMPLE_RATE);
+        run(&mut apu, CPU_FREQUENCY as usize / 10);
+        let samples: Vec<i16> = apu.take_samples();
+        assert!(samples.len().abs_diff(DEFAULT_SAMPLE_RATE as usize / 10) <= 1);
+        // The triangle rests at 15, the high-pass filter removes that offset
+        assert!(samples[samples.len() / 2..].iter().all(|sample| sample.abs() <= 1));
+        assert!(apu.samples.is_empty());
+    }
+
+    #[test]
+    fn test_pulse() {
+        let mut apu: Apu = Apu::new(DEFAULT_SAMPLE_RATE);
+        apu.write_register(APU_STATUS_REGISTER, 0x01);
+        // 50% duty, constant volume 15, 440 Hz
+        apu.write_register(0x4000, 0b1011_1111);
+        let period: u16 = (CPU_FREQUENCY / (16.0 * 440.0) - 1.0) as u16;
+        apu.write_register(0x4002, period as u8);
+        apu.write_register(0x4003, (period >> 8) as u8 | 0b0000_1000);
+        assert_eq!(apu.read_status(), 0x01);
+        run(&mut apu, CPU_FREQUENCY as usize / 10);
+        let samples: Vec<i16> = apu.take_samples();
+        // A square wave centered by the high-pass filter, about 44 periods
+        let crossings: usize = samples.windows(2).filter(|pair| (pair[0] < 0) != (pair[1] < 0)).count();
+        assert!((80..=96).contains(&crossings), "{} crossings", crossings);
+        assert!(samples.iter().any(|sample| *sample > 1000) && samples.iter().any(|sample| *sample < -1000));
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut apu: Apu = Apu::new(DEFAULT_SAMPLE_RATE);
+        apu.write_register(APU_STATUS_REGISTER, 0x0c);
+        // Length index 1 is 254 half frames, index 3 is 2
+        apu.write_register(0x400c, 0b0001_1111);
+        apu.write_register(0x400f, 3 << 3);
+        apu.write_register(0x400b, 1 << 3);
+        apu.write_register(0x4008, 0xff);
+        assert_eq!(apu.read_status(), 0x0c);
+        run(&mut apu, FOUR_STEP_LENGTH);
+        assert_eq!(apu.read_status(), 0x04);
+        // Disabling a channel clears its length counter
+        apu.write_register(APU_STATUS_REGISTER, 0x00);
+        assert_eq!(apu.read_status(), 0x00);
+    }
+
+    #[test]
+    fn test_dmc() {
+        let mut apu: Apu = Apu::new(DEFAULT_SAMPLE_RATE);
+        apu.write_register(0x4010, 0x0f);
+        apu.write_register(0x4012, 0x01);
+        apu.write_register(0x4013, 0x01);
+        apu.write_register(APU_STATUS_REGISTER, 0x10);
+        assert_eq!(apu.read_status(), 0x10);
+        // The sample is $C040-$C050, a byte is fetched each time the buffer empties
+        let mut fetched: Vec<u16> = vec![];
+        for _ in 0..17 * 8 * 54 + 100 {
+            if let Some(address) = apu.dmc_fetch_address() {
+                fetched.push(address);
+                apu.dmc_fill(0xff);
+            }
+            apu.tick();
+        }
+        assert_eq!(fetched, (0xc040..=0xc050).collect::<Vec<u16>>());
+        assert_eq!((apu.read_status(), apu.dmc_fetch_address()), (0x00, None));
+    }
+
+    #[test]
+    fn test_wav() {
+        let wav: Vec<u8> = to_wav(&[0, 1, -1], 44100);
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes([wav[24], wav[25], wav[26], wav[27]]), 44100);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(&wav[44..], &[0, 0, 1, 0, 0xff, 0xff]);
+    }
+}
@@ -0,0 +1,34 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::error::Error;
+
+// 16 bits PCM mono, as the APU produces it
+pub fn to_wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data_size: u32 = samples.len() as u32 * 2;
+    let mut wav: Vec<u8> = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // size of the fmt chunk
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // channels
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes()); // bytes per frame
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+pub fn write_wav(path: &Path, samples: &[i16], sample_rate: u32) -> Result<(), Error> {
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(path)?);
+    writer.write_all(&to_wav(samples, sample_rate))?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,210 @@
+mod test;
+
+use std::io::{Read, Write};
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::error::{Error::ArchiveError, Error};
+
+// Zip archives (BizHawk movies are zip files). Only what the common tools write is supported:
+// a single disk, stored or deflated files, no encryption nor zip64.
+// ROMs may also be loaded from a zip or a gzip file
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// What a ROM inside an archive is named
+pub const ROM_EXTENSIONS: [&str; 4] = ["nes", "fds", "unf", "unif"];
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+// 1980-01-01 00:00, the earliest date a zip can hold
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = 0x21;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZipEntry {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Result<u16, Error> {
+    data.get(pos..pos + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(truncated)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32, Error> {
+    data.get(pos..pos + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(truncated)
+}
+
+fn truncated() -> Error {
+    ArchiveError(String::from("The zip file is truncated"))
+}
+
+pub fn is_zip(data: &[u8]) -> bool {
+    u32_at(data, 0).is_ok_and(|signature| signature == LOCAL_HEADER_SIGNATURE)
+}
+
+// A file listed by the central directory, not decompressed yet
+struct ZipRecord {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    local_header: usize,
+}
+
+// The files of the archive, the directories are skipped
+fn list_zip(data: &[u8]) -> Result<Vec<ZipRecord>, Error> {
+    let end: usize = (0..=data.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE)).rev()
+        .find(|pos| u32_at(data, *pos).is_ok_and(|signature| signature == END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+        .ok_or_else(|| ArchiveError(String::from("This is not a zip file")))?;
+    let count: usize = u16_at(data, end + 10)? as usize;
+    let mut pos: usize = u32_at(data, end + 16)? as usize;
+
+    let mut records: Vec<ZipRecord> = vec![];
+    for _ in 0..count {
+        if u32_at(data, pos)? != CENTRAL_HEADER_SIGNATURE {
+            return Err(ArchiveError(String::from("Invalid zip central directory")));
+        }
+        let name_len: usize = u16_at(data, pos + 28)? as usize;
+        let extra_len: usize = u16_at(data, pos + 30)? as usize;
+        let comment_len: usize = u16_at(data, pos + 32)? as usize;
+        let record: ZipRecord = ZipRecord {
+            name: String::from_utf8_lossy(data.get(pos + 46..pos + 46 + name_len).ok_or_else(truncated)?).into_owned(),
+            flags: u16_at(data, pos + 8)?,
+            method: u16_at(data, pos + 10)?,
+            crc: u32_at(data, pos + 16)?,
+            compressed_size: u32_at(data, pos + 20)? as usize,
+            local_header: u32_at(data, pos + 42)? as usize,
+        };
+        pos += 46 + name_len + extra_len + comment_len;
+        if !record.name.ends_with('/') {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+fn extract(data: &[u8], record: ZipRecord) -> Result<ZipEntry, Error> {
+    let ZipRecord { name, flags, method, crc, compressed_size, local_header } = record;
+    if flags & 1 != 0 {
+        return Err(ArchiveError(format!("{} is encrypted", name)));
+    }
+    if u32_at(data, local_header)? != LOCAL_HEADER_SIGNATURE {
+        return Err(ArchiveError(format!("Invalid zip header for {}", name)));
+    }
+    let start: usize = local_header + 30 + u16_at(data, local_header + 26)? as usize + u16_at(data, local_header + 28)? as usize;
+    let compressed: &[u8] = data.get(start..start + compressed_size).ok_or_else(truncated)?;
+    let data: Vec<u8> = match method {
+        METHOD_STORED => compressed.to_vec(),
+        METHOD_DEFLATED => {
+            let mut data: Vec<u8> = vec![];
+            DeflateDecoder::new(compressed).read_to_end(&mut data).map_err(|e| ArchiveError(format!("{}: {}", name, e)))?;
+            data
+        }
+        _ => return Err(ArchiveError(format!("{} uses the unsupported compression method {}", name, method))),
+    };
+    if crc32fast::hash(&data) != crc {
+        return Err(ArchiveError(format!("{} is corrupted (bad CRC)", name)));
+    }
+    Ok(ZipEntry { name, data })
+}
+
+// Every file of the archive, the directories are skipped
+pub fn read_zip(data: &[u8]) -> Result<Vec<ZipEntry>, Error> {
+    list_zip(data)?.into_iter().map(|record| extract(data, record)).collect()
+}
+
+// Deflated files
+pub fn write_zip(entries: &[ZipEntry]) -> Vec<u8> {
+    let mut zip: Vec<u8> = vec![];
+    let mut central_directory: Vec<u8> = vec![];
+    for entry in entries {
+        let mut encoder: DeflateEncoder<Vec<u8>> = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(&entry.data).expect("Writing to memory cannot fail");
+        let compressed: Vec<u8> = encoder.finish().expect("Writing to memory cannot fail");
+        let offset: u32 = zip.len() as u32;
+
+        // Fields shared by both headers, from the version needed to the extra field length
+        let mut common: Vec<u8> = vec![];
+        for value in [20, 0, METHOD_DEFLATED, DOS_TIME, DOS_DATE] {
+            common.extend_from_slice(&u16::to_le_bytes(value));
+        }
+        for value in [crc32fast::hash(&entry.data), compressed.len() as u32, entry.data.len() as u32] {
+            common.extend_from_slice(&value.to_le_bytes());
+        }
+        common.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        zip.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        zip.extend_from_slice(&common);
+        zip.extend_from_slice(entry.name.as_bytes());
+        zip.extend_from_slice(&compressed);
+
+        central_directory.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+        central_directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central_directory.extend_from_slice(&common);
+        central_directory.extend_from_slice(&[0; 10]); // comment length, disk, internal and external attributes
+        central_directory.extend_from_slice(&offset.to_le_bytes());
+        central_directory.extend_from_slice(entry.name.as_bytes());
+    }
+    let offset: u32 = zip.len() as u32;
+    zip.extend_from_slice(&central_directory);
+    zip.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+    zip.extend_from_slice(&[0; 4]); // disk numbers
+    zip.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&offset.to_le_bytes());
+    zip.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    zip
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+pub fn read_gzip(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decompressed: Vec<u8> = vec![];
+    GzDecoder::new(data).read_to_end(&mut decompressed).map_err(|e| ArchiveError(format!("Invalid gzip file: {}", e)))?;
+    Ok(decompressed)
+}
+
+fn is_rom(name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(_, extension)| ROM_EXTENSIONS.iter().any(|rom| rom.eq_ignore_ascii_case(extension)))
+}
+
+// The ROM in a zip or gzip file, the data itself otherwise.
+// A zip holding several ROMs needs the name of one, with or without its directory
+pub fn extract_rom(data: Vec<u8>, entry: Option<&str>) -> Result<Vec<u8>, Error> {
+    if is_gzip(&data) {
+        return read_gzip(&data);
+    }
+    if !is_zip(&data) {
+        return Ok(data);
+    }
+    // Only the chosen file is decompressed
+    let records: Vec<ZipRecord> = list_zip(&data)?;
+    let base_name = |record: &ZipRecord| record.name.rsplit('/').next().unwrap_or_default().to_string();
+    let mut candidates: Vec<ZipRecord> = match entry {
+        Some(name) => records.into_iter().filter(|record| record.name == name || base_name(record) == name).collect(),
+        None => records.into_iter().filter(|record| is_rom(&record.name)).collect(),
+    };
+    match (candidates.len(), entry) {
+        (1, _) => Ok(extract(&data, candidates.remove(0))?.data),
+        (0, Some(name)) => Err(ArchiveError(format!("There is no {} in the zip file", name))),
+        (0, None) => Err(ArchiveError(format!("There is no ROM in the zip file, expected a .{} file", ROM_EXTENSIONS.join(" or .")))),
+        _ => Err(ArchiveError(format!(
+            "The zip file holds several ROMs, pick one with --entry: {}",
+            candidates.iter().map(|record| record.name.as_str()).collect::<Vec<&str>>().join(", "),
+        ))),
+    }
+}
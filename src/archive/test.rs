@@ -0,0 +1,79 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::rom::hash;
+
+    use super::super::*;
+
+    // Made by Python's zipfile: a stored file, a directory and a deflated file
+    const PYTHON_ZIP: &str = "504b03041400000000000000210086a6103605000000050000000a00000073746f7265642e74787468656c6c6f504b0304140000000000b0b4525d000000000000000000000000040000006469722f504b0304140000000800000021002dfa91e1080000003c0000000c0000006465666c617465642e7478744b4c4a4e24170100504b010214031400000000000000210086a6103605000000050000000a000000000000000000000080010000000073746f7265642e747874504b01021403140000000000b0b4525d000000000000000000000000040000000000000000001000fd412d0000006469722f504b01021403140000000800000021002dfa91e1080000003c0000000c000000000000000000000080014f0000006465666c617465642e747874504b05060000000003000300a4000000810000000000";
+
+    #[test]
+    fn test_read_zip() {
+        let zip: Vec<u8> = hash::from_hex(PYTHON_ZIP).unwrap();
+        assert!(is_zip(&zip));
+        let entries: Vec<ZipEntry> = read_zip(&zip).unwrap();
+        assert_eq!(entries, vec![
+            ZipEntry { name: String::from("stored.txt"), data: b"hello".to_vec() },
+            ZipEntry { name: String::from("deflated.txt"), data: b"abc".repeat(20) },
+        ]);
+
+        let mut corrupted: Vec<u8> = zip.clone();
+        corrupted[0x2a] ^= 1;
+        assert!(read_zip(&corrupted).unwrap_err().to_string().contains("bad CRC"));
+        assert!(read_zip(&zip[..zip.len() - 30]).is_err());
+        assert!(!is_zip(b"NES\x1a"));
+    }
+
+    #[test]
+    fn test_write_zip() {
+        let entries: Vec<ZipEntry> = vec![
+            ZipEntry { name: String::from("Header.txt"), data: b"Platform NES\n".to_vec() },
+            ZipEntry { name: String::from("empty"), data: vec![] },
+            ZipEntry { name: String::from("Input Log.txt"), data: b"|..|........|\n".repeat(1000) },
+        ];
+        let zip: Vec<u8> = write_zip(&entries);
+        assert!(zip.len() < 1000);
+        assert_eq!(read_zip(&zip).unwrap(), entries);
+    }
+
+    #[test]
+    fn test_extract_rom() {
+        let rom: Vec<u8> = b"NES\x1a".repeat(100);
+        let entry = |name: &str, data: &[u8]| ZipEntry { name: String::from(name), data: data.to_vec() };
+        assert_eq!(extract_rom(rom.clone(), None).unwrap(), rom);
+
+        let mut encoder: GzEncoder<Vec<u8>> = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&rom).unwrap();
+        let gzip: Vec<u8> = encoder.finish().unwrap();
+        assert!(is_gzip(&gzip));
+        assert_eq!(extract_rom(gzip.clone(), None).unwrap(), rom);
+        assert!(extract_rom(gzip[..20].to_vec(), None).unwrap_err().to_string().contains("Invalid gzip file"));
+
+        let zip: Vec<u8> = write_zip(&[entry("readme.txt", b"hello"), entry("roms/Game.NES", &rom)]);
+        assert_eq!(extract_rom(zip, None).unwrap(), rom);
+
+        let zip: Vec<u8> = write_zip(&[entry("a.nes", &rom), entry("roms/b.nes", b"other")]);
+        assert!(extract_rom(zip.clone(), None).unwrap_err().to_string().contains("several ROMs, pick one with --entry: a.nes, roms/b.nes"));
+        assert_eq!(extract_rom(zip.clone(), Some("a.nes")).unwrap(), rom);
+        assert_eq!(extract_rom(zip.clone(), Some("b.nes")).unwrap(), b"other");
+        assert!(extract_rom(zip, Some("c.nes")).unwrap_err().to_string().contains("There is no c.nes"));
+        assert!(extract_rom(write_zip(&[entry("readme.txt", b"hello")]), None).unwrap_err().to_string().contains("no ROM in the zip file"));
+
+        // Only the ROM is decompressed, the other files may use any method
+        let mut zip: Vec<u8> = write_zip(&[entry("readme.txt", b"hello"), entry("game.nes", &rom)]);
+        let central: usize = zip.windows(4).position(|bytes| bytes == CENTRAL_HEADER_SIGNATURE.to_le_bytes()).unwrap();
+        zip[central + 10] = 14;
+        assert!(read_zip(&zip).unwrap_err().to_string().contains("readme.txt uses the unsupported compression method 14"));
+        assert_eq!(extract_rom(zip, None).unwrap(), rom);
+    }
+}
@@ -0,0 +1,588 @@
+mod test;
+
+use std::collections::BTreeMap;
+
+use crate::cpu::opcode::{AddressingMode, Opcode, OPCODES};
+use crate::error::{Error::AssemblerError, Error};
+
+// Small two-pass 6502 assembler, mostly meant to write test programs and patches
+//
+//   ; comment
+//   label:  LDA #<table          ; low byte, >table is the high byte
+//           STA $10
+//           BNE label
+//   SPEED = 3                    ; constant
+//   .org $8100                   ; (also org)
+//   table:  .byte 1, $02, %11, "text"   ; (also .db)
+//           .word label, table+2        ; (also .dw)
+//
+// Mnemonics are the ones of OPCODES (official and unofficial, the usual aliases such as ISC or ALR
+// are accepted too). Numbers are $hex, %binary, decimal or 'c', * is the current address, and the
+// operators are + - * / & | ^ << >> with the unary - ~ < >. Zero page addressing is picked when the
+// operand is known to fit in the first pass, unless it has ca65's "a:" prefix.
+
+pub const DEFAULT_ORIGIN: u16 = 0x8000;
+
+// Other names commonly given to the unofficial opcodes
+const ALIASES: [(&str, &str); 13] = [
+    ("ANC", "AAC"), ("ALR", "ASR"), ("ISC", "ISB"), ("LXA", "ATX"), ("SHA", "AXA"), ("AHX", "AXA"), ("SBX", "AXS"),
+    ("JAM", "KIL"), ("LAS", "LAR"), ("SHX", "SXA"), ("SHY", "SYA"), ("TAS", "XAS"), ("ANE", "XAA"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Index {
+    None,
+    X,
+    Y,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(String),
+    Direct(String, Index),
+    Indirect(String),
+    IndirectX(String),
+    IndirectY(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    Empty,
+    Org(String),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Constant(String, String),
+    Instruction(String, Operand),
+}
+
+struct Line {
+    number: usize,
+    label: Option<String>,
+    statement: Statement,
+}
+
+fn error(line: usize, message: String) -> Error {
+    AssemblerError(format!("line {}: {}", line, message))
+}
+
+// Assembles a program starting at $8000 unless it has an .org
+pub fn assemble(source: &str) -> Result<Assembly, Error> {
+    assemble_at(source, DEFAULT_ORIGIN)
+}
+
+pub fn assemble_at(source: &str, origin: u16) -> Result<Assembly, Error> {
+    let lines: Vec<Line> = source.lines().enumerate()
+        .map(|(i, text)| parse_line(text).map(|(label, statement)| Line { number: i + 1, label, statement }).map_err(|e| error(i + 1, e)))
+        .collect::<Result<Vec<Line>, Error>>()?;
+
+    let mut assembler: Assembler = Assembler { symbols: BTreeMap::new(), choices: vec![None; lines.len()], final_pass: false };
+    assembler.pass(&lines, origin)?;
+    assembler.final_pass = true;
+    let (start, bytes): (u16, Vec<u8>) = assembler.pass(&lines, origin)?;
+
+    let labels: BTreeMap<String, u16> = assembler.symbols.into_iter().map(|(name, value)| (name, value as u16)).collect();
+    Ok(Assembly { origin: start, bytes, labels })
+}
+
+impl Assembly {
+    // The bytes placed the way Rom::new_from_program_rom expects them (index 0 is $8000)
+    pub fn program_rom(&self) -> Result<Vec<u8>, Error> {
+        if self.origin < 0x8000 {
+            return Err(AssemblerError(format!("The program starts at ${:04X}, outside of the program ROM", self.origin)));
+        }
+        let mut program: Vec<u8> = vec![0; self.origin as usize - 0x8000];
+        program.extend(self.bytes.iter());
+        Ok(program)
+    }
+}
+
+// ===================================================================
+// ============================ Parsing ==============================
+// ===================================================================
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '@')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Removes the comment, taking care of the ';' inside strings and characters
+fn strip_comment(text: &str) -> &str {
+    let mut quote: Option<char> = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &text[..i],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => (),
+        }
+    }
+    text
+}
+
+// Splits on the commas that are not inside strings or parentheses
+fn split_list(text: &str) -> Vec<String> {
+    let mut items: Vec<String> = vec![];
+    let mut current: String = String::new();
+    let mut quote: Option<char> = None;
+    let mut depth: usize = 0;
+    for c in text.chars() {
+        match (quote, c) {
+            (None, ',') if depth == 0 => items.push(std::mem::take(&mut current).trim().to_string()),
+            (None, '"') | (None, '\'') => { quote = Some(c); current.push(c) }
+            (None, '(') => { depth += 1; current.push(c) }
+            (None, ')') => { depth = depth.saturating_sub(1); current.push(c) }
+            (Some(q), c) if q == c => { quote = None; current.push(c) }
+            _ => current.push(c),
+        }
+    }
+    items.push(current.trim().to_string());
+    items
+}
+
+// Index of the parenthesis closing the one opening the text
+fn closing_parenthesis(text: &str) -> Option<usize> {
+    let mut depth: usize = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+fn strip_suffix_ignore_case<'a>(text: &'a str, suffix: &str) -> Option<&'a str> {
+    let split: usize = text.len().checked_sub(suffix.len())?;
+    (text.is_char_boundary(split) && text[split..].eq_ignore_ascii_case(suffix)).then(|| &text[..split])
+}
+
+fn parse_operand(text: &str) -> Operand {
+    let text: String = text.split_whitespace().collect();
+    if text.is_empty() {
+        return Operand::None;
+    }
+    if text.eq_ignore_ascii_case("A") {
+        return Operand::Accumulator;
+    }
+    if let Some(value) = text.strip_prefix('#') {
+        return Operand::Immediate(value.to_string());
+    }
+    if text.starts_with('(') {
+        if let Some(inner) = strip_suffix_ignore_case(&text, ",X)").filter(|_| closing_parenthesis(&text) == Some(text.len() - 1)) {
+            return Operand::IndirectX(inner[1..].to_string());
+        }
+        if let Some(inner) = strip_suffix_ignore_case(&text, ",Y").filter(|inner| closing_parenthesis(inner) == Some(inner.len() - 1)) {
+            return Operand::IndirectY(inner[1..inner.len() - 1].to_string());
+        }
+        if closing_parenthesis(&text) == Some(text.len() - 1) {
+            return Operand::Indirect(text[1..text.len() - 1].to_string());
+        }
+    }
+    match (strip_suffix_ignore_case(&text, ",X"), strip_suffix_ignore_case(&text, ",Y")) {
+        (Some(value), _) => Operand::Direct(value.to_string(), Index::X),
+        (_, Some(value)) => Operand::Direct(value.to_string(), Index::Y),
+        _ => Operand::Direct(text, Index::None),
+    }
+}
+
+fn parse_line(text: &str) -> Result<(Option<String>, Statement), String> {
+    let mut text: &str = strip_comment(text).trim();
+    let mut label: Option<String> = None;
+
+    // "name = value" or "name equ value"
+    if let Some((name, value)) = text.split_once('=') {
+        if is_identifier(name.trim()) {
+            return Ok((None, Statement::Constant(name.trim().to_string(), value.trim().to_string())));
+        }
+    }
+    if let Some((name, rest)) = text.split_once(char::is_whitespace) {
+        let (keyword, value): (&str, &str) = rest.trim().split_once(char::is_whitespace).unwrap_or((rest.trim(), ""));
+        if keyword.eq_ignore_ascii_case("equ") && is_identifier(name) {
+            return Ok((None, Statement::Constant(name.to_string(), value.trim().to_string())));
+        }
+    }
+
+    if let Some((name, rest)) = text.split_once(':') {
+        if is_identifier(name.trim()) {
+            label = Some(name.trim().to_string());
+            text = rest.trim();
+        }
+    }
+    if text.is_empty() {
+        return Ok((label, Statement::Empty));
+    }
+
+    let (word, rest): (&str, &str) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let statement: Statement = match word.to_ascii_lowercase().as_str() {
+        ".org" | "org" => Statement::Org(rest.trim().to_string()),
+        ".byte" | ".db" | "db" => Statement::Bytes(split_list(rest)),
+        ".word" | ".dw" | "dw" => Statement::Words(split_list(rest)),
+        _ if word.starts_with('.') => return Err(format!("Unknown directive {}", word)),
+        _ => {
+            let mnemonic: String = word.to_ascii_uppercase();
+            let mnemonic: String = ALIASES.iter().find(|(alias, _)| *alias == mnemonic).map(|(_, name)| name.to_string()).unwrap_or(mnemonic);
+            if !OPCODES.iter().any(|opcode| opcode.name == mnemonic && opcode.inst_size > 0) {
+                // A label alone on its line doesn't need the colon
+                if is_identifier(word) && rest.is_empty() && label.is_none() {
+                    return Ok((Some(word.to_string()), Statement::Empty));
+                }
+                return Err(format!("Unknown mnemonic {}", word));
+            }
+            Statement::Instruction(mnemonic, parse_operand(rest))
+        }
+    };
+    Ok((label, statement))
+}
+
+// ===================================================================
+// ========================== Expressions ============================
+// ===================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 13] = ["<<", ">>", "+", "-", "*", "/", "&", "|", "^", "~", "<", ">", "("];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens: Vec<Token> = vec![];
+    let mut i: usize = 0;
+    while i < chars.len() {
+        let c: char = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' {
+            if chars.get(i + 2) != Some(&'\'') {
+                return Err(format!("Invalid character literal in \"{}\"", text));
+            }
+            tokens.push(Token::Number(chars[i + 1] as i64));
+            i += 3;
+        } else if c == '$' || c == '%' || c.is_ascii_digit() {
+            let (radix, start): (u32, usize) = match c {
+                '$' => (16, i + 1),
+                '%' => (2, i + 1),
+                _ => (10, i),
+            };
+            let mut end: usize = start;
+            while end < chars.len() && chars[end].is_digit(radix) {
+                end += 1;
+            }
+            let digits: String = chars[start..end].iter().collect();
+            tokens.push(Token::Number(i64::from_str_radix(&digits, radix).map_err(|_| format!("Invalid number in \"{}\"", text))?));
+            i = end;
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '@' {
+            let start: usize = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '@') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else if c == ')' {
+            tokens.push(Token::Op(")"));
+            i += 1;
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            let op: &str = OPERATORS.iter().find(|op| rest.starts_with(*op)).ok_or_else(|| format!("Unexpected '{}' in \"{}\"", c, text))?;
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// Overflows are errors rather than panics or silently wrapped values
+fn binary_op(op: &str, l: i64, r: i64) -> Result<i64, String> {
+    let shift: Option<u32> = u32::try_from(r).ok();
+    let value: Option<i64> = match op {
+        "|" => Some(l | r),
+        "^" => Some(l ^ r),
+        "&" => Some(l & r),
+        "<<" => shift.and_then(|r| l.checked_shl(r)),
+        ">>" => shift.and_then(|r| l.checked_shr(r)),
+        "+" => l.checked_add(r),
+        "-" => l.checked_sub(r),
+        "*" => l.checked_mul(r),
+        _ => l.checked_div(r),
+    };
+    value.ok_or_else(|| format!("Overflow in {} {} {}", l, op, r))
+}
+
+// Evaluates to None when a symbol is not known yet (only allowed in the first pass)
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    symbols: &'a BTreeMap<String, i64>,
+    pc: u16,
+}
+
+impl Evaluator<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    // Binary operators by increasing priority
+    fn binary(&mut self, level: usize) -> Result<Option<i64>, String> {
+        const LEVELS: [&[&str]; 5] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"]];
+        if level == LEVELS.len() {
+            return self.product();
+        }
+        let mut value: Option<i64> = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| LEVELS[level].contains(op)) {
+            self.pos += 1;
+            let right: Option<i64> = self.binary(level + 1)?;
+            value = value.zip(right).map(|(l, r)| binary_op(op, l, r)).transpose()?;
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<Option<i64>, String> {
+        let mut value: Option<i64> = self.unary()?;
+        while let Some(op) = self.peek_op().filter(|op| *op == "*" || *op == "/") {
+            self.pos += 1;
+            let right: Option<i64> = self.unary()?;
+            if op == "/" && right == Some(0) {
+                return Err(String::from("Division by zero"));
+            }
+            value = value.zip(right).map(|(l, r)| binary_op(op, l, r)).transpose()?;
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<Option<i64>, String> {
+        let op: Option<&str> = self.peek_op().filter(|op| ["-", "~", "<", ">"].contains(op));
+        if let Some(op) = op {
+            self.pos += 1;
+            let value: Option<i64> = self.unary()?;
+            return value.map(|v| match op {
+                "-" => v.checked_neg().ok_or_else(|| format!("Overflow in -{}", v)),
+                "~" => Ok(!v),
+                "<" => Ok(v & 0xff),
+                _ => Ok((v >> 8) & 0xff),
+            }).transpose();
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Option<i64>, String> {
+        let token: Option<Token> = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Some(value)),
+            Some(Token::Name(name)) => Ok(self.symbols.get(&name).copied()),
+            Some(Token::Op("*")) => Ok(Some(self.pc as i64)),
+            Some(Token::Op("(")) => {
+                let value: Option<i64> = self.binary(0)?;
+                if self.peek_op() != Some(")") {
+                    return Err(String::from("Missing ')'"));
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err(String::from("Unexpected end of expression")),
+        }
+    }
+}
+
+// ===================================================================
+// ============================ Passes ===============================
+// ===================================================================
+
+struct Assembler {
+    symbols: BTreeMap<String, i64>,
+    // Opcode picked for each line in the first pass, so that the sizes don't change in the second one
+    choices: Vec<Option<u8>>,
+    final_pass: bool,
+}
+
+fn find_opcode(mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+    // The official encoding wins when there are several (NOP, SBC #imm)
+    let mut candidates: Vec<u8> = (0..=255u8).filter(|i| {
+        let opcode: &Opcode = &OPCODES[*i as usize];
+        opcode.name == mnemonic && opcode.address_mode == mode && opcode.inst_size > 0
+    }).collect();
+    candidates.sort_by_key(|i| !OPCODES[*i as usize].official);
+    candidates.first().copied()
+}
+
+impl Assembler {
+    fn evaluate(&self, text: &str, pc: u16) -> Result<Option<i64>, String> {
+        let text: &str = text.strip_prefix("a:").unwrap_or(text);
+        let mut evaluator: Evaluator = Evaluator { tokens: tokenize(text)?, pos: 0, symbols: &self.symbols, pc };
+        let value: Option<i64> = evaluator.binary(0)?;
+        if evaluator.pos != evaluator.tokens.len() {
+            return Err(format!("Unexpected {:?} in \"{}\"", evaluator.tokens[evaluator.pos], text));
+        }
+        match value {
+            None if self.final_pass => Err(format!("Unknown symbol in \"{}\"", text)),
+            _ => Ok(value),
+        }
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), String> {
+        match self.symbols.insert(name.to_string(), value) {
+            Some(previous) if previous != value && !self.final_pass => Err(format!("{} is already defined", name)),
+            _ => Ok(()),
+        }
+    }
+
+    fn choose_opcode(&self, mnemonic: &str, operand: &Operand, pc: u16) -> Result<u8, String> {
+        let not_supported = || format!("{} doesn't support this addressing mode", mnemonic);
+        let mode: AddressingMode = match operand {
+            Operand::None => {
+                let mode: AddressingMode = if find_opcode(mnemonic, AddressingMode::Implied).is_some() { AddressingMode::Implied } else { AddressingMode::Accumulator };
+                return find_opcode(mnemonic, mode).ok_or_else(not_supported);
+            }
+            Operand::Accumulator => AddressingMode::Accumulator,
+            Operand::Immediate(_) => AddressingMode::Immediate,
+            // Parentheses around a plain operand are only grouping for the instructions without (addr)
+            Operand::Indirect(value) if find_opcode(mnemonic, AddressingMode::Indirect).is_none() => {
+                return self.choose_opcode(mnemonic, &Operand::Direct(value.clone(), Index::None), pc);
+            }
+            Operand::Indirect(_) => AddressingMode::Indirect,
+            Operand::IndirectX(_) => AddressingMode::IndirectX,
+            Operand::IndirectY(_) => AddressingMode::IndirectY,
+            Operand::Direct(value, index) => {
+                if *index == Index::None && find_opcode(mnemonic, AddressingMode::Relative).is_some() {
+                    return find_opcode(mnemonic, AddressingMode::Relative).ok_or_else(not_supported);
+                }
+                let (zero_page, absolute): (AddressingMode, AddressingMode) = match index {
+                    Index::None => (AddressingMode::ZeroPage, AddressingMode::Absolute),
+                    Index::X => (AddressingMode::ZeroPageX, AddressingMode::AbsoluteX),
+                    Index::Y => (AddressingMode::ZeroPageY, AddressingMode::AbsoluteY),
+                };
+                // ca65's "a:" prefix forces the absolute addressing
+                let fits: bool = !value.starts_with("a:") && matches!(self.evaluate(value, pc)?, Some(v) if (0..0x100).contains(&v));
+                return match (fits, find_opcode(mnemonic, zero_page), find_opcode(mnemonic, absolute)) {
+                    (true, Some(opcode), _) | (_, _, Some(opcode)) => Ok(opcode),
+                    _ => Err(not_supported()),
+                };
+            }
+        };
+        find_opcode(mnemonic, mode).ok_or_else(not_supported)
+    }
+
+    // Returns the start address and the bytes (meaningful in the final pass only)
+    fn pass(&mut self, lines: &[Line], origin: u16) -> Result<(u16, Vec<u8>), Error> {
+        let mut start: Option<u16> = None;
+        let mut pc: usize = origin as usize;
+        let mut bytes: Vec<u8> = vec![];
+        for (i, line) in lines.iter().enumerate() {
+            let number: usize = line.number;
+            if let Some(label) = &line.label {
+                self.define(label, pc as i64).map_err(|e| error(number, e))?;
+            }
+            let mut emitted: Vec<u8> = vec![];
+            match &line.statement {
+                Statement::Empty => (),
+                Statement::Constant(name, value) => {
+                    if let Some(value) = self.evaluate(value, pc as u16).map_err(|e| error(number, e))? {
+                        self.define(name, value).map_err(|e| error(number, e))?;
+                    }
+                }
+                Statement::Org(value) => {
+                    let value: i64 = self.evaluate(value, pc as u16).map_err(|e| error(number, e))?
+                        .ok_or_else(|| error(number, String::from(".org needs an address known in advance")))?;
+                    if !(0..0x10000).contains(&value) || (start.is_some() && (value as usize) < pc) {
+                        return Err(error(number, format!(".org ${:X} goes backwards or out of the address space", value)));
+                    }
+                    if start.is_some() {
+                        bytes.resize(bytes.len() + value as usize - pc, 0);
+                    }
+                    pc = value as usize;
+                }
+                Statement::Bytes(items) => {
+                    for item in items {
+                        if item.len() >= 2 && item.starts_with('"') && item.ends_with('"') {
+                            emitted.extend(item[1..item.len() - 1].bytes());
+                            continue;
+                        }
+                        let value: i64 = self.evaluate(item, pc as u16).map_err(|e| error(number, e))?.unwrap_or(0);
+                        if !(-0x80..0x100).contains(&value) {
+                            return Err(error(number, format!("{} doesn't fit in a byte", item)));
+                        }
+                        emitted.push(value as u8);
+                    }
+                }
+                Statement::Words(items) => {
+                    for item in items {
+                        let value: i64 = self.evaluate(item, pc as u16).map_err(|e| error(number, e))?.unwrap_or(0);
+                        if !(-0x8000..0x10000).contains(&value) {
+                            return Err(error(number, format!("{} doesn't fit in a word", item)));
+                        }
+                        emitted.extend((value as u16).to_le_bytes());
+                    }
+                }
+                Statement::Instruction(mnemonic, operand) => {
+                    let opcode: u8 = match self.choices[i] {
+                        Some(opcode) => opcode,
+                        None => self.choose_opcode(mnemonic, operand, pc as u16).map_err(|e| error(number, e))?,
+                    };
+                    self.choices[i] = Some(opcode);
+                    emitted.push(opcode);
+
+                    let info: &Opcode = &OPCODES[opcode as usize];
+                    let value: i64 = match operand {
+                        Operand::Immediate(v) | Operand::Direct(v, _) | Operand::Indirect(v) | Operand::IndirectX(v) | Operand::IndirectY(v) => {
+                            self.evaluate(v, pc as u16).map_err(|e| error(number, e))?.unwrap_or(0)
+                        }
+                        Operand::None | Operand::Accumulator => 0,
+                    };
+                    match (info.address_mode, info.inst_size) {
+                        (AddressingMode::Relative, _) => {
+                            let offset: i64 = value - (pc as i64 + 2);
+                            if self.final_pass && !(-128..128).contains(&offset) {
+                                return Err(error(number, format!("Branch target is {} bytes away", offset)));
+                            }
+                            emitted.push(offset as u8);
+                        }
+                        (_, 2) => {
+                            if self.final_pass && !(-0x80..0x100).contains(&value) {
+                                return Err(error(number, format!("Operand ${:X} doesn't fit in a byte", value)));
+                            }
+                            emitted.push(value as u8);
+                        }
+                        (_, 3) => {
+                            if self.final_pass && !(-0x8000..0x10000).contains(&value) {
+                                return Err(error(number, format!("Operand ${:X} doesn't fit in a word", value)));
+                            }
+                            emitted.extend((value as u16).to_le_bytes());
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            if !emitted.is_empty() {
+                start.get_or_insert(pc as u16);
+                if pc + emitted.len() > 0x10000 {
+                    return Err(error(number, String::from("The program goes past $FFFF")));
+                }
+                pc += emitted.len();
+                bytes.extend(emitted);
+            }
+        }
+        Ok((start.unwrap_or(origin), bytes))
+    }
+}
@@ -0,0 +1,129 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::disasm::{Disassembler, Syntax};
+
+    use super::super::*;
+
+    #[test]
+    fn test_addressing_modes() {
+        let assembly: Assembly = assemble("
+            LDA #$05
+            LDA $10
+            LDA $10,X
+            LDX $10,Y
+            LDA $1234
+            LDA $1234,X
+            LDA $1234,y
+            LDA ($20,X)
+            LDA ($20),Y
+            JMP ($FFFC)
+            ASL A
+            ASL
+            TAX
+        ").unwrap();
+        assert_eq!(assembly.origin, 0x8000);
+        assert_eq!(assembly.bytes, vec![
+            0xa9, 0x05, 0xa5, 0x10, 0xb5, 0x10, 0xb6, 0x10, 0xad, 0x34, 0x12, 0xbd, 0x34, 0x12, 0xb9, 0x34, 0x12,
+            0xa1, 0x20, 0xb1, 0x20, 0x6c, 0xfc, 0xff, 0x0a, 0x0a, 0xaa,
+        ]);
+    }
+
+    #[test]
+    fn test_labels_and_branches() {
+        let assembly: Assembly = assemble("
+                    LDX #0
+            loop:   INX
+                    CPX #COUNT
+                    BNE loop
+                    JSR sub
+                    BRK
+            COUNT = 5
+            sub     ; no colon needed when alone
+                    LDA #$42
+                    BEQ end
+            end:    RTS
+        ").unwrap();
+        assert_eq!(assembly.bytes, vec![0xa2, 0x00, 0xe8, 0xe0, 0x05, 0xd0, 0xfb, 0x20, 0x0b, 0x80, 0x00, 0xa9, 0x42, 0xf0, 0x00, 0x60]);
+        assert_eq!(assembly.labels.get("sub"), Some(&0x800b));
+    }
+
+    #[test]
+    fn test_directives_and_expressions() {
+        let assembly: Assembly = assemble("
+            .org $C000
+            start:  LDA #<table
+                    LDX #>table
+                    LDY #(2 + 3) * 4 - 1
+                    LDA table + 1, x
+                    STA $FF + 1          ; does not fit in the zero page
+            table:  .byte 1, $02, %11, 'A', \"hi;\"
+                    .word start, * + 2
+        ").unwrap();
+        assert_eq!(assembly.origin, 0xc000);
+        assert_eq!(assembly.labels.get("table"), Some(&0xc00c));
+        assert_eq!(assembly.bytes, vec![
+            0xa9, 0x0c, 0xa2, 0xc0, 0xa0, 19, 0xbd, 0x0d, 0xc0, 0x8d, 0x00, 0x01,
+            0x01, 0x02, 0x03, 0x41, b'h', b'i', b';', 0x00, 0xc0, 0x15, 0xc0,
+        ]);
+        assert_eq!(&assembly.program_rom().unwrap()[0x4000..0x4002], [0xa9, 0x0c]);
+    }
+
+    #[test]
+    fn test_forward_references() {
+        // Unknown in the first pass, so the absolute addressing is kept even though it fits
+        let assembly: Assembly = assemble("LDA var\nvar = $10\nLDA var").unwrap();
+        assert_eq!(assembly.bytes, vec![0xad, 0x10, 0x00, 0xa5, 0x10]);
+        assert_eq!(assemble("LDA a:$10").unwrap().bytes, vec![0xad, 0x10, 0x00]);
+    }
+
+    #[test]
+    fn test_unofficial() {
+        let assembly: Assembly = assemble("SLO $10\nISC $1234,X\nNOP\nNOP #$12\nSBC #1\nLAX ($20),Y").unwrap();
+        assert_eq!(assembly.bytes, vec![0x07, 0x10, 0xff, 0x34, 0x12, 0xea, 0x80, 0x12, 0xe9, 0x01, 0xb3, 0x20]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let message = |source: &str| assemble(source).unwrap_err().to_string();
+        assert_eq!(message("NOP\nFOO #1"), "Assembler Error: line 2: Unknown mnemonic FOO");
+        assert!(message("LDA undefined").contains("Unknown symbol"));
+        assert!(message("STX $1234,X").contains("doesn't support"));
+        assert!(message("a: NOP\na: NOP").contains("already defined"));
+        assert!(message("loop: NOP\n.org $9000\nBNE loop").contains("bytes away"));
+        assert!(message(".org $9000\nNOP\n.org $8000").contains("backwards"));
+        assert!(message(".byte 256").contains("doesn't fit"));
+        assert_eq!(message("NOP\n.word $12345"), "Assembler Error: line 2: $12345 doesn't fit in a word");
+        assert_eq!(message("LDA $12345"), "Assembler Error: line 1: Operand $12345 doesn't fit in a word");
+        assert!(message("JMP ($10000)").contains("doesn't fit in a word"));
+        // Overflows while evaluating, on the line of the expression
+        assert_eq!(message("NOP\nLDA #1<<70"), "Assembler Error: line 2: Overflow in 1 << 70");
+        assert!(message("LDA #1>>-1").contains("Overflow"));
+        assert!(message("LDA #$7fffffffffffffff+1").contains("Overflow"));
+        assert!(message("LDA #0-$7fffffffffffffff-2").contains("Overflow"));
+        assert!(message("LDA #$100000000*$100000000").contains("Overflow"));
+        assert!(message("LDA #-(0-$7fffffffffffffff-1)").contains("Overflow"));
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        let source: &str = "
+            .org $8000
+            L8000:
+                LDX #$00
+            L8002:
+                INX
+                STA $0200,X
+                LDA a:$0010
+                BNE L8002
+                JMP L8000
+        ";
+        let assembly: Assembly = assemble(source).unwrap();
+        let listing: String = Disassembler::new(&assembly.bytes, assembly.origin, Syntax::Ca65).listing();
+        assert_eq!(assemble(&listing).unwrap().bytes, assembly.bytes);
+    }
+}
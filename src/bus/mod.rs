@@ -1,7 +1,18 @@
+use crate::apu::{Apu, APU_FRAME_COUNTER_REGISTER, APU_REGISTERS_END, APU_REGISTERS_START, APU_STATUS_REGISTER};
+use crate::cdl::{CodeDataLog, CHR_READ, CHR_RENDERED, PRG_DMC};
+use crate::cheat::Cheats;
+use crate::cpu::opcode::Opcode;
+use crate::error::{Error::StateError, Error};
+use crate::fds::{audio, Fds, FDS_REGISTERS_START};
 use crate::input::Joypad;
 use crate::mem::Mem;
+use crate::memview::{self, Freeze, Region};
+use crate::movie::MovieSession;
+use crate::nsf::{NsfMemory, BANK_REGISTERS_END, BANK_REGISTERS_START};
+use crate::poweron::PowerOn;
 use crate::ppu::PPU;
-use crate::rom::Rom;
+use crate::rom::{hash, Rom};
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use crate::screen::render::Renderer;
 use crate::screen::Screen;
 
@@ -24,32 +35,109 @@ const PPU_OAM_DMA_REGISTER: u16 = 0x4014;
 const JOYPAD1_ADDRESS: u16 = 0x4016;
 const JOYPAD2_ADDRESS: u16 = 0x4017;
 
+pub const PRG_RAM_START: u16 = 0x6000;
+pub const PRG_RAM_END: u16 = 0x7fff;
+
 pub const PROGRAM_ROM_START: u16 = 0x8000;
 pub const PROGRAM_ROM_END: u16 = 0xffff;
 
 pub const PROGRAM_BASE_POINTER: u16 = 0xfffc;
 pub const NMI_ADDRESS_POINTER: u16 = 0xfffa;
+pub const IRQ_ADDRESS_POINTER: u16 = 0xfffe;
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressSpace {
+    Cpu,
+    Ppu,
+}
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryAccess {
+    pub space: AddressSpace,
+    pub kind: AccessKind,
+    pub addr: u16,
+    pub value: u8,
+}
 
 pub struct Bus {
     cpu_cycles: usize,
+    frames: usize,
     cpu_vram: [u8; 0x800],
+    // Cartridge RAM, battery-backed or not
+    prg_ram: [u8; 0x2000],
     program_rom: [u8; 0x8000],
+    program_rom_size: usize,
+    // How the memories were filled at power-on, kept for save states and movies
+    pub power_on: PowerOn,
+    // Identify the ROM in save states and movies
+    rom_md5: [u8; 16],
+    rom_sha1: [u8; 20],
     pub ppu: PPU,
     pub screen: Screen,
+    // Every read and write goes here when set (used by the debugger watchpoints)
+    pub access_log: Option<Vec<MemoryAccess>>,
+    // Code/data logging of the PRG and CHR bytes when set
+    pub cdl: Option<CodeDataLog>,
+    // Values written back at the start of every vblank (memory viewer freezes)
+    pub freezes: Vec<Freeze>,
+    // Game Genie and Pro Action Replay codes, not part of the emulated state (save states keep them)
+    pub cheats: Cheats,
+    // Movie being recorded or played back, its input replaces the joypads at every frame
+    pub movie: Option<MovieSession>,
+    // Sound, when someone listens to it
+    pub apu: Option<Apu>,
+    // NSF player: the banks of the music data replace the program ROM and the PPU is stopped
+    pub nsf: Option<NsfMemory>,
+    // Famicom Disk System: the RAM adapter replaces the program ROM, $4020-$4092 are its registers
+    pub fds: Option<Fds>,
+    // Set at vblank, until the input of the new frame has gone through the movie
+    frame_started: bool,
     gameloop_callback: fn(&PPU, &mut Screen)
 }
 
 impl Bus {
     pub fn new(rom: Rom, gameloop_callback: fn(&PPU, &mut Screen), joypad1: Joypad, joypad2: Joypad) -> Self {
+        Bus::with_screen(rom, gameloop_callback, Screen::new(joypad1, joypad2))
+    }
+
+    // Same as new but without opening any window, the frames are still rendered in screen.frame
+    pub fn new_headless(rom: Rom, gameloop_callback: fn(&PPU, &mut Screen), joypad1: Joypad, joypad2: Joypad) -> Self {
+        Bus::with_screen(rom, gameloop_callback, Screen::new_headless(joypad1, joypad2))
+    }
+
+    fn with_screen(rom: Rom, gameloop_callback: fn(&PPU, &mut Screen), screen: Screen) -> Self {
+        let (rom_md5, rom_sha1): ([u8; 16], [u8; 20]) = (rom.md5(), rom.sha1());
         Bus {
             cpu_cycles: 0,
+            frames: 0,
             cpu_vram: [0; 0x800],
+            prg_ram: [0; 0x2000],
             program_rom: rom.program_rom,
+            program_rom_size: rom.program_rom_size.min(0x8000),
+            power_on: PowerOn::Zeros,
+            rom_md5,
+            rom_sha1,
             ppu: PPU::new(rom.chr_rom, rom.screen_mirroring),
-            screen: Screen::new(joypad1, joypad2),
-            gameloop_callback: gameloop_callback
+            screen,
+            access_log: None,
+            cdl: None,
+            freezes: vec![],
+            cheats: Cheats::default(),
+            movie: None,
+            apu: None,
+            nsf: None,
+            fds: None,
+            frame_started: false,
+            gameloop_callback
         }
     }
 
@@ -62,54 +150,311 @@ impl Bus {
         self.program_rom[addr as usize]
     }
 
+    // Plugs the RAM adapter with its disk, which identifies the game in save states and movies
+    pub fn attach_fds(&mut self, fds: Fds) {
+        let disk: Vec<u8> = fds.image().to_bytes();
+        (self.rom_md5, self.rom_sha1) = (hash::md5(&disk), hash::sha1(&disk));
+        self.ppu.chr_rom = vec![0; 0x2000];
+        self.ppu.chr_ram = true;
+        self.ppu.mirroring = fds.mirroring();
+        self.fds = Some(fds);
+    }
+
     pub fn rom_write_program_base(&mut self, program_base: u16) {
         let pos: u16 = PROGRAM_BASE_POINTER - PROGRAM_ROM_START;
         self.program_rom[pos as usize] = (program_base & 0xff) as u8; 
         self.program_rom[(pos+1) as usize] = (program_base >> 8) as u8; 
     }
 
+    // Side-effect-free write, used by debuggers to patch memory (even the program ROM)
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        match addr {
+            CPU_RAM_START..=CPU_RAM_END => self.cpu_vram[(addr & 0x7ff) as usize] = value,
+            PRG_RAM_START..=PROGRAM_ROM_END if self.fds.is_some() => self.fds.as_mut().map_or((), |fds| fds.poke(addr, value)),
+            PRG_RAM_START..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM_START) as usize] = value,
+            PROGRAM_ROM_START..=PROGRAM_ROM_END => {
+                let pos: usize = (addr - PROGRAM_ROM_START) as usize;
+                self.program_rom[pos] = value;
+            }
+            _ => (),
+        }
+    }
+
     pub fn tick(&mut self, op_cycles : usize){
         self.cpu_cycles += op_cycles;
+        self.tick_apu(op_cycles);
+        if self.nsf.is_some() {
+            return;
+        }
         let nmi_before = self.ppu.nmi_interrupt.is_some();
-        Renderer::forward(&self.ppu, &mut self.screen.frame, op_cycles * 3);
+        let vblank_before = self.ppu.reg_status.is_in_vblank();
+        let fetches: Vec<usize> = Renderer::forward(&self.ppu, &mut self.screen.frame, op_cycles * 3);
+        self.log_rendered_chr(&fetches);
         self.ppu.tick(op_cycles * 3); // PPU runs 3 times faster than CPU
         let nmi_after = self.ppu.nmi_interrupt.is_some();
+        let vblank_after = self.ppu.reg_status.is_in_vblank();
+
+        // A frame is over as soon as the PPU enters vblank, whether the game asked for an NMI or not
+        if !vblank_before && vblank_after {
+            self.frames += 1;
+            self.frame_started = true;
+            self.log_rendered_chr(&Renderer::sprite_fetches(&self.ppu));
+            self.apply_freezes();
+            self.cheats.apply_frame(&mut self.cpu_vram);
+            // The sound of the frame goes to the sound card, or nowhere when running headless
+            if let Some(apu) = self.apu.as_mut() {
+                let samples: Vec<i16> = apu.take_samples();
+                if let Some(display) = self.screen.display.as_mut() {
+                    display.queue_samples(&samples);
+                }
+            }
+        }
         
         if !nmi_before && nmi_after {
             (self.gameloop_callback)(&self.ppu, &mut self.screen);
         } 
     }
 
+    // The DMC reads its samples through the bus, without side effects but for the code/data log.
+    // The RAM adapter runs along, its wavetable goes to the APU mix when there is one (the headless
+    // runs have no APU)
+    fn tick_apu(&mut self, cycles: usize) {
+        if let Some(fds) = self.fds.as_mut().filter(|_| self.apu.is_none()) {
+            for _ in 0..cycles {
+                fds.tick();
+            }
+        }
+        if let Some(mut apu) = self.apu.take() {
+            for _ in 0..cycles {
+                if let Some(fds) = self.fds.as_mut() {
+                    fds.tick();
+                    apu.set_expansion_output(fds.audio.output());
+                }
+                apu.tick();
+                if let Some(addr) = apu.dmc_fetch_address() {
+                    apu.dmc_fill(self.mem_read_u8_no_fail(addr, true));
+                    self.log_dmc_fetch(addr);
+                }
+            }
+            self.apu = Some(apu);
+        }
+    }
+
+    // Called by the CPU before every instruction. The movie records or replaces the joypads once per
+    // frame, after the front end (or whoever runs the frames) has updated them and before the game reads them
+    pub fn poll_movie(&mut self) {
+        if self.frame_started {
+            self.frame_started = false;
+            if let Some(movie) = self.movie.as_mut() {
+                movie.next_frame(&mut self.screen.joypad1, &mut self.screen.joypad2);
+            }
+        }
+    }
+
+    // Offset in the PRG of the ROM file of a CPU address, None when no PRG is mapped there
+    pub fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let index: usize = addr.checked_sub(PROGRAM_ROM_START)? as usize;
+        index.checked_sub(0x8000 - self.program_rom_size)
+    }
+
+    pub fn chr_size(&self) -> usize {
+        self.ppu.chr_rom.len()
+    }
+
+    pub fn prg_size(&self) -> usize {
+        self.program_rom_size
+    }
+
+    // Fills the CPU RAM, the VRAM, the OAM and the palette as the console would come up,
+    // before the first instruction runs
+    pub fn power_on(&mut self, power_on: PowerOn) {
+        self.power_on = power_on;
+        power_on.fill(&mut [&mut self.cpu_vram, &mut self.ppu.vram, &mut self.ppu.oam_data, &mut self.ppu.palette_table]);
+        // Palette entries only have 6 bits
+        for color in self.ppu.palette_table.iter_mut() {
+            *color &= 0x3f;
+        }
+    }
+
+    pub fn rom_md5(&self) -> [u8; 16] {
+        self.rom_md5
+    }
+
+    pub fn rom_sha1(&self) -> [u8; 20] {
+        self.rom_sha1
+    }
+
+    // Starts logging which PRG/CHR bytes are code or data
+    pub fn enable_cdl(&mut self) {
+        if self.cdl.is_none() {
+            self.cdl = Some(CodeDataLog::new(self.prg_size(), self.chr_size()));
+        }
+    }
+
+    // Called by the CPU before it executes an instruction
+    pub fn log_execution(&mut self, pc: u16, opcode: &Opcode) {
+        if self.cdl.is_none() {
+            return;
+        }
+        let offsets: Vec<Option<usize>> = (0..opcode.inst_size.max(1) as u16).map(|i| self.prg_offset(pc.wrapping_add(i))).collect();
+        if let Some(cdl) = self.cdl.as_mut() {
+            cdl.start_instruction(pc, opcode, &offsets);
+        }
+    }
+
+    // The DMC sample bytes, flagged apart from the CPU reads
+    fn log_dmc_fetch(&mut self, addr: u16) {
+        let offset: Option<usize> = self.prg_offset(addr);
+        if let Some((cdl, offset)) = self.cdl.as_mut().zip(offset) {
+            cdl.mark_prg(offset, addr, PRG_DMC);
+        }
+    }
+
+    // Marks the pattern tiles fetched by the renderer: the background ones as the frame is drawn,
+    // the sprite ones once per frame
+    fn log_rendered_chr(&mut self, tiles: &[usize]) {
+        if let Some(cdl) = self.cdl.as_mut() {
+            for tile in tiles {
+                for offset in *tile..*tile + 0x10 {
+                    cdl.mark_chr(offset, CHR_RENDERED);
+                }
+            }
+        }
+    }
+
+    // Freezes a byte of a memory region to a value, which is written right away and then every frame
+    pub fn freeze(&mut self, region: Region, offset: usize, value: u8) {
+        self.unfreeze(region, offset);
+        self.freezes.push(Freeze { region, offset, value });
+        memview::poke(self, region, offset, value);
+    }
+
+    pub fn unfreeze(&mut self, region: Region, offset: usize) -> bool {
+        let count: usize = self.freezes.len();
+        self.freezes.retain(|freeze| (freeze.region, freeze.offset) != (region, offset));
+        self.freezes.len() != count
+    }
+
+    pub fn is_frozen(&self, region: Region, offset: usize) -> bool {
+        self.freezes.iter().any(|freeze| (freeze.region, freeze.offset) == (region, offset))
+    }
+
+    pub fn apply_freezes(&mut self) {
+        for i in 0..self.freezes.len() {
+            let Freeze { region, offset, value } = self.freezes[i];
+            memview::poke(self, region, offset, value);
+        }
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cpu_cycles
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
     pub fn poll_interrupt_nmi(&mut self) -> Option<()> {
         self.ppu.poll_nmi_interrupt()
     }
 
+    // The IRQ line is level triggered, it stays up until the game acknowledges the cartridge
+    pub fn poll_interrupt_irq(&self) -> bool {
+        self.fds.as_ref().is_some_and(Fds::irq)
+    }
+
+    fn log_access(&mut self, space: AddressSpace, kind: AccessKind, addr: u16, value: u8) {
+        if let Some(log) = self.access_log.as_mut() {
+            log.push(MemoryAccess { space, kind, addr, value });
+        }
+    }
+
+    // The devices plugged in the ports see the frame being drawn, for the light guns
     pub fn read_joypad1(&mut self) -> u8 {
-        self.screen.joypad1.read()
+        self.screen.read_port(0, self.ppu.scanline, self.ppu.cycles)
     }
 
     pub fn read_joypad2(&mut self) -> u8 {
-        self.screen.joypad2.read()
-        // 0
+        self.screen.read_port(1, self.ppu.scanline, self.ppu.cycles)
     }
 
+    // Both ports and the expansion port are strobed by $4016
     pub fn write_joypad1(&mut self, value: u8) {
-        self.screen.joypad1.write(value);
-        self.screen.joypad2.write(value);
+        self.screen.write_ports(value);
     }
 
     pub fn write_joypad2(&mut self, value: u8) {
-        self.screen.joypad1.write(value);
-        self.screen.joypad2.write(value);
+        self.screen.write_ports(value);
     }
 
     
 }
 
 
+// The cheats, freezes, logs and movie are not part of the emulated state, the power-on fill is kept
+// so that a movie recorded from a loaded state still knows how the console started
+impl SaveState for Bus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.usize(self.cpu_cycles);
+        writer.usize(self.frames);
+        writer.bool(self.frame_started);
+        writer.bytes(&self.power_on.to_bytes());
+        writer.bytes(&self.cpu_vram);
+        writer.bytes(&self.prg_ram);
+        self.ppu.save_state(writer);
+        self.screen.joypad1.save_state(writer);
+        self.screen.joypad2.save_state(writer);
+        self.screen.frame.save_state(writer);
+        // The layout of the device states depends on what is plugged
+        writer.vec(self.screen.input_setup.name().as_bytes());
+        self.screen.port1.save_state(writer);
+        self.screen.port2.save_state(writer);
+        if let Some(expansion) = self.screen.expansion.as_ref() {
+            expansion.save_state(writer);
+        }
+        if let Some(fds) = self.fds.as_ref() {
+            fds.save_state(writer);
+            writer.bytes(&self.ppu.chr_rom);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.cpu_cycles = reader.usize()?;
+        self.frames = reader.usize()?;
+        self.frame_started = reader.bool()?;
+        let mut power_on: [u8; 9] = [0; 9];
+        reader.bytes(&mut power_on)?;
+        self.power_on = PowerOn::from_bytes(power_on)?;
+        reader.bytes(&mut self.cpu_vram)?;
+        reader.bytes(&mut self.prg_ram)?;
+        self.ppu.load_state(reader)?;
+        self.screen.joypad1.load_state(reader)?;
+        self.screen.joypad2.load_state(reader)?;
+        self.screen.frame.load_state(reader)?;
+        let input_setup: Vec<u8> = reader.vec()?;
+        if input_setup != self.screen.input_setup.name().as_bytes() {
+            return Err(StateError(format!("The save state was made with the {} input setup, not {}",
+                String::from_utf8_lossy(&input_setup), self.screen.input_setup.name())));
+        }
+        self.screen.port1.load_state(reader)?;
+        self.screen.port2.load_state(reader)?;
+        if let Some(expansion) = self.screen.expansion.as_mut() {
+            expansion.load_state(reader)?;
+        }
+        if let Some(fds) = self.fds.as_mut() {
+            fds.load_state(reader)?;
+            reader.bytes(&mut self.ppu.chr_rom)?;
+            self.ppu.mirroring = fds.mirroring();
+        }
+        Ok(())
+    }
+}
+
+
 impl Mem for Bus {
+    // With no_fail, the read is a side-effect-free peek (used by logs and debuggers)
     fn mem_read_u8_no_fail(&mut self, addr: u16, no_fail: bool) -> u8 {
-        match addr {
+        let value: u8 = match addr {
             CPU_RAM_START..=CPU_RAM_END => {// from 0x0000 to 0x1fff
                 let real_addr: u16 = addr & 0x7ff;
                 self.cpu_vram[real_addr as usize]
@@ -119,27 +464,58 @@ impl Mem for Bus {
             PPU_CONTROLER_REGISTER | PPU_MASK_REGISTER | PPU_OAM_ADDRESS_REGISTER | 
             PPU_SCROLL_REGISTER | PPU_ADDRESS_REGISTER | PPU_OAM_DMA_REGISTER => {
                     if no_fail {
-                        println!("Trying to read from write-only address {:04x}", addr); 0
+                        0
                     } else {
                         // Some games try to do it on purpose, so just don't panic!
                         println!("Trying to read from write-only address {:04x}", addr); 0
                     }
             }
 
+            PPU_STATUS_REGISTER if no_fail => self.ppu.reg_status.snapshot(),
             PPU_STATUS_REGISTER => self.ppu.read_status(), // 0x2002 
-            PPU_DATA_REGISTER => self.ppu.read_data(),// 0x2007
+            PPU_DATA_REGISTER if no_fail => self.ppu.peek_data(),
+            PPU_DATA_REGISTER => {// 0x2007
+                let ppu_addr: u16 = self.ppu.reg_addr.get();
+                if let Some(cdl) = self.cdl.as_mut().filter(|_| ppu_addr < 0x2000) {
+                    cdl.mark_chr(ppu_addr as usize, CHR_READ);
+                }
+                let value: u8 = self.ppu.read_data();
+                self.log_access(AddressSpace::Ppu, AccessKind::Read, ppu_addr, value);
+                value
+            }
             
             PPU_REGISTERS_MIRRORING_START..=PPU_REGISTERS_MIRRORING_END => {// from 0x2000 to 0x3fff
                 let mirrored_addr: u16 = addr & 0x2007;
-                println!("{:04x}", mirrored_addr);
-                self.mem_read_u8(mirrored_addr)
+                return self.mem_read_u8_no_fail(mirrored_addr, no_fail);
             }
 
+            JOYPAD1_ADDRESS if no_fail => self.screen.peek_port(0, self.ppu.scanline, self.ppu.cycles),
+            JOYPAD2_ADDRESS if no_fail => self.screen.peek_port(1, self.ppu.scanline, self.ppu.cycles),
             JOYPAD1_ADDRESS => self.read_joypad1(), // 0x4016
             JOYPAD2_ADDRESS => self.read_joypad2(), // 0x4017
 
+            APU_STATUS_REGISTER => self.apu.as_ref().map_or(0, Apu::read_status), // 0x4015
+
+            // from 0x4030 to 0x4092, and from 0x6000 to 0xffff
+            FDS_REGISTERS_START..=audio::MODULATION_GAIN_REGISTER | PRG_RAM_START..=PROGRAM_ROM_END if self.fds.is_some() => {
+                self.fds.as_mut().map_or(0, |fds| fds.read(addr, no_fail))
+            }
+
+            PRG_RAM_START..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM_START) as usize], // from 0x6000 to 0x7fff
+
+            PROGRAM_ROM_START..=PROGRAM_ROM_END if self.nsf.is_some() => self.nsf.as_ref().map_or(0, |nsf| nsf.read(addr)),
+
             PROGRAM_ROM_START..=PROGRAM_ROM_END => {// from 0x8000 to 0xffff
-                self.read_program_rom(addr)
+                if let Some(offset) = self.prg_offset(addr).filter(|_| !no_fail) {
+                    if let Some(cdl) = self.cdl.as_mut() {
+                        cdl.read(offset, addr);
+                    }
+                }
+                let value: u8 = self.read_program_rom(addr);
+                match self.cheats.is_empty() {
+                    true => value,
+                    false => self.cheats.patch_read(addr, value),
+                }
             }
 
             _ => {
@@ -147,10 +523,18 @@ impl Mem for Bus {
                 // println!("Ignoring access to address {:x}", addr);
                 0
             }
+        };
+        if !no_fail {
+            self.log_access(AddressSpace::Cpu, AccessKind::Read, addr, value);
         }
+        value
     }
 
     fn mem_write_u8(&mut self, addr: u16, value: u8) {
+        // Mirrors of the PPU registers are logged once, under their canonical address
+        if !(0x2008..=PPU_REGISTERS_MIRRORING_END).contains(&addr) {
+            self.log_access(AddressSpace::Cpu, AccessKind::Write, addr, value);
+        }
         match addr {
             CPU_RAM_START..=CPU_RAM_END => {// from 0x0000 to 0x1fff
                 let real_addr: u16 = addr & 0x7ff;
@@ -160,7 +544,10 @@ impl Mem for Bus {
             PPU_CONTROLER_REGISTER => self.ppu.write_to_control(value),// 0x2000
             PPU_MASK_REGISTER => self.ppu.write_to_mask(value), // 0x2001
             PPU_ADDRESS_REGISTER => self.ppu.write_to_ppu_addr(value),// 0x2006
-            PPU_DATA_REGISTER => self.ppu.write_to_data(value),// 0x2007
+            PPU_DATA_REGISTER => {// 0x2007
+                self.log_access(AddressSpace::Ppu, AccessKind::Write, self.ppu.reg_addr.get(), value);
+                self.ppu.write_to_data(value);
+            }
 
             PPU_OAM_ADDRESS_REGISTER => self.ppu.write_to_oam_addr(value), // 0x2003
             PPU_OAM_DATA_REGISTER => self.ppu.write_to_oam_data(value), // 0x2004
@@ -186,7 +573,37 @@ impl Mem for Bus {
             }
 
             JOYPAD1_ADDRESS => self.write_joypad1(value), // 0x4016
-            JOYPAD2_ADDRESS => self.write_joypad2(value), // 0x4017
+            JOYPAD2_ADDRESS => {// 0x4017, also the APU frame counter
+                self.write_joypad2(value);
+                if let Some(apu) = self.apu.as_mut() {
+                    apu.write_register(APU_FRAME_COUNTER_REGISTER, value);
+                }
+            }
+
+            APU_REGISTERS_START..=APU_REGISTERS_END | APU_STATUS_REGISTER => {// from 0x4000 to 0x4013, and 0x4015
+                if let Some(apu) = self.apu.as_mut() {
+                    apu.write_register(addr, value);
+                }
+            }
+
+            // from 0x4020 to 0x408a, and from 0x6000 to 0xdfff
+            FDS_REGISTERS_START..=audio::REGISTERS_END | PRG_RAM_START..=PROGRAM_ROM_END if self.fds.is_some() => {
+                if let Some(fds) = self.fds.as_mut() {
+                    fds.write(addr, value);
+                    self.ppu.mirroring = fds.mirroring();
+                }
+            }
+
+            BANK_REGISTERS_START..=BANK_REGISTERS_END if self.nsf.is_some() => {// from 0x5ff8 to 0x5fff
+                if let Some(nsf) = self.nsf.as_mut() {
+                    nsf.write_bank(addr, value);
+                }
+            }
+
+            PRG_RAM_START..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM_START) as usize] = value, // from 0x6000 to 0x7fff
+
+            // Music rips may write to their former mapper
+            PROGRAM_ROM_START..=PROGRAM_ROM_END if self.nsf.is_some() => (),
 
             PROGRAM_ROM_START..=PROGRAM_ROM_END => {// from 0x8000 to 0xffff
                 panic!("Attempting to write on program ROM (at {:x})", addr);
@@ -199,4 +616,4 @@ impl Mem for Bus {
         }
     }
 
-}
+}
\ No newline at end of file
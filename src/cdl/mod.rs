@@ -0,0 +1,124 @@
+mod test;
+
+use std::path::Path;
+
+use crate::cpu::opcode::{AddressingMode, Opcode};
+use crate::error::{Error::RomError, Error};
+
+// Code/Data Logger, in the FCEUX .cdl format: one byte of flags per PRG byte, followed by one per
+// CHR byte, both indexed by their offset in the ROM file (so the log doesn't depend on the banks)
+
+pub const PRG_CODE: u8 = 0x01;
+pub const PRG_DATA: u8 = 0x02;
+// The 8kB window of $8000-$FFFF the byte was accessed through (0 for $8000 to 3 for $E000)
+pub const PRG_WINDOW_MASK: u8 = 0x0c;
+pub const PRG_INDIRECT_CODE: u8 = 0x10;
+pub const PRG_INDIRECT_DATA: u8 = 0x20;
+pub const PRG_DMC: u8 = 0x40;
+
+pub const CHR_RENDERED: u8 = 0x01;
+pub const CHR_READ: u8 = 0x02;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeDataLog {
+    pub prg: Vec<u8>,
+    pub chr: Vec<u8>,
+    // Bytes of the instruction being executed, their reads are not data
+    instruction: Option<(u16, u16)>,
+    indirect_data: bool,
+    indirect_jump: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CdlStats {
+    pub code: usize,
+    pub data: usize,
+    pub unknown: usize,
+    pub chr_used: usize,
+}
+
+impl CodeDataLog {
+    pub fn new(prg_size: usize, chr_size: usize) -> Self {
+        CodeDataLog { prg: vec![0; prg_size], chr: vec![0; chr_size], instruction: None, indirect_data: false, indirect_jump: false }
+    }
+
+    pub fn from_bytes(data: &[u8], prg_size: usize, chr_size: usize) -> Result<Self, Error> {
+        if data.len() != prg_size + chr_size {
+            return Err(RomError(format!("The CDL file has {} bytes, {} expected for this ROM", data.len(), prg_size + chr_size)));
+        }
+        let mut log: CodeDataLog = CodeDataLog::new(prg_size, chr_size);
+        log.prg.copy_from_slice(&data[..prg_size]);
+        log.chr.copy_from_slice(&data[prg_size..]);
+        Ok(log)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data: Vec<u8> = self.prg.clone();
+        data.extend(self.chr.iter());
+        data
+    }
+
+    pub fn load(path: &Path, prg_size: usize, chr_size: usize) -> Result<Self, Error> {
+        CodeDataLog::from_bytes(&std::fs::read(path)?, prg_size, chr_size)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> CdlStats {
+        CdlStats {
+            code: self.prg.iter().filter(|flags| *flags & PRG_CODE != 0).count(),
+            data: self.prg.iter().filter(|flags| *flags & (PRG_CODE | PRG_DATA) == PRG_DATA).count(),
+            unknown: self.prg.iter().filter(|flags| *flags & (PRG_CODE | PRG_DATA) == 0).count(),
+            chr_used: self.chr.iter().filter(|flags| **flags != 0).count(),
+        }
+    }
+
+    // PRG bytes only ever read as data, for Disassembler::with_data_mask
+    pub fn data_mask(&self) -> Vec<bool> {
+        self.prg.iter().map(|flags| flags & (PRG_CODE | PRG_DATA | PRG_DMC) != 0 && flags & PRG_CODE == 0).collect()
+    }
+
+    pub fn mark_prg(&mut self, offset: usize, addr: u16, flags: u8) {
+        if let Some(byte) = self.prg.get_mut(offset) {
+            *byte |= flags | (((addr >> 13) & 0b11) as u8) << 2;
+        }
+    }
+
+    pub fn mark_chr(&mut self, offset: usize, flags: u8) {
+        if let Some(byte) = self.chr.get_mut(offset) {
+            *byte |= flags;
+        }
+    }
+
+    // ===================================================================
+    // ========================= Bus hooks ===============================
+    // ===================================================================
+
+    // Called before an instruction executes, with the PRG offset of each of its bytes
+    pub fn start_instruction(&mut self, pc: u16, opcode: &Opcode, offsets: &[Option<usize>]) {
+        let mut flags: u8 = PRG_CODE;
+        if std::mem::take(&mut self.indirect_jump) {
+            flags |= PRG_INDIRECT_CODE;
+        }
+        for (i, offset) in offsets.iter().enumerate() {
+            if let Some(offset) = offset {
+                self.mark_prg(*offset, pc.wrapping_add(i as u16), flags);
+            }
+        }
+        self.instruction = Some((pc, pc.wrapping_add(opcode.inst_size.max(1) as u16 - 1)));
+        self.indirect_data = matches!(opcode.address_mode, AddressingMode::IndirectX | AddressingMode::IndirectY);
+        self.indirect_jump = matches!(opcode.address_mode, AddressingMode::Indirect);
+    }
+
+    // A read of the program ROM done by the CPU
+    pub fn read(&mut self, offset: usize, addr: u16) {
+        if self.instruction.is_some_and(|(start, end)| (start..=end).contains(&addr)) {
+            return;
+        }
+        let flags: u8 = if self.indirect_data { PRG_DATA | PRG_INDIRECT_DATA } else { PRG_DATA };
+        self.mark_prg(offset, addr, flags);
+    }
+}
@@ -0,0 +1,147 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::apu::{Apu, DEFAULT_SAMPLE_RATE};
+    use crate::asm::{self, Assembly};
+    use crate::cpu::CPU;
+    use crate::disasm::{Disassembler, Syntax};
+    use crate::rom::{Mirroring, Rom};
+
+    use super::super::*;
+
+    const PROGRAM: &str = "
+                LDX #0
+        loop:   LDA table,X
+                INX
+                CPX #2
+                BNE loop
+                LDA #<pointed
+                STA $10
+                LDA #>pointed
+                STA $11
+                LDY #0
+                LDA ($10),Y
+                JMP (vector)
+        vector: .word target
+        table:  .byte $aa, $bb
+        pointed: .byte $cc
+        target: BRK
+    ";
+
+    fn load(rom: Rom) -> CPU {
+        let mut cpu: CPU = CPU::test_load_rom(rom);
+        cpu.bus.enable_cdl();
+        cpu
+    }
+
+    #[test]
+    fn test_prg_flags() {
+        let assembly: Assembly = asm::assemble(PROGRAM).unwrap();
+        let mut cpu: CPU = load(Rom::new_from_program_rom(assembly.program_rom().unwrap()).unwrap());
+        cpu.run();
+
+        let cdl: &CodeDataLog = cpu.bus.cdl.as_ref().unwrap();
+        let offset = |label: &str| (assembly.labels[label] - 0x8000) as usize;
+        assert_eq!(cdl.prg.len(), 0x8000);
+        assert_eq!(cdl.prg[0], PRG_CODE);
+        assert_eq!(cdl.prg[offset("loop") + 2], PRG_CODE);
+        assert_eq!(cdl.prg[offset("vector")], PRG_DATA);
+        assert_eq!(cdl.prg[offset("table") + 1], PRG_DATA);
+        assert_eq!(cdl.prg[offset("pointed")], PRG_DATA | PRG_INDIRECT_DATA);
+        assert_eq!(cdl.prg[offset("target")], PRG_CODE | PRG_INDIRECT_CODE);
+        assert_eq!(cdl.prg[offset("target") + 1], 0);
+        // The reset vector was read when the CPU started, before the logging
+        assert_eq!(cdl.stats(), CdlStats { code: offset("vector") + 1, data: 5, unknown: 0x8000 - offset("vector") - 6, chr_used: 0 });
+
+        // The disassembler keeps the data as bytes
+        let program: Vec<u8> = assembly.bytes.clone();
+        let mask: Vec<bool> = cdl.data_mask()[..program.len()].to_vec();
+        let listing: String = Disassembler::new(&program, 0x8000, Syntax::Ca65).with_data_mask(mask).listing();
+        assert!(listing.contains("    JMP ($8019)              ; 8016 6C 19 80\n    .byte $1E, $80, $AA, $BB, $CC\n    BRK"));
+    }
+
+    #[test]
+    fn test_chr_flags_and_file() {
+        let assembly: Assembly = asm::assemble("
+                    LDA #$00
+                    STA $2006
+                    LDA #$20
+                    STA $2006
+                    LDA $2007
+                    LDA #$18
+                    STA $2001
+            loop:   JMP loop
+        ").unwrap();
+        let mut rom: Rom = Rom::new_from_program_rom(assembly.program_rom().unwrap()).unwrap();
+        rom.chr_rom = vec![0; 0x2000];
+        rom.screen_mirroring = Mirroring::HORIZONTAL;
+        let mut cpu: CPU = load(rom);
+        cpu.run_frame();
+
+        let cdl: CodeDataLog = cpu.bus.cdl.clone().unwrap();
+        // Tile 0 is drawn by the empty nametables and sprites, $0020 was read through $2007
+        assert!(cdl.chr[..0x10].iter().all(|flags| *flags == CHR_RENDERED));
+        assert_eq!(cdl.chr[0x10], 0);
+        assert_eq!(cdl.chr[0x20], CHR_READ);
+
+        let path = std::env::temp_dir().join(format!("nes_emul_cdl_{}.cdl", std::process::id()));
+        cdl.save(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0x8000 + 0x2000);
+        let loaded: CodeDataLog = CodeDataLog::load(&path, 0x8000, 0x2000).unwrap();
+        assert_eq!((loaded.prg, loaded.chr), (cdl.prg, cdl.chr));
+        assert!(CodeDataLog::load(&path, 0x4000, 0x2000).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_chr_sprites_8x16() {
+        let assembly: Assembly = asm::assemble("
+                    LDA #$20
+                    STA $2000
+                    LDA #$10
+                    STA $2001
+            loop:   JMP loop
+        ").unwrap();
+        let mut rom: Rom = Rom::new_from_program_rom(assembly.program_rom().unwrap()).unwrap();
+        rom.chr_rom = vec![0; 0x2000];
+        rom.screen_mirroring = Mirroring::HORIZONTAL;
+        let mut cpu: CPU = load(rom);
+        cpu.bus.ppu.oam_data.fill(0xff);
+        cpu.bus.ppu.oam_data[..4].copy_from_slice(&[0x20, 0x03, 0x00, 0x40]);
+        cpu.run_frame();
+
+        // Bit 0 of the tile index 3 selects the bank at $1000, the sprite is made of its tiles 2 and 3.
+        // The background isn't shown, so its tile 0 isn't fetched
+        let cdl: &CodeDataLog = cpu.bus.cdl.as_ref().unwrap();
+        assert!(cdl.chr[0x1020..0x1040].iter().all(|flags| *flags == CHR_RENDERED));
+        assert_eq!(cdl.stats().chr_used, 0x20);
+    }
+
+    #[test]
+    fn test_dmc_samples() {
+        // A sample of 17 bytes at $C040, played at the fastest rate
+        let assembly: Assembly = asm::assemble("
+                    LDA #$0f
+                    STA $4010
+                    LDA #$01
+                    STA $4012
+                    STA $4013
+                    LDA #$10
+                    STA $4015
+            loop:   JMP loop
+        ").unwrap();
+        let mut cpu: CPU = load(Rom::new_from_program_rom(assembly.program_rom().unwrap()).unwrap());
+        cpu.bus.apu = Some(Apu::new(DEFAULT_SAMPLE_RATE));
+        cpu.run_frame();
+
+        let data: Vec<u8> = cpu.bus.cdl.as_ref().unwrap().to_bytes();
+        // Accessed through the $C000 window
+        assert!(data[0x4040..=0x4050].iter().all(|flags| *flags == PRG_DMC | 0b10 << 2));
+        assert_eq!((data[0x403f], data[0x4051]), (0, 0));
+        assert!(cpu.bus.cdl.as_ref().unwrap().data_mask()[0x4040]);
+    }
+}
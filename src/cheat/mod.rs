@@ -0,0 +1,241 @@
+mod test;
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error::CheatError, Error};
+
+// Cheat codes applied by the bus. Game Genie codes patch what the CPU reads from the PRG ROM,
+// Pro Action Replay codes write a value to the internal RAM every frame.
+// The cheats are not part of the emulated state, so loading a save state keeps them as they are.
+
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+// Per-ROM cheat files sit next to the ROM: game.nes -> game.cht
+pub const CHEAT_FILE_EXTENSION: &str = "cht";
+
+pub const HELP: &str = "\
+Cheat commands:
+  cheat list                        cheats with their index and state
+  cheat add <code> [name]           Game Genie (6 or 8 letters) or Pro Action Replay (AAAAVV) code
+  cheat on | off <index>            enable or disable a cheat
+  cheat rm <index>                  remove a cheat";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatKind {
+    // Reads of the address give the value, only when the ROM holds the compare value if any
+    GameGenie { address: u16, value: u8, compare: Option<u8> },
+    // The RAM address is set to the value every frame
+    ProActionReplay { address: u16, value: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    pub code: String,
+    pub name: String,
+    pub kind: CheatKind,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cheats {
+    pub list: Vec<Cheat>,
+}
+
+// ===================================================================
+// ========================= Game Genie ==============================
+// ===================================================================
+
+// Returns (address, value, compare), the compare value only exists for 8 letter codes
+pub fn decode_game_genie(code: &str) -> Result<(u16, u8, Option<u8>), Error> {
+    let n: Vec<u16> = code.to_ascii_uppercase().chars().map(|c| GAME_GENIE_LETTERS.find(c).map(|n| n as u16))
+        .collect::<Option<_>>()
+        .ok_or_else(|| CheatError(format!("{} is not a Game Genie code, the letters are {}", code, GAME_GENIE_LETTERS)))?;
+    if n.len() != 6 && n.len() != 8 {
+        return Err(CheatError(format!("{} is not a Game Genie code, it should have 6 or 8 letters", code)));
+    }
+    let address: u16 = 0x8000 | ((n[3] & 7) << 12) | ((n[5] & 7) << 8) | ((n[4] & 8) << 8) | ((n[2] & 7) << 4) | ((n[1] & 8) << 4) | (n[4] & 7) | (n[3] & 8);
+    let value = |last: u16| (((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | (last & 8)) as u8;
+    match n.len() {
+        6 => Ok((address, value(n[5]), None)),
+        _ => Ok((address, value(n[7]), Some((((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8)) as u8))),
+    }
+}
+
+pub fn encode_game_genie(address: u16, value: u8, compare: Option<u8>) -> String {
+    let (address, value): (u16, u16) = (address & 0x7fff, value as u16);
+    let mut n: Vec<u16> = vec![
+        (value & 7) | ((value >> 4) & 8),
+        ((value >> 4) & 7) | ((address >> 4) & 8),
+        (address >> 4) & 7,
+        ((address >> 12) & 7) | (address & 8),
+        (address & 7) | ((address >> 8) & 8),
+        (address >> 8) & 7,
+    ];
+    match compare.map(u16::from) {
+        None => n[5] |= value & 8,
+        Some(compare) => {
+            n[2] |= 8;
+            n[5] |= compare & 8;
+            n.push((compare & 7) | ((compare >> 4) & 8));
+            n.push(((compare >> 4) & 7) | (value & 8));
+        }
+    }
+    n.iter().map(|n| GAME_GENIE_LETTERS.as_bytes()[*n as usize] as char).collect()
+}
+
+// ===================================================================
+// ====================== Pro Action Replay ==========================
+// ===================================================================
+
+// AAAAVV or AAAA:VV, in hexadecimal
+pub fn decode_pro_action_replay(code: &str) -> Result<(u16, u8), Error> {
+    let digits: String = code.replace(':', "");
+    let invalid = || CheatError(format!("{} is not a Pro Action Replay code, expected AAAAVV in hexadecimal", code));
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let address: u16 = u16::from_str_radix(&digits[..4], 16).map_err(|_| invalid())?;
+    let value: u8 = u8::from_str_radix(&digits[4..], 16).map_err(|_| invalid())?;
+    if address > 0x7ff {
+        return Err(CheatError(format!("{} is outside of the RAM ($0000-$07FF)", code)));
+    }
+    Ok((address, value))
+}
+
+impl Cheat {
+    pub fn parse(code: &str, name: &str) -> Result<Cheat, Error> {
+        let kind: CheatKind = match code.chars().all(|c| c.is_ascii_alphabetic()) {
+            true => {
+                let (address, value, compare) = decode_game_genie(code)?;
+                CheatKind::GameGenie { address, value, compare }
+            }
+            false => {
+                let (address, value) = decode_pro_action_replay(code)?;
+                CheatKind::ProActionReplay { address, value }
+            }
+        };
+        Ok(Cheat { code: code.to_ascii_uppercase(), name: String::from(name), kind, enabled: true })
+    }
+}
+
+impl Cheats {
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn add(&mut self, code: &str, name: &str) -> Result<usize, Error> {
+        self.list.push(Cheat::parse(code, name)?);
+        Ok(self.list.len() - 1)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<Cheat, Error> {
+        self.get(index)?;
+        Ok(self.list.remove(index))
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> Result<(), Error> {
+        self.get(index)?;
+        self.list[index].enabled = enabled;
+        Ok(())
+    }
+
+    fn get(&self, index: usize) -> Result<&Cheat, Error> {
+        self.list.get(index).ok_or_else(|| CheatError(format!("No cheat {}", index)))
+    }
+
+    // What the CPU reads at a PRG address holding the value
+    pub fn patch_read(&self, addr: u16, value: u8) -> u8 {
+        for cheat in self.list.iter().filter(|cheat| cheat.enabled) {
+            if let CheatKind::GameGenie { address, value: patched, compare } = cheat.kind {
+                if address == addr && compare.is_none_or(|compare| compare == value) {
+                    return patched;
+                }
+            }
+        }
+        value
+    }
+
+    // Called once per frame with the internal RAM
+    pub fn apply_frame(&self, ram: &mut [u8]) {
+        for cheat in self.list.iter().filter(|cheat| cheat.enabled) {
+            if let CheatKind::ProActionReplay { address, value } = cheat.kind {
+                if let Some(byte) = ram.get_mut(address as usize) {
+                    *byte = value;
+                }
+            }
+        }
+    }
+
+    // ===================================================================
+    // ============================ Files ================================
+    // ===================================================================
+
+    // One cheat per line: the code then its name, a leading - disables it, # starts a comment
+    pub fn from_text(text: &str) -> Result<Cheats, Error> {
+        let mut cheats: Cheats = Cheats::default();
+        for (number, line) in text.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (enabled, line): (bool, &str) = match line.strip_prefix('-') {
+                Some(line) => (false, line.trim_start()),
+                None => (true, line),
+            };
+            let (code, name): (&str, &str) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let mut cheat: Cheat = Cheat::parse(code, name.trim()).map_err(|e| CheatError(format!("line {}: {}", number + 1, e)))?;
+            cheat.enabled = enabled;
+            cheats.list.push(cheat);
+        }
+        Ok(cheats)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.list.iter().map(|cheat| {
+            let line: String = format!("{}{} {}", if cheat.enabled { "" } else { "-" }, cheat.code, cheat.name);
+            format!("{}\n", line.trim_end())
+        }).collect()
+    }
+
+    pub fn load(path: &Path) -> Result<Cheats, Error> {
+        Cheats::from_text(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    pub fn path_for_rom(rom_path: &Path) -> PathBuf {
+        rom_path.with_extension(CHEAT_FILE_EXTENSION)
+    }
+
+    // Runs a command of HELP (without the cheat prefix), returns what to show to the user
+    pub fn execute(&mut self, line: &str) -> Result<String, Error> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let index = |text: &str| text.parse::<usize>().map_err(|_| CheatError(format!("Invalid cheat index {}", text)));
+        match words.as_slice() {
+            [] | ["list"] => Ok(self.list.iter().enumerate().map(|(i, cheat)| {
+                let effect: String = match cheat.kind {
+                    CheatKind::GameGenie { address, value, compare: Some(compare) } => format!("${:04X} = ${:02X} if ${:02X}", address, value, compare),
+                    CheatKind::GameGenie { address, value, compare: None } => format!("${:04X} = ${:02X}", address, value),
+                    CheatKind::ProActionReplay { address, value } => format!("${:04X} := ${:02X} every frame", address, value),
+                };
+                format!("{:3} [{}] {:8} {} {}\n", i, if cheat.enabled { "x" } else { " " }, cheat.code, effect, cheat.name)
+            }).collect()),
+            ["add", code, name @ ..] => {
+                let i: usize = self.add(code, &name.join(" "))?;
+                Ok(format!("Cheat {} added", i))
+            }
+            ["on", i] | ["off", i] => {
+                self.set_enabled(index(i)?, words[0] == "on")?;
+                Ok(format!("Cheat {} {}", i, if words[0] == "on" { "enabled" } else { "disabled" }))
+            }
+            ["rm", i] => {
+                let cheat: Cheat = self.remove(index(i)?)?;
+                Ok(format!("Cheat {} removed", cheat.code))
+            }
+            _ => Err(CheatError(format!("Unknown cheat command {}, try help", line.trim()))),
+        }
+    }
+}
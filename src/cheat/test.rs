@@ -0,0 +1,89 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::cpu::CPU;
+    use crate::mem::Mem;
+
+    use super::super::*;
+
+    // Copies two bytes of the ROM to $0200, then loops
+    const PROGRAM: &str = "
+                LDA table
+                STA $0200
+                LDA table + 1
+                STA $0201
+        loop:   JMP loop
+        .org $9000
+        table:  .byte $11, $22
+    ";
+
+    fn load() -> CPU {
+        CPU::test_load(PROGRAM)
+    }
+
+    #[test]
+    fn test_game_genie() {
+        assert_eq!(decode_game_genie("SXIOPO").unwrap(), (0x91d9, 0xad, None));
+        assert_eq!(decode_game_genie("zexpygla").unwrap(), (0x94a7, 0x02, Some(0x03)));
+        for code in ["SXIOPO", "ZEXPYGLA", "AAAAAA", "NNNNNNNN"] {
+            let (address, value, compare) = decode_game_genie(code).unwrap();
+            assert_eq!(encode_game_genie(address, value, compare), code);
+        }
+        // The high bit of the third letter only tells the length of the code
+        assert_eq!(decode_game_genie("GOSSIP").unwrap(), decode_game_genie("GOISIP").unwrap());
+        assert!(decode_game_genie("SXIOP").is_err());
+        assert!(decode_game_genie("SXIOPB").is_err());
+    }
+
+    #[test]
+    fn test_pro_action_replay() {
+        assert_eq!(decode_pro_action_replay("075A09").unwrap(), (0x075a, 0x09));
+        assert_eq!(decode_pro_action_replay("0010:ff").unwrap(), (0x0010, 0xff));
+        assert!(decode_pro_action_replay("0800FF").unwrap_err().to_string().contains("outside of the RAM"));
+        assert!(decode_pro_action_replay("0010FG").is_err());
+        assert!(Cheat::parse("12345", "").is_err());
+    }
+
+    #[test]
+    fn test_bus_cheats() {
+        let mut cpu: CPU = load();
+        cpu.bus.cheats.add(&encode_game_genie(0x9000, 0x55, None), "patched").unwrap();
+        // The ROM holds $22, not $99: no patch
+        cpu.bus.cheats.add(&encode_game_genie(0x9001, 0x66, Some(0x99)), "compare").unwrap();
+        cpu.bus.cheats.add("0010:42", "frozen").unwrap();
+        cpu.run_frame();
+        assert_eq!(cpu.mem_read_u8(0x0200), 0x55);
+        assert_eq!(cpu.mem_read_u8(0x0201), 0x22);
+        assert_eq!(cpu.mem_read_u8(0x0010), 0x42);
+
+        // Disabled cheats do nothing
+        cpu.bus.cheats.set_enabled(0, false).unwrap();
+        assert_eq!(cpu.mem_read_u8(0x9000), 0x11);
+        cpu.bus.poke(0x0010, 0);
+        cpu.bus.cheats.set_enabled(2, false).unwrap();
+        cpu.run_frame();
+        assert_eq!(cpu.mem_read_u8(0x0010), 0);
+    }
+
+    #[test]
+    fn test_files_and_commands() {
+        let text: &str = "# Super Mario Bros.\nSXIOPO Infinite lives\n-075A09  9 lives\n\nZEXPYGLA\n";
+        let mut cheats: Cheats = Cheats::from_text(text).unwrap();
+        assert_eq!(cheats.list.len(), 3);
+        assert_eq!(cheats.list[1], Cheat { code: String::from("075A09"), name: String::from("9 lives"), kind: CheatKind::ProActionReplay { address: 0x075a, value: 9 }, enabled: false });
+        assert_eq!(cheats.to_text(), "SXIOPO Infinite lives\n-075A09 9 lives\nZEXPYGLA\n");
+        assert_eq!(Cheats::from_text(&cheats.to_text()).unwrap(), cheats);
+        assert!(Cheats::from_text("SXIOPO\nBAD").unwrap_err().to_string().contains("line 2"));
+        assert_eq!(Cheats::path_for_rom(Path::new("roms/smb.nes")), Path::new("roms/smb.cht"));
+
+        assert_eq!(cheats.execute("on 1").unwrap(), "Cheat 1 enabled");
+        assert_eq!(cheats.execute("add gzuxxkvs More time").unwrap(), "Cheat 3 added");
+        assert_eq!(cheats.execute("rm 0").unwrap(), "Cheat SXIOPO removed");
+        assert_eq!(cheats.execute("list").unwrap().lines().next(), Some("  0 [x] 075A09   $075A := $09 every frame 9 lives"));
+        assert!(cheats.execute("off 9").is_err());
+    }
+}
@@ -0,0 +1,406 @@
+mod test;
+
+use std::path::PathBuf;
+
+use crate::disasm::Syntax;
+use crate::error::{Error::ConfigError, Error};
+use crate::input::InputSetup;
+use crate::memview;
+use crate::poweron::PowerOn;
+use crate::screen::viewer::ViewerKind;
+use crate::screen::{DisplayOptions, DEFAULT_SCALE};
+use crate::testrom;
+use crate::trace::{TraceFormat, TraceOptions};
+
+// Command line of the emulator:
+//   nes_emul [run] <rom> [options]
+//   nes_emul info <rom> [--json]
+//   nes_emul disasm <rom> [--syntax ca65|asm6] [--cdl <file>] [-o <output>]
+//   nes_emul test <rom> [--frames <count>]
+//   nes_emul convert <input.fm2|bk2> <output.fm2|bk2>
+//   nes_emul nsf <file.nsf|nsfe> [--track <n>] [--wav <file> [--seconds <s>]]
+// Options may come before or after the ROM, an unknown option is an error.
+// The ROM may be zipped or gzipped, --entry <name> picks one in a zip holding several.
+// A .fds disk runs on the Famicom Disk System with the BIOS given by --bios.
+
+pub const USAGE: &str = "\
+Usage:
+  nes_emul [run] <rom> [options]      play a game
+  nes_emul info <rom> [--json]        describe the ROM and check its header
+  nes_emul disasm <rom> [--syntax ca65|asm6] [--cdl <file>] [-o <output>]
+  nes_emul test <rom> [--frames <count>]
+                                      run a test ROM reporting through $6000, exit with 1 if it fails
+  nes_emul convert <input> <output>   convert a movie between the FM2 and BK2 formats
+  nes_emul nsf <file> [--track <n>] [--wav <file.wav> [--seconds <s>]]
+                                      play a NSF or NSFe music file, or render a track to a WAV file
+
+The ROM may be a .nes or .unf file, a .fds disk or a .zip or .gz archive, --entry <name> picks the ROM in a zip holding several.
+
+Run options:
+  --scale <n>                 size of a pixel, 3 by default
+  --fullscreen
+  --region ntsc|pal|dendy     frame rate of the window (the emulation timing stays NTSC)
+  --frames <count>            run headless for that many frames, then exit
+  --screenshot <file.png>     write the last frame when leaving
+  --state <file>              start from a save state
+  --record <file.fm2|bk2>     record a movie
+  --play <file.fm2|bk2>       play a movie back, read-only unless --read-write
+  --power-on zeros|ff|pattern|random[:<seed>]
+  --patch <file.ips|ups|bps>  game.ips, game.ups or game.bps next to the ROM by default
+  --no-database               trust the ROM header even when the game database knows better
+  --bios <file>               FDS BIOS for .fds disks, disksys.rom next to the disk by default
+  --zapper                    plug a Zapper in port 2, aimed with the mouse and fired with its left button
+  --four-score [nes|famicom]  four players through a Four Score (nes, by default) or the Famicom expansion port
+  --input <device>            joypads, four-score, famicom-four-players, zapper, power-pad, family-trainer,
+                              arkanoid, arkanoid-famicom or snes-mouse, picked from the NES 2.0 header by default
+  --cheats <file>             game.cht next to the ROM by default
+  --trace <file>              with --trace-format, --trace-no-ppu, --trace-no-cycles,
+                              --trace-range <start>-<end>, --trace-start <addr>, --trace-lines <count>
+  --cdl <file>                code/data log
+  --memview [region]          live memory view in the terminal
+  --viewers <list>            pattern[:<palette>],nametables,oam,palette
+  --debug                     start in the debugger
+  --gdb [port]                wait for a GDB client
+";
+
+// How an option takes its value
+#[derive(Clone, Copy)]
+enum Arity {
+    Flag,
+    Value,
+    // Only when the next argument is a valid value, so that "--gdb game.nes" still finds the ROM
+    Optional(fn(&str) -> bool),
+}
+
+const RUN_OPTIONS: [(&str, Arity); 31] = [
+    ("--scale", Arity::Value),
+    ("--fullscreen", Arity::Flag),
+    ("--region", Arity::Value),
+    ("--frames", Arity::Value),
+    ("--screenshot", Arity::Value),
+    ("--state", Arity::Value),
+    ("--record", Arity::Value),
+    ("--play", Arity::Value),
+    ("--read-write", Arity::Flag),
+    ("--power-on", Arity::Value),
+    ("--entry", Arity::Value),
+    ("--patch", Arity::Value),
+    ("--no-database", Arity::Flag),
+    ("--bios", Arity::Value),
+    ("--zapper", Arity::Flag),
+    ("--four-score", Arity::Optional(|value| value == "nes" || value == "famicom")),
+    ("--input", Arity::Value),
+    ("--cheats", Arity::Value),
+    ("--trace", Arity::Value),
+    ("--trace-format", Arity::Value),
+    ("--trace-no-ppu", Arity::Flag),
+    ("--trace-no-cycles", Arity::Flag),
+    ("--trace-range", Arity::Value),
+    ("--trace-start", Arity::Value),
+    ("--trace-lines", Arity::Value),
+    ("--cdl", Arity::Value),
+    ("--memview", Arity::Optional(|value| memview::Region::parse(value).is_ok())),
+    ("--viewers", Arity::Value),
+    ("--debug", Arity::Flag),
+    ("--gdb", Arity::Optional(|value| value.parse::<u16>().is_ok())),
+    ("--help", Arity::Flag),
+];
+
+const DISASM_OPTIONS: [(&str, Arity); 4] = [("--syntax", Arity::Value), ("--cdl", Arity::Value), ("-o", Arity::Value), ("--entry", Arity::Value)];
+const INFO_OPTIONS: [(&str, Arity); 2] = [("--json", Arity::Flag), ("--entry", Arity::Value)];
+const TEST_OPTIONS: [(&str, Arity); 2] = [("--frames", Arity::Value), ("--entry", Arity::Value)];
+const NSF_OPTIONS: [(&str, Arity); 3] = [("--track", Arity::Value), ("--wav", Arity::Value), ("--seconds", Arity::Value)];
+
+pub const DEFAULT_GDB_PORT: u16 = 6502;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    // Famiclones, PAL frame rate with NTSC-like timings
+    Dendy,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunOptions {
+    pub rom: PathBuf,
+    // The file to load from a zip archive
+    pub entry: Option<String>,
+    pub display: DisplayOptions,
+    pub region: Region,
+    // Headless run of that many frames
+    pub frames: Option<usize>,
+    pub screenshot: Option<PathBuf>,
+    pub state: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub read_write: bool,
+    pub power_on: Option<PowerOn>,
+    pub patch: Option<PathBuf>,
+    // Correct the header of the ROMs found in the game database
+    pub database: bool,
+    // The FDS BIOS, disksys.rom by default
+    pub bios: Option<PathBuf>,
+    // What is plugged in the controller ports, chosen from the NES 2.0 header when None
+    pub input: Option<InputSetup>,
+    pub cheats: Option<PathBuf>,
+    pub trace: Option<(PathBuf, TraceOptions)>,
+    pub cdl: Option<PathBuf>,
+    pub memview: Option<memview::Region>,
+    pub viewers: Vec<ViewerKind>,
+    pub debug: bool,
+    pub gdb: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(Box<RunOptions>),
+    Info { rom: PathBuf, entry: Option<String>, json: bool },
+    Disasm { rom: PathBuf, entry: Option<String>, syntax: Syntax, cdl: Option<PathBuf>, output: Option<PathBuf> },
+    Test { rom: PathBuf, entry: Option<String>, frames: usize },
+    Convert { input: PathBuf, output: PathBuf },
+    // The track counts from 1, the file's starting song by default
+    Nsf { file: PathBuf, track: Option<u8>, wav: Option<PathBuf>, seconds: Option<f64> },
+    Help,
+}
+
+// The arguments split into positional ones and options with their values, in order
+struct Parsed {
+    positional: Vec<String>,
+    options: Vec<(&'static str, Option<String>)>,
+}
+
+impl Region {
+    pub fn parse(name: &str) -> Result<Region, Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(ConfigError(format!("Unknown region {}, expected ntsc, pal or dendy", name))),
+        }
+    }
+
+    pub fn frame_rate(self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal | Region::Dendy => 50.007,
+        }
+    }
+}
+
+impl Parsed {
+    fn new(args: &[String], known: &[(&'static str, Arity)]) -> Result<Parsed, Error> {
+        let mut parsed: Parsed = Parsed { positional: vec![], options: vec![] };
+        let mut args = args.iter().peekable();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                parsed.positional.push(arg.clone());
+                continue;
+            }
+            let (name, arity): (&'static str, Arity) = *known.iter().find(|(name, _)| name == arg)
+                .ok_or_else(|| ConfigError(format!("Unknown option {}, see --help", arg)))?;
+            let value: Option<String> = match arity {
+                Arity::Flag => None,
+                Arity::Value => Some(args.next().ok_or_else(|| ConfigError(format!("{} needs a value", name)))?.clone()),
+                Arity::Optional(valid) => args.next_if(|value| valid(value)).cloned(),
+            };
+            parsed.options.push((name, value));
+        }
+        Ok(parsed)
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| *option == name)
+    }
+
+    // The last one wins when an option is repeated
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(option, _)| *option == name).and_then(|(_, value)| value.as_deref())
+    }
+
+    fn values(&self, name: &str) -> Vec<&str> {
+        self.options.iter().filter(|(option, _)| *option == name).filter_map(|(_, value)| value.as_deref()).collect()
+    }
+
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        self.value(name).map(PathBuf::from)
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, Error> {
+        self.value(name).map(|value| value.parse().map_err(|_| ConfigError(format!("Invalid {} {}, expected a number", name, value)))).transpose()
+    }
+
+    // The ROM, alone
+    fn rom(&self, command: &str) -> Result<PathBuf, Error> {
+        match self.positional.as_slice() {
+            [rom] => Ok(PathBuf::from(rom)),
+            [] => Err(ConfigError(format!("Missing the ROM path, usage: {}", command))),
+            [_, extra, ..] => Err(ConfigError(format!("Unexpected argument {}, usage: {}", extra, command))),
+        }
+    }
+}
+
+fn parse_address(text: &str) -> Result<u16, Error> {
+    let digits: &str = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| ConfigError(format!("Invalid address {}", text)))
+}
+
+// --trace <file> [--trace-format nestest|fceux|mesen] [--trace-no-ppu] [--trace-no-cycles]
+//   [--trace-range <start>-<end>]... [--trace-start <addr>] [--trace-lines <count>]
+fn trace_options(parsed: &Parsed) -> Result<Option<(PathBuf, TraceOptions)>, Error> {
+    let path: PathBuf = match parsed.path("--trace") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let mut options: TraceOptions = TraceOptions {
+        ppu: !parsed.has("--trace-no-ppu"),
+        cycles: !parsed.has("--trace-no-cycles"),
+        ..TraceOptions::default()
+    };
+    if let Some(format) = parsed.value("--trace-format") {
+        options.format = TraceFormat::parse(format)?;
+    }
+    for range in parsed.values("--trace-range") {
+        let (start, end): (&str, &str) = range.split_once('-').ok_or_else(|| ConfigError(format!("Invalid range {}, expected <start>-<end>", range)))?;
+        options.ranges.push((parse_address(start)?, parse_address(end)?));
+    }
+    options.start_pc = parsed.value("--trace-start").map(parse_address).transpose()?;
+    options.max_lines = parsed.number("--trace-lines")?;
+    Ok(Some((path, options)))
+}
+
+fn run_options(parsed: &Parsed) -> Result<RunOptions, Error> {
+    let region: Region = parsed.value("--region").map(Region::parse).transpose()?.unwrap_or(Region::Ntsc);
+    let scale: u32 = parsed.number("--scale")?.unwrap_or(DEFAULT_SCALE);
+    if scale == 0 {
+        return Err(ConfigError(String::from("The scale must be at least 1")));
+    }
+    if parsed.has("--record") && parsed.has("--play") {
+        return Err(ConfigError(String::from("--record and --play cannot be used together")));
+    }
+    let frames: Option<usize> = parsed.number("--frames")?;
+    if frames.is_some() && (parsed.has("--debug") || parsed.has("--gdb")) {
+        return Err(ConfigError(String::from("--frames runs without any window nor debugger")));
+    }
+    // --zapper and --four-score are shortcuts for --input
+    let mut inputs: Vec<InputSetup> = parsed.values("--input").into_iter().map(InputSetup::parse).collect::<Result<_, _>>()?;
+    if parsed.has("--zapper") {
+        inputs.push(InputSetup::Zapper);
+    }
+    if parsed.has("--four-score") {
+        inputs.push(match parsed.value("--four-score") {
+            Some("famicom") => InputSetup::FamicomFourPlayers,
+            _ => InputSetup::FourScore,
+        });
+    }
+    if inputs.len() > 1 {
+        return Err(ConfigError(String::from("Only one of --input, --zapper and --four-score can be given")));
+    }
+    let input: Option<InputSetup> = inputs.pop();
+    let memview: Option<memview::Region> = match parsed.has("--memview") {
+        true => Some(parsed.value("--memview").map(memview::Region::parse).transpose()?.unwrap_or(memview::Region::Ram)),
+        false => None,
+    };
+    let viewers: Vec<ViewerKind> = match parsed.value("--viewers") {
+        Some(viewers) => viewers.split(',').map(ViewerKind::parse).collect::<Result<_, _>>()?,
+        None => vec![],
+    };
+    Ok(RunOptions {
+        rom: parsed.rom("nes_emul [run] <rom> [options]")?,
+        entry: parsed.value("--entry").map(String::from),
+        display: DisplayOptions { scale, fullscreen: parsed.has("--fullscreen"), frame_rate: region.frame_rate() },
+        region,
+        frames,
+        screenshot: parsed.path("--screenshot"),
+        state: parsed.path("--state"),
+        record: parsed.path("--record"),
+        play: parsed.path("--play"),
+        read_write: parsed.has("--read-write"),
+        power_on: parsed.value("--power-on").map(PowerOn::parse).transpose()?,
+        patch: parsed.path("--patch"),
+        database: !parsed.has("--no-database"),
+        bios: parsed.path("--bios"),
+        input,
+        cheats: parsed.path("--cheats"),
+        trace: trace_options(parsed)?,
+        cdl: parsed.path("--cdl"),
+        memview,
+        viewers,
+        debug: parsed.has("--debug"),
+        gdb: parsed.has("--gdb").then(|| parsed.number("--gdb")).transpose()?.map(|port| port.unwrap_or(DEFAULT_GDB_PORT)),
+    })
+}
+
+// The arguments after the program name
+pub fn parse(args: &[String]) -> Result<Command, Error> {
+    let (command, rest): (&str, &[String]) = match args.first().map(|arg| arg.as_str()) {
+        None => return Err(ConfigError(format!("Missing the ROM path\n{}", USAGE))),
+        Some("-h") | Some("--help") | Some("help") => return Ok(Command::Help),
+        Some(command @ ("run" | "info" | "disasm" | "test" | "convert" | "nsf")) => (command, &args[1..]),
+        Some(_) => ("run", args),
+    };
+    match command {
+        "info" => {
+            let parsed: Parsed = Parsed::new(rest, &INFO_OPTIONS)?;
+            Ok(Command::Info {
+                rom: parsed.rom("nes_emul info <rom> [--json]")?,
+                entry: parsed.value("--entry").map(String::from),
+                json: parsed.has("--json"),
+            })
+        }
+        "disasm" => {
+            let parsed: Parsed = Parsed::new(rest, &DISASM_OPTIONS)?;
+            let syntax: Syntax = match parsed.value("--syntax") {
+                None | Some("ca65") => Syntax::Ca65,
+                Some("asm6") => Syntax::Asm6,
+                Some(other) => return Err(ConfigError(format!("Unknown syntax {}, expected ca65 or asm6", other))),
+            };
+            Ok(Command::Disasm {
+                rom: parsed.rom("nes_emul disasm <rom> [--syntax ca65|asm6] [--cdl <file>] [-o <output>]")?,
+                entry: parsed.value("--entry").map(String::from),
+                syntax,
+                cdl: parsed.path("--cdl"),
+                output: parsed.path("-o"),
+            })
+        }
+        "test" => {
+            let parsed: Parsed = Parsed::new(rest, &TEST_OPTIONS)?;
+            Ok(Command::Test {
+                rom: parsed.rom("nes_emul test <rom> [--frames <count>]")?,
+                entry: parsed.value("--entry").map(String::from),
+                frames: parsed.number("--frames")?.unwrap_or(testrom::DEFAULT_FRAMES),
+            })
+        }
+        "convert" => match Parsed::new(rest, &[])?.positional.as_slice() {
+            [input, output] => Ok(Command::Convert { input: PathBuf::from(input), output: PathBuf::from(output) }),
+            _ => Err(ConfigError(String::from("Usage: nes_emul convert <input.fm2|bk2> <output.fm2|bk2>"))),
+        },
+        "nsf" => {
+            let parsed: Parsed = Parsed::new(rest, &NSF_OPTIONS)?;
+            let track: Option<u8> = parsed.number("--track")?;
+            if track == Some(0) {
+                return Err(ConfigError(String::from("Invalid --track 0, the first track is 1")));
+            }
+            let seconds: Option<f64> = parsed.number("--seconds")?;
+            if seconds.is_some_and(|seconds| !(seconds > 0.0 && seconds.is_finite())) {
+                return Err(ConfigError(String::from("Invalid --seconds, expected a positive duration")));
+            }
+            if seconds.is_some() && !parsed.has("--wav") {
+                return Err(ConfigError(String::from("--seconds needs --wav")));
+            }
+            Ok(Command::Nsf {
+                file: parsed.rom("nes_emul nsf <file.nsf|nsfe> [--track <n>] [--wav <file.wav> [--seconds <s>]]")?,
+                track,
+                wav: parsed.path("--wav"),
+                seconds,
+            })
+        }
+        _ => {
+            let parsed: Parsed = Parsed::new(rest, &RUN_OPTIONS)?;
+            match parsed.has("--help") {
+                true => Ok(Command::Help),
+                false => Ok(Command::Run(Box::new(run_options(&parsed)?))),
+            }
+        }
+    }
+}
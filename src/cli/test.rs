@@ -0,0 +1,124 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::disasm::Syntax;
+    use crate::memview;
+    use crate::poweron::PowerOn;
+    use crate::screen::viewer::ViewerKind;
+    use crate::trace::TraceFormat;
+
+    use super::super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn run(line: &str) -> RunOptions {
+        match parse(&args(line)).unwrap() {
+            Command::Run(options) => *options,
+            other => panic!("Expected a run command, got {:?}", other),
+        }
+    }
+
+    fn error(line: &str) -> String {
+        parse(&args(line)).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_run() {
+        let options: RunOptions = run("games/smb.nes");
+        assert_eq!(options.rom, PathBuf::from("games/smb.nes"));
+        assert_eq!(options.display, DisplayOptions::default());
+        assert_eq!((options.region, options.frames, options.gdb, options.debug), (Region::Ntsc, None, None, false));
+
+        // Options before or after the ROM, with or without the subcommand
+        let options: RunOptions = run("--scale 2 smb.nes --fullscreen --region pal --frames 120 --screenshot out.png");
+        assert_eq!(options, run("run smb.nes --frames 120 --region pal --screenshot out.png --fullscreen --scale 2"));
+        assert_eq!(options.display, DisplayOptions { scale: 2, fullscreen: true, frame_rate: Region::Pal.frame_rate() });
+        assert_eq!((options.frames, options.screenshot), (Some(120), Some(PathBuf::from("out.png"))));
+
+        let options: RunOptions = run("smb.nes --play a.fm2 --read-write --state a.ss0 --power-on random:7 --cheats a.cht --viewers oam,pattern:2");
+        assert_eq!((options.play, options.record, options.read_write), (Some(PathBuf::from("a.fm2")), None, true));
+        assert_eq!((options.state, options.cheats), (Some(PathBuf::from("a.ss0")), Some(PathBuf::from("a.cht"))));
+        assert_eq!(options.power_on, Some(PowerOn::Random(7)));
+        assert_eq!(options.viewers, vec![ViewerKind::Oam, ViewerKind::PatternTables(2)]);
+        assert!(options.database);
+        assert!(!run("smb.nes --no-database").database);
+        assert_eq!(run("smb.nes --patch fr.ips").patch, Some(PathBuf::from("fr.ips")));
+        assert_eq!(run("zelda.fds --bios disksys.rom").bios, Some(PathBuf::from("disksys.rom")));
+        assert_eq!(run("duckhunt.nes --zapper").input, Some(InputSetup::Zapper));
+        assert_eq!(run("duckhunt.nes").input, None);
+        assert_eq!(run("--four-score gauntlet2.nes").input, Some(InputSetup::FourScore));
+        assert_eq!(run("gauntlet2.nes --four-score famicom").input, Some(InputSetup::FamicomFourPlayers));
+        assert_eq!(run("arkanoid.nes --input arkanoid").input, Some(InputSetup::Arkanoid));
+        assert_eq!(run("stadium.nes --input family-trainer").input, Some(InputSetup::FamilyTrainer));
+        assert_eq!(run("games.zip --entry smb.nes").entry, Some(String::from("smb.nes")));
+
+        let options: RunOptions = run("--trace out.log --trace-format fceux --trace-no-ppu --trace-range $8000-$80FF --trace-range c000-c0ff --trace-lines 10 smb.nes");
+        let (path, trace): (PathBuf, TraceOptions) = options.trace.unwrap();
+        assert_eq!((path, trace.format, trace.ppu, trace.cycles), (PathBuf::from("out.log"), TraceFormat::Fceux, false, true));
+        assert_eq!((trace.ranges, trace.max_lines), (vec![(0x8000, 0x80ff), (0xc000, 0xc0ff)], Some(10)));
+    }
+
+    #[test]
+    fn test_optional_values() {
+        assert_eq!(run("--gdb smb.nes").gdb, Some(DEFAULT_GDB_PORT));
+        assert_eq!(run("--gdb 1234 smb.nes").gdb, Some(1234));
+        assert_eq!(run("smb.nes --memview").memview, Some(memview::Region::Ram));
+        let options: RunOptions = run("--memview oam smb.nes");
+        assert_eq!((options.memview, options.rom), (Some(memview::Region::Oam), PathBuf::from("smb.nes")));
+    }
+
+    #[test]
+    fn test_subcommands() {
+        assert_eq!(parse(&args("info smb.nes")).unwrap(), Command::Info { rom: PathBuf::from("smb.nes"), entry: None, json: false });
+        assert_eq!(parse(&args("info --json smb.nes")).unwrap(), Command::Info { rom: PathBuf::from("smb.nes"), entry: None, json: true });
+        assert_eq!(parse(&args("disasm smb.nes --syntax asm6 -o out.s")).unwrap(), Command::Disasm {
+            rom: PathBuf::from("smb.nes"), entry: None, syntax: Syntax::Asm6, cdl: None, output: Some(PathBuf::from("out.s")),
+        });
+        assert_eq!(parse(&args("test cpu.nes")).unwrap(), Command::Test { rom: PathBuf::from("cpu.nes"), entry: None, frames: testrom::DEFAULT_FRAMES });
+        assert_eq!(parse(&args("test --frames 60 cpu.nes")).unwrap(), Command::Test { rom: PathBuf::from("cpu.nes"), entry: None, frames: 60 });
+        assert_eq!(parse(&args("info games.zip --entry smb.nes")).unwrap(), Command::Info {
+            rom: PathBuf::from("games.zip"), entry: Some(String::from("smb.nes")), json: false,
+        });
+        assert_eq!(parse(&args("convert a.fm2 a.bk2")).unwrap(), Command::Convert { input: PathBuf::from("a.fm2"), output: PathBuf::from("a.bk2") });
+        assert_eq!(parse(&args("nsf smb.nsf")).unwrap(), Command::Nsf { file: PathBuf::from("smb.nsf"), track: None, wav: None, seconds: None });
+        assert_eq!(parse(&args("nsf smb.nsf --track 3 --wav out.wav --seconds 1.5")).unwrap(), Command::Nsf {
+            file: PathBuf::from("smb.nsf"), track: Some(3), wav: Some(PathBuf::from("out.wav")), seconds: Some(1.5),
+        });
+        assert_eq!(parse(&args("--help")).unwrap(), Command::Help);
+        assert_eq!(parse(&args("smb.nes --help")).unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(error("").contains("Missing the ROM path"));
+        assert!(error("--scale 2").contains("Missing the ROM path"));
+        assert!(error("smb.nes other.nes").contains("Unexpected argument other.nes"));
+        assert!(error("smb.nes --turbo").contains("Unknown option --turbo"));
+        assert!(error("smb.nes --scale").contains("--scale needs a value"));
+        assert!(error("smb.nes --scale big").contains("Invalid --scale big"));
+        assert!(error("smb.nes --scale 0").contains("at least 1"));
+        assert!(error("smb.nes --region secam").contains("Unknown region secam"));
+        assert!(error("smb.nes --record a.fm2 --play b.fm2").contains("cannot be used together"));
+        assert!(error("smb.nes --frames 10 --debug").contains("--frames"));
+        assert!(error("smb.nes --trace-range 8000 --trace t.log").contains("Invalid range 8000"));
+        assert!(error("smb.nes --power-on maybe").contains("Unknown power-on state"));
+        assert!(error("smb.nes --zapper --four-score").contains("Only one of"));
+        assert!(error("smb.nes --input zapper --input snes-mouse").contains("Only one of"));
+        assert!(error("smb.nes --input keyboard").contains("Unknown input device keyboard"));
+        assert!(error("info").contains("nes_emul info <rom>"));
+        assert!(error("info smb.nes --scale 2").contains("Unknown option --scale"));
+        assert!(error("disasm smb.nes --syntax nasm").contains("Unknown syntax nasm"));
+        assert!(error("convert a.fm2").contains("Usage: nes_emul convert"));
+        assert!(error("nsf smb.nsf --track 0").contains("the first track is 1"));
+        assert!(error("nsf smb.nsf --seconds 10").contains("--seconds needs --wav"));
+        assert!(error("nsf smb.nsf --wav a.wav --seconds -1").contains("Invalid --seconds"));
+    }
+}
@@ -1,4 +1,5 @@
-use super::{CPU, AddressingMode, CPUFlag, Mem};
+use super::{CPU, CPUFlag, Mem};
+use super::opcode::AddressingMode;
 
 
 
@@ -105,7 +106,7 @@ impl CPU {
     
     // ======================== FLAG MANIPULATION ========================
 
-    pub(super) fn mask_from_flag(flag : CPUFlag) -> u8 {
+    pub fn mask_from_flag(flag : CPUFlag) -> u8 {
         match flag {
             CPUFlag::Negative  => 0b1000_0000,
             CPUFlag::Overflow  => 0b0100_0000,
@@ -143,7 +144,7 @@ impl CPU {
         self.status &= !CPU::mask_from_flag(flag);
     }
 
-    pub(super) fn get_flag(&self, flag: CPUFlag) -> bool {
+    pub fn get_flag(&self, flag: CPUFlag) -> bool {
         self.status & CPU::mask_from_flag(flag) != 0
     }
 
@@ -2,13 +2,12 @@ pub mod opcode;
 pub mod instruction;
 mod test;
 
-use std::path::Path;
-
 use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use crate::mem::Mem;
-use crate::bus::{Bus, PROGRAM_BASE_POINTER, NMI_ADDRESS_POINTER};
+use crate::bus::{Bus, PROGRAM_BASE_POINTER, NMI_ADDRESS_POINTER, IRQ_ADDRESS_POINTER};
 
-use opcode::{AddressingMode, Opcode, OPCODES};
+use opcode::{Opcode, OPCODES};
 
 const DEFAULT_STATUS: u8 = 0x24;
 
@@ -25,6 +24,13 @@ pub struct CPU {
     pub running         : bool,
 }
 
+// The interrupt taken before an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
 #[derive(Debug)]
 pub enum CPUFlag {
     Negative,
@@ -86,6 +92,12 @@ impl CPU {
         self.reg_pc = self.mem_read_u16(PROGRAM_BASE_POINTER);
     }
 
+    // Jumps to a subroutine as a JSR would, its RTS lands on return_address (used by the NSF player)
+    pub fn call(&mut self, addr: u16, return_address: u16) {
+        self.stack_push_u16(return_address.wrapping_sub(1));
+        self.reg_pc = addr;
+    }
+
     pub fn interrupt_nmi(&mut self) {
         self.stack_push_u16(self.reg_pc);
         let mut status: u8 = self.status;
@@ -98,144 +110,62 @@ impl CPU {
         self.reg_pc = self.mem_read_u16(NMI_ADDRESS_POINTER);
     }
 
+    // Only taken when interrupts are enabled, the B flag is pushed clear
+    pub fn interrupt_irq(&mut self) {
+        self.stack_push_u16(self.reg_pc);
+        let mut status: u8 = self.status;
+        status &= !CPU::mask_from_flag(CPUFlag::Break);
+        status |= CPU::mask_from_flag(CPUFlag::Break2);
+        self.stack_push_u8(status);
+        self.set_flag(CPUFlag::InterruptDisabled);
 
-    pub fn log_args_str(cpu: &mut CPU, opcode: &Opcode, args: u16, addressing_mode: AddressingMode) -> String {
-        match addressing_mode {
-            AddressingMode::Immediate => format!("#${:02X}", args & 0xff),
-            AddressingMode::Absolute => if opcode.name == "JMP" || opcode.name == "JSR" { format!("${:04X}", args) } else { format!("${:04X} = {:02X}", args, cpu.mem_read_u8_no_fail(args, true)) },
-            AddressingMode::ZeroPage => format!("${:02X} = {:02X}", args & 0xff, cpu.mem_read_u8_no_fail(args & 0xff, true)),
-            AddressingMode::ZeroPageX => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("${:02X},X @ {:02X} = {:02X}", args & 0xff, addr, cpu.mem_read_u8_no_fail(addr, true))
-            }
-            AddressingMode::ZeroPageY => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("${:02X},Y @ {:02X} = {:02X}", args & 0xff, addr, cpu.mem_read_u8_no_fail(addr, true))
-            }
-            AddressingMode::AbsoluteX => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("${:04X},X @ {:04X} = {:02X}", args, addr, cpu.mem_read_u8_no_fail(addr, true))
-            } 
-            AddressingMode::AbsoluteY => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("${:04X},Y @ {:04X} = {:02X}", args, addr, cpu.mem_read_u8_no_fail(addr, true))
-            }
-            AddressingMode::Indirect => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                if opcode.name == "JMP" || opcode.name == "JSR" {
-                    format!("(${:04X}) = {:04X}", args, addr)
-                } else {
-                    format!("(${:04X}) @ {:04X} = {:02X}", args, addr, cpu.mem_read_u8_no_fail(addr, true))
-                }
-            }
-            AddressingMode::IndirectX => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("(${:02X},X) @ {:02X} = {:04X} = {:02X}", args & 0xff, args.wrapping_add(cpu.reg_x as u16) & 0xff, addr, cpu.mem_read_u8_no_fail(addr, true))
-            }
-            AddressingMode::IndirectY => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("(${:02X}),Y = {:04X} @ {:04X} = {:02X}", args & 0xff, addr.wrapping_sub(cpu.reg_y as u16), addr, cpu.mem_read_u8_no_fail(addr, true))
-            }
-            AddressingMode::Relative => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                let offset: u8 = cpu.mem_read_u8_no_fail(addr, true);
-                let value: u16 = if offset < 127 { cpu.reg_pc.wrapping_add(2).wrapping_add(offset as u16) } else { cpu.reg_pc.wrapping_add(2).wrapping_sub(256 - offset as u16) };
-                format!("${:04X}", value)
-            }
-            AddressingMode::Accumulator => format!("A"),
-            _ => String::from("")
-        }
+        self.bus.tick(2);
+        self.reg_pc = self.mem_read_u16(IRQ_ADDRESS_POINTER);
     }
 
-    pub fn log_args_str_without_addr_resolution(cpu: &mut CPU, _opcode: &Opcode, args: u16, addressing_mode: AddressingMode) -> String {
-        match addressing_mode {
-            AddressingMode::Immediate => format!("#${:02X}", args & 0xff),
-            AddressingMode::Absolute => format!("${:04X}", args),
-            AddressingMode::ZeroPage => format!("${:02X}", args & 0xff),
-            AddressingMode::ZeroPageX => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("${:02X},X @ {:02X}", args & 0xff, addr)
-            }
-            AddressingMode::ZeroPageY => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("${:02X},Y @ {:02X}", args & 0xff, addr)
-            }
-            AddressingMode::AbsoluteX => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("${:04X},X @ {:04X}", args, addr)
-            } 
-            AddressingMode::AbsoluteY => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("${:04X},Y @ {:04X}", args, addr)
-            }
-            AddressingMode::Indirect => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("(${:04X}) = {:04X}", args, addr)
-            }
-            AddressingMode::IndirectX => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("(${:02X},X) @ {:02X} = {:04X}", args & 0xff, args.wrapping_add(cpu.reg_x as u16) & 0xff, addr)
-            }
-            AddressingMode::IndirectY => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                format!("(${:02X}),Y = {:04X} @ {:04X}", args & 0xff, addr.wrapping_sub(cpu.reg_y as u16), addr)
-            }
-            AddressingMode::Relative => {
-                let (_, addr): (bool, u16) = cpu.get_address_from_mode(addressing_mode, 0);
-                let offset: u8 = cpu.mem_read_u8_no_fail(addr, true);
-                let value: u16 = if offset < 127 { cpu.reg_pc.wrapping_add(2).wrapping_add(offset as u16) } else { cpu.reg_pc.wrapping_add(2).wrapping_sub(256 - offset as u16) };
-                format!("${:04X}", value)
-            }
-            AddressingMode::Accumulator => format!("A"),
-            _ => String::from("")
-        }
+
+    // Executes a single instruction (handling a pending interrupt first) and returns the cycles it took
+    pub fn step(&mut self) -> usize {
+        self.poll_interrupts();
+        self.execute_instruction()
     }
 
+    // Jumps to the NMI or IRQ handler if one is pending, returns the one it took
+    pub fn poll_interrupts(&mut self) -> Option<Interrupt> {
+        self.bus.poll_movie();
+        if let Some(()) = self.bus.poll_interrupt_nmi() {
+            self.interrupt_nmi();
+            return Some(Interrupt::Nmi);
+        }
+        if self.bus.poll_interrupt_irq() && !self.get_flag(CPUFlag::InterruptDisabled) {
+            self.interrupt_irq();
+            return Some(Interrupt::Irq);
+        }
+        None
+    }
 
-    pub fn run_with_logs(&mut self, game_path : &str) -> Result<(), Error>{
-        let mut logs : String = String::from("");
-        let path = Path::new(game_path);
-        let folder = path.parent().map(|p| p.to_str()).unwrap().unwrap();
-        let file_stem = path.file_stem().map(|s| s.to_str()).unwrap().unwrap();
-        let log_path = String::from("./") + folder + "/" + file_stem + ".log";
-        let mut all_cycles: usize = 7;
+    // Tells the bus which instruction is about to run, before its bytes are fetched
+    fn log_execution(&mut self) {
+        if self.bus.cdl.is_some() {
+            let opcode: Opcode = OPCODES[self.mem_read_u8_no_fail(self.reg_pc, true) as usize];
+            self.bus.log_execution(self.reg_pc, &opcode);
+        }
+    }
 
-        loop {
-            if let Some(()) = self.bus.poll_interrupt_nmi() {
-                self.interrupt_nmi();
-            }
-            let opcode_num : u8 = self.mem_read_u8(self.reg_pc);
-            let opcode : Opcode = OPCODES[opcode_num as usize];
-            // ================ Creating logs ==================
-            let mut cpu_state : String = format!("{:04X}  {:02X}", self.reg_pc, opcode_num);
-            let args: u16 = self.mem_read_u16(self.reg_pc.wrapping_add(1));
-            match opcode.inst_size {
-                1 => cpu_state.push_str("      "),
-                2 => cpu_state.push_str(&format!(" {:02X}   ", (args & 0xff) as u8)),
-                3 => cpu_state.push_str(&format!(" {:02X} {:02X}", (args & 0xff) as u8, (args >> 8) as u8 )),
-                _ => println!("Should not happen")
-            }
-            //Instruction in ASM
-            cpu_state.push_str(&format!(" {}{} {:27} ", if opcode.official { " " } else { "*" }, opcode.name, CPU::log_args_str_without_addr_resolution(self, &opcode, args, opcode.address_mode)));   
-            // Registers state
-            cpu_state.push_str(&format!("A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}", self.reg_a, self.reg_x, self.reg_y, self.status,self.reg_sp));
-            cpu_state.push_str(&format!(" PPU:{:3},{:3} ", self.bus.ppu.scanline, self.bus.ppu.cycles));
-            cpu_state.push_str(&format!("CYC:{}\n", all_cycles));
-            logs.push_str(cpu_state.as_str());
-            print!("{}", cpu_state);
+    pub fn execute_instruction(&mut self) -> usize {
+        self.log_execution();
+        let opcode: Opcode = OPCODES[self.mem_read_u8(self.reg_pc) as usize];
+        let cpu_cycles: usize = opcode.exec(self);
+        self.bus.tick(cpu_cycles);
+        cpu_cycles
+    }
 
-            // =============== Execution ========================
-            let cpu_cycles: usize = opcode.exec(self);
-            all_cycles += cpu_cycles;
-            self.bus.tick(cpu_cycles);
-            if !(self.running) {
-                break;
-            }
+    // Runs until the PPU enters the next vblank (or the CPU halts)
+    pub fn run_frame(&mut self) {
+        let frame: usize = self.bus.frames();
+        while self.running && self.bus.frames() == frame {
+            self.step();
         }
-        
-        std::fs::write(log_path, logs)?;
-        println!("Execution is over !\n");
-        Ok(())
     }
 
     pub fn run(&mut self) {
@@ -249,11 +179,10 @@ impl CPU {
     pub fn run_with_callback<F>(&mut self, mut callback: F, debug : bool)
     where F: FnMut(&mut CPU) {
         loop {
-            if let Some(()) = self.bus.poll_interrupt_nmi() {
-                self.interrupt_nmi();
-            }
+            self.poll_interrupts();
             callback(self);
 
+            self.log_execution();
             let opcode: Opcode = OPCODES[self.mem_read_u8(self.reg_pc) as usize];
             if debug {
                 println!("opcode {:02x} at {:04x}", self.mem_read_u8(self.reg_pc), self.reg_pc);
@@ -267,4 +196,26 @@ impl CPU {
         println!("Execution is over !\n");
     }
      
-}
\ No newline at end of file
+}
+
+impl SaveState for CPU {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u16(self.reg_pc);
+        writer.u8(self.reg_sp);
+        writer.u8(self.reg_a);
+        writer.u8(self.reg_x);
+        writer.u8(self.reg_y);
+        writer.u8(self.status);
+        self.bus.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.reg_pc = reader.u16()?;
+        self.reg_sp = reader.u8()?;
+        self.reg_a = reader.u8()?;
+        self.reg_x = reader.u8()?;
+        self.reg_y = reader.u8()?;
+        self.status = reader.u8()?;
+        self.bus.load_state(reader)
+    }
+}
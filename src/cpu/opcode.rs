@@ -1,6 +1,6 @@
 use super::CPU;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum AddressingMode {
     Immediate,
     ZeroPage,
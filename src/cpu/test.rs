@@ -8,23 +8,36 @@
 #[cfg(test)]
 mod test {
     use std::vec;
-    use crate::{input::Joypad, rom::Rom};
+    use crate::{asm, input::Joypad, rom::Rom};
 
     use super::super::*;
 
 
     impl CPU {
-        pub fn test_prog(program: Vec<u8>) -> Self {
-            let rom: Rom = Rom::new_from_program_rom(program).unwrap();
+        // Headless CPU, reset but not run yet
+        pub fn test_load_rom(rom: Rom) -> Self {
             let j1: Joypad = Joypad::new();
             let j2: Joypad = Joypad::new();
-            let bus: Bus = Bus::new(rom, |_, _| {}, j1, j2);
+            let bus: Bus = Bus::new_headless(rom, |_, _| {}, j1, j2);
             let mut cpu = CPU::new(bus);
             cpu.set_program_base(0x8000).unwrap();
             cpu.reset();
+            cpu
+        }
+
+        pub fn test_load(source: &str) -> Self {
+            CPU::test_load_rom(Rom::new_from_program_rom(asm::assemble(source).unwrap().program_rom().unwrap()).unwrap())
+        }
+
+        pub fn test_prog(program: Vec<u8>) -> Self {
+            let mut cpu = CPU::test_load_rom(Rom::new_from_program_rom(program).unwrap());
             cpu.run();
             cpu
         }
+
+        pub fn test_asm(source: &str) -> Self {
+            CPU::test_prog(asm::assemble(source).unwrap().program_rom().unwrap())
+        }
     }
 
     #[test]
@@ -336,6 +349,41 @@ mod test {
         assert_eq!(cpu.reg_a, 0x46);
         assert_eq!(cpu.reg_pc, 0x8008);
     }
-    
+
+    #[test]
+    fn test_unofficial() {
+        // LAX, SAX then DCP on the stored value
+        let mut cpu = CPU::test_asm("
+                    LDA #$3c
+                    STA $10
+                    LAX $10
+                    LDA #$0f
+                    SAX $11         ; $3c & $0f
+                    DCP $11
+                    BRK
+        ");
+        assert_eq!(cpu.reg_x, 0x3c);
+        assert_eq!(cpu.mem_read_u8(0x11), 0x0b);
+        assert!(cpu.get_flag(CPUFlag::Carry));
+
+        // Count down a table with a subroutine
+        let cpu = CPU::test_asm("
+                    LDY #0
+                    LDX #COUNT - 1
+            loop:   JSR add
+                    DEX
+                    BPL loop
+                    BRK
+            add:    TYA
+                    CLC
+                    ADC table,X
+                    TAY
+                    RTS
+            COUNT = 4
+            table:  .byte 1, 2, 3, $10
+        ");
+        assert_eq!(cpu.reg_y, 0x16);
+        assert_eq!(cpu.reg_x, 0xff);
+    }
 
 }
\ No newline at end of file
@@ -0,0 +1,296 @@
+use crate::cpu::{CPU, CPUFlag};
+use crate::error::{Error::DebuggerError, Error};
+use crate::mem::Mem;
+
+// Conditions of the breakpoints, such as "A == $3F && X > 2" or "[$0300] != 0 || SCANLINE >= 240"
+//
+// Operands: numbers ($hex, %binary or decimal), registers (A X Y P SP PC), flags (N V D I Z C),
+// SCANLINE, CYCLE, FRAME and memory reads [addr] (side-effect free)
+// Operators, by increasing priority: || ; && ; == != < <= > >= ; | ^ & ; + - ; unary ! -
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Register(Register),
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    P,
+    SP,
+    PC,
+    Flag(char),
+    Scanline,
+    Cycle,
+    Frame,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 19] = ["||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "^", "&", "+", "-", "!", "(", ")", "[", "]", "="];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, Error> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens: Vec<Token> = vec![];
+    let mut i: usize = 0;
+    while i < chars.len() {
+        let c: char = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '$' || c == '%' || c.is_ascii_digit() {
+            let (radix, start): (u32, usize) = match c {
+                '$' => (16, i + 1),
+                '%' => (2, i + 1),
+                _ => (10, i),
+            };
+            let mut end: usize = start;
+            while end < chars.len() && chars[end].is_digit(radix) {
+                end += 1;
+            }
+            let digits: String = chars[start..end].iter().collect();
+            let value: i64 = i64::from_str_radix(&digits, radix).map_err(|_| DebuggerError(format!("Invalid number near \"{}\"", &text[i..])))?;
+            tokens.push(Token::Number(value));
+            i = end;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start: usize = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect::<String>().to_ascii_uppercase()));
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            let op: &'static str = OPERATORS.iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| DebuggerError(format!("Unexpected character '{}'", c)))?;
+            // A single '=' is accepted as '=='
+            tokens.push(Token::Op(if op == "=" { "==" } else { op }));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), Error> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(DebuggerError(format!("Expected '{}'", op)))
+        }
+    }
+
+    // Parses a left-associative level made of the given operators
+    fn binary_level(&mut self, ops: &[(&str, BinaryOp)], next: fn(&mut Parser) -> Result<Expr, Error>) -> Result<Expr, Error> {
+        let mut left: Expr = next(self)?;
+        while let Some(op) = self.peek_op() {
+            let binary: BinaryOp = match ops.iter().find(|(name, _)| *name == op) {
+                Some((_, binary)) => *binary,
+                None => break,
+            };
+            self.pos += 1;
+            let right: Expr = next(self)?;
+            left = Expr::Binary(Box::new(left), binary, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        self.binary_level(&[("||", BinaryOp::Or)], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        self.binary_level(&[("&&", BinaryOp::And)], Parser::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, Error> {
+        self.binary_level(&[
+            ("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual),
+            ("<", BinaryOp::Less), ("<=", BinaryOp::LessEqual),
+            (">", BinaryOp::Greater), (">=", BinaryOp::GreaterEqual),
+        ], Parser::bitwise)
+    }
+
+    fn bitwise(&mut self) -> Result<Expr, Error> {
+        self.binary_level(&[("|", BinaryOp::BitOr), ("^", BinaryOp::BitXor), ("&", BinaryOp::BitAnd)], Parser::sum)
+    }
+
+    fn sum(&mut self) -> Result<Expr, Error> {
+        self.binary_level(&[("+", BinaryOp::Add), ("-", BinaryOp::Sub)], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        match self.peek_op() {
+            Some("!") => { self.pos += 1; Ok(Expr::Not(Box::new(self.unary()?))) }
+            Some("-") => { self.pos += 1; Ok(Expr::Negate(Box::new(self.unary()?))) }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        let token: Option<Token> = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Name(name)) => Ok(Expr::Register(match name.as_str() {
+                "A" => Register::A,
+                "X" => Register::X,
+                "Y" => Register::Y,
+                "P" => Register::P,
+                "SP" | "S" => Register::SP,
+                "PC" => Register::PC,
+                "N" | "V" | "D" | "I" | "Z" | "C" => Register::Flag(name.chars().next().unwrap_or('C')),
+                "SCANLINE" | "SL" => Register::Scanline,
+                "CYCLE" | "DOT" => Register::Cycle,
+                "FRAME" => Register::Frame,
+                _ => return Err(DebuggerError(format!("Unknown name {}", name))),
+            })),
+            Some(Token::Op("(")) => {
+                let inner: Expr = self.or()?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Some(Token::Op("[")) => {
+                let inner: Expr = self.or()?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(inner)))
+            }
+            Some(token) => Err(DebuggerError(format!("Unexpected {:?}", token))),
+            None => Err(DebuggerError(String::from("Unexpected end of expression"))),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut parser: Parser = Parser { tokens: tokenize(text)?, pos: 0 };
+        let expr: Expr = parser.or()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(DebuggerError(format!("Trailing characters in \"{}\"", text)));
+        }
+        Ok(expr)
+    }
+
+    pub fn eval(&self, cpu: &mut CPU) -> i64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Register(register) => register.value(cpu),
+            Expr::Memory(addr) => {
+                let addr: u16 = addr.eval(cpu) as u16;
+                cpu.mem_read_u8_no_fail(addr, true) as i64
+            }
+            Expr::Not(inner) => (inner.eval(cpu) == 0) as i64,
+            Expr::Negate(inner) => -inner.eval(cpu),
+            Expr::Binary(left, op, right) => {
+                let left: i64 = left.eval(cpu);
+                // Short circuit so that memory reads on the right side are skipped
+                match op {
+                    BinaryOp::Or if left != 0 => return 1,
+                    BinaryOp::And if left == 0 => return 0,
+                    _ => (),
+                }
+                let right: i64 = right.eval(cpu);
+                match op {
+                    BinaryOp::Or | BinaryOp::And => (right != 0) as i64,
+                    BinaryOp::Equal => (left == right) as i64,
+                    BinaryOp::NotEqual => (left != right) as i64,
+                    BinaryOp::Less => (left < right) as i64,
+                    BinaryOp::LessEqual => (left <= right) as i64,
+                    BinaryOp::Greater => (left > right) as i64,
+                    BinaryOp::GreaterEqual => (left >= right) as i64,
+                    BinaryOp::BitOr => left | right,
+                    BinaryOp::BitXor => left ^ right,
+                    BinaryOp::BitAnd => left & right,
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Sub => left.wrapping_sub(right),
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, cpu: &mut CPU) -> bool {
+        self.eval(cpu) != 0
+    }
+}
+
+// A parsed expression along with the text it was written as
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub source: String,
+    pub expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        Ok(Condition { source: text.trim().to_string(), expr: Expr::parse(text)? })
+    }
+
+    pub fn is_true(&self, cpu: &mut CPU) -> bool {
+        self.expr.is_true(cpu)
+    }
+}
+
+impl Register {
+    fn value(&self, cpu: &CPU) -> i64 {
+        match self {
+            Register::A => cpu.reg_a as i64,
+            Register::X => cpu.reg_x as i64,
+            Register::Y => cpu.reg_y as i64,
+            Register::P => cpu.status as i64,
+            Register::SP => cpu.reg_sp as i64,
+            Register::PC => cpu.reg_pc as i64,
+            Register::Flag(flag) => cpu.get_flag(match flag {
+                'N' => CPUFlag::Negative,
+                'V' => CPUFlag::Overflow,
+                'D' => CPUFlag::Decimal,
+                'I' => CPUFlag::InterruptDisabled,
+                'Z' => CPUFlag::Zero,
+                _ => CPUFlag::Carry,
+            }) as i64,
+            Register::Scanline => cpu.bus.ppu.scanline as i64,
+            Register::Cycle => cpu.bus.ppu.cycles as i64,
+            Register::Frame => cpu.bus.frames() as i64,
+        }
+    }
+}
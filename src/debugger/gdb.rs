@@ -0,0 +1,362 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::bus::{AccessKind, AddressSpace};
+use crate::cpu::CPU;
+use crate::error::{Error::DebuggerError, Error};
+use crate::mem::Mem;
+
+use super::{Debugger, StopReason};
+
+// GDB remote serial protocol stub for the 6502 core
+// Registers are sent in this order: A, X, Y, P, SP (8 bits each) then PC (16 bits, little endian)
+
+const SIGTRAP: u8 = 5;
+const SIGINT: u8 = 2;
+// Number of instructions executed between two checks for a ^C from the client
+const INSTRUCTIONS_PER_POLL: usize = 10_000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nes.6502.core">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>"#;
+
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+impl GdbServer {
+    // Only listens on localhost, the protocol has no authentication at all
+    pub fn bind(port: u16) -> Result<Self, Error> {
+        Ok(GdbServer { listener: TcpListener::bind(("127.0.0.1", port))? })
+    }
+
+    pub fn port(&self) -> Result<u16, Error> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    // Waits for a client and serves it until it detaches or kills the session
+    pub fn serve(&self, cpu: &mut CPU) -> Result<(), Error> {
+        println!("Waiting for gdb on 127.0.0.1:{}", self.port()?);
+        let (stream, peer) = self.listener.accept()?;
+        println!("gdb connected from {}", peer);
+        stream.set_nodelay(true)?;
+        let mut stub: GdbStub<TcpStream> = GdbStub::new(stream);
+        stub.run(cpu)
+    }
+}
+
+// Largest memory read a client can ask for
+const MAX_READ_LENGTH: usize = 0x10000;
+
+pub struct GdbStub<S: Read + Write> {
+    stream: S,
+    pub debugger: Debugger,
+    no_ack: bool,
+    pending: Vec<u8>,
+}
+
+fn hex_value(text: &str) -> Result<usize, Error> {
+    usize::from_str_radix(text, 16).map_err(|_| DebuggerError(format!("Invalid hex value {}", text)))
+}
+
+// A CPU address, anything above $FFFF is refused rather than truncated
+fn hex_address(text: &str) -> Result<u16, Error> {
+    u16::try_from(hex_value(text)?).map_err(|_| DebuggerError(format!("Address {} out of range", text)))
+}
+
+fn hex_bytes(text: &str) -> Result<Vec<u8>, Error> {
+    // Checked first, slicing the text by byte index needs ASCII
+    if !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(DebuggerError(format!("Invalid hex data {}", text)));
+    }
+    (0..text.len() / 2).map(|i| hex_value(&text[2 * i..2 * i + 2]).map(|v| v as u8)).collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl GdbStub<TcpStream> {
+    fn interrupted(&mut self) -> Result<bool, Error> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer: [u8; 64] = [0; 64];
+        let result = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Ok(true),
+            Ok(n) => {
+                // Anything but ^C is kept for the next packet
+                let interrupted: bool = buffer[..n].contains(&0x03);
+                self.pending.extend(buffer[..n].iter().filter(|b| **b != 0x03));
+                Ok(interrupted)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl<S: Read + Write> GdbStub<S> {
+    pub fn new(stream: S) -> Self {
+        GdbStub { stream, debugger: Debugger::new(), no_ack: false, pending: vec![] }
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, Error> {
+        if !self.pending.is_empty() {
+            return Ok(Some(self.pending.remove(0)));
+        }
+        let mut byte: [u8; 1] = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // Returns the next packet content, None when the connection is closed
+    fn read_packet(&mut self) -> Result<Option<String>, Error> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => break,
+                Some(0x03) => return Ok(Some(String::from("\x03"))),
+                Some(_) => continue, // acks and noise
+            }
+        }
+        let mut data: Vec<u8> = vec![];
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(byte) => data.push(byte),
+            }
+        }
+        let mut checksum: [u8; 2] = [0; 2];
+        for c in checksum.iter_mut() {
+            *c = self.read_byte()?.unwrap_or(0);
+        }
+        let expected: usize = hex_value(std::str::from_utf8(&checksum).unwrap_or("")).unwrap_or(0x100);
+        let actual: u8 = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        if !self.no_ack {
+            self.stream.write_all(if expected == actual as usize { b"+" } else { b"-" })?;
+        }
+        if expected != actual as usize {
+            return self.read_packet();
+        }
+        Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    fn send(&mut self, data: &str) -> Result<(), Error> {
+        let checksum: u8 = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        self.stream.write_all(format!("${}#{:02x}", data, checksum).as_bytes())?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    fn registers(cpu: &CPU) -> String {
+        to_hex(&[cpu.reg_a, cpu.reg_x, cpu.reg_y, cpu.status, cpu.reg_sp, (cpu.reg_pc & 0xff) as u8, (cpu.reg_pc >> 8) as u8])
+    }
+
+    fn set_register(cpu: &mut CPU, index: usize, bytes: &[u8]) -> Result<(), Error> {
+        let byte: u8 = *bytes.first().ok_or_else(|| DebuggerError(String::from("Missing register value")))?;
+        match index {
+            0 => cpu.reg_a = byte,
+            1 => cpu.reg_x = byte,
+            2 => cpu.reg_y = byte,
+            3 => cpu.status = byte,
+            4 => cpu.reg_sp = byte,
+            5 => cpu.reg_pc = byte as u16 | (*bytes.get(1).unwrap_or(&0) as u16) << 8,
+            _ => return Err(DebuggerError(format!("No register {}", index))),
+        }
+        Ok(())
+    }
+
+    fn stop_reply(reason: &StopReason) -> String {
+        match reason {
+            StopReason::Watchpoint(_, access) if access.space == AddressSpace::Cpu && access.kind != AccessKind::Execute => {
+                let kind: &str = if access.kind == AccessKind::Write { "watch" } else { "rwatch" };
+                format!("T{:02x}{}:{:04x};", SIGTRAP, kind, access.addr)
+            }
+            StopReason::Halted => String::from("W00"),
+            _ => format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    // Handles one packet, returns the reply (None when the session is over)
+    fn handle<F>(&mut self, cpu: &mut CPU, packet: &str, resume: &mut F) -> Result<Option<String>, Error>
+    where F: FnMut(&mut Self, &mut CPU, bool) -> Result<StopReason, Error> {
+        let (command, args): (char, &str) = match packet.chars().next() {
+            Some(c) => (c, &packet[c.len_utf8()..]),
+            None => return Ok(Some(String::new())),
+        };
+        let reply: String = match command {
+            '?' => format!("S{:02x}", SIGTRAP),
+            'g' => GdbStub::<S>::registers(cpu),
+            'G' => {
+                let bytes: Vec<u8> = hex_bytes(args)?;
+                if bytes.len() < 7 {
+                    return Err(DebuggerError(String::from("Malformed G packet")));
+                }
+                for index in 0..=5 {
+                    GdbStub::<S>::set_register(cpu, index, &bytes[index..])?;
+                }
+                String::from("OK")
+            }
+            'p' => {
+                let index: usize = hex_value(args)?;
+                let registers: Vec<u8> = hex_bytes(&GdbStub::<S>::registers(cpu))?;
+                match index {
+                    0..=4 => to_hex(&registers[index..index + 1]),
+                    5 => to_hex(&registers[5..7]),
+                    _ => String::from("E01"),
+                }
+            }
+            'P' => {
+                let (index, value) = args.split_once('=').ok_or_else(|| DebuggerError(String::from("Malformed P packet")))?;
+                GdbStub::<S>::set_register(cpu, hex_value(index)?, &hex_bytes(value)?)?;
+                String::from("OK")
+            }
+            'm' => {
+                let (addr, len) = args.split_once(',').ok_or_else(|| DebuggerError(String::from("Malformed m packet")))?;
+                let (addr, len): (u16, usize) = (hex_address(addr)?, hex_value(len)?);
+                if len > MAX_READ_LENGTH {
+                    return Ok(Some(String::from("E01")));
+                }
+                let bytes: Vec<u8> = (0..len).map(|i| cpu.mem_read_u8_no_fail(addr.wrapping_add(i as u16), true)).collect();
+                to_hex(&bytes)
+            }
+            'M' => {
+                let (location, data) = args.split_once(':').ok_or_else(|| DebuggerError(String::from("Malformed M packet")))?;
+                let (addr, _) = location.split_once(',').ok_or_else(|| DebuggerError(String::from("Malformed M packet")))?;
+                let addr: u16 = hex_address(addr)?;
+                for (i, byte) in hex_bytes(data)?.into_iter().enumerate() {
+                    cpu.bus.poke(addr.wrapping_add(i as u16), byte);
+                }
+                String::from("OK")
+            }
+            'c' | 's' => {
+                if !args.is_empty() {
+                    cpu.reg_pc = hex_address(args)?;
+                }
+                let reason: StopReason = resume(self, cpu, command == 's')?;
+                if reason == StopReason::InstructionLimit {
+                    format!("S{:02x}", SIGINT)
+                } else {
+                    GdbStub::<S>::stop_reply(&reason)
+                }
+            }
+            'Z' | 'z' => {
+                let fields: Vec<&str> = args.split(',').collect();
+                let (kind, addr): (&str, u16) = match fields.as_slice() {
+                    [kind, addr, ..] => (kind, hex_address(addr)?),
+                    _ => return Err(DebuggerError(String::from("Malformed Z packet"))),
+                };
+                // The watched range must end by $FFFF
+                let len: usize = fields.get(2).map(|l| hex_value(l)).transpose()?.unwrap_or(1).max(1);
+                let end: u16 = match u16::try_from(len - 1).ok().and_then(|last| addr.checked_add(last)) {
+                    Some(end) => end,
+                    None => return Ok(Some(String::from("E01"))),
+                };
+                if command == 'Z' {
+                    match kind {
+                        "0" | "1" => { self.debugger.add_breakpoint(addr, None); }
+                        "2" => { self.debugger.add_watchpoint(AddressSpace::Cpu, addr, end, false, true, false); }
+                        "3" => { self.debugger.add_watchpoint(AddressSpace::Cpu, addr, end, true, false, false); }
+                        "4" => { self.debugger.add_watchpoint(AddressSpace::Cpu, addr, end, true, true, false); }
+                        _ => return Ok(Some(String::new())),
+                    }
+                } else {
+                    let (read, write): (bool, bool) = match kind {
+                        "2" => (false, true),
+                        "3" => (true, false),
+                        _ => (true, true),
+                    };
+                    match kind {
+                        "0" | "1" => self.debugger.breakpoints.retain(|b| b.addr != addr),
+                        "2" | "3" | "4" => self.debugger.watchpoints.retain(|w| !(w.start == addr && w.end == end && w.read == read && w.write == write)),
+                        _ => return Ok(Some(String::new())),
+                    }
+                }
+                String::from("OK")
+            }
+            'q' if args.starts_with("Supported") => String::from("PacketSize=1000;qXfer:features:read+;QStartNoAckMode+"),
+            'q' if args.starts_with("Xfer:features:read:target.xml:") => {
+                let (offset, length) = args.trim_start_matches("Xfer:features:read:target.xml:").split_once(',')
+                    .ok_or_else(|| DebuggerError(String::from("Malformed qXfer packet")))?;
+                let (offset, length): (usize, usize) = (hex_value(offset)?, hex_value(length)?);
+                let end: usize = offset.saturating_add(length);
+                let chunk: &str = TARGET_XML.get(offset.min(TARGET_XML.len())..end.min(TARGET_XML.len())).unwrap_or("");
+                format!("{}{}", if end >= TARGET_XML.len() { "l" } else { "m" }, chunk)
+            }
+            'q' if args == "Attached" => String::from("1"),
+            'q' if args == "C" => String::from("QC1"),
+            'q' if args == "fThreadInfo" => String::from("m1"),
+            'q' if args == "sThreadInfo" => String::from("l"),
+            'Q' if args == "StartNoAckMode" => {
+                // This packet has already been acknowledged, the next ones won't
+                self.no_ack = true;
+                String::from("OK")
+            }
+            'H' | 'T' => String::from("OK"),
+            'D' => {
+                self.send("OK")?;
+                return Ok(None);
+            }
+            'k' => return Ok(None),
+            '\x03' => format!("S{:02x}", SIGINT),
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    // Serves packets until the client detaches, stepping the CPU with the given resume function
+    fn serve_with<F>(&mut self, cpu: &mut CPU, mut resume: F) -> Result<(), Error>
+    where F: FnMut(&mut Self, &mut CPU, bool) -> Result<StopReason, Error> {
+        self.debugger.attach(cpu);
+        while let Some(packet) = self.read_packet()? {
+            let reply: Option<String> = match self.handle(cpu, &packet, &mut resume) {
+                Ok(reply) => reply,
+                Err(_) => Some(String::from("E01")),
+            };
+            match reply {
+                None => break,
+                Some(reply) => self.send(&reply)?,
+            }
+        }
+        self.debugger.detach(cpu);
+        Ok(())
+    }
+
+    // Serves without being interruptible (continue only stops on breakpoints, watchpoints or a halt)
+    pub fn run_blocking(&mut self, cpu: &mut CPU) -> Result<(), Error> {
+        self.serve_with(cpu, |stub, cpu, step| {
+            Ok(if step { stub.debugger.step_into(cpu) } else { stub.debugger.continue_execution(cpu) })
+        })
+    }
+}
+
+impl GdbStub<TcpStream> {
+    // Serves the connection, a ^C from the client interrupts a continue
+    pub fn run(&mut self, cpu: &mut CPU) -> Result<(), Error> {
+        self.debugger.max_instructions = INSTRUCTIONS_PER_POLL;
+        self.serve_with(cpu, |stub, cpu, step| {
+            if step {
+                return Ok(stub.debugger.step_into(cpu));
+            }
+            loop {
+                let reason: StopReason = stub.debugger.continue_execution(cpu);
+                if reason != StopReason::InstructionLimit || stub.interrupted()? {
+                    return Ok(reason);
+                }
+            }
+        })
+    }
+}
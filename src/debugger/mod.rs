@@ -0,0 +1,310 @@
+pub mod expr;
+pub mod gdb;
+pub mod repl;
+mod test;
+
+use crate::bus::{AccessKind, AddressSpace, MemoryAccess};
+use crate::cpu::opcode::{Opcode, OPCODES};
+use crate::cpu::{CPU, CPUFlag, Interrupt};
+use crate::mem::Mem;
+
+use expr::Condition;
+
+// Safety net so that "continue" on a program that never hits anything gives the hand back
+const DEFAULT_MAX_INSTRUCTIONS: usize = 100_000_000;
+
+pub struct Breakpoint {
+    pub id: usize,
+    pub addr: u16,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+}
+
+pub struct Watchpoint {
+    pub id: usize,
+    pub space: AddressSpace,
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Step,
+    Breakpoint(usize),
+    Watchpoint(usize, MemoryAccess),
+    Scanline(usize),
+    Nmi,
+    Halted,
+    InstructionLimit,
+}
+
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub max_instructions: usize,
+    next_id: usize,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &MemoryAccess) -> bool {
+        self.enabled && self.space == access.space && (self.start..=self.end).contains(&access.addr) && match access.kind {
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+            AccessKind::Execute => self.execute,
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+
+    // ===================================================================
+    // ============================= API =================================
+    // ===================================================================
+
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: vec![],
+            watchpoints: vec![],
+            max_instructions: DEFAULT_MAX_INSTRUCTIONS,
+            next_id: 1,
+        }
+    }
+
+    // Starts recording the bus accesses, needed by the watchpoints
+    pub fn attach(&self, cpu: &mut CPU) {
+        if cpu.bus.access_log.is_none() {
+            cpu.bus.access_log = Some(vec![]);
+        }
+    }
+
+    pub fn detach(&self, cpu: &mut CPU) {
+        cpu.bus.access_log = None;
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16, condition: Option<Condition>) -> usize {
+        let id: usize = self.new_id();
+        self.breakpoints.push(Breakpoint { id, addr, condition, enabled: true });
+        id
+    }
+
+    pub fn add_watchpoint(&mut self, space: AddressSpace, start: u16, end: u16, read: bool, write: bool, execute: bool) -> usize {
+        let id: usize = self.new_id();
+        self.watchpoints.push(Watchpoint { id, space, start, end, read, write, execute, enabled: true });
+        id
+    }
+
+    // Removes a breakpoint or a watchpoint, returns whether the id existed
+    pub fn remove(&mut self, id: usize) -> bool {
+        let count: usize = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|b| b.id != id);
+        self.watchpoints.retain(|w| w.id != id);
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        let mut found: bool = false;
+        for breakpoint in self.breakpoints.iter_mut().filter(|b| b.id == id) {
+            breakpoint.enabled = enabled;
+            found = true;
+        }
+        for watchpoint in self.watchpoints.iter_mut().filter(|w| w.id == id) {
+            watchpoint.enabled = enabled;
+            found = true;
+        }
+        found
+    }
+
+    pub fn step_into(&mut self, cpu: &mut CPU) -> StopReason {
+        match self.single_step(cpu, false) {
+            Some(reason) => reason,
+            None => StopReason::Step,
+        }
+    }
+
+    // Runs a whole subroutine when the next instruction is a JSR
+    pub fn step_over(&mut self, cpu: &mut CPU) -> StopReason {
+        let opcode: Opcode = OPCODES[cpu.mem_read_u8_no_fail(cpu.reg_pc, true) as usize];
+        if opcode.name != "JSR" {
+            return self.step_into(cpu);
+        }
+        let return_addr: u16 = cpu.reg_pc.wrapping_add(opcode.inst_size as u16);
+        let sp: u8 = cpu.reg_sp;
+        if let Some(reason) = self.single_step(cpu, false) {
+            return reason;
+        }
+        self.run_until(cpu, |cpu| cpu.reg_pc == return_addr && cpu.reg_sp == sp)
+            .unwrap_or(StopReason::Step)
+    }
+
+    // Runs until the current subroutine returns (RTS or RTI popping above the current stack frame)
+    pub fn step_out(&mut self, cpu: &mut CPU) -> StopReason {
+        let sp: u8 = cpu.reg_sp;
+        let mut instructions: usize = 0;
+        loop {
+            let name: &str = OPCODES[cpu.mem_read_u8_no_fail(cpu.reg_pc, true) as usize].name;
+            if let Some(reason) = self.single_step(cpu, false) {
+                return reason;
+            }
+            if (name == "RTS" || name == "RTI") && cpu.reg_sp > sp {
+                return StopReason::Step;
+            }
+            instructions += 1;
+            if instructions >= self.max_instructions {
+                return StopReason::InstructionLimit;
+            }
+            if let Some(reason) = self.check_breakpoints(cpu) {
+                return reason;
+            }
+        }
+    }
+
+    pub fn continue_execution(&mut self, cpu: &mut CPU) -> StopReason {
+        if let Some(reason) = self.single_step(cpu, false) {
+            return reason;
+        }
+        self.run_until(cpu, |_| false).unwrap_or(StopReason::InstructionLimit)
+    }
+
+    pub fn run_to_scanline(&mut self, cpu: &mut CPU, scanline: usize) -> StopReason {
+        if let Some(reason) = self.single_step(cpu, false) {
+            return reason;
+        }
+        self.run_until(cpu, |cpu| cpu.bus.ppu.scanline == scanline)
+            .map(|reason| if reason == StopReason::Step { StopReason::Scanline(scanline) } else { reason })
+            .unwrap_or(StopReason::InstructionLimit)
+    }
+
+    // Stops on the first instruction of the NMI handler
+    pub fn run_to_nmi(&mut self, cpu: &mut CPU) -> StopReason {
+        let mut instructions: usize = 0;
+        loop {
+            if let Some(reason) = self.single_step(cpu, true) {
+                return reason;
+            }
+            instructions += 1;
+            if instructions >= self.max_instructions {
+                return StopReason::InstructionLimit;
+            }
+            if let Some(reason) = self.check_breakpoints(cpu) {
+                return reason;
+            }
+        }
+    }
+
+    // ===================================================================
+    // ============================= Views ===============================
+    // ===================================================================
+
+    pub fn registers(cpu: &CPU) -> String {
+        let flags: String = [
+            (CPUFlag::Negative, 'N'), (CPUFlag::Overflow, 'V'), (CPUFlag::Break2, '-'), (CPUFlag::Break, 'B'),
+            (CPUFlag::Decimal, 'D'), (CPUFlag::InterruptDisabled, 'I'), (CPUFlag::Zero, 'Z'), (CPUFlag::Carry, 'C'),
+        ].into_iter().map(|(flag, name)| if cpu.get_flag(flag) { name } else { name.to_ascii_lowercase() }).collect();
+        format!("PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} [{}] SP:{:02X} PPU:{:3},{:3} FRAME:{} CYC:{}",
+            cpu.reg_pc, cpu.reg_a, cpu.reg_x, cpu.reg_y, cpu.status, flags, cpu.reg_sp,
+            cpu.bus.ppu.scanline, cpu.bus.ppu.cycles, cpu.bus.frames(), cpu.bus.cycles())
+    }
+
+    // Bytes currently on the stack, from the top (last pushed) to the bottom
+    pub fn stack(cpu: &mut CPU) -> Vec<u8> {
+        let top: u16 = cpu.reg_sp as u16 + 1;
+        (top..=0xff).map(|offset| cpu.mem_read_u8_no_fail(cpu.stack_base + offset, true)).collect()
+    }
+
+    // ===================================================================
+    // =========================== Internals =============================
+    // ===================================================================
+
+    fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    // Executes one instruction and reports the watchpoints it triggered. A pending interrupt is taken
+    // first, and the handler entry is checked like any instruction before it runs
+    fn single_step(&mut self, cpu: &mut CPU, stop_on_nmi: bool) -> Option<StopReason> {
+        if !cpu.running {
+            return Some(StopReason::Halted);
+        }
+        if let Some(log) = cpu.bus.access_log.as_mut() {
+            log.clear();
+        }
+        if let Some(interrupt) = cpu.poll_interrupts() {
+            if interrupt == Interrupt::Nmi && stop_on_nmi {
+                return Some(StopReason::Nmi);
+            }
+            // The pushes on the stack and the vector reads
+            let accesses: Vec<MemoryAccess> = cpu.bus.access_log.as_mut().map(std::mem::take).unwrap_or_default();
+            if let Some(reason) = self.check_watchpoints(accesses).or_else(|| self.check_breakpoints(cpu)) {
+                return Some(reason);
+            }
+        }
+        let pc: u16 = cpu.reg_pc;
+        let size: u16 = OPCODES[cpu.mem_read_u8_no_fail(pc, true) as usize].inst_size as u16;
+        cpu.execute_instruction();
+
+        let execute: MemoryAccess = MemoryAccess { space: AddressSpace::Cpu, kind: AccessKind::Execute, addr: pc, value: cpu.mem_read_u8_no_fail(pc, true) };
+        let accesses: Vec<MemoryAccess> = cpu.bus.access_log.as_mut().map(std::mem::take).unwrap_or_default();
+        // The instruction fetch itself is reported as an execution, not as reads
+        let accesses = std::iter::once(execute).chain(accesses.into_iter().filter(|access| {
+            !(access.space == AddressSpace::Cpu && access.kind == AccessKind::Read && access.addr.wrapping_sub(pc) < size)
+        }));
+        if let Some(reason) = self.check_watchpoints(accesses) {
+            return Some(reason);
+        }
+        if !cpu.running {
+            return Some(StopReason::Halted);
+        }
+        None
+    }
+
+    fn check_watchpoints<I: IntoIterator<Item = MemoryAccess>>(&self, accesses: I) -> Option<StopReason> {
+        for access in accesses {
+            if let Some(watchpoint) = self.watchpoints.iter().find(|w| w.matches(&access)) {
+                return Some(StopReason::Watchpoint(watchpoint.id, access));
+            }
+        }
+        None
+    }
+
+    fn check_breakpoints(&self, cpu: &mut CPU) -> Option<StopReason> {
+        let pc: u16 = cpu.reg_pc;
+        for breakpoint in self.breakpoints.iter().filter(|b| b.enabled && b.addr == pc) {
+            let hit: bool = match &breakpoint.condition {
+                Some(condition) => condition.is_true(cpu),
+                None => true,
+            };
+            if hit {
+                return Some(StopReason::Breakpoint(breakpoint.id));
+            }
+        }
+        None
+    }
+
+    // Steps until the predicate holds (Step), a breakpoint/watchpoint is hit or the limit is reached (None)
+    fn run_until<F>(&mut self, cpu: &mut CPU, done: F) -> Option<StopReason>
+    where F: Fn(&CPU) -> bool {
+        for _ in 0..self.max_instructions {
+            if done(cpu) {
+                return Some(StopReason::Step);
+            }
+            if let Some(reason) = self.check_breakpoints(cpu) {
+                return Some(reason);
+            }
+            if let Some(reason) = self.single_step(cpu, false) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,315 @@
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::bus::{AccessKind, AddressSpace};
+use crate::asm::{self, Assembly};
+use crate::cheat;
+use crate::cpu::CPU;
+use crate::disasm::{self, Line};
+use crate::memview::panel::MemoryPanel;
+use crate::memview::Region;
+use crate::error::{Error::DebuggerError, Error};
+use crate::mem::Mem;
+use crate::movie::MovieSession;
+use crate::savestate;
+use crate::screen::viewer;
+
+use super::expr::{Condition, Expr};
+use super::{Debugger, StopReason};
+
+const HELP: &str = "\
+Commands (addresses and values use the expression syntax, e.g. $8000):
+  r | regs                          registers and flags
+  stack                             stack content, top first
+  s | step [count]                  step into
+  n | next                          step over (runs whole subroutines)
+  out | finish                      run until the current subroutine returns
+  c | continue                      run until a breakpoint or watchpoint
+  scanline <line>                   run until the PPU reaches the scanline
+  nmi                               run until the next NMI handler starts
+  b | break <addr> [if <cond>]      breakpoint, e.g. b $8123 if A == $3F && X > 2
+  w | watch <r|w|x|rw|rwx> <addr>[..<end>] [ppu]
+                                    watchpoint on the CPU (default) or PPU address space
+  l | list                          breakpoints and watchpoints
+  d | delete <id>                   remove a breakpoint or watchpoint
+  enable | disable <id>
+  x <addr> [len]                    dump CPU memory (side-effect free)
+  xp <addr> [len]                   dump PPU memory
+  m | mem [command]                 memory viewer: hex of a region, changes since the last view marked
+                                    with *, see mem help for poke, freeze and find
+  cheat [command]                   Game Genie / Pro Action Replay cheats, see cheat help
+  dis [addr] [count]                disassemble instructions (from PC by default)
+  state save | load <file>          save or load the whole console state
+  movie [ro | rw]                   movie status, switch it to read-only or read+write
+  ppu <dir> [palette]               export the pattern tables (colored with the palette 0-7), nametables,
+                                    sprites and palette as png images
+  a | asm <addr> <instruction>      assemble an instruction and patch it in RAM or ROM
+  set <a|x|y|p|sp|pc> <value>       change a register
+  print <expr>                      evaluate an expression
+  q | quit";
+
+pub struct Repl {
+    pub debugger: Debugger,
+    pub memory: MemoryPanel,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl::new()
+    }
+}
+
+fn parse_value(text: &str, cpu: &mut CPU) -> Result<i64, Error> {
+    Ok(Expr::parse(text)?.eval(cpu))
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl { debugger: Debugger::new(), memory: MemoryPanel::new(Region::Ram) }
+    }
+
+    pub fn run<R: BufRead, W: Write>(&mut self, cpu: &mut CPU, input: R, output: &mut W) -> Result<(), Error> {
+        self.debugger.attach(cpu);
+        writeln!(output, "{}", Repl::location(cpu))?;
+        write!(output, "(nes) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            match self.execute(cpu, &line?, output) {
+                Ok(true) => break,
+                Ok(false) => (),
+                Err(e) => writeln!(output, "{}", e)?,
+            }
+            write!(output, "(nes) ")?;
+            output.flush()?;
+        }
+        self.debugger.detach(cpu);
+        Ok(())
+    }
+
+    // Returns true when the user asked to quit
+    pub fn execute<W: Write>(&mut self, cpu: &mut CPU, line: &str, output: &mut W) -> Result<bool, Error> {
+        let line: &str = line.trim();
+        let (command, args): (&str, &str) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args: &str = args.trim();
+        let words: Vec<&str> = args.split_whitespace().collect();
+
+        match command {
+            "" => (),
+            "h" | "help" => writeln!(output, "{}", HELP)?,
+            "q" | "quit" | "exit" => return Ok(true),
+            "r" | "regs" => writeln!(output, "{}", Debugger::registers(cpu))?,
+            "stack" => {
+                let stack: Vec<String> = Debugger::stack(cpu).iter().map(|b| format!("{:02X}", b)).collect();
+                writeln!(output, "SP:{:02X} [{}]", cpu.reg_sp, stack.join(" "))?;
+            }
+            "s" | "step" => {
+                let count: i64 = if args.is_empty() { 1 } else { parse_value(args, cpu)? };
+                let mut reason: StopReason = StopReason::Step;
+                for _ in 0..count.max(1) {
+                    reason = self.debugger.step_into(cpu);
+                    if reason != StopReason::Step {
+                        break;
+                    }
+                }
+                self.report(cpu, reason, output)?;
+            }
+            "n" | "next" => {
+                let reason: StopReason = self.debugger.step_over(cpu);
+                self.report(cpu, reason, output)?;
+            }
+            "out" | "finish" => {
+                let reason: StopReason = self.debugger.step_out(cpu);
+                self.report(cpu, reason, output)?;
+            }
+            "c" | "continue" => {
+                let reason: StopReason = self.debugger.continue_execution(cpu);
+                self.report(cpu, reason, output)?;
+            }
+            "scanline" => {
+                let scanline: i64 = parse_value(args, cpu)?;
+                let reason: StopReason = self.debugger.run_to_scanline(cpu, scanline as usize);
+                self.report(cpu, reason, output)?;
+            }
+            "nmi" => {
+                let reason: StopReason = self.debugger.run_to_nmi(cpu);
+                self.report(cpu, reason, output)?;
+            }
+            "b" | "break" => {
+                let (addr, condition): (&str, Option<&str>) = match args.split_once(" if ") {
+                    Some((addr, condition)) => (addr, Some(condition)),
+                    None => (args, None),
+                };
+                let addr: u16 = parse_value(addr, cpu)? as u16;
+                let condition: Option<Condition> = condition.map(Condition::parse).transpose()?;
+                let id: usize = self.debugger.add_breakpoint(addr, condition);
+                writeln!(output, "Breakpoint {} at ${:04X}", id, addr)?;
+            }
+            "w" | "watch" => {
+                let (kinds, range): (&str, &str) = match words.as_slice() {
+                    [kinds, range] | [kinds, range, _] => (kinds, range),
+                    _ => return Err(DebuggerError(String::from("Usage: watch <r|w|x|rw|rwx> <addr>[..<end>] [ppu]"))),
+                };
+                let space: AddressSpace = if words.get(2).is_some_and(|s| s.eq_ignore_ascii_case("ppu")) { AddressSpace::Ppu } else { AddressSpace::Cpu };
+                let (start, end): (u16, u16) = match range.split_once("..") {
+                    Some((start, end)) => (parse_value(start, cpu)? as u16, parse_value(end, cpu)? as u16),
+                    None => { let addr: u16 = parse_value(range, cpu)? as u16; (addr, addr) }
+                };
+                let id: usize = self.debugger.add_watchpoint(space, start, end, kinds.contains('r'), kinds.contains('w'), kinds.contains('x'));
+                writeln!(output, "Watchpoint {} on {:?} ${:04X}..${:04X} ({})", id, space, start, end, kinds)?;
+            }
+            "l" | "list" => {
+                for b in self.debugger.breakpoints.iter() {
+                    let condition: String = b.condition.as_ref().map(|c| format!(" if {}", c.source)).unwrap_or_default();
+                    writeln!(output, "{:3} break ${:04X}{}{}", b.id, b.addr, condition, if b.enabled { "" } else { " (disabled)" })?;
+                }
+                for w in self.debugger.watchpoints.iter() {
+                    let kinds: String = [(w.read, 'r'), (w.write, 'w'), (w.execute, 'x')].iter().filter(|(on, _)| *on).map(|(_, c)| *c).collect();
+                    writeln!(output, "{:3} watch {:?} ${:04X}..${:04X} {}{}", w.id, w.space, w.start, w.end, kinds, if w.enabled { "" } else { " (disabled)" })?;
+                }
+            }
+            "d" | "delete" | "enable" | "disable" => {
+                let id: usize = parse_value(args, cpu)? as usize;
+                let found: bool = match command {
+                    "enable" => self.debugger.set_enabled(id, true),
+                    "disable" => self.debugger.set_enabled(id, false),
+                    _ => self.debugger.remove(id),
+                };
+                if !found {
+                    return Err(DebuggerError(format!("No breakpoint or watchpoint {}", id)));
+                }
+            }
+            "x" | "xp" => {
+                let addr: u16 = parse_value(words.first().copied().unwrap_or("PC"), cpu)? as u16;
+                let len: u16 = match words.get(1) { Some(len) => parse_value(len, cpu)? as u16, None => 0x40 };
+                for row in (0..len).step_by(16) {
+                    let start: u16 = addr.wrapping_add(row);
+                    let bytes: Vec<String> = (0..16.min(len - row)).map(|i| {
+                        let value: u8 = if command == "x" { cpu.mem_read_u8_no_fail(start.wrapping_add(i), true) } else { cpu.bus.ppu.peek(start.wrapping_add(i)) };
+                        format!("{:02X}", value)
+                    }).collect();
+                    writeln!(output, "{:04X}: {}", start, bytes.join(" "))?;
+                }
+            }
+            "m" | "mem" => {
+                if args.is_empty() {
+                    self.memory.refresh(&mut cpu.bus);
+                }
+                let text: String = self.memory.execute(&mut cpu.bus, args)?;
+                writeln!(output, "{}", text.trim_end())?;
+            }
+            "cheat" => {
+                let text: String = match args {
+                    "h" | "help" => String::from(cheat::HELP),
+                    _ => cpu.bus.cheats.execute(args)?,
+                };
+                writeln!(output, "{}", text.trim_end())?;
+            }
+            "state" => match words.as_slice() {
+                ["save", path] => {
+                    savestate::save_file(cpu, Path::new(path))?;
+                    writeln!(output, "State saved to {}", path)?;
+                }
+                ["load", path] => {
+                    savestate::load_file(cpu, Path::new(path))?;
+                    writeln!(output, "{}", Repl::location(cpu))?;
+                }
+                _ => return Err(DebuggerError(String::from("Usage: state save | load <file>"))),
+            },
+            "movie" => {
+                let session: &mut MovieSession = cpu.bus.movie.as_mut().ok_or_else(|| DebuggerError(String::from("No movie is recorded or played")))?;
+                match args {
+                    "" => (),
+                    "ro" => session.read_only = true,
+                    "rw" => session.read_only = false,
+                    _ => return Err(DebuggerError(String::from("Usage: movie [ro | rw]"))),
+                }
+                writeln!(output, "{}", session.status())?;
+            }
+            "ppu" => {
+                let dir: &str = words.first().ok_or_else(|| DebuggerError(String::from("Usage: ppu <dir> [palette]")))?;
+                let palette: i64 = match words.get(1) { Some(palette) => parse_value(palette, cpu)?, None => 0 };
+                viewer::export_all(&cpu.bus.ppu, Path::new(dir), palette as usize % 8)?;
+                writeln!(output, "PPU viewers written to {}", dir)?;
+            }
+            "dis" | "disasm" => {
+                let mut addr: u16 = parse_value(words.first().copied().unwrap_or("PC"), cpu)? as u16;
+                let count: i64 = match words.get(1) { Some(count) => parse_value(count, cpu)?, None => 10 };
+                for _ in 0..count {
+                    let (size, text): (usize, String) = match Repl::disassemble(cpu, addr) {
+                        Some(line) => (line.bytes.len(), disasm::format_line(&line)),
+                        None => (1, String::from("???")),
+                    };
+                    writeln!(output, "{:04X}  {}", addr, text)?;
+                    addr = addr.wrapping_add(size as u16);
+                }
+            }
+            "a" | "asm" => {
+                let (addr, source): (&str, &str) = args.split_once(char::is_whitespace).ok_or_else(|| DebuggerError(String::from("Usage: asm <addr> <instruction>")))?;
+                let addr: u16 = parse_value(addr, cpu)? as u16;
+                let assembly: Assembly = asm::assemble_at(source, addr)?;
+                for (i, byte) in assembly.bytes.iter().enumerate() {
+                    cpu.bus.poke(addr.wrapping_add(i as u16), *byte);
+                }
+                let bytes: Vec<String> = assembly.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                writeln!(output, "{:04X}: {}", addr, bytes.join(" "))?;
+            }
+            "set" => {
+                let (register, value): (&str, &str) = args.split_once(char::is_whitespace).ok_or_else(|| DebuggerError(String::from("Usage: set <register> <value>")))?;
+                let value: i64 = parse_value(value, cpu)?;
+                match register.to_ascii_lowercase().as_str() {
+                    "a" => cpu.reg_a = value as u8,
+                    "x" => cpu.reg_x = value as u8,
+                    "y" => cpu.reg_y = value as u8,
+                    "p" => cpu.status = value as u8,
+                    "sp" => cpu.reg_sp = value as u8,
+                    "pc" => cpu.reg_pc = value as u16,
+                    other => return Err(DebuggerError(format!("Unknown register {}", other))),
+                }
+                writeln!(output, "{}", Debugger::registers(cpu))?;
+            }
+            "p" | "print" => {
+                let value: i64 = parse_value(args, cpu)?;
+                writeln!(output, "{} (${:X})", value, value)?;
+            }
+            other => return Err(DebuggerError(format!("Unknown command {}, try help", other))),
+        }
+        Ok(false)
+    }
+
+    fn report<W: Write>(&self, cpu: &mut CPU, reason: StopReason, output: &mut W) -> Result<(), Error> {
+        match reason {
+            StopReason::Step => (),
+            StopReason::Breakpoint(id) => writeln!(output, "Breakpoint {} hit", id)?,
+            StopReason::Watchpoint(id, access) => {
+                let kind: &str = match access.kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                    AccessKind::Execute => "execution",
+                };
+                writeln!(output, "Watchpoint {} hit: {} of ${:02X} at {:?} ${:04X}", id, kind, access.value, access.space, access.addr)?;
+            }
+            StopReason::Scanline(scanline) => writeln!(output, "Reached scanline {}", scanline)?,
+            StopReason::Nmi => writeln!(output, "NMI")?,
+            StopReason::Halted => writeln!(output, "CPU halted")?,
+            StopReason::InstructionLimit => writeln!(output, "Stopped after {} instructions", self.debugger.max_instructions)?,
+        }
+        writeln!(output, "{}", Repl::location(cpu))?;
+        Ok(())
+    }
+
+    // Instruction at an address, read without side effects
+    fn disassemble(cpu: &mut CPU, addr: u16) -> Option<Line> {
+        disasm::decode(&disasm::read_memory(cpu, addr, 3), addr)
+    }
+
+    // Current instruction and registers
+    pub fn location(cpu: &mut CPU) -> String {
+        let pc: u16 = cpu.reg_pc;
+        let (bytes, text): (Vec<u8>, String) = match Repl::disassemble(cpu, pc) {
+            Some(line) => (line.bytes.clone(), disasm::format_line(&line)),
+            None => (vec![cpu.mem_read_u8_no_fail(pc, true)], String::from("???")),
+        };
+        let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{:04X}  {:8}  {}\n{}", pc, bytes.join(" "), text, Debugger::registers(cpu))
+    }
+}
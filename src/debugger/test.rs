@@ -0,0 +1,225 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::bus::{AccessKind, AddressSpace};
+    use crate::cpu::CPU;
+    use crate::rom::Rom;
+
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use super::super::expr::{Condition, Expr};
+    use super::super::gdb::GdbServer;
+    use super::super::repl::Repl;
+    use super::super::*;
+
+    fn load_prog(program: Vec<u8>) -> CPU {
+        CPU::test_load_rom(Rom::new_from_program_rom(program).unwrap())
+    }
+
+    // 8000: LDX #$00
+    // 8002: INX
+    // 8003: CPX #$05
+    // 8005: BNE $8002
+    // 8007: JSR $800B
+    // 800A: BRK
+    // 800B: LDA #$42
+    // 800D: STA $0200
+    // 8010: RTS
+    fn sample() -> CPU {
+        load_prog(vec![0xa2, 0x00, 0xe8, 0xe0, 0x05, 0xd0, 0xfb, 0x20, 0x0b, 0x80, 0x00, 0xa9, 0x42, 0x8d, 0x00, 0x02, 0x60])
+    }
+
+    #[test]
+    fn test_expressions() {
+        let mut cpu: CPU = sample();
+        cpu.reg_a = 0x3f;
+        cpu.reg_x = 3;
+        assert!(Expr::parse("A == $3F && X > 2").unwrap().is_true(&mut cpu));
+        assert!(!Expr::parse("A == $3F && X > 3").unwrap().is_true(&mut cpu));
+        assert!(Expr::parse("a = 63 || [$0000]").unwrap().is_true(&mut cpu));
+        assert_eq!(Expr::parse("(X + 1) & %110").unwrap().eval(&mut cpu), 4);
+        assert_eq!(Expr::parse("[PC + 1]").unwrap().eval(&mut cpu), 0x00);
+        assert_eq!(Expr::parse("!Z").unwrap().eval(&mut cpu), 1);
+        assert!(Expr::parse("A ==").is_err());
+        assert!(Expr::parse("FOO > 1").is_err());
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        let mut cpu: CPU = sample();
+        let mut debugger: Debugger = Debugger::new();
+        let id: usize = debugger.add_breakpoint(0x8002, Some(Condition::parse("X == 3").unwrap()));
+        assert_eq!(debugger.continue_execution(&mut cpu), StopReason::Breakpoint(id));
+        assert_eq!(cpu.reg_pc, 0x8002);
+        assert_eq!(cpu.reg_x, 3);
+
+        debugger.set_enabled(id, false);
+        assert_eq!(debugger.continue_execution(&mut cpu), StopReason::Halted);
+        assert_eq!(cpu.reg_a, 0x42);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut cpu: CPU = sample();
+        let mut debugger: Debugger = Debugger::new();
+        debugger.attach(&mut cpu);
+        let write: usize = debugger.add_watchpoint(AddressSpace::Cpu, 0x0200, 0x02ff, false, true, false);
+        match debugger.continue_execution(&mut cpu) {
+            StopReason::Watchpoint(id, access) => {
+                assert_eq!(id, write);
+                assert_eq!(access.kind, AccessKind::Write);
+                assert_eq!((access.addr, access.value), (0x0200, 0x42));
+            }
+            other => panic!("Unexpected stop {:?}", other),
+        }
+        assert_eq!(cpu.reg_pc, 0x8010);
+
+        let mut cpu: CPU = sample();
+        let mut debugger: Debugger = Debugger::new();
+        debugger.attach(&mut cpu);
+        let execute: usize = debugger.add_watchpoint(AddressSpace::Cpu, 0x800b, 0x800b, false, false, true);
+        assert!(matches!(debugger.continue_execution(&mut cpu), StopReason::Watchpoint(id, _) if id == execute));
+        assert_eq!(cpu.reg_pc, 0x800d);
+    }
+
+    #[test]
+    fn test_stepping() {
+        let mut cpu: CPU = sample();
+        let mut debugger: Debugger = Debugger::new();
+        debugger.add_breakpoint(0x8007, None);
+        debugger.continue_execution(&mut cpu);
+        assert_eq!(cpu.reg_pc, 0x8007);
+
+        // Over the subroutine
+        assert_eq!(debugger.step_over(&mut cpu), StopReason::Step);
+        assert_eq!(cpu.reg_pc, 0x800a);
+        assert_eq!(cpu.reg_a, 0x42);
+
+        // Into then out of it
+        let mut cpu: CPU = sample();
+        debugger.continue_execution(&mut cpu);
+        assert_eq!(debugger.step_into(&mut cpu), StopReason::Step);
+        assert_eq!(cpu.reg_pc, 0x800b);
+        assert_eq!(Debugger::stack(&mut cpu)[..2], [0x09, 0x80]);
+        assert_eq!(debugger.step_out(&mut cpu), StopReason::Step);
+        assert_eq!(cpu.reg_pc, 0x800a);
+    }
+
+    #[test]
+    fn test_break_on_nmi_handler() {
+        let program: Vec<u8> = crate::asm::assemble("
+            reset:  LDA #$80
+                    STA $2000
+            loop:   JMP loop
+            nmi:    INC $10
+                    RTI
+            .org $fffa
+                    .word nmi, reset, reset
+        ").unwrap().program_rom().unwrap();
+        let mut cpu: CPU = load_prog(program);
+        let mut debugger: Debugger = Debugger::new();
+        let id: usize = debugger.add_breakpoint(0x8008, None);
+        // The interrupt is taken, then the breakpoint stops before the handler runs
+        assert_eq!(debugger.continue_execution(&mut cpu), StopReason::Breakpoint(id));
+        assert_eq!((cpu.reg_pc, cpu.mem_read_u8_no_fail(0x10, true)), (0x8008, 0));
+        assert_eq!(debugger.continue_execution(&mut cpu), StopReason::Breakpoint(id));
+        assert_eq!(cpu.mem_read_u8_no_fail(0x10, true), 1);
+
+        // The pushes of the interrupt reach the watchpoints
+        debugger.breakpoints.clear();
+        debugger.attach(&mut cpu);
+        let stack: usize = debugger.add_watchpoint(AddressSpace::Cpu, 0x0100, 0x01ff, false, true, false);
+        assert!(matches!(debugger.continue_execution(&mut cpu), StopReason::Watchpoint(hit, _) if hit == stack));
+        assert_eq!(cpu.reg_pc, 0x8008);
+    }
+
+    #[test]
+    fn test_repl() {
+        let mut cpu: CPU = sample();
+        let mut repl: Repl = Repl::new();
+        let mut output: Vec<u8> = vec![];
+        repl.run(&mut cpu, "b $8005 if X == 2\nc\nset a $12\nprint A + 1\nx $8000 4\ndis $8005 2\na $800b LDA #$24\nx $800b 2\nq\ns\n".as_bytes(), &mut output).unwrap();
+        let output: String = String::from_utf8(output).unwrap();
+        assert!(output.contains("Breakpoint 1 at $8005"));
+        assert!(output.contains("Breakpoint 1 hit"));
+        assert!(output.contains("PC:8005 A:12 X:02"));
+        assert!(output.contains("19 ($13)"));
+        assert!(output.contains("8000: A2 00 E8 E0"));
+        assert!(output.contains("8005  BNE $8002\n8007  JSR $800B"));
+        assert!(output.contains("800B: A9 24\n(nes) 800B: A9 24"));
+        assert_eq!(cpu.reg_pc, 0x8005);
+    }
+
+    // Minimal RSP client: sends a packet, acknowledges and returns the reply
+    fn rsp_exchange(stream: &mut TcpStream, packet: &str) -> String {
+        let checksum: u8 = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        stream.write_all(format!("${}#{:02x}", packet, checksum).as_bytes()).unwrap();
+        let mut reply: Vec<u8> = vec![];
+        let mut byte: [u8; 1] = [0];
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            match byte[0] {
+                b'+' if reply.is_empty() => continue,
+                b'#' => break,
+                b'$' => reply.clear(),
+                other => reply.push(other),
+            }
+        }
+        let mut checksum: [u8; 2] = [0; 2];
+        stream.read_exact(&mut checksum).unwrap();
+        stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    #[test]
+    fn test_gdb_stub() {
+        let mut cpu: CPU = sample();
+        let server: GdbServer = GdbServer::bind(0).unwrap();
+        let port: u16 = server.port().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut stream: TcpStream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let script: [(&str, &str); 21] = [
+                ("qSupported:multiprocess+", "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+"),
+                ("?", "S05"),
+                ("g", "00000024fd0080"),
+                ("Z0,8007,1", "OK"),
+                ("c", "S05"),
+                ("p1", "05"),
+                ("m8000,3", "a200e8"),
+                ("Z2,200,1", "OK"),
+                ("c", "T05watch:0200;"),
+                ("M0300,2:abcd", "OK"),
+                ("m300,2", "abcd"),
+                ("P0=7f", "OK"),
+                ("s", "S05"),
+                // Malformed or oversized requests get an error, not a dead stub
+                ("M0300,2:a\u{e9}", "E01"),
+                ("m0,10001", "E01"),
+                ("qXfer:features:read:target.xml:ffffffffffffffff,10", "l"),
+                ("mffffffffffffffff,2", "E01"),
+                ("Mffffffffffffffff,1:00", "E01"),
+                ("Z2,8000,10000", "E01"),
+                ("Z2,ffff,2", "E01"),
+                ("mffff,2", "0000"),
+            ];
+            for (packet, expected) in script.iter() {
+                assert_eq!(rsp_exchange(&mut stream, packet), *expected, "reply to {}", packet);
+            }
+            assert_eq!(rsp_exchange(&mut stream, "D"), "OK");
+        });
+
+        server.serve(&mut cpu).unwrap();
+        client.join().unwrap();
+        assert_eq!(cpu.reg_a, 0x7f);
+        // The last step executed the RTS
+        assert_eq!(cpu.reg_pc, 0x800a);
+        assert_eq!(cpu.bus.access_log, None);
+    }
+}
@@ -0,0 +1,286 @@
+mod test;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::cpu::opcode::{AddressingMode, Opcode, OPCODES};
+use crate::mem::Mem;
+use crate::rom::Rom;
+
+// Side-effect-free 6502 disassembler working on plain bytes
+//
+// Unofficial opcodes are emitted as raw bytes with the instruction in a comment (prefixed by a '*',
+// like in the nestest logs) since assemblers disagree on their names and encodings.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    Ca65,
+    Asm6,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineKind {
+    Instruction { opcode: u8, operand: u16 },
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub kind: LineKind,
+}
+
+pub struct Disassembler<'a> {
+    pub syntax: Syntax,
+    pub base: u16,
+    bytes: &'a [u8],
+    // Bytes that must not be decoded as instructions (filled from a code/data log for instance)
+    data_mask: Option<Vec<bool>>,
+    // Named addresses such as the vectors, added to the generated labels
+    names: BTreeMap<u16, String>,
+}
+
+impl Syntax {
+    fn byte_directive(&self) -> &'static str {
+        match self {
+            Syntax::Ca65 => ".byte",
+            Syntax::Asm6 => ".db",
+        }
+    }
+
+    fn word_directive(&self) -> &'static str {
+        match self {
+            Syntax::Ca65 => ".word",
+            Syntax::Asm6 => ".dw",
+        }
+    }
+}
+
+impl Line {
+    pub fn opcode(&self) -> Option<Opcode> {
+        match self.kind {
+            LineKind::Instruction { opcode, .. } => Some(OPCODES[opcode as usize]),
+            LineKind::Data => None,
+        }
+    }
+
+    // Address jumped or branched to, for the instructions that have one
+    pub fn target(&self) -> Option<u16> {
+        let opcode: Opcode = self.opcode()?;
+        let LineKind::Instruction { operand, .. } = self.kind else { return None };
+        match opcode.address_mode {
+            AddressingMode::Relative => Some(branch_target(self.addr, operand as u8)),
+            AddressingMode::Absolute if opcode.name == "JMP" || opcode.name == "JSR" => Some(operand),
+            _ => None,
+        }
+    }
+}
+
+pub fn branch_target(addr: u16, offset: u8) -> u16 {
+    addr.wrapping_add(2).wrapping_add(offset as i8 as u16)
+}
+
+// Decodes the instruction at the start of the bytes, None if it is not a valid (or complete) one
+pub fn decode(bytes: &[u8], addr: u16) -> Option<Line> {
+    let opcode: Opcode = OPCODES[*bytes.first()? as usize];
+    if opcode.inst_size == 0 || bytes.len() < opcode.inst_size {
+        return None;
+    }
+    let operand: u16 = match opcode.inst_size {
+        2 => bytes[1] as u16,
+        3 => (bytes[2] as u16) << 8 | bytes[1] as u16,
+        _ => 0,
+    };
+    Some(Line { addr, bytes: bytes[..opcode.inst_size].to_vec(), kind: LineKind::Instruction { opcode: bytes[0], operand } })
+}
+
+// Formats the operand of an instruction, using the label of the target when there is one
+pub fn format_operand(opcode: &Opcode, operand: u16, addr: u16, labels: &BTreeMap<u16, String>) -> String {
+    let absolute = |value: u16| labels.get(&value).cloned().unwrap_or_else(|| format!("${:04X}", value));
+    match opcode.address_mode {
+        AddressingMode::Immediate => format!("#${:02X}", operand),
+        AddressingMode::ZeroPage => format!("${:02X}", operand),
+        AddressingMode::ZeroPageX => format!("${:02X},X", operand),
+        AddressingMode::ZeroPageY => format!("${:02X},Y", operand),
+        AddressingMode::Absolute => absolute(operand),
+        AddressingMode::AbsoluteX => format!("{},X", absolute(operand)),
+        AddressingMode::AbsoluteY => format!("{},Y", absolute(operand)),
+        AddressingMode::Indirect => format!("({})", absolute(operand)),
+        AddressingMode::IndirectX => format!("(${:02X},X)", operand),
+        AddressingMode::IndirectY => format!("(${:02X}),Y", operand),
+        AddressingMode::Relative => absolute(branch_target(addr, operand as u8)),
+        AddressingMode::Accumulator => String::from("A"),
+        AddressingMode::Implied | AddressingMode::NoneAddressing => String::new(),
+    }
+}
+
+// One instruction formatted the nestest way ("*" before unofficial opcodes), without labels
+pub fn format_line(line: &Line) -> String {
+    match (line.opcode(), &line.kind) {
+        (Some(opcode), LineKind::Instruction { operand, .. }) => {
+            let operand: String = format_operand(&opcode, *operand, line.addr, &BTreeMap::new());
+            format!("{}{} {}", if opcode.official { "" } else { "*" }, opcode.name, operand).trim_end().to_string()
+        }
+        _ => format!("{} {}", Syntax::Ca65.byte_directive(), hex_list(&line.bytes)),
+    }
+}
+
+// Side-effect-free copy of a range of the CPU address space
+pub fn read_memory<M: Mem>(mem: &mut M, start: u16, len: usize) -> Vec<u8> {
+    (0..len).map(|i| mem.mem_read_u8_no_fail(start.wrapping_add(i as u16), true)).collect()
+}
+
+fn hex_list(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("${:02X}", b)).collect::<Vec<String>>().join(", ")
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(bytes: &'a [u8], base: u16, syntax: Syntax) -> Self {
+        Disassembler { syntax, base, bytes, data_mask: None, names: BTreeMap::new() }
+    }
+
+    // Whole program ROM of a cartridge, a 16kB one being mapped at $C000
+    pub fn for_rom(rom: &'a Rom, syntax: Syntax) -> Self {
+        let start: usize = 0x8000 - rom.program_rom_size.min(0x8000);
+        // An empty program ROM (0 banks in the header) gives an empty listing
+        let base: u16 = u16::try_from(0x8000 + start).unwrap_or(0x8000);
+        let mut disassembler: Disassembler = Disassembler::new(&rom.program_rom[start..], base, syntax);
+        disassembler.name_vectors();
+        disassembler
+    }
+
+    pub fn with_data_mask(mut self, data_mask: Vec<bool>) -> Self {
+        self.data_mask = Some(data_mask);
+        self
+    }
+
+    pub fn name(&mut self, addr: u16, name: &str) {
+        self.names.insert(addr, name.to_string());
+    }
+
+    // Names the NMI/RESET/IRQ handlers when the vectors are part of the bytes
+    pub fn name_vectors(&mut self) {
+        let end: usize = self.base as usize + self.bytes.len();
+        if end != 0x10000 || self.bytes.len() < 6 {
+            return;
+        }
+        let vectors: &[u8] = &self.bytes[self.bytes.len() - 6..];
+        for (i, name) in ["nmi", "reset", "irq"].iter().enumerate() {
+            let addr: u16 = (vectors[2 * i + 1] as u16) << 8 | vectors[2 * i] as u16;
+            if addr >= self.base && !self.names.contains_key(&addr) {
+                self.names.insert(addr, name.to_string());
+            }
+        }
+    }
+
+    fn is_data(&self, offset: usize) -> bool {
+        self.data_mask.as_ref().is_some_and(|mask| mask.get(offset).copied().unwrap_or(false))
+    }
+
+    fn vectors_offset(&self) -> Option<usize> {
+        let end: usize = self.base as usize + self.bytes.len();
+        (end == 0x10000 && self.bytes.len() >= 6).then(|| self.bytes.len() - 6)
+    }
+
+    // Linear sweep over the bytes, anything that doesn't decode (or is masked) becomes data
+    pub fn lines(&self) -> Vec<Line> {
+        let mut lines: Vec<Line> = vec![];
+        let mut offset: usize = 0;
+        let code_end: usize = self.vectors_offset().unwrap_or(self.bytes.len());
+        while offset < self.bytes.len() {
+            let addr: u16 = self.base.wrapping_add(offset as u16);
+            let decoded: Option<Line> = if offset < code_end && !self.is_data(offset) {
+                decode(&self.bytes[offset..code_end], addr)
+                    .filter(|line| !(1..line.bytes.len()).any(|i| self.is_data(offset + i)))
+            } else {
+                None
+            };
+            let line: Line = decoded.unwrap_or_else(|| Line { addr, bytes: vec![self.bytes[offset]], kind: LineKind::Data });
+            offset += line.bytes.len();
+            lines.push(line);
+        }
+        lines
+    }
+
+    // Labels for every branch/jump target that starts an instruction, plus the named addresses
+    pub fn labels(&self, lines: &[Line]) -> BTreeMap<u16, String> {
+        let starts: BTreeSet<u16> = lines.iter().filter(|l| l.opcode().is_some()).map(|l| l.addr).collect();
+        let mut labels: BTreeMap<u16, String> = lines.iter()
+            .filter_map(|line| line.target())
+            .filter(|target| starts.contains(target))
+            .map(|target| (target, format!("L{:04X}", target)))
+            .collect();
+        for (addr, name) in self.names.iter() {
+            labels.insert(*addr, name.clone());
+        }
+        labels
+    }
+
+    pub fn listing(&self) -> String {
+        let lines: Vec<Line> = self.lines();
+        let labels: BTreeMap<u16, String> = self.labels(&lines);
+        let vectors: Option<u16> = self.vectors_offset().map(|offset| self.base.wrapping_add(offset as u16));
+        let mut output: String = String::new();
+        if self.bytes.is_empty() {
+            let _ = writeln!(output, "; Nothing to disassemble");
+            return output;
+        }
+
+        let _ = writeln!(output, "; Disassembled from ${:04X} to ${:04X}", self.base, self.base as usize + self.bytes.len() - 1);
+        let _ = writeln!(output, "{} ${:04X}", if self.syntax == Syntax::Ca65 { ".org" } else { "org" }, self.base);
+
+        // Consecutive data bytes are grouped, up to 8 per line
+        let mut pending_data: Vec<u8> = vec![];
+        let flush = |output: &mut String, pending_data: &mut Vec<u8>| {
+            if !pending_data.is_empty() {
+                let _ = writeln!(output, "    {} {}", self.syntax.byte_directive(), hex_list(pending_data));
+                pending_data.clear();
+            }
+        };
+
+        for line in lines.iter() {
+            if Some(line.addr) == vectors {
+                flush(&mut output, &mut pending_data);
+                break;
+            }
+            if let Some(label) = labels.get(&line.addr) {
+                flush(&mut output, &mut pending_data);
+                let _ = writeln!(output, "{}:", label);
+            }
+            let (opcode, operand): (Opcode, u16) = match line.kind {
+                LineKind::Instruction { operand, .. } => (line.opcode().unwrap_or(OPCODES[0]), operand),
+                LineKind::Data => {
+                    pending_data.extend(line.bytes.iter());
+                    if pending_data.len() >= 8 {
+                        flush(&mut output, &mut pending_data);
+                    }
+                    continue;
+                }
+            };
+            flush(&mut output, &mut pending_data);
+
+            let text: String = format!("{} {}", opcode.name, format_operand(&opcode, operand, line.addr, &labels)).trim_end().to_string();
+            // Absolute addressing of the zero page would be shortened by the assembler
+            let zero_page_absolute: bool = matches!(opcode.address_mode, AddressingMode::Absolute | AddressingMode::AbsoluteX | AddressingMode::AbsoluteY)
+                && operand < 0x100 && !labels.contains_key(&operand);
+            if !opcode.official || (zero_page_absolute && self.syntax == Syntax::Asm6) {
+                let _ = writeln!(output, "    {:24} ; {:04X} {}{}", format!("{} {}", self.syntax.byte_directive(), hex_list(&line.bytes)), line.addr, if opcode.official { "" } else { "*" }, text);
+            } else {
+                let text: String = if zero_page_absolute { text.replacen(" $", " a:$", 1) } else { text };
+                let _ = writeln!(output, "    {:24} ; {:04X} {}", text, line.addr, line.bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(" "));
+            }
+        }
+        flush(&mut output, &mut pending_data);
+
+        if let Some(offset) = self.vectors_offset() {
+            let _ = writeln!(output, "\n; Vectors");
+            for (i, vector) in ["NMI", "RESET", "IRQ"].iter().enumerate() {
+                let addr: u16 = (self.bytes[offset + 2 * i + 1] as u16) << 8 | self.bytes[offset + 2 * i] as u16;
+                let target: String = labels.get(&addr).cloned().unwrap_or_else(|| format!("${:04X}", addr));
+                let _ = writeln!(output, "    {} {:17} ; {}", self.syntax.word_directive(), target, vector);
+            }
+        }
+        output
+    }
+}
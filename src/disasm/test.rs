@@ -0,0 +1,94 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::rom::Rom;
+
+    use super::super::*;
+
+    // 8000: LDX #$00
+    // 8002: INX
+    // 8003: CPX #$05
+    // 8005: BNE $8002
+    // 8007: JSR $800B
+    // 800A: BRK
+    // 800B: *SLO $10
+    // 800D: LDA $0010
+    // 8010: RTS
+    // 8011: *KIL
+    const PROGRAM: [u8; 18] = [0xa2, 0x00, 0xe8, 0xe0, 0x05, 0xd0, 0xfb, 0x20, 0x0b, 0x80, 0x00, 0x07, 0x10, 0xad, 0x10, 0x00, 0x60, 0x02];
+
+    #[test]
+    fn test_decode() {
+        let line: Line = decode(&PROGRAM[5..], 0x8005).unwrap();
+        assert_eq!(line.bytes, vec![0xd0, 0xfb]);
+        assert_eq!(line.target(), Some(0x8002));
+        assert_eq!(format_line(&line), "BNE $8002");
+        assert_eq!(format_line(&decode(&PROGRAM[11..], 0x800b).unwrap()), "*SLO $10");
+        assert_eq!(format_line(&decode(&[0x02], 0x8000).unwrap()), "*KIL");
+        // Truncated instructions are not decoded
+        assert_eq!(decode(&[0xad, 0x10], 0x8000), None);
+    }
+
+    #[test]
+    fn test_listing_ca65() {
+        let listing: String = Disassembler::new(&PROGRAM, 0x8000, Syntax::Ca65).listing();
+        assert!(listing.contains(".org $8000"));
+        assert!(listing.contains("L8002:\n    INX"));
+        assert!(listing.contains("BNE L8002"));
+        assert!(listing.contains("JSR L800B"));
+        assert!(listing.contains("    .byte $07, $10           ; 800B *SLO $10"));
+        assert!(listing.contains("    LDX #$00                 ; 8000 A2 00"));
+        assert!(listing.contains("LDA a:$0010"));
+        assert!(listing.contains("RTS"));
+        assert!(listing.trim_end().ends_with("; 8011 *KIL"));
+    }
+
+    #[test]
+    fn test_listing_asm6() {
+        let listing: String = Disassembler::new(&PROGRAM, 0x8000, Syntax::Asm6).listing();
+        assert!(listing.contains("org $8000"));
+        assert!(listing.contains(".db $07, $10"));
+        // asm6 would shorten the absolute addressing, so the bytes are kept
+        assert!(listing.contains("    .db $AD, $10, $00        ; 800D LDA $0010"));
+    }
+
+    #[test]
+    fn test_data_mask() {
+        let mut mask: Vec<bool> = vec![false; PROGRAM.len()];
+        mask[3] = true;
+        mask[4] = true;
+        let lines: Vec<Line> = Disassembler::new(&PROGRAM, 0x8000, Syntax::Ca65).with_data_mask(mask).lines();
+        assert_eq!(lines.last().map(format_line).as_deref(), Some("*KIL"));
+        assert_eq!(lines[2], Line { addr: 0x8003, bytes: vec![0xe0], kind: LineKind::Data });
+        assert_eq!(lines[4].addr, 0x8005);
+        assert_eq!(format_line(&lines[4]), "BNE $8002");
+    }
+
+    #[test]
+    fn test_rom_vectors() {
+        let mut program: Vec<u8> = vec![0xea; 0x8000];
+        program[0x7ffa..].copy_from_slice(&[0x00, 0x90, 0x00, 0x80, 0x00, 0x80]);
+        program[0x1000] = 0x40;
+        let rom: Rom = Rom::new_from_program_rom(program).unwrap();
+        let listing: String = Disassembler::for_rom(&rom, Syntax::Ca65).listing();
+        assert!(listing.contains("reset:\n    NOP"));
+        assert!(listing.contains("nmi:\n    RTI"));
+        assert!(listing.contains(".word nmi"));
+        assert!(listing.contains(".word reset"));
+        // RESET and IRQ share the handler, the first name wins
+        assert!(!listing.contains("irq:"));
+    }
+
+    #[test]
+    fn test_empty_program() {
+        let mut data: Vec<u8> = vec![0x4e, 0x45, 0x53, 0x1a, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.resize(16 + 0x2000, 0);
+        let rom: Rom = Rom::load(&data, false).unwrap();
+        assert_eq!(Disassembler::for_rom(&rom, Syntax::Ca65).listing(), "; Nothing to disassemble\n");
+        assert_eq!(Disassembler::new(&[], 0xffff, Syntax::Asm6).lines(), vec![]);
+    }
+}
@@ -14,4 +14,34 @@ pub enum Error {
 
     #[error("Rom Error: {0}")]
     RomError(String),
+
+    #[error("Image Error: {0}")]
+    ImageError(String),
+
+    #[error("Input Error: {0}")]
+    InputError(String),
+
+    #[error("Debugger Error: {0}")]
+    DebuggerError(String),
+
+    #[error("Assembler Error: {0}")]
+    AssemblerError(String),
+
+    #[error("Cheat Error: {0}")]
+    CheatError(String),
+
+    #[error("Save State Error: {0}")]
+    StateError(String),
+
+    #[error("Movie Error: {0}")]
+    MovieError(String),
+
+    #[error("Archive Error: {0}")]
+    ArchiveError(String),
+
+    #[error("Audio Error: {0}")]
+    AudioError(String),
+
+    #[error("Config Error: {0}")]
+    ConfigError(String),
 }
\ No newline at end of file
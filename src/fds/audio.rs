@@ -0,0 +1,294 @@
+use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+// The wavetable channel of the RAM adapter: a 64 steps wave of 6 bits samples, a volume envelope,
+// and a frequency modulator driven by its own table and envelope.
+// https://www.nesdev.org/wiki/FDS_audio
+
+pub const WAVE_START: u16 = 0x4040;
+pub const WAVE_END: u16 = 0x407f;
+pub const REGISTERS_START: u16 = 0x4080;
+pub const REGISTERS_END: u16 = 0x408a;
+pub const VOLUME_GAIN_REGISTER: u16 = 0x4090;
+pub const MODULATION_GAIN_REGISTER: u16 = 0x4092;
+
+// Master volume of $4089: 2/2, 2/3, 2/4 and 2/5
+const MASTER_VOLUMES: [f64; 4] = [1.0, 2.0 / 3.0, 0.5, 0.4];
+// Counter changes of the modulation table entries, 4 resets the counter
+const MODULATION_STEPS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+// At full volume, about 2.4 times a pulse channel at full volume in the APU mix
+const MIX_LEVEL: f64 = 0.36;
+
+#[derive(Debug, Clone, Default)]
+struct Envelope {
+    // Bit 7: the gain is set directly, bit 6: it increases, bits 0-5: speed or gain
+    direct: bool,
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    timer: usize,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.direct = value & 0x80 != 0;
+        self.increase = value & 0x40 != 0;
+        self.speed = value & 0x3f;
+        if self.direct {
+            self.gain = self.speed;
+        }
+        self.timer = 0;
+    }
+
+    // Every CPU cycle, one step every 8 * (speed + 1) * master speed cycles
+    fn clock(&mut self, master_speed: u8) {
+        if self.direct {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = 8 * (self.speed as usize + 1) * master_speed as usize;
+        match self.increase {
+            true if self.gain < 32 => self.gain += 1,
+            false if self.gain > 0 => self.gain -= 1,
+            _ => (),
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.direct);
+        writer.bool(self.increase);
+        writer.u8(self.speed);
+        writer.u8(self.gain);
+        writer.usize(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.direct = reader.bool()?;
+        self.increase = reader.bool()?;
+        self.speed = reader.u8()?;
+        self.gain = reader.u8()?;
+        self.timer = reader.usize()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FdsAudio {
+    wave: [u8; 64],
+    // $4089 bit 7: the wave can be written, its output is held meanwhile
+    wave_write: bool,
+    master_volume: u8,
+    // $408A, 0 stops the envelopes
+    master_speed: u8,
+    volume: Envelope,
+    frequency: u16,
+    wave_halted: bool,
+    envelopes_halted: bool,
+    wave_accumulator: u32,
+    wave_position: u8,
+    // The last sample, kept while the wave is being written or halted
+    output: u8,
+    modulation: Envelope,
+    modulation_table: [u8; 32],
+    modulation_position: u8,
+    // 7 bits signed
+    modulation_counter: i8,
+    modulation_frequency: u16,
+    modulation_halted: bool,
+    modulation_accumulator: u32,
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        FdsAudio::new()
+    }
+}
+
+impl FdsAudio {
+    pub fn new() -> FdsAudio {
+        FdsAudio {
+            wave: [0; 64],
+            wave_write: false,
+            master_volume: 0,
+            master_speed: 0xe8,
+            volume: Envelope::default(),
+            frequency: 0,
+            wave_halted: true,
+            envelopes_halted: false,
+            wave_accumulator: 0,
+            wave_position: 0,
+            output: 0,
+            modulation: Envelope::default(),
+            modulation_table: [0; 32],
+            modulation_position: 0,
+            modulation_counter: 0,
+            modulation_frequency: 0,
+            modulation_halted: true,
+            modulation_accumulator: 0,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            WAVE_START..=WAVE_END => self.wave[(addr - WAVE_START) as usize] | 0x40,
+            VOLUME_GAIN_REGISTER => self.volume.gain | 0x40,
+            MODULATION_GAIN_REGISTER => self.modulation.gain | 0x40,
+            _ => 0x40,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            WAVE_START..=WAVE_END if self.wave_write => self.wave[(addr - WAVE_START) as usize] = value & 0x3f,
+            0x4080 => self.volume.write(value),
+            0x4082 => self.frequency = (self.frequency & 0x0f00) | value as u16,
+            0x4083 => {
+                self.frequency = (self.frequency & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.wave_halted = value & 0x80 != 0;
+                self.envelopes_halted = value & 0x40 != 0;
+                if self.wave_halted {
+                    (self.wave_accumulator, self.wave_position) = (0, 0);
+                }
+                if self.envelopes_halted {
+                    (self.volume.timer, self.modulation.timer) = (0, 0);
+                }
+            }
+            0x4084 => self.modulation.write(value),
+            // Sign extension of the 7 bits
+            0x4085 => self.modulation_counter = ((value << 1) as i8) >> 1,
+            0x4086 => self.modulation_frequency = (self.modulation_frequency & 0x0f00) | value as u16,
+            0x4087 => {
+                self.modulation_frequency = (self.modulation_frequency & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.modulation_halted = value & 0x80 != 0;
+                if self.modulation_halted {
+                    self.modulation_accumulator = 0;
+                }
+            }
+            // Each write fills two entries of the table, only while the modulator is halted
+            0x4088 if self.modulation_halted => {
+                for _ in 0..2 {
+                    self.modulation_table[self.modulation_position as usize] = value & 0b111;
+                    self.modulation_position = (self.modulation_position + 1) & 0x1f;
+                }
+            }
+            0x4089 => {
+                self.wave_write = value & 0x80 != 0;
+                self.master_volume = value & 0b11;
+            }
+            0x408a => self.master_speed = value,
+            _ => (),
+        }
+    }
+
+    // https://www.nesdev.org/wiki/FDS_audio#Frequency_calculation
+    fn modulated_frequency(&self) -> u32 {
+        let mut temp: i32 = self.modulation_counter as i32 * self.modulation.gain as i32;
+        let remainder: i32 = temp & 0x0f;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.modulation_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= self.frequency as i32;
+        let remainder: i32 = temp & 0x3f;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (self.frequency as i32 + temp).max(0) as u32
+    }
+
+    // One CPU cycle
+    pub fn tick(&mut self) {
+        if !self.envelopes_halted && !self.wave_halted && self.master_speed > 0 {
+            self.volume.clock(self.master_speed);
+            self.modulation.clock(self.master_speed);
+        }
+
+        if !self.modulation_halted && self.modulation_frequency > 0 {
+            self.modulation_accumulator += self.modulation_frequency as u32;
+            if self.modulation_accumulator >= 0x10000 {
+                self.modulation_accumulator &= 0xffff;
+                let step: u8 = self.modulation_table[self.modulation_position as usize];
+                self.modulation_position = (self.modulation_position + 1) & 0x1f;
+                self.modulation_counter = match step {
+                    4 => 0,
+                    _ => {
+                        // Wraps around within 7 bits
+                        let counter: i8 = self.modulation_counter.wrapping_add(MODULATION_STEPS[step as usize]);
+                        (counter << 1) >> 1
+                    }
+                };
+            }
+        }
+
+        if !self.wave_halted {
+            self.wave_accumulator += self.modulated_frequency();
+            if self.wave_accumulator >= 0x10000 {
+                self.wave_accumulator &= 0xffff;
+                self.wave_position = (self.wave_position + 1) & 0x3f;
+            }
+        }
+        if !self.wave_write {
+            self.output = self.wave[self.wave_position as usize];
+        }
+    }
+
+    // Between 0 and MIX_LEVEL, to be added to the APU mix
+    pub fn output(&self) -> f64 {
+        let gain: f64 = self.volume.gain.min(32) as f64 / 32.0;
+        self.output as f64 / 63.0 * gain * MASTER_VOLUMES[self.master_volume as usize] * MIX_LEVEL
+    }
+}
+
+impl SaveState for FdsAudio {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.wave);
+        writer.bool(self.wave_write);
+        writer.u8(self.master_volume);
+        writer.u8(self.master_speed);
+        self.volume.save_state(writer);
+        writer.u16(self.frequency);
+        writer.bool(self.wave_halted);
+        writer.bool(self.envelopes_halted);
+        writer.u64(self.wave_accumulator as u64);
+        writer.u8(self.wave_position);
+        writer.u8(self.output);
+        self.modulation.save_state(writer);
+        writer.bytes(&self.modulation_table);
+        writer.u8(self.modulation_position);
+        writer.u8(self.modulation_counter as u8);
+        writer.u16(self.modulation_frequency);
+        writer.bool(self.modulation_halted);
+        writer.u64(self.modulation_accumulator as u64);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        reader.bytes(&mut self.wave)?;
+        self.wave_write = reader.bool()?;
+        self.master_volume = reader.u8()? & 0b11;
+        self.master_speed = reader.u8()?;
+        self.volume.load_state(reader)?;
+        self.frequency = reader.u16()?;
+        self.wave_halted = reader.bool()?;
+        self.envelopes_halted = reader.bool()?;
+        self.wave_accumulator = reader.u64()? as u32 & 0xffff;
+        self.wave_position = reader.u8()? & 0x3f;
+        self.output = reader.u8()?;
+        self.modulation.load_state(reader)?;
+        reader.bytes(&mut self.modulation_table)?;
+        self.modulation_position = reader.u8()? & 0x1f;
+        self.modulation_counter = reader.u8()? as i8;
+        self.modulation_frequency = reader.u16()?;
+        self.modulation_halted = reader.bool()?;
+        self.modulation_accumulator = reader.u64()? as u32 & 0xffff;
+        Ok(())
+    }
+}
@@ -0,0 +1,564 @@
+pub mod audio;
+mod test;
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error::{RomError, StateError}, Error};
+use crate::rom::Mirroring;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+use audio::FdsAudio;
+
+// Famicom Disk System: the RAM adapter takes the place of the cartridge. It holds 32kB of PRG-RAM
+// at $6000-$DFFF, the BIOS at $E000-$FFFF, 8kB of CHR-RAM, a timer IRQ, the disk drive registers
+// and a wavetable sound channel. Games are loaded from the disk by the BIOS, a byte at a time.
+//   .fds  an optional 16 bytes header ("FDS\x1a", number of sides) followed by sides of 65500 bytes
+//   side  blocks 1 (disk info), 2 (file count), then 3 (file header) and 4 (file data) per file
+// https://www.nesdev.org/wiki/Family_Computer_Disk_System and https://www.nesdev.org/wiki/FDS_disk_format
+
+const FDS_HEADER_TAG: &[u8; 4] = b"FDS\x1a";
+const FDS_HEADER_SIZE: usize = 16;
+pub const SIDE_SIZE: usize = 65500;
+const DISK_INFO_TAG: &[u8; 15] = b"\x01*NINTENDO-HVC*";
+
+pub const BIOS_SIZE: usize = 0x2000;
+// Looked for next to the disk, then in the current directory
+pub const BIOS_FILE_NAME: &str = "disksys.rom";
+
+pub const FDS_REGISTERS_START: u16 = 0x4020;
+pub const FDS_REGISTERS_END: u16 = 0x4033;
+pub const FDS_RAM_START: u16 = 0x6000;
+pub const FDS_RAM_END: u16 = 0xdfff;
+pub const BIOS_START: u16 = 0xe000;
+
+// The NES 2.0 mapper number of the FDS
+pub const FDS_MAPPER: u8 = 20;
+
+// Gaps of the raw side in bytes, before the first block and after each block
+const LEAD_IN_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+const BLOCK_START_MARK: u8 = 0x80;
+// Room for the gaps of the blocks of a full side
+const RAW_SIDE_SIZE: usize = 0x14000;
+
+// In CPU cycles: from the motor start to the first byte, between two bytes (about 96 kbit/s),
+// and how long the drive stays empty when the side is changed
+const SPIN_UP_CYCLES: usize = 50000;
+const BYTE_CYCLES: usize = 150;
+const EJECT_CYCLES: usize = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FdsImage {
+    pub sides: Vec<Vec<u8>>,
+    // Whether the file had the fwNES header, kept when the disk is written back
+    pub header: bool,
+}
+
+impl FdsImage {
+    pub fn detect(data: &[u8]) -> bool {
+        data.starts_with(FDS_HEADER_TAG) || data.starts_with(DISK_INFO_TAG)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<FdsImage, Error> {
+        let header: bool = data.starts_with(FDS_HEADER_TAG);
+        let data: &[u8] = match header {
+            true => data.get(FDS_HEADER_SIZE..).unwrap_or_default(),
+            false => data,
+        };
+        if data.is_empty() || !data.len().is_multiple_of(SIDE_SIZE) {
+            return Err(RomError(format!("The FDS image has {} bytes, expected a multiple of {}", data.len(), SIDE_SIZE)));
+        }
+        let sides: Vec<Vec<u8>> = data.chunks(SIDE_SIZE).map(|side| side.to_vec()).collect();
+        if let Some(index) = sides.iter().position(|side| !side.starts_with(DISK_INFO_TAG)) {
+            return Err(RomError(format!("The {} of the FDS image has no disk info block", FdsImage::side_name(index))));
+        }
+        Ok(FdsImage { sides, header })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![];
+        if self.header {
+            data.extend_from_slice(FDS_HEADER_TAG);
+            data.push(self.sides.len() as u8);
+            data.resize(FDS_HEADER_SIZE, 0);
+        }
+        for side in self.sides.iter() {
+            data.extend_from_slice(side);
+        }
+        data
+    }
+
+    // "disk 1 side A"
+    pub fn side_name(side: usize) -> String {
+        format!("disk {} side {}", side / 2 + 1, if side.is_multiple_of(2) { 'A' } else { 'B' })
+    }
+}
+
+// game.fds -> game.fds.sav, where the disk goes once the game has written to it
+pub fn save_path(disk_path: &Path) -> PathBuf {
+    let mut name = disk_path.as_os_str().to_owned();
+    name.push(".sav");
+    PathBuf::from(name)
+}
+
+// The BIOS given, or disksys.rom next to the disk or in the current directory
+pub fn bios_path(disk_path: &Path, bios: Option<&Path>) -> Result<PathBuf, Error> {
+    if let Some(bios) = bios {
+        return Ok(bios.to_path_buf());
+    }
+    [disk_path.with_file_name(BIOS_FILE_NAME), PathBuf::from(BIOS_FILE_NAME)].into_iter().find(|path| path.is_file())
+        .ok_or_else(|| RomError(format!("The FDS BIOS is missing, give it with --bios <file> or put {} next to the disk", BIOS_FILE_NAME)))
+}
+
+// The CRC of the blocks: CRC-16 with the polynomial $8408, the start mark included
+fn update_crc(crc: u16, value: u8) -> u16 {
+    let mut crc: u16 = crc;
+    for bit in 0..8 {
+        let carry: bool = crc & 1 != 0;
+        crc >>= 1;
+        if carry {
+            crc ^= 0x8408;
+        }
+        if value & (1 << bit) != 0 {
+            crc ^= 0x8000;
+        }
+    }
+    crc
+}
+
+fn block_crc(block: &[u8]) -> u16 {
+    let crc: u16 = std::iter::once(BLOCK_START_MARK).chain(block.iter().copied()).fold(0, update_crc);
+    update_crc(update_crc(crc, 0), 0)
+}
+
+// Length of the block starting at pos, the size of file data blocks comes from the header before them
+fn block_length(side: &[u8], pos: usize, file_size: usize) -> Option<usize> {
+    match side.get(pos)? {
+        1 => Some(56),
+        2 => Some(2),
+        3 => Some(16),
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+fn file_size(header: &[u8]) -> usize {
+    header.get(13..15).map_or(0, |size| size[0] as usize | (size[1] as usize) << 8)
+}
+
+// The side as the head reads it: a gap, then each block with a start mark, its CRC and a gap
+pub fn to_raw_side(side: &[u8]) -> Vec<u8> {
+    let mut raw: Vec<u8> = vec![0; LEAD_IN_GAP];
+    let (mut pos, mut size): (usize, usize) = (0, 0);
+    while let Some(length) = block_length(side, pos, size) {
+        let block: &[u8] = match side.get(pos..pos + length) {
+            Some(block) => block,
+            None => break,
+        };
+        if block[0] == 3 {
+            size = file_size(block);
+        }
+        raw.push(BLOCK_START_MARK);
+        raw.extend_from_slice(block);
+        raw.extend_from_slice(&block_crc(block).to_le_bytes());
+        raw.resize(raw.len() + BLOCK_GAP, 0);
+        pos += length;
+    }
+    raw.resize(raw.len().max(RAW_SIDE_SIZE), 0);
+    raw
+}
+
+// Back to the 65500 bytes of the .fds format: the blocks without the gaps, marks and CRCs
+pub fn from_raw_side(raw: &[u8]) -> Vec<u8> {
+    let mut side: Vec<u8> = Vec::with_capacity(SIDE_SIZE);
+    let (mut pos, mut size): (usize, usize) = (0, 0);
+    // The start mark is the first byte that is not a gap
+    while let Some(gap) = raw.get(pos..).and_then(|rest| rest.iter().position(|byte| *byte != 0)) {
+        pos += gap + 1;
+        // A stray byte in a gap is skipped
+        let length: usize = match block_length(raw, pos, size) {
+            Some(length) => length,
+            None => continue,
+        };
+        let block: &[u8] = &raw[pos..(pos + length).min(raw.len())];
+        if block[0] == 3 {
+            size = file_size(block);
+        }
+        side.extend_from_slice(block);
+        pos += length + 2;
+    }
+    side.resize(SIDE_SIZE, 0);
+    side
+}
+
+#[derive(Debug)]
+pub struct Fds {
+    bios: Vec<u8>,
+    ram: Vec<u8>,
+    // Header of the image and sides as the head reads them
+    header: bool,
+    raw_sides: Vec<Vec<u8>>,
+    // The side in the drive, and the one to insert once the drive has been empty long enough
+    side: Option<usize>,
+    next_side: Option<(usize, usize)>,
+    // Set when the game writes to the disk
+    pub modified: bool,
+    pub audio: FdsAudio,
+
+    // $4020-$4022
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_enabled: bool,
+    timer_repeat: bool,
+    timer_irq: bool,
+    // $4023
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+    // $4025
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    horizontal_mirroring: bool,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+
+    // Drive
+    position: usize,
+    delay: usize,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    previous_crc_control: bool,
+    crc: u16,
+    read_data: u8,
+    write_data: u8,
+    transfer_complete: bool,
+    disk_irq: bool,
+}
+
+impl Fds {
+    // The first side is in the drive
+    pub fn new(image: &FdsImage, bios: Vec<u8>) -> Result<Fds, Error> {
+        if bios.len() != BIOS_SIZE {
+            return Err(RomError(format!("The FDS BIOS has {} bytes instead of {}", bios.len(), BIOS_SIZE)));
+        }
+        Ok(Fds {
+            bios,
+            ram: vec![0; (FDS_RAM_END - FDS_RAM_START) as usize + 1],
+            header: image.header,
+            raw_sides: image.sides.iter().map(|side| to_raw_side(side)).collect(),
+            side: Some(0),
+            next_side: None,
+            modified: false,
+            audio: FdsAudio::new(),
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_enabled: false,
+            timer_repeat: false,
+            timer_irq: false,
+            disk_registers_enabled: false,
+            sound_registers_enabled: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            horizontal_mirroring: false,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            previous_crc_control: false,
+            crc: 0,
+            read_data: 0,
+            write_data: 0,
+            transfer_complete: false,
+            disk_irq: false,
+        })
+    }
+
+    // The disk with what the game wrote on it
+    pub fn image(&self) -> FdsImage {
+        FdsImage { sides: self.raw_sides.iter().map(|raw| from_raw_side(raw)).collect(), header: self.header }
+    }
+
+    pub fn sides(&self) -> usize {
+        self.raw_sides.len()
+    }
+
+    pub fn side(&self) -> Option<usize> {
+        self.side
+    }
+
+    pub fn eject(&mut self) {
+        (self.side, self.next_side) = (None, None);
+    }
+
+    pub fn insert(&mut self, side: usize) {
+        (self.side, self.next_side) = (Some(side % self.sides()), None);
+    }
+
+    // Ejects the disk and inserts the next side a moment later (the BIOS has to see the drive empty),
+    // returns that side
+    pub fn switch_side(&mut self) -> usize {
+        let side: usize = match (self.side, self.next_side) {
+            (_, Some((side, _))) | (Some(side), None) => (side + 1) % self.sides(),
+            (None, None) => 0,
+        };
+        (self.side, self.next_side) = (None, Some((side, EJECT_CYCLES)));
+        side
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        match self.horizontal_mirroring {
+            true => Mirroring::HORIZONTAL,
+            false => Mirroring::VERTICAL,
+        }
+    }
+
+    // The IRQ line, held until the game acknowledges it
+    pub fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    // $4030-$4033, the sound registers, the RAM and the BIOS. With no_fail, the flags are left alone
+    pub fn read(&mut self, addr: u16, no_fail: bool) -> u8 {
+        match addr {
+            0x4030 => {
+                let value: u8 = self.timer_irq as u8 | (self.transfer_complete as u8) << 1 | (self.end_of_head as u8) << 6;
+                if !no_fail {
+                    (self.timer_irq, self.disk_irq, self.transfer_complete) = (false, false, false);
+                }
+                value
+            }
+            0x4031 => {
+                if !no_fail {
+                    (self.disk_irq, self.transfer_complete) = (false, false);
+                }
+                self.read_data
+            }
+            0x4032 => {
+                let inserted: bool = self.side.is_some();
+                0x40 | !inserted as u8 | ((!inserted || !self.scanning) as u8) << 1 | (!inserted as u8) << 2
+            }
+            // Battery good
+            0x4033 => 0x80,
+            audio::WAVE_START..=audio::MODULATION_GAIN_REGISTER => self.audio.read(addr),
+            FDS_RAM_START..=FDS_RAM_END => self.ram[(addr - FDS_RAM_START) as usize],
+            BIOS_START..=0xffff => self.bios[(addr - BIOS_START) as usize],
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4020 => self.timer_reload = (self.timer_reload & 0xff00) | value as u16,
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00ff) | (value as u16) << 8,
+            0x4022 => {
+                self.timer_repeat = value & 0x01 != 0;
+                self.timer_enabled = value & 0x02 != 0 && self.disk_registers_enabled;
+                self.timer_counter = self.timer_reload;
+                self.timer_irq = false;
+            }
+            0x4023 => {
+                self.disk_registers_enabled = value & 0x01 != 0;
+                self.sound_registers_enabled = value & 0x02 != 0;
+                if !self.disk_registers_enabled {
+                    (self.timer_enabled, self.timer_irq, self.disk_irq) = (false, false, false);
+                }
+            }
+            0x4024 => {
+                self.write_data = value;
+                (self.disk_irq, self.transfer_complete) = (false, false);
+            }
+            0x4025 => {
+                self.motor_on = value & 0x01 != 0;
+                self.reset_transfer = value & 0x02 != 0;
+                self.read_mode = value & 0x04 != 0;
+                self.horizontal_mirroring = value & 0x08 != 0;
+                self.crc_control = value & 0x10 != 0;
+                self.disk_ready = value & 0x40 != 0;
+                self.disk_irq_enabled = value & 0x80 != 0;
+                self.disk_irq = false;
+            }
+            audio::WAVE_START..=audio::REGISTERS_END if self.sound_registers_enabled => self.audio.write(addr, value),
+            FDS_RAM_START..=FDS_RAM_END => self.ram[(addr - FDS_RAM_START) as usize] = value,
+            _ => (),
+        }
+    }
+
+    // Side-effect-free write, used by debuggers to patch memory (even the BIOS)
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        match addr {
+            FDS_RAM_START..=FDS_RAM_END => self.ram[(addr - FDS_RAM_START) as usize] = value,
+            BIOS_START..=0xffff => self.bios[(addr - BIOS_START) as usize] = value,
+            _ => (),
+        }
+    }
+
+    // One CPU cycle
+    pub fn tick(&mut self) {
+        if self.timer_enabled {
+            if self.timer_counter == 0 {
+                self.timer_irq = true;
+                self.timer_counter = self.timer_reload;
+                self.timer_enabled = self.timer_repeat;
+            } else {
+                self.timer_counter -= 1;
+            }
+        }
+        self.audio.tick();
+        if let Some((side, cycles)) = self.next_side {
+            self.next_side = match cycles {
+                0 => {
+                    self.side = Some(side);
+                    None
+                }
+                _ => Some((side, cycles - 1)),
+            };
+        }
+        self.tick_drive();
+    }
+
+    // The head moves over the side while the motor runs, a byte every BYTE_CYCLES
+    fn tick_drive(&mut self) {
+        let side: usize = match self.side {
+            Some(side) if self.motor_on => side,
+            _ => {
+                (self.end_of_head, self.scanning) = (true, false);
+                return;
+            }
+        };
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            (self.delay, self.end_of_head, self.position, self.gap_ended) = (SPIN_UP_CYCLES, false, 0, false);
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let raw: &mut Vec<u8> = &mut self.raw_sides[side];
+        if self.read_mode {
+            let value: u8 = raw[self.position];
+            // The game waits for the start mark at the end of a gap, which raises no IRQ
+            let mut irq: bool = self.disk_irq_enabled;
+            if !self.disk_ready {
+                self.gap_ended = false;
+            } else if value != 0 && !self.gap_ended {
+                self.gap_ended = true;
+                irq = false;
+            }
+            if self.gap_ended {
+                (self.transfer_complete, self.read_data) = (true, value);
+                self.disk_irq |= irq;
+            }
+        } else {
+            let mut value: u8 = self.write_data;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                self.disk_irq |= self.disk_irq_enabled;
+            }
+            if !self.disk_ready {
+                (value, self.crc) = (0, 0);
+            }
+            // The CRC of what was written follows the block, low byte first
+            if !self.crc_control {
+                self.crc = update_crc(self.crc, value);
+            } else {
+                if !self.previous_crc_control {
+                    self.crc = update_crc(update_crc(self.crc, 0), 0);
+                }
+                value = self.crc as u8;
+                self.crc >>= 8;
+            }
+            if raw[self.position] != value {
+                raw[self.position] = value;
+                self.modified = true;
+            }
+            self.gap_ended = false;
+        }
+        self.previous_crc_control = self.crc_control;
+
+        self.position += 1;
+        match self.position >= raw.len() {
+            true => {
+                (self.motor_on, self.end_of_head) = (false, true);
+                self.disk_irq |= self.disk_irq_enabled;
+            }
+            false => self.delay = BYTE_CYCLES,
+        }
+    }
+}
+
+// The BIOS is not saved, the sides are since the game may write to them
+impl SaveState for Fds {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        for raw in self.raw_sides.iter() {
+            writer.vec(raw);
+        }
+        writer.u8(self.side.map_or(0xff, |side| side as u8));
+        writer.u8(self.next_side.map_or(0xff, |(side, _)| side as u8));
+        writer.usize(self.next_side.map_or(0, |(_, cycles)| cycles));
+        writer.bool(self.modified);
+        self.audio.save_state(writer);
+        writer.u16(self.timer_reload);
+        writer.u16(self.timer_counter);
+        for flag in [self.timer_enabled, self.timer_repeat, self.timer_irq, self.disk_registers_enabled, self.sound_registers_enabled,
+            self.motor_on, self.reset_transfer, self.read_mode, self.horizontal_mirroring, self.crc_control, self.disk_ready,
+            self.disk_irq_enabled, self.end_of_head, self.scanning, self.gap_ended, self.previous_crc_control,
+            self.transfer_complete, self.disk_irq] {
+            writer.bool(flag);
+        }
+        writer.usize(self.position);
+        writer.usize(self.delay);
+        writer.u16(self.crc);
+        writer.u8(self.read_data);
+        writer.u8(self.write_data);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        reader.bytes(&mut self.ram)?;
+        for raw in self.raw_sides.iter_mut() {
+            *raw = reader.vec()?;
+        }
+        let side = |value: u8| (value != 0xff).then_some(value as usize);
+        self.side = side(reader.u8()?);
+        let next_side: Option<usize> = side(reader.u8()?);
+        let cycles: usize = reader.usize()?;
+        self.next_side = next_side.map(|side| (side, cycles));
+        self.modified = reader.bool()?;
+        self.audio.load_state(reader)?;
+        self.timer_reload = reader.u16()?;
+        self.timer_counter = reader.u16()?;
+        for flag in [&mut self.timer_enabled, &mut self.timer_repeat, &mut self.timer_irq, &mut self.disk_registers_enabled,
+            &mut self.sound_registers_enabled, &mut self.motor_on, &mut self.reset_transfer, &mut self.read_mode,
+            &mut self.horizontal_mirroring, &mut self.crc_control, &mut self.disk_ready, &mut self.disk_irq_enabled,
+            &mut self.end_of_head, &mut self.scanning, &mut self.gap_ended, &mut self.previous_crc_control,
+            &mut self.transfer_complete, &mut self.disk_irq] {
+            *flag = reader.bool()?;
+        }
+        self.position = reader.usize()?;
+        self.delay = reader.usize()?;
+        self.crc = reader.u16()?;
+        self.read_data = reader.u8()?;
+        self.write_data = reader.u8()?;
+        // The head reads within the side inserted (or about to be), unless it goes back to its start
+        let head_side: Option<usize> = self.side.or(self.next_side.map(|(side, _)| side));
+        let valid: bool = self.raw_sides.iter().all(|raw| !raw.is_empty())
+            && [self.side, self.next_side.map(|(side, _)| side)].into_iter().flatten().all(|side| side < self.sides())
+            && (self.end_of_head || head_side.is_none_or(|side| self.position < self.raw_sides[side].len()));
+        if !valid {
+            return Err(StateError(String::from("The save state does not match the disk")));
+        }
+        Ok(())
+    }
+}
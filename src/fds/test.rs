@@ -0,0 +1,269 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::apu::{Apu, DEFAULT_SAMPLE_RATE};
+    use crate::asm::{assemble_at, Assembly};
+    use crate::bus::Bus;
+    use crate::cpu::{Interrupt, CPU};
+    use crate::debugger::{Debugger, StopReason};
+    use crate::input::Joypad;
+    use crate::mem::Mem;
+    use crate::ppu::PPU;
+    use crate::rom::{Mirroring, Rom};
+    use crate::savestate::{SaveState, StateReader, StateWriter};
+    use crate::screen::Screen;
+
+    use super::super::*;
+
+    fn no_display(_: &PPU, _: &mut Screen) {}
+
+    // Disk info, file count, then one file of 3 bytes
+    fn side(name: &[u8; 4]) -> Vec<u8> {
+        let mut side: Vec<u8> = DISK_INFO_TAG.to_vec();
+        side.extend_from_slice(name);
+        side.resize(56, 0);
+        side.extend_from_slice(&[2, 1]);
+        let mut header: Vec<u8> = vec![3, 0, 0];
+        header.extend_from_slice(b"FILE0000");
+        header.extend_from_slice(&[0x00, 0x60, 3, 0, 0]);
+        side.extend_from_slice(&header);
+        side.extend_from_slice(&[4, 0xaa, 0xbb, 0xcc]);
+        side.resize(SIDE_SIZE, 0);
+        side
+    }
+
+    fn image() -> FdsImage {
+        FdsImage { sides: vec![side(b"GAMA"), side(b"GAMB")], header: true }
+    }
+
+    fn peek(fds: &mut Fds, addr: u16) -> u8 {
+        fds.read(addr, true)
+    }
+
+    #[test]
+    fn test_image() {
+        let data: Vec<u8> = image().to_bytes();
+        assert_eq!((data.len(), &data[..5]), (16 + 2 * SIDE_SIZE, &b"FDS\x1a\x02"[..]));
+        assert!(FdsImage::detect(&data) && FdsImage::detect(&data[16..]));
+        assert_eq!(FdsImage::parse(&data).unwrap(), image());
+        assert!(!FdsImage::parse(&data[16..]).unwrap().header);
+
+        assert!(FdsImage::parse(&data[..100]).unwrap_err().to_string().contains("expected a multiple of 65500"));
+        let mut data: Vec<u8> = data;
+        data[16 + SIDE_SIZE] = 0;
+        assert!(FdsImage::parse(&data).unwrap_err().to_string().contains("disk 1 side B of the FDS image has no disk info"));
+        assert_eq!(FdsImage::side_name(2), "disk 2 side A");
+        assert_eq!(save_path(Path::new("dir/zelda.fds")), PathBuf::from("dir/zelda.fds.sav"));
+    }
+
+    #[test]
+    fn test_raw_side() {
+        let side: Vec<u8> = side(b"GAME");
+        let raw: Vec<u8> = to_raw_side(&side);
+        assert_eq!(raw.len(), RAW_SIDE_SIZE);
+        assert!(raw[..LEAD_IN_GAP].iter().all(|byte| *byte == 0));
+        assert_eq!(&raw[LEAD_IN_GAP..LEAD_IN_GAP + 3], &[BLOCK_START_MARK, 0x01, b'*']);
+        // The CRC of a block followed by its CRC is 0
+        let block: &[u8] = &raw[LEAD_IN_GAP..LEAD_IN_GAP + 1 + 56 + 2];
+        assert_eq!(block[1..].iter().copied().chain([0, 0]).fold(update_crc(0, block[0]), update_crc), 0);
+        assert_eq!(from_raw_side(&raw), side);
+    }
+
+    #[test]
+    fn test_read_disk() {
+        let mut fds: Fds = Fds::new(&image(), vec![0; BIOS_SIZE]).unwrap();
+        assert!(Fds::new(&image(), vec![0; 16]).unwrap_err().to_string().contains("16 bytes instead of 8192"));
+        fds.write(0x4023, 0x01);
+        // Motor on, read mode, horizontal mirroring, ready for the data
+        fds.write(0x4025, 0b0100_1101);
+        assert_eq!(fds.mirroring(), Mirroring::HORIZONTAL);
+        let mut bytes: Vec<u8> = vec![];
+        while bytes.len() < 4 {
+            fds.tick();
+            if peek(&mut fds, 0x4030) & 0x02 != 0 {
+                bytes.push(fds.read(0x4031, false));
+            }
+        }
+        assert_eq!(bytes, [BLOCK_START_MARK, 0x01, b'*', b'N']);
+        assert_eq!(peek(&mut fds, 0x4032) & 0b111, 0);
+        assert!(!fds.modified);
+    }
+
+    // Waits for the start mark of the next block, then reads that many bytes as the BIOS does
+    fn read_block(fds: &mut Fds, count: usize) -> Vec<u8> {
+        fds.write(0x4025, 0b0000_1101);
+        for _ in 0..=BYTE_CYCLES {
+            fds.tick();
+        }
+        fds.write(0x4025, 0b0100_1101);
+        let mut bytes: Vec<u8> = vec![];
+        while bytes.len() < count {
+            fds.tick();
+            if peek(fds, 0x4030) & 0x02 != 0 {
+                bytes.push(fds.read(0x4031, false));
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_write_disk() {
+        let mut fds: Fds = Fds::new(&image(), vec![0; BIOS_SIZE]).unwrap();
+        fds.write(0x4023, 0x01);
+        assert_eq!(read_block(&mut fds, 1 + 56 + 2)[..3], [BLOCK_START_MARK, 0x01, b'*']);
+        assert_eq!(read_block(&mut fds, 1 + 2 + 2)[..3], [BLOCK_START_MARK, 0x02, 0x01]);
+        assert_eq!(read_block(&mut fds, 1 + 16 + 2)[..2], [BLOCK_START_MARK, 0x03]);
+        assert_eq!(read_block(&mut fds, 3), [BLOCK_START_MARK, 0x04, 0xaa]);
+        // Writes over the second byte of the file
+        fds.write(0x4025, 0b0100_1001);
+        fds.write(0x4024, 0x11);
+        while peek(&mut fds, 0x4030) & 0x02 == 0 {
+            fds.tick();
+        }
+        assert!(fds.modified);
+        let side: &[u8] = &fds.image().sides[0];
+        assert_eq!(&side[56 + 2 + 16..56 + 2 + 16 + 4], &[4, 0xaa, 0x11, 0xcc]);
+        assert_eq!(fds.image().sides[1], image().sides[1]);
+    }
+
+    #[test]
+    fn test_switch_side() {
+        let mut fds: Fds = Fds::new(&image(), vec![0; BIOS_SIZE]).unwrap();
+        assert_eq!((fds.side(), fds.switch_side()), (Some(0), 1));
+        assert_eq!((fds.side(), peek(&mut fds, 0x4032) & 0b111), (None, 0b111));
+        for _ in 0..=EJECT_CYCLES {
+            fds.tick();
+        }
+        assert_eq!(fds.side(), Some(1));
+        assert_eq!(fds.switch_side(), 0);
+        fds.insert(3);
+        assert_eq!(fds.side(), Some(1));
+    }
+
+    #[test]
+    fn test_state() {
+        let inserted = || {
+            let mut fds: Fds = Fds::new(&image(), vec![0; BIOS_SIZE]).unwrap();
+            fds.insert(1);
+            (fds.position, fds.end_of_head) = (100, false);
+            fds
+        };
+        let state = |fds: &Fds| {
+            let mut writer: StateWriter = StateWriter::new();
+            fds.save_state(&mut writer);
+            writer.finish()
+        };
+        let mut loaded: Fds = Fds::new(&image(), vec![0; BIOS_SIZE]).unwrap();
+        loaded.load_state(&mut StateReader::new(&state(&inserted()))).unwrap();
+        assert_eq!((loaded.side(), loaded.position), (Some(1), 100));
+
+        // The head past the end of its side, a side the disk doesn't have, an empty side
+        let corruptions: [fn(&mut Fds); 4] = [
+            |fds| fds.position = fds.raw_sides[1].len(),
+            |fds| fds.side = Some(2),
+            |fds| (fds.side, fds.next_side) = (None, Some((2, 10))),
+            |fds| fds.raw_sides[0].clear(),
+        ];
+        for corrupt in corruptions {
+            let mut corrupted: Fds = inserted();
+            corrupt(&mut corrupted);
+            assert!(loaded.load_state(&mut StateReader::new(&state(&corrupted))).unwrap_err().to_string().contains("does not match the disk"));
+        }
+    }
+
+    #[test]
+    fn test_audio() {
+        let mut fds: Fds = Fds::new(&image(), vec![0; BIOS_SIZE]).unwrap();
+        fds.write(0x4089, 0x80);
+        fds.write(0x4040, 0x3f);
+        // The sound registers are disabled
+        assert_eq!(peek(&mut fds, 0x4040), 0x40);
+        fds.write(0x4023, 0x03);
+        fds.write(0x4089, 0x80);
+        for addr in 0x4040..0x4060 {
+            fds.write(addr, 0x3f);
+        }
+        fds.write(0x4089, 0x00);
+        fds.write(0x4080, 0x80 | 32);
+        fds.write(0x4082, 0x00);
+        fds.write(0x4083, 0x04);
+        assert_eq!(peek(&mut fds, 0x4090), 0x40 | 32);
+        let mut levels: Vec<f64> = vec![];
+        for _ in 0..20000 {
+            fds.tick();
+            levels.push(fds.audio.output());
+        }
+        // A square wave: half high, half low
+        let high: usize = levels.iter().filter(|level| **level > 0.0).count();
+        assert!((9000..11000).contains(&high), "{} high", high);
+
+        // Heard through the APU of the bus, the sound of a frame is dropped when running headless
+        let mut bus: Bus = Bus::new_headless(Rom::new_from_program_rom(vec![]).unwrap(), no_display, Joypad::new(), Joypad::new());
+        bus.apu = Some(Apu::new(DEFAULT_SAMPLE_RATE));
+        bus.attach_fds(fds);
+        bus.tick(20000);
+        assert!(bus.apu.as_mut().unwrap().take_samples().iter().any(|sample| *sample != 0));
+        while bus.frames() == 0 {
+            bus.tick(1);
+        }
+        assert!(bus.apu.as_ref().unwrap().samples.is_empty());
+    }
+
+    #[test]
+    fn test_timer_irq() {
+        let source: &str = "
+            reset:  LDA #$01
+                    STA $4023
+                    LDA #$10
+                    STA $4020
+                    LDA #$00
+                    STA $4021
+                    LDA #$03
+                    STA $4022
+                    CLI
+            loop:   JMP loop
+            irq:    INC $00
+                    LDA $4030
+                    RTI
+        ";
+        let assembly: Assembly = assemble_at(source, BIOS_START).unwrap();
+        let mut bios: Vec<u8> = assembly.bytes.clone();
+        bios.resize(BIOS_SIZE, 0);
+        bios[0x1ffc..].copy_from_slice(&[BIOS_START as u8, (BIOS_START >> 8) as u8, assembly.labels["irq"] as u8, (assembly.labels["irq"] >> 8) as u8]);
+
+        let mut bus: Bus = Bus::new_headless(Rom::new_from_program_rom(vec![]).unwrap(), no_display, Joypad::new(), Joypad::new());
+        bus.attach_fds(Fds::new(&image(), bios).unwrap());
+        let mut cpu: CPU = CPU::new(bus);
+        cpu.reset();
+        assert_eq!(cpu.reg_pc, BIOS_START);
+        while cpu.bus.cycles() < 1000 {
+            cpu.step();
+        }
+        // An IRQ every 17 cycles, the handler takes about as long
+        let count: u8 = cpu.mem_read_u8(0x00);
+        assert!((20..=60).contains(&count), "{} IRQs", count);
+
+        // Taken as IRQs, running to the NMI doesn't stop on them
+        while cpu.poll_interrupts() != Some(Interrupt::Irq) {
+            cpu.execute_instruction();
+        }
+        assert_eq!(cpu.reg_pc, assembly.labels["irq"]);
+        let mut debugger: Debugger = Debugger::new();
+        debugger.max_instructions = 200;
+        assert_eq!(debugger.run_to_nmi(&mut cpu), StopReason::InstructionLimit);
+        assert!(cpu.mem_read_u8(0x00) > count);
+
+        // The RAM adapter memory and the CHR-RAM
+        cpu.mem_write_u8(0x6000, 0x12);
+        cpu.mem_write_u8(0xdfff, 0x34);
+        assert_eq!((cpu.mem_read_u8(0x6000), cpu.mem_read_u8(0xdfff)), (0x12, 0x34));
+        cpu.mem_write_u8(0x2006, 0x00);
+        cpu.mem_write_u8(0x2006, 0x10);
+        cpu.mem_write_u8(0x2007, 0x56);
+        assert_eq!(cpu.bus.ppu.chr_rom[0x10], 0x56);
+    }
+}
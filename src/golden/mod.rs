@@ -0,0 +1,257 @@
+mod test;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::error::{Error::InputError, Error};
+use crate::input::{Joypad, JoypadButton};
+use crate::ppu::PPU;
+use crate::rom::Rom;
+use crate::screen::frame::Frame;
+use crate::screen::render::Renderer;
+use crate::screen::Screen;
+
+pub const GOLDEN_DIR_VAR: &str = "NES_GOLDEN_DIR";
+pub const GOLDEN_BLESS_VAR: &str = "NES_GOLDEN_BLESS";
+pub const DEFAULT_GOLDEN_DIR: &str = "rom_examples/golden";
+
+const DEFAULT_FRAMES: usize = 60;
+
+// ==================================================================================
+// ================================ Input scripts ===================================
+// ==================================================================================
+
+// Buttons held on both pads, starting at a given frame and until the next event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputEvent {
+    pub frame: usize,
+    pub joypad1: JoypadButton,
+    pub joypad2: JoypadButton,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputScript {
+    events: Vec<InputEvent>,
+}
+
+impl InputScript {
+    pub fn new() -> Self {
+        InputScript { events: vec![] }
+    }
+
+    pub fn press(mut self, frame: usize, joypad1: JoypadButton, joypad2: JoypadButton) -> Self {
+        self.events.push(InputEvent { frame, joypad1, joypad2 });
+        self.events.sort_by_key(|e| e.frame);
+        self
+    }
+
+    // State of both pads during the given frame
+    pub fn state_at(&self, frame: usize) -> (JoypadButton, JoypadButton) {
+        self.events.iter()
+            .take_while(|e| e.frame <= frame)
+            .last()
+            .map(|e| (e.joypad1, e.joypad2))
+            .unwrap_or((JoypadButton::empty(), JoypadButton::empty()))
+    }
+
+    // Buttons are written like "START+A", "." meaning nothing pressed
+    pub fn parse_buttons(text: &str) -> Result<JoypadButton, Error> {
+        let mut buttons: JoypadButton = JoypadButton::empty();
+        if text == "." {
+            return Ok(buttons);
+        }
+        for name in text.split('+') {
+            buttons |= match name.trim().to_ascii_uppercase().as_str() {
+                "RIGHT" => JoypadButton::RIGHT,
+                "LEFT" => JoypadButton::LEFT,
+                "DOWN" => JoypadButton::DOWN,
+                "UP" => JoypadButton::UP,
+                "START" => JoypadButton::START,
+                "SELECT" => JoypadButton::SELECT,
+                "B" => JoypadButton::BUTTON_B,
+                "A" => JoypadButton::BUTTON_A,
+                other => return Err(InputError(format!("Unknown joypad button {}", other))),
+            };
+        }
+        Ok(buttons)
+    }
+}
+
+// ==================================================================================
+// ================================ Headless runs ===================================
+// ==================================================================================
+
+// Runs the rom without any window for the given number of frames and returns the last frame
+pub fn run_headless(rom: Rom, frames: usize, script: &InputScript) -> Frame {
+    let bus: Bus = Bus::new_headless(rom, |ppu: &PPU, screen: &mut Screen| {
+        Renderer::render(ppu, &mut screen.frame);
+    }, Joypad::new(), Joypad::new());
+
+    let mut cpu: CPU = CPU::new(bus);
+    cpu.reset();
+
+    for frame in 0..frames {
+        let (joypad1, joypad2): (JoypadButton, JoypadButton) = script.state_at(frame);
+        cpu.bus.screen.joypad1.set_button_pressed_status(JoypadButton::all(), false);
+        cpu.bus.screen.joypad1.set_button_pressed_status(joypad1, true);
+        cpu.bus.screen.joypad2.set_button_pressed_status(JoypadButton::all(), false);
+        cpu.bus.screen.joypad2.set_button_pressed_status(joypad2, true);
+
+        cpu.run_frame();
+        if !cpu.running {
+            break;
+        }
+    }
+    cpu.bus.screen.frame.clone()
+}
+
+// ==================================================================================
+// ================================ Comparisons =====================================
+// ==================================================================================
+
+#[derive(Debug, PartialEq)]
+pub enum GoldenResult {
+    Match,
+    Mismatch { differing_pixels: usize },
+    MissingReference,
+}
+
+// Builds an image where identical pixels are dimmed and differing ones are bright red
+pub fn diff_frames(actual: &Frame, expected: &Frame) -> (usize, Frame) {
+    let mut diff: Frame = Frame::new();
+    let mut differing_pixels: usize = 0;
+    for y in 0..Frame::HEIGHT {
+        for x in 0..Frame::WIDTH {
+            let a: (u8, u8, u8) = actual.get_pixel(x, y);
+            let e: (u8, u8, u8) = expected.get_pixel(x, y);
+            if a != e {
+                differing_pixels += 1;
+                diff.set_pixel(x, y, (0xff, 0x00, 0x00));
+            } else {
+                diff.set_pixel(x, y, (a.0 / 4, a.1 / 4, a.2 / 4));
+            }
+        }
+    }
+    (differing_pixels, diff)
+}
+
+// Compares against the reference png and writes a diff image next to it on mismatch
+pub fn compare_with_reference(actual: &Frame, reference: &Path, diff_path: &Path) -> Result<GoldenResult, Error> {
+    if !reference.exists() {
+        return Ok(GoldenResult::MissingReference);
+    }
+    let expected: Frame = Frame::load_png(reference)?;
+    if expected.hash() == actual.hash() && expected.data == actual.data {
+        return Ok(GoldenResult::Match);
+    }
+    let (differing_pixels, diff): (usize, Frame) = diff_frames(actual, &expected);
+    diff.save_png(diff_path)?;
+    Ok(GoldenResult::Mismatch { differing_pixels })
+}
+
+// ==================================================================================
+// ================================ Test cases ======================================
+// ==================================================================================
+
+// A rom of the golden directory, along with its optional <name>.golden script:
+//
+//     frames 120
+//     input 30 START
+//     input 32 .
+//     input 90 RIGHT+A LEFT
+pub struct GoldenCase {
+    pub name: String,
+    pub rom_path: PathBuf,
+    pub frames: usize,
+    pub script: InputScript,
+}
+
+impl GoldenCase {
+    pub fn load(rom_path: &Path) -> Result<Self, Error> {
+        let name: String = rom_path.file_stem().and_then(|s| s.to_str()).unwrap_or("rom").to_string();
+        let mut case: GoldenCase = GoldenCase { name, rom_path: rom_path.to_path_buf(), frames: DEFAULT_FRAMES, script: InputScript::new() };
+
+        let script_path: PathBuf = rom_path.with_extension("golden");
+        if !script_path.exists() {
+            return Ok(case);
+        }
+        for (number, line) in fs::read_to_string(&script_path)?.lines().enumerate() {
+            let words: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+            let bad_line = || InputError(format!("{}:{}: cannot parse \"{}\"", script_path.display(), number + 1, line));
+            match words.as_slice() {
+                [] => (),
+                ["frames", frames] => case.frames = frames.parse().map_err(|_| bad_line())?,
+                ["input", frame, joypad1] | ["input", frame, joypad1, _] => {
+                    let frame: usize = frame.parse().map_err(|_| bad_line())?;
+                    let joypad2: JoypadButton = match words.get(3) {
+                        Some(text) => InputScript::parse_buttons(text)?,
+                        None => JoypadButton::empty(),
+                    };
+                    case.script = case.script.press(frame, InputScript::parse_buttons(joypad1)?, joypad2);
+                }
+                _ => return Err(bad_line()),
+            }
+        }
+        Ok(case)
+    }
+
+    pub fn reference_path(&self) -> PathBuf {
+        self.rom_path.with_extension("png")
+    }
+
+    pub fn diff_path(&self) -> PathBuf {
+        self.rom_path.with_extension("diff.png")
+    }
+
+    pub fn actual_path(&self) -> PathBuf {
+        self.rom_path.with_extension("actual.png")
+    }
+
+    // Runs the case and checks it, when blessing a missing or different reference is (re)written
+    pub fn check(&self, bless: bool) -> Result<GoldenResult, Error> {
+        let data: Vec<u8> = fs::read(&self.rom_path)?;
+        let rom: Rom = Rom::new(&data)?;
+        let frame: Frame = run_headless(rom, self.frames, &self.script);
+
+        let result: GoldenResult = compare_with_reference(&frame, &self.reference_path(), &self.diff_path())?;
+        match result {
+            GoldenResult::Match => self.remove_failure_images()?,
+            _ if bless => {
+                frame.save_png(&self.reference_path())?;
+                self.remove_failure_images()?;
+            }
+            _ => frame.save_png(&self.actual_path())?,
+        }
+        Ok(result)
+    }
+
+    // The diff and actual images of a previous failure, stale once the case passes or is blessed
+    fn remove_failure_images(&self) -> Result<(), Error> {
+        for path in [self.diff_path(), self.actual_path()] {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+// Every .nes file of the directory, an empty list when the directory does not exist
+pub fn discover(dir: &Path) -> Result<Vec<GoldenCase>, Error> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut roms: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("nes")))
+        .collect();
+    roms.sort();
+    roms.iter().map(|path| GoldenCase::load(path)).collect()
+}
+
+pub fn golden_dir() -> PathBuf {
+    std::env::var(GOLDEN_DIR_VAR).map(PathBuf::from).unwrap_or_else(|_| PathBuf::from(DEFAULT_GOLDEN_DIR))
+}
@@ -0,0 +1,98 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::input::JoypadButton;
+    use crate::rom::Rom;
+    use crate::screen::frame::Frame;
+
+    use super::super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nes_emul_golden_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_input_script() {
+        let script: InputScript = InputScript::new()
+            .press(30, JoypadButton::START, JoypadButton::empty())
+            .press(10, JoypadButton::BUTTON_A, JoypadButton::LEFT)
+            .press(32, JoypadButton::empty(), JoypadButton::empty());
+        assert_eq!(script.state_at(0), (JoypadButton::empty(), JoypadButton::empty()));
+        assert_eq!(script.state_at(12), (JoypadButton::BUTTON_A, JoypadButton::LEFT));
+        assert_eq!(script.state_at(31), (JoypadButton::START, JoypadButton::empty()));
+        assert_eq!(script.state_at(100), (JoypadButton::empty(), JoypadButton::empty()));
+
+        assert_eq!(InputScript::parse_buttons("right+a").unwrap(), JoypadButton::RIGHT | JoypadButton::BUTTON_A);
+        assert_eq!(InputScript::parse_buttons(".").unwrap(), JoypadButton::empty());
+        assert!(InputScript::parse_buttons("TURBO").is_err());
+    }
+
+    #[test]
+    fn test_png_round_trip_and_diff() {
+        let mut frame: Frame = Frame::new();
+        frame.set_pixel(10, 20, (0x12, 0x34, 0x56));
+        let reference: PathBuf = temp_path("reference.png");
+        let diff: PathBuf = temp_path("diff.png");
+        frame.save_png(&reference).unwrap();
+
+        assert_eq!(Frame::load_png(&reference).unwrap().data, frame.data);
+        assert_eq!(compare_with_reference(&frame, &reference, &diff).unwrap(), GoldenResult::Match);
+        assert!(!diff.exists());
+
+        let mut other: Frame = frame.clone();
+        other.set_pixel(0, 0, (0xff, 0xff, 0xff));
+        other.set_pixel(255, 239, (0xff, 0xff, 0xff));
+        assert_ne!(other.hash(), frame.hash());
+        assert_eq!(compare_with_reference(&other, &reference, &diff).unwrap(), GoldenResult::Mismatch { differing_pixels: 2 });
+        assert_eq!(Frame::load_png(&diff).unwrap().get_pixel(0, 0), (0xff, 0x00, 0x00));
+
+        assert_eq!(compare_with_reference(&frame, &temp_path("missing.png"), &diff).unwrap(), GoldenResult::MissingReference);
+
+        std::fs::remove_file(reference).unwrap();
+        std::fs::remove_file(diff).unwrap();
+    }
+
+    #[test]
+    fn test_run_headless() {
+        // Infinite loop, the run must stop after the requested frames
+        let rom: Rom = Rom::new_from_program_rom(vec![0x4c, 0x00, 0x80]).unwrap();
+        let frame: Frame = run_headless(rom, 3, &InputScript::new());
+        assert_eq!(frame.hash(), Frame::new().hash());
+    }
+
+    #[test]
+    fn test_stale_failure_images() {
+        // iNES file of an infinite loop, without CHR
+        let mut data: Vec<u8> = vec![0x4e, 0x45, 0x53, 0x1a, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut program: Vec<u8> = vec![0xea; 0x8000];
+        program[..3].copy_from_slice(&[0x4c, 0x00, 0x80]);
+        program[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        data.extend(program);
+        let rom_path: PathBuf = temp_path("loop.nes");
+        std::fs::write(&rom_path, data).unwrap();
+        let mut case: GoldenCase = GoldenCase::load(&rom_path).unwrap();
+        case.frames = 2;
+
+        let mut reference: Frame = Frame::new();
+        reference.set_pixel(0, 0, (0xff, 0xff, 0xff));
+        reference.save_png(&case.reference_path()).unwrap();
+        assert_eq!(case.check(false).unwrap(), GoldenResult::Mismatch { differing_pixels: 1 });
+        assert!(case.diff_path().exists() && case.actual_path().exists());
+
+        // Blessing, then passing, leave no failure images behind
+        assert_eq!(case.check(true).unwrap(), GoldenResult::Mismatch { differing_pixels: 1 });
+        assert!(!case.diff_path().exists() && !case.actual_path().exists());
+        std::fs::write(case.diff_path(), b"stale").unwrap();
+        assert_eq!(case.check(false).unwrap(), GoldenResult::Match);
+        assert!(!case.diff_path().exists());
+
+        std::fs::remove_file(case.reference_path()).unwrap();
+        std::fs::remove_file(rom_path).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use std::any::Any;
+
+use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use crate::screen::frame::Frame;
+
+use super::{InputContext, InputDevice, MouseInput};
+
+// The Vaus paddle of Arkanoid: a knob read as an 8 bits value latched by the strobe and shifted out
+// inverted, most significant bit first, and a fire button
+//   NES      port 2, bit 4 of $4017 is the knob and bit 3 the button (1 when pressed)
+//   Famicom  expansion port, bit 1 of $4017 is the knob and bit 1 of $4016 the button
+// https://www.nesdev.org/wiki/Arkanoid_controller
+
+// Values of the knob turned all the way left and right
+pub const KNOB_MIN: u8 = 0x54;
+pub const KNOB_MAX: u8 = 0xf4;
+const REPORT_BITS: u8 = 8;
+
+#[derive(Debug, Clone)]
+pub struct Arkanoid {
+    famicom: bool,
+    knob: u8,
+    button: bool,
+    strobe: bool,
+    latched: u8,
+    index: u8,
+}
+
+impl Arkanoid {
+    pub fn new() -> Self {
+        Arkanoid { famicom: false, knob: KNOB_MIN, button: false, strobe: false, latched: KNOB_MIN, index: 0 }
+    }
+
+    pub fn new_famicom() -> Self {
+        Arkanoid { famicom: true, ..Arkanoid::new() }
+    }
+
+    pub fn set_knob(&mut self, value: u8) {
+        self.knob = value.clamp(KNOB_MIN, KNOB_MAX);
+    }
+
+    pub fn knob(&self) -> u8 {
+        self.knob
+    }
+
+    // The knob follows a column of the picture, the left edge turns it all the way left
+    pub fn follow(&mut self, x: usize) {
+        let x: usize = x.min(Frame::WIDTH - 1);
+        self.knob = KNOB_MIN + (x * (KNOB_MAX - KNOB_MIN) as usize / (Frame::WIDTH - 1)) as u8;
+    }
+
+    pub fn set_button(&mut self, pressed: bool) {
+        self.button = pressed;
+    }
+
+    fn knob_bit(&self) -> u8 {
+        match self.index {
+            0..=7 => !(self.latched >> (7 - self.index)) & 1,
+            _ => 1,
+        }
+    }
+}
+
+impl Default for Arkanoid {
+    fn default() -> Self {
+        Arkanoid::new()
+    }
+}
+
+impl InputDevice for Arkanoid {
+    fn name(&self) -> &'static str {
+        match self.famicom {
+            true => "Arkanoid Vaus (Famicom)",
+            false => "Arkanoid Vaus",
+        }
+    }
+
+    fn write(&mut self, value: u8, _context: &mut InputContext) {
+        self.strobe = value & 1 == 1;
+        if self.strobe {
+            self.latched = self.knob;
+            self.index = 0;
+        }
+    }
+
+    fn read(&mut self, register: usize, context: &mut InputContext) -> u8 {
+        let value: u8 = self.peek(register, context);
+        // The knob is only shifted by $4017
+        if register == 1 && !self.strobe && self.index < REPORT_BITS {
+            self.index += 1;
+        }
+        value
+    }
+
+    fn peek(&self, register: usize, _context: &InputContext) -> u8 {
+        match (self.famicom, register) {
+            (false, 1) => self.knob_bit() << 4 | (self.button as u8) << 3,
+            (true, 0) => (self.button as u8) << 1,
+            (true, _) => self.knob_bit() << 1,
+            (false, _) => 0,
+        }
+    }
+
+    // The mouse moves the paddle left and right, its left button fires
+    fn update_mouse(&mut self, mouse: &MouseInput) {
+        if let Some((x, _)) = mouse.position {
+            self.follow(x);
+        }
+        self.set_button(mouse.left);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for Arkanoid {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.strobe);
+        writer.u8(self.latched);
+        writer.u8(self.index);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.strobe = reader.bool()?;
+        self.latched = reader.u8()?;
+        self.index = reader.u8()?.min(REPORT_BITS);
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use std::any::Any;
+
+use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+use super::{InputContext, InputDevice};
+
+// Four players adapters, the joypads 3 and 4 are read along with the first two:
+//   NES Four Score/Satellite  plugged in both ports, each one returns a 24 bits report after the
+//                             strobe: the 8 buttons of joypad 1 (or 2), then of joypad 3 (or 4), then
+//                             a signature
+//   Famicom expansion port    the joypads 3 and 4 are shifted out in bit 1 of $4016 and $4017, along
+//                             with the first two in bit 0 (Hori adapter in its simple mode)
+// https://www.nesdev.org/wiki/Four_Score
+
+// In read order: 0,0,0,1,0,0,0,0 on $4016 and 0,0,1,0,0,0,0,0 on $4017
+const SIGNATURES: [u8; 2] = [0b0000_1000, 0b0000_0100];
+const FOUR_SCORE_REPORT_BITS: u8 = 24;
+const FAMICOM_REPORT_BITS: u8 = 8;
+
+// One half of the Four Score: port 0 reads the joypads 1 and 3, port 1 the joypads 2 and 4
+#[derive(Debug, Clone)]
+pub struct FourScore {
+    port: usize,
+    strobe: bool,
+    // Bits already read
+    index: u8,
+}
+
+impl FourScore {
+    pub fn new(port: usize) -> Self {
+        FourScore { port, strobe: false, index: 0 }
+    }
+}
+
+impl InputDevice for FourScore {
+    fn name(&self) -> &'static str {
+        "NES Four Score"
+    }
+
+    fn write(&mut self, value: u8, _context: &mut InputContext) {
+        self.strobe = value & 1 == 1;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    fn read(&mut self, register: usize, context: &mut InputContext) -> u8 {
+        let value: u8 = self.peek(register, context);
+        if !self.strobe && self.index < FOUR_SCORE_REPORT_BITS {
+            self.index += 1;
+        }
+        value
+    }
+
+    fn peek(&self, _register: usize, context: &InputContext) -> u8 {
+        let first: u8 = context.joypads[self.port].buttons().bits();
+        let second: u8 = context.joypads[self.port + 2].buttons().bits();
+        match self.index {
+            0..=7 => (first >> self.index) & 1,
+            8..=15 => (second >> (self.index - 8)) & 1,
+            16..=23 => (SIGNATURES[self.port] >> (self.index - 16)) & 1,
+            _ => 1,
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for FourScore {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.strobe);
+        writer.u8(self.index);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.strobe = reader.bool()?;
+        self.index = reader.u8()?.min(FOUR_SCORE_REPORT_BITS);
+        Ok(())
+    }
+}
+
+// In the expansion port, the joypads 1 and 2 stay in the ports
+#[derive(Debug, Clone, Default)]
+pub struct FamicomFourPlayers {
+    strobe: bool,
+    // Bits already read on $4016 and $4017
+    indexes: [u8; 2],
+}
+
+impl FamicomFourPlayers {
+    pub fn new() -> Self {
+        FamicomFourPlayers::default()
+    }
+}
+
+impl InputDevice for FamicomFourPlayers {
+    fn name(&self) -> &'static str {
+        "Famicom four players adapter"
+    }
+
+    fn write(&mut self, value: u8, _context: &mut InputContext) {
+        self.strobe = value & 1 == 1;
+        if self.strobe {
+            self.indexes = [0, 0];
+        }
+    }
+
+    fn read(&mut self, register: usize, context: &mut InputContext) -> u8 {
+        let value: u8 = self.peek(register, context);
+        if !self.strobe && self.indexes[register] < FAMICOM_REPORT_BITS {
+            self.indexes[register] += 1;
+        }
+        value
+    }
+
+    fn peek(&self, register: usize, context: &InputContext) -> u8 {
+        let index: u8 = self.indexes[register];
+        match index {
+            0..=7 => ((context.joypads[register + 2].buttons().bits() >> index) & 1) << 1,
+            _ => 0b10,
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for FamicomFourPlayers {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.strobe);
+        writer.bytes(&self.indexes);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.strobe = reader.bool()?;
+        reader.bytes(&mut self.indexes)
+    }
+}
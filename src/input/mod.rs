@@ -1,4 +1,26 @@
+pub mod arkanoid;
+pub mod fourscore;
+pub mod powerpad;
+pub mod snesmouse;
+pub mod zapper;
+mod test;
+
+use std::any::Any;
+
 use bitflags::bitflags;
+use sdl2::keyboard::Keycode;
+
+use crate::error::{Error::ConfigError, Error};
+use crate::rom::header::ExpansionDevice;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use crate::screen::frame::Frame;
+use crate::screen::Screen;
+
+use arkanoid::Arkanoid;
+use fourscore::{FamicomFourPlayers, FourScore};
+use powerpad::PowerPad;
+use snesmouse::SnesMouse;
+use zapper::Zapper;
 
 bitflags! {
     pub struct JoypadButton: u8 {
@@ -46,7 +68,217 @@ impl Joypad {
         response
     }
 
+    // The bit the next read would return, without shifting
+    pub fn peek(&self) -> u8 {
+        if self.button_index > 7 {
+            return 1;
+        }
+        (self.button_status.bits >> self.button_index) & 1
+    }
+
     pub fn set_button_pressed_status(&mut self, button: JoypadButton, value: bool) {
         self.button_status.set(button, value);
     }
+
+    pub fn buttons(&self) -> JoypadButton {
+        self.button_status
+    }
+
+    // Every button at once, used by movies
+    pub fn set_buttons(&mut self, buttons: JoypadButton) {
+        self.button_status = buttons;
+    }
+}
+
+impl SaveState for Joypad {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.strobe);
+        writer.u8(self.button_index);
+        writer.u8(self.button_status.bits);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.strobe = reader.bool()?;
+        self.button_index = reader.u8()?;
+        self.button_status = JoypadButton::from_bits_truncate(reader.u8()?);
+        Ok(())
+    }
+}
+
+// What the devices plugged in the controller ports see of the console: the joypads held by the
+// players, and the frame being drawn with the position of the beam for light guns
+pub struct InputContext<'a> {
+    pub joypads: [&'a mut Joypad; 4],
+    pub frame: &'a Frame,
+    pub scanline: usize,
+    pub dot: usize,
+}
+
+// The mouse of the front end, once per frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseInput {
+    // The NES pixel under the mouse, None when it's away from the picture
+    pub position: Option<(usize, usize)>,
+    // Movement since the last frame, in window pixels
+    pub motion: (i32, i32),
+    pub left: bool,
+    pub right: bool,
+}
+
+// Something plugged in port 1 ($4016), port 2 ($4017) or the Famicom expansion port (both).
+// Register 0 is $4016 and 1 is $4017, the bits a device returns are ORed with the other devices
+pub trait InputDevice: SaveState {
+    fn name(&self) -> &'static str;
+
+    // Every write to $4016: bit 0 strobes the ports, bits 0-2 are the OUT lines of the expansion port
+    fn write(&mut self, value: u8, context: &mut InputContext);
+
+    fn read(&mut self, register: usize, context: &mut InputContext) -> u8;
+
+    // The bits the next read would return, without side effects
+    fn peek(&self, register: usize, context: &InputContext) -> u8;
+
+    // The keyboard and mouse of the front end, ignored by default
+    fn press_key(&mut self, _keycode: Keycode, _pressed: bool) {}
+
+    fn update_mouse(&mut self, _mouse: &MouseInput) {}
+
+    // To reach the device behind the trait, e.g. to drive it from tests
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+// What can be plugged, the joypads stay in the ports the device doesn't take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputSetup {
+    Joypads,
+    // NES Four Score in both ports
+    FourScore,
+    // Joypads 3 and 4 in the expansion port
+    FamicomFourPlayers,
+    // In port 2
+    Zapper,
+    PowerPad,
+    Arkanoid,
+    SnesMouse,
+    // In the expansion port
+    FamilyTrainer,
+    ArkanoidFamicom,
+}
+
+impl InputSetup {
+    pub const NAMES: [(&'static str, InputSetup); 9] = [
+        ("joypads", InputSetup::Joypads),
+        ("four-score", InputSetup::FourScore),
+        ("famicom-four-players", InputSetup::FamicomFourPlayers),
+        ("zapper", InputSetup::Zapper),
+        ("power-pad", InputSetup::PowerPad),
+        ("arkanoid", InputSetup::Arkanoid),
+        ("snes-mouse", InputSetup::SnesMouse),
+        ("family-trainer", InputSetup::FamilyTrainer),
+        ("arkanoid-famicom", InputSetup::ArkanoidFamicom),
+    ];
+
+    // As given to --input, also the tag of the device states in the save states
+    pub fn name(self) -> &'static str {
+        InputSetup::NAMES.iter().find(|(_, setup)| *setup == self).map_or("joypads", |(name, _)| name)
+    }
+
+    pub fn parse(name: &str) -> Result<InputSetup, Error> {
+        InputSetup::NAMES.iter().find(|(setup, _)| *setup == name).map(|(_, setup)| *setup)
+            .ok_or_else(|| ConfigError(format!("Unknown input device {}", name)))
+    }
+
+    // None when the header doesn't ask for anything that can be plugged
+    pub fn from_expansion_device(device: ExpansionDevice) -> Option<InputSetup> {
+        match device {
+            ExpansionDevice::StandardControllers => Some(InputSetup::Joypads),
+            ExpansionDevice::FourScore => Some(InputSetup::FourScore),
+            ExpansionDevice::FamicomFourPlayers => Some(InputSetup::FamicomFourPlayers),
+            ExpansionDevice::Zapper => Some(InputSetup::Zapper),
+            ExpansionDevice::PowerPad => Some(InputSetup::PowerPad),
+            ExpansionDevice::FamilyTrainer => Some(InputSetup::FamilyTrainer),
+            ExpansionDevice::Arkanoid => Some(InputSetup::Arkanoid),
+            ExpansionDevice::ArkanoidFamicom => Some(InputSetup::ArkanoidFamicom),
+            ExpansionDevice::SnesMouse => Some(InputSetup::SnesMouse),
+            ExpansionDevice::Unspecified | ExpansionDevice::Other(_) => None,
+        }
+    }
+
+    // Replaces what was plugged in the screen
+    pub fn plug(self, screen: &mut Screen) {
+        screen.port1 = Box::new(StandardController::new(0));
+        screen.port2 = Box::new(StandardController::new(1));
+        screen.expansion = None;
+        screen.input_setup = self;
+        match self {
+            InputSetup::Joypads => (),
+            InputSetup::FourScore => {
+                screen.port1 = Box::new(FourScore::new(0));
+                screen.port2 = Box::new(FourScore::new(1));
+            }
+            InputSetup::FamicomFourPlayers => screen.expansion = Some(Box::new(FamicomFourPlayers::new())),
+            InputSetup::Zapper => screen.port2 = Box::new(Zapper::new()),
+            InputSetup::PowerPad => screen.port2 = Box::new(PowerPad::new()),
+            InputSetup::Arkanoid => screen.port2 = Box::new(Arkanoid::new()),
+            InputSetup::SnesMouse => screen.port2 = Box::new(SnesMouse::new()),
+            InputSetup::FamilyTrainer => screen.expansion = Some(Box::new(PowerPad::new_family_trainer())),
+            InputSetup::ArkanoidFamicom => screen.expansion = Some(Box::new(Arkanoid::new_famicom())),
+        }
+    }
+
+    // How to play with it
+    pub fn help(self) -> &'static str {
+        match self {
+            InputSetup::Joypads => "Joypads in both ports",
+            InputSetup::FourScore | InputSetup::FamicomFourPlayers => "Four players: the joypads 3 and 4 play with T F G H, X, Z, V, N and the keypad 8 4 5 6, 1, 2, 7, 9",
+            InputSetup::Zapper => "Zapper in port 2: aim with the mouse, fire with its left button",
+            InputSetup::PowerPad | InputSetup::FamilyTrainer => "Mat: its 3 rows of 4 buttons are U I O P, J K L ;, M , . /",
+            InputSetup::Arkanoid | InputSetup::ArkanoidFamicom => "Vaus paddle: the mouse turns the knob, its left button fires",
+            InputSetup::SnesMouse => "SNES mouse in port 2: moves with the mouse and its buttons",
+        }
+    }
+}
+
+// A joypad of the screen plugged in a port, the joypads keep the state so that movies and
+// key bindings work on them whatever is plugged
+pub struct StandardController {
+    joypad: usize,
+}
+
+impl StandardController {
+    // 0 for the first joypad
+    pub fn new(joypad: usize) -> Self {
+        StandardController { joypad }
+    }
+}
+
+impl InputDevice for StandardController {
+    fn name(&self) -> &'static str {
+        "standard controller"
+    }
+
+    fn write(&mut self, value: u8, context: &mut InputContext) {
+        context.joypads[self.joypad].write(value);
+    }
+
+    fn read(&mut self, _register: usize, context: &mut InputContext) -> u8 {
+        context.joypads[self.joypad].read()
+    }
+
+    fn peek(&self, _register: usize, context: &InputContext) -> u8 {
+        context.joypads[self.joypad].peek()
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// The joypad itself is saved with the screen
+impl SaveState for StandardController {
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> Result<(), Error> {
+        Ok(())
+    }
 }
\ No newline at end of file
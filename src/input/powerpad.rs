@@ -0,0 +1,145 @@
+use std::any::Any;
+
+use sdl2::keyboard::Keycode;
+
+use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+use super::{InputContext, InputDevice};
+
+// The 12 buttons mat, numbered like its side B (side A only has 8 of them, numbered differently):
+//    1  2  3  4
+//    5  6  7  8
+//    9 10 11 12
+//   Power Pad       port 2, after the strobe bit 3 of $4017 shifts out the buttons 2, 1, 5, 9, 6, 10,
+//                   11, 7 and bit 4 the buttons 4, 3, 12, 8, then 1s, 1 meaning pressed
+//   Family Trainer  expansion port, the writes to $4016 select a row with a 0 in bits 2 (1-4), 1 (5-8)
+//                   or 0 (9-12), and bits 1-4 of $4017 are the 4 buttons of that row, 0 meaning pressed
+// https://www.nesdev.org/wiki/Power_Pad
+
+const BIT3_BUTTONS: [u8; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const BIT4_BUTTONS: [u8; 4] = [4, 3, 12, 8];
+const REPORT_BITS: u8 = 8;
+
+// Row by row, like the mat seen from above
+const KEYS: [Keycode; 12] = [
+    Keycode::U, Keycode::I, Keycode::O, Keycode::P,
+    Keycode::J, Keycode::K, Keycode::L, Keycode::Semicolon,
+    Keycode::M, Keycode::Comma, Keycode::Period, Keycode::Slash,
+];
+
+#[derive(Debug, Clone)]
+pub struct PowerPad {
+    family_trainer: bool,
+    // Bit 0 is the button 1
+    buttons: u16,
+    strobe: bool,
+    index: u8,
+    // Family Trainer: bits 0-2 of the last write
+    rows: u8,
+}
+
+impl PowerPad {
+    pub fn new() -> Self {
+        PowerPad { family_trainer: false, buttons: 0, strobe: false, index: 0, rows: 0b111 }
+    }
+
+    pub fn new_family_trainer() -> Self {
+        PowerPad { family_trainer: true, ..PowerPad::new() }
+    }
+
+    // From 1 to 12
+    pub fn set_button(&mut self, button: u8, pressed: bool) {
+        if (1..=12).contains(&button) {
+            let mask: u16 = 1 << (button - 1);
+            match pressed {
+                true => self.buttons |= mask,
+                false => self.buttons &= !mask,
+            }
+        }
+    }
+
+    pub fn pressed(&self, button: u8) -> bool {
+        (1..=12).contains(&button) && self.buttons & (1 << (button - 1)) != 0
+    }
+
+    fn bit(&self, button: u8) -> u8 {
+        self.pressed(button) as u8
+    }
+}
+
+impl Default for PowerPad {
+    fn default() -> Self {
+        PowerPad::new()
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn name(&self) -> &'static str {
+        match self.family_trainer {
+            true => "Family Trainer",
+            false => "Power Pad",
+        }
+    }
+
+    fn write(&mut self, value: u8, _context: &mut InputContext) {
+        self.strobe = value & 1 == 1;
+        if self.strobe {
+            self.index = 0;
+        }
+        self.rows = value & 0b111;
+    }
+
+    fn read(&mut self, register: usize, context: &mut InputContext) -> u8 {
+        let value: u8 = self.peek(register, context);
+        if !self.family_trainer && !self.strobe && self.index < REPORT_BITS {
+            self.index += 1;
+        }
+        value
+    }
+
+    fn peek(&self, register: usize, _context: &InputContext) -> u8 {
+        if register != 1 {
+            return 0;
+        }
+        if self.family_trainer {
+            let first: u8 = match (self.rows & 0b001 == 0, self.rows & 0b010 == 0, self.rows & 0b100 == 0) {
+                (true, _, _) => 9,
+                (_, true, _) => 5,
+                (_, _, true) => 1,
+                _ => return 0b1_1110,
+            };
+            let row: u8 = (0..4).fold(0, |row, column| row | self.bit(first + column) << column);
+            return (!row & 0x0f) << 1;
+        }
+        let index: usize = self.index as usize;
+        let bit3: u8 = BIT3_BUTTONS.get(index).map_or(1, |button| self.bit(*button));
+        let bit4: u8 = BIT4_BUTTONS.get(index).map_or(1, |button| self.bit(*button));
+        bit3 << 3 | bit4 << 4
+    }
+
+    fn press_key(&mut self, keycode: Keycode, pressed: bool) {
+        if let Some(button) = KEYS.iter().position(|key| *key == keycode) {
+            self.set_button(button as u8 + 1, pressed);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for PowerPad {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.strobe);
+        writer.u8(self.index);
+        writer.u8(self.rows);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.strobe = reader.bool()?;
+        self.index = reader.u8()?.min(REPORT_BITS);
+        self.rows = reader.u8()? & 0b111;
+        Ok(())
+    }
+}
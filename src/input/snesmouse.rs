@@ -0,0 +1,127 @@
+use std::any::Any;
+
+use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+use super::{InputContext, InputDevice, MouseInput};
+
+// The SNES mouse through a port adapter, in bit 0 of its port. The strobe latches a 32 bits report
+// read most significant bit first, then 1s:
+//   byte 0  0
+//   byte 1  right button, left button, sensitivity (2 bits), signature 0001
+//   byte 2  1 when moving up, then the vertical movement (7 bits)
+//   byte 3  1 when moving left, then the horizontal movement (7 bits)
+// The movements are counted since the previous report. Reading while the strobe is held cycles the
+// sensitivity, which multiplies the movements by 1, 2 or 3 here.
+// https://www.nesdev.org/wiki/Super_NES_Mouse
+
+const SIGNATURE: u32 = 0b0001;
+const REPORT_BITS: u8 = 32;
+const MAX_MOVEMENT: i32 = 0x7f;
+
+#[derive(Debug, Clone, Default)]
+pub struct SnesMouse {
+    // Movement since the last report
+    motion: (i32, i32),
+    left: bool,
+    right: bool,
+    sensitivity: u8,
+    strobe: bool,
+    report: u32,
+    index: u8,
+}
+
+// Sign and magnitude, the sign bit is set for a negative movement
+fn movement(value: i32) -> u32 {
+    ((value < 0) as u32) << 7 | value.unsigned_abs().min(MAX_MOVEMENT as u32)
+}
+
+impl SnesMouse {
+    pub fn new() -> Self {
+        SnesMouse::default()
+    }
+
+    // Right and down are positive
+    pub fn move_by(&mut self, dx: i32, dy: i32) {
+        self.motion = (self.motion.0.saturating_add(dx), self.motion.1.saturating_add(dy));
+    }
+
+    pub fn set_buttons(&mut self, left: bool, right: bool) {
+        self.left = left;
+        self.right = right;
+    }
+
+    // 0 (low) to 2 (high)
+    pub fn sensitivity(&self) -> u8 {
+        self.sensitivity
+    }
+
+    fn latch(&mut self) {
+        let scale: i32 = self.sensitivity as i32 + 1;
+        let (dx, dy) = (self.motion.0.saturating_mul(scale), self.motion.1.saturating_mul(scale));
+        self.report = (self.right as u32) << 23 | (self.left as u32) << 22 | (self.sensitivity as u32) << 20
+            | SIGNATURE << 16 | movement(dy) << 8 | movement(dx);
+        self.motion = (0, 0);
+        self.index = 0;
+    }
+}
+
+impl InputDevice for SnesMouse {
+    fn name(&self) -> &'static str {
+        "SNES mouse"
+    }
+
+    fn write(&mut self, value: u8, _context: &mut InputContext) {
+        let strobe: bool = value & 1 == 1;
+        if strobe && !self.strobe {
+            self.latch();
+        }
+        self.strobe = strobe;
+    }
+
+    fn read(&mut self, register: usize, context: &mut InputContext) -> u8 {
+        let value: u8 = self.peek(register, context);
+        if self.strobe {
+            self.sensitivity = (self.sensitivity + 1) % 3;
+            self.latch();
+        } else if self.index < REPORT_BITS {
+            self.index += 1;
+        }
+        value
+    }
+
+    fn peek(&self, _register: usize, _context: &InputContext) -> u8 {
+        match self.index {
+            0..=31 => ((self.report >> (31 - self.index)) & 1) as u8,
+            _ => 1,
+        }
+    }
+
+    fn update_mouse(&mut self, mouse: &MouseInput) {
+        self.move_by(mouse.motion.0, mouse.motion.1);
+        self.set_buttons(mouse.left, mouse.right);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for SnesMouse {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.sensitivity);
+        writer.bool(self.strobe);
+        writer.bytes(&self.report.to_le_bytes());
+        writer.u8(self.index);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.sensitivity = reader.u8()? % 3;
+        self.strobe = reader.bool()?;
+        let mut report: [u8; 4] = [0; 4];
+        reader.bytes(&mut report)?;
+        self.report = u32::from_le_bytes(report);
+        self.index = reader.u8()?.min(REPORT_BITS);
+        Ok(())
+    }
+}
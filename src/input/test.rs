@@ -0,0 +1,327 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::bus::Bus;
+    use crate::mem::Mem;
+    use crate::rom::Rom;
+    use crate::screen::frame::Frame;
+    use crate::screen::Screen;
+    use sdl2::keyboard::Keycode;
+
+    use crate::rom::header::ExpansionDevice;
+    use crate::savestate::{SaveState, StateReader, StateWriter};
+
+    use super::super::arkanoid::*;
+    use super::super::powerpad::*;
+    use super::super::snesmouse::*;
+    use super::super::zapper::*;
+    use super::super::*;
+
+    const WHITE: (u8, u8, u8) = (236, 238, 236);
+    const SKY: (u8, u8, u8) = (64, 156, 248);
+
+    // Black, with a white box of 16x16 pixels at (64, 96)
+    fn target_frame() -> Frame {
+        let mut frame: Frame = Frame::new();
+        for y in 96..112 {
+            for x in 64..80 {
+                frame.set_pixel(x, y, WHITE);
+            }
+        }
+        frame
+    }
+
+    #[test]
+    fn test_joypad() {
+        let mut joypad: Joypad = Joypad::new();
+        joypad.set_buttons(JoypadButton::BUTTON_A | JoypadButton::START);
+        joypad.write(1);
+        joypad.write(0);
+        let bits: Vec<u8> = (0..9).map(|_| joypad.read()).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_zapper_trigger() {
+        let frame: Frame = Frame::new();
+        let mut zapper: Zapper = Zapper::new();
+        assert_eq!(zapper.report(&frame, 0, 0), LIGHT_SENSE_BIT);
+        zapper.set_trigger(true);
+        assert_eq!(zapper.report(&frame, 0, 0), TRIGGER_BIT | LIGHT_SENSE_BIT);
+        zapper.set_trigger(false);
+        assert!(!zapper.trigger());
+    }
+
+    #[test]
+    fn test_zapper_light() {
+        let frame: Frame = target_frame();
+        let mut zapper: Zapper = Zapper::new();
+        zapper.aim(72, 104);
+        assert_eq!(zapper.aim_point(), Some((72, 104)));
+        // Before the beam reaches the aim point, then for a few scanlines after
+        assert!(!zapper.detects_light(&frame, 50, 0));
+        assert!(!zapper.detects_light(&frame, 104, 71));
+        assert!(zapper.detects_light(&frame, 104, 72));
+        assert!(zapper.detects_light(&frame, 115, 0));
+        assert!(!zapper.detects_light(&frame, 130, 0));
+        assert_eq!(zapper.report(&frame, 110, 0), 0);
+
+        // The black around the box, the edge of the box, and away from the screen
+        zapper.aim(20, 104);
+        assert!(!zapper.detects_light(&frame, 110, 0));
+        zapper.aim(64, 104);
+        assert!(!zapper.detects_light(&frame, 110, 0));
+        zapper.aim(300, 104);
+        assert_eq!(zapper.aim_point(), None);
+        zapper.aim(72, 104);
+        zapper.aim_off_screen();
+        assert!(!zapper.detects_light(&frame, 110, 0));
+    }
+
+    #[test]
+    fn test_zapper_dim_colors() {
+        let mut frame: Frame = Frame::new();
+        for y in 0..Frame::HEIGHT {
+            for x in 0..Frame::WIDTH {
+                frame.set_pixel(x, y, SKY);
+            }
+        }
+        let mut zapper: Zapper = Zapper::new();
+        zapper.aim(0, 239);
+        assert!(!zapper.detects_light(&frame, 239, 100));
+        frame.data.fill(0xff);
+        assert!(zapper.detects_light(&frame, 239, 100));
+    }
+
+    #[test]
+    fn test_zapper_on_port_2() {
+        let rom: Rom = Rom::new_from_program_rom(vec![0xea; 0x8000]).unwrap();
+        let mut bus: Bus = Bus::new_headless(rom, |_, _| {}, Joypad::new(), Joypad::new());
+        bus.screen.joypad2.set_buttons(JoypadButton::BUTTON_A);
+        bus.mem_write_u8(0x4016, 1);
+        assert_eq!(bus.mem_read_u8(0x4017), 1);
+
+        InputSetup::Zapper.plug(&mut bus.screen);
+        let zapper: &mut Zapper = bus.screen.device_mut::<Zapper>().unwrap();
+        zapper.aim(72, 104);
+        zapper.set_trigger(true);
+        bus.screen.frame = target_frame();
+        bus.ppu.scanline = 20;
+        assert_eq!(bus.mem_read_u8(0x4017), TRIGGER_BIT | LIGHT_SENSE_BIT);
+        bus.ppu.scanline = 105;
+        assert_eq!(bus.mem_read_u8(0x4017), TRIGGER_BIT);
+        assert_eq!(bus.mem_read_u8_no_fail(0x4017, true), TRIGGER_BIT);
+        // The first joypad is still there
+        bus.screen.joypad1.set_buttons(JoypadButton::BUTTON_A);
+        bus.mem_write_u8(0x4016, 1);
+        assert_eq!(bus.mem_read_u8(0x4016), 1);
+    }
+
+    fn bus() -> Bus {
+        let rom: Rom = Rom::new_from_program_rom(vec![0xea; 0x8000]).unwrap();
+        Bus::new_headless(rom, |_, _| {}, Joypad::new(), Joypad::new())
+    }
+
+    fn read_bits(bus: &mut Bus, addr: u16, count: usize) -> Vec<u8> {
+        (0..count).map(|_| bus.mem_read_u8(addr)).collect()
+    }
+
+    #[test]
+    fn test_four_score() {
+        let mut bus: Bus = bus();
+        InputSetup::FourScore.plug(&mut bus.screen);
+        bus.screen.joypad1.set_buttons(JoypadButton::BUTTON_A);
+        bus.screen.joypad2.set_buttons(JoypadButton::BUTTON_B);
+        bus.screen.joypad3.set_buttons(JoypadButton::START);
+        bus.screen.joypad4.set_buttons(JoypadButton::RIGHT);
+
+        // While strobing, the A button of the joypads 1 and 2
+        bus.mem_write_u8(0x4016, 1);
+        assert_eq!(read_bits(&mut bus, 0x4016, 3), vec![1, 1, 1]);
+        assert_eq!(bus.mem_read_u8(0x4017), 0);
+        bus.mem_write_u8(0x4016, 0);
+
+        // Joypad 1, joypad 3, then the signature
+        let port1: Vec<u8> = read_bits(&mut bus, 0x4016, 26);
+        assert_eq!(port1[..8], [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(port1[8..16], [0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(port1[16..24], [0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(port1[24..], [1, 1]);
+        assert_eq!(bus.mem_read_u8_no_fail(0x4017, true), 0);
+        let port2: Vec<u8> = read_bits(&mut bus, 0x4017, 24);
+        assert_eq!(port2[..8], [0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(port2[8..16], [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(port2[16..24], [0, 0, 1, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_famicom_four_players() {
+        let mut bus: Bus = bus();
+        InputSetup::FamicomFourPlayers.plug(&mut bus.screen);
+        bus.screen.joypad1.set_buttons(JoypadButton::BUTTON_A | JoypadButton::UP);
+        bus.screen.joypad3.set_buttons(JoypadButton::BUTTON_A | JoypadButton::SELECT);
+        bus.screen.joypad4.set_buttons(JoypadButton::BUTTON_B);
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_write_u8(0x4016, 0);
+        assert_eq!(read_bits(&mut bus, 0x4016, 9), vec![0b11, 0, 0b10, 0, 0b01, 0, 0, 0, 0b11]);
+        assert_eq!(read_bits(&mut bus, 0x4017, 3), vec![0, 0b10, 0]);
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let mut screen: Screen = Screen::new_headless(Joypad::new(), Joypad::new());
+        screen.press_key(Keycode::X, true);
+        screen.press_key(Keycode::Kp9, true);
+        screen.press_key(Keycode::A, true);
+        screen.press_key(Keycode::A, false);
+        assert_eq!(screen.joypad1.buttons(), JoypadButton::empty());
+        assert_eq!(screen.joypad3.buttons(), JoypadButton::BUTTON_A);
+        assert_eq!(screen.joypad4.buttons(), JoypadButton::START);
+    }
+
+    #[test]
+    fn test_power_pad() {
+        let mut bus: Bus = bus();
+        InputSetup::PowerPad.plug(&mut bus.screen);
+        let power_pad: &mut PowerPad = bus.screen.device_mut::<PowerPad>().unwrap();
+        power_pad.set_button(1, true);
+        power_pad.set_button(12, true);
+        power_pad.press_key(Keycode::L, true);
+        assert!(power_pad.pressed(7));
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_write_u8(0x4016, 0);
+        // Bit 3: 2, 1, 5, 9, 6, 10, 11, 7 and bit 4: 4, 3, 12, 8, then 1s
+        let bits: Vec<u8> = read_bits(&mut bus, 0x4017, 9);
+        assert_eq!(bits, vec![0, 0b01000, 0b10000, 0, 0b10000, 0b10000, 0b10000, 0b11000, 0b11000]);
+        // The first joypad is still in port 1
+        assert_eq!(bus.mem_read_u8(0x4016), 0);
+    }
+
+    #[test]
+    fn test_family_trainer() {
+        let mut bus: Bus = bus();
+        InputSetup::FamilyTrainer.plug(&mut bus.screen);
+        let mat: &mut PowerPad = bus.screen.device_mut::<PowerPad>().unwrap();
+        for button in [2, 5, 12] {
+            mat.set_button(button, true);
+        }
+        // The selected row in bits 1-4, 0 when pressed, the joypads are still in the ports
+        bus.screen.joypad2.set_buttons(JoypadButton::BUTTON_A);
+        bus.mem_write_u8(0x4016, 0b011);
+        assert_eq!(bus.mem_read_u8(0x4017), 0b11011);
+        bus.mem_write_u8(0x4016, 0b101);
+        assert_eq!(bus.mem_read_u8(0x4017), 0b11101);
+        bus.mem_write_u8(0x4016, 0b110);
+        assert_eq!(bus.mem_read_u8(0x4017), 0b01111);
+        bus.mem_write_u8(0x4016, 0b111);
+        assert_eq!(bus.mem_read_u8(0x4017), 0b11111);
+        assert_eq!(bus.mem_read_u8(0x4016) & 0b11110, 0);
+    }
+
+    #[test]
+    fn test_arkanoid() {
+        let mut bus: Bus = bus();
+        InputSetup::Arkanoid.plug(&mut bus.screen);
+        let vaus: &mut Arkanoid = bus.screen.device_mut::<Arkanoid>().unwrap();
+        vaus.set_knob(0xa5);
+        vaus.set_button(true);
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_write_u8(0x4016, 0);
+        // The knob is latched by the strobe
+        bus.screen.device_mut::<Arkanoid>().unwrap().set_knob(0x60);
+        let bits: Vec<u8> = read_bits(&mut bus, 0x4017, 9).iter().map(|bit| bit >> 3).collect();
+        // $A5 = 10100101, inverted, with the button in bit 0
+        assert_eq!(bits, vec![0b01, 0b11, 0b01, 0b11, 0b11, 0b01, 0b11, 0b01, 0b11]);
+
+        let mut vaus: Arkanoid = Arkanoid::new();
+        vaus.update_mouse(&MouseInput { position: Some((0, 10)), ..MouseInput::default() });
+        assert_eq!(vaus.knob(), KNOB_MIN);
+        vaus.update_mouse(&MouseInput { position: None, motion: (5, 0), left: true, right: false });
+        assert_eq!(vaus.knob(), KNOB_MIN);
+        vaus.follow(255);
+        assert_eq!(vaus.knob(), KNOB_MAX);
+        vaus.set_knob(0);
+        assert_eq!(vaus.knob(), KNOB_MIN);
+    }
+
+    #[test]
+    fn test_arkanoid_famicom() {
+        let mut bus: Bus = bus();
+        InputSetup::ArkanoidFamicom.plug(&mut bus.screen);
+        let vaus: &mut Arkanoid = bus.screen.device_mut::<Arkanoid>().unwrap();
+        vaus.set_knob(0xf0);
+        vaus.set_button(true);
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_write_u8(0x4016, 0);
+        // The button in bit 1 of $4016, the knob in bit 1 of $4017
+        assert_eq!(bus.mem_read_u8(0x4016), 0b10);
+        let bits: Vec<u8> = read_bits(&mut bus, 0x4017, 8).iter().map(|bit| bit >> 1).collect();
+        assert_eq!(bits, vec![0, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_snes_mouse() {
+        let mut bus: Bus = bus();
+        InputSetup::SnesMouse.plug(&mut bus.screen);
+        let mouse: &mut SnesMouse = bus.screen.device_mut::<SnesMouse>().unwrap();
+        mouse.update_mouse(&MouseInput { position: None, motion: (-3, 200), left: true, right: false });
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_write_u8(0x4016, 0);
+        let bits: Vec<u8> = read_bits(&mut bus, 0x4017, 33);
+        let report: u32 = bits[..32].iter().fold(0, |report, bit| report << 1 | *bit as u32);
+        // Left button, low sensitivity, signature, 127 down, 3 left
+        assert_eq!(report, 0x0041_7f83);
+        assert_eq!(bits[32], 1);
+
+        // The movement was consumed by the report, reading while strobing cycles the sensitivity
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_read_u8(0x4017);
+        assert_eq!(bus.screen.device_mut::<SnesMouse>().unwrap().sensitivity(), 1);
+        bus.screen.device_mut::<SnesMouse>().unwrap().move_by(2, -1);
+        bus.mem_write_u8(0x4016, 0);
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_write_u8(0x4016, 0);
+        let report: u32 = read_bits(&mut bus, 0x4017, 32).iter().fold(0, |report, bit| report << 1 | *bit as u32);
+        assert_eq!(report, 0x0051_8204);
+    }
+
+    #[test]
+    fn test_input_setup() {
+        assert_eq!(InputSetup::from_expansion_device(ExpansionDevice::from_byte(0x0c)), Some(InputSetup::PowerPad));
+        assert_eq!(InputSetup::from_expansion_device(ExpansionDevice::from_byte(0x29)), Some(InputSetup::SnesMouse));
+        assert_eq!(InputSetup::from_expansion_device(ExpansionDevice::Unspecified), None);
+        for (name, setup) in InputSetup::NAMES {
+            assert_eq!(InputSetup::parse(name).unwrap(), setup);
+        }
+
+        let mut screen: Screen = Screen::new_headless(Joypad::new(), Joypad::new());
+        InputSetup::ArkanoidFamicom.plug(&mut screen);
+        assert_eq!(screen.devices().iter().map(|device| device.name()).collect::<Vec<_>>(),
+            vec!["standard controller", "standard controller", "Arkanoid Vaus (Famicom)"]);
+        InputSetup::Joypads.plug(&mut screen);
+        assert!(screen.expansion.is_none());
+        assert!(screen.device_mut::<Arkanoid>().is_none());
+    }
+
+    #[test]
+    fn test_devices_state() {
+        let mut bus: Bus = bus();
+        InputSetup::PowerPad.plug(&mut bus.screen);
+        bus.screen.device_mut::<PowerPad>().unwrap().set_button(1, true);
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_write_u8(0x4016, 0);
+        bus.mem_read_u8(0x4017);
+        let mut writer: StateWriter = StateWriter::new();
+        bus.save_state(&mut writer);
+        assert_eq!(bus.mem_read_u8(0x4017), 0b01000);
+
+        // Back to the second bit of the report
+        let data: Vec<u8> = writer.finish();
+        bus.load_state(&mut StateReader::new(&data)).unwrap();
+        assert_eq!(bus.mem_read_u8(0x4017), 0b01000);
+    }
+}
@@ -0,0 +1,132 @@
+use std::any::Any;
+
+use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use crate::screen::frame::Frame;
+
+use super::{InputContext, InputDevice, MouseInput};
+
+// The light gun, plugged in port 2: $4017 bit 4 is set while the trigger is pulled and bit 3 is
+// cleared while the photodiode sees light. The diode looks at a few pixels around the aim point,
+// lights up when the beam draws them bright, and stays lit for a few scanlines after.
+// https://www.nesdev.org/wiki/Zapper
+
+pub const TRIGGER_BIT: u8 = 0b0001_0000;
+pub const LIGHT_SENSE_BIT: u8 = 0b0000_1000;
+
+// Half width of the square of pixels seen by the diode
+const SENSOR_RADIUS: usize = 2;
+// Scanlines the diode stays lit after the beam went past the aim point
+const LIGHT_SCANLINES: usize = 20;
+// Average luma (0-255) of the square above which the diode sees light: white is about 236, the
+// light blue sky of Duck Hunt about 140
+const LIGHT_THRESHOLD: u32 = 0xa0;
+
+#[derive(Debug, Clone, Default)]
+pub struct Zapper {
+    // None when aiming away from the screen
+    aim: Option<(usize, usize)>,
+    trigger: bool,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Zapper { aim: None, trigger: false }
+    }
+
+    // In NES pixels, a point outside of the picture aims away from the screen
+    pub fn aim(&mut self, x: usize, y: usize) {
+        self.aim = (x < Frame::WIDTH && y < Frame::HEIGHT).then_some((x, y));
+    }
+
+    pub fn aim_off_screen(&mut self) {
+        self.aim = None;
+    }
+
+    pub fn aim_point(&self) -> Option<(usize, usize)> {
+        self.aim
+    }
+
+    pub fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+    }
+
+    pub fn trigger(&self) -> bool {
+        self.trigger
+    }
+
+    // Whether the diode sees light while the PPU is at that scanline and dot, from the pixels of the
+    // frame being drawn
+    pub fn detects_light(&self, frame: &Frame, scanline: usize, dot: usize) -> bool {
+        let (x, y) = match self.aim {
+            Some(aim) => aim,
+            None => return false,
+        };
+        let beam_went_past: bool = scanline > y || (scanline == y && dot >= x);
+        if !beam_went_past || scanline >= y + LIGHT_SCANLINES {
+            return false;
+        }
+        let columns = x.saturating_sub(SENSOR_RADIUS)..=(x + SENSOR_RADIUS).min(Frame::WIDTH - 1);
+        let rows = y.saturating_sub(SENSOR_RADIUS)..=(y + SENSOR_RADIUS).min(Frame::HEIGHT - 1);
+        let mut luma: u32 = 0;
+        let mut pixels: u32 = 0;
+        for row in rows {
+            for column in columns.clone() {
+                let (r, g, b) = frame.get_pixel(column, row);
+                luma += (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+                pixels += 1;
+            }
+        }
+        luma / pixels >= LIGHT_THRESHOLD
+    }
+
+    // The $4017 value, reading has no side effect
+    pub fn report(&self, frame: &Frame, scanline: usize, dot: usize) -> u8 {
+        let mut value: u8 = 0;
+        if self.trigger {
+            value |= TRIGGER_BIT;
+        }
+        if !self.detects_light(frame, scanline, dot) {
+            value |= LIGHT_SENSE_BIT;
+        }
+        value
+    }
+}
+
+impl InputDevice for Zapper {
+    fn name(&self) -> &'static str {
+        "Zapper"
+    }
+
+    fn write(&mut self, _value: u8, _context: &mut InputContext) {}
+
+    fn read(&mut self, register: usize, context: &mut InputContext) -> u8 {
+        self.peek(register, context)
+    }
+
+    fn peek(&self, _register: usize, context: &InputContext) -> u8 {
+        self.report(context.frame, context.scanline, context.dot)
+    }
+
+    // The mouse aims and its left button pulls the trigger
+    fn update_mouse(&mut self, mouse: &MouseInput) {
+        match mouse.position {
+            Some((x, y)) => self.aim(x, y),
+            None => self.aim_off_screen(),
+        }
+        self.set_trigger(mouse.left);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Nothing but what the player does
+impl SaveState for Zapper {
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -5,4 +5,22 @@ pub mod bus;
 pub mod rom;
 pub mod ppu;
 pub mod screen;
-pub mod input;
\ No newline at end of file
+pub mod input;
+pub mod golden;
+pub mod debugger;
+pub mod disasm;
+pub mod asm;
+pub mod trace;
+pub mod cdl;
+pub mod memview;
+pub mod cheat;
+pub mod savestate;
+pub mod movie;
+pub mod archive;
+pub mod patch;
+pub mod poweron;
+pub mod testrom;
+pub mod apu;
+pub mod nsf;
+pub mod fds;
+pub mod cli;
\ No newline at end of file
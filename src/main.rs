@@ -1,80 +1,449 @@
 use anyhow::Result;
+use nes_emul::apu::{wav, Apu, DEFAULT_SAMPLE_RATE};
+use nes_emul::archive;
 use nes_emul::bus::Bus;
+use nes_emul::cdl::CodeDataLog;
+use nes_emul::cheat::Cheats;
+use nes_emul::cli::{self, Command, RunOptions};
 use nes_emul::cpu::CPU;
-use nes_emul::input::Joypad;
+use nes_emul::debugger::gdb::GdbServer;
+use nes_emul::debugger::repl::Repl;
+use nes_emul::disasm::{Disassembler, Syntax};
+use nes_emul::error::Error::{AudioError, RomError};
+use nes_emul::fds::{self, Fds, FdsImage};
+use nes_emul::input::{InputSetup, Joypad, MouseInput};
+use nes_emul::memview::panel::MemoryPanel;
+use nes_emul::movie::{Movie, MovieSession};
+use nes_emul::nsf::player::NsfPlayer;
+use nes_emul::nsf::Nsf;
+use nes_emul::patch;
 use nes_emul::ppu::PPU;
+use nes_emul::rom::header::ExpansionDevice;
+use nes_emul::rom::info::RomInfo;
 use nes_emul::rom::Rom;
-use nes_emul::screen::{render, Screen};
+use nes_emul::savestate;
+use nes_emul::screen::frame::Frame;
+use nes_emul::screen::{render, snapshot, Display, Screen, DEFAULT_FRAME_RATE};
+use nes_emul::testrom::{self, TestReport, TestStatus};
+use nes_emul::trace::Tracer;
 
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::Texture;
 use sdl2::keyboard::Keycode;
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 
-use std::io::{self, Read, Write};
+use std::io::{self, BufWriter, Write};
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
 
-fn main() -> Result<()>{
-    let mut game_path: String = String::from("rom_examples/");
-    let mut buffer: String = String::new();
-    print!("Enter the rom path: rom_examples/");
-    io::stdout().flush().unwrap();
-    io::stdin().read_line(&mut buffer).unwrap();
-    game_path.push_str(&buffer[..&buffer.len()-1]);
-        
-    let mut file: File = File::open(game_path.clone())?;
-    let mut data: Vec<u8> = Vec::new();
-    file.read_to_end(&mut data)?;
+static FLUSH_ON_QUIT: AtomicBool = AtomicBool::new(false);
+static QUIT: AtomicBool = AtomicBool::new(false);
+// Hotkeys handled between two instructions, the window callback cannot reach the CPU
+static SAVE_STATE: AtomicBool = AtomicBool::new(false);
+static LOAD_STATE: AtomicBool = AtomicBool::new(false);
+static TOGGLE_READ_ONLY: AtomicBool = AtomicBool::new(false);
+static SWITCH_DISK_SIDE: AtomicBool = AtomicBool::new(false);
 
+// A .nes file, or the ROM in a zip or gzip archive
+fn read_rom_file(path: &Path, entry: Option<&str>) -> Result<Vec<u8>> {
+    let data: Vec<u8> = std::fs::read(path).map_err(|e| RomError(format!("Cannot read {}: {}", path.display(), e)))?;
+    Ok(archive::extract_rom(data, entry)?)
+}
 
-    // ================================== CPU initialization ========================================
+// The patch is applied to the file before it gets parsed, game.ips/ups/bps next to the ROM by default
+fn read_patched_file(path: &Path, entry: Option<&str>, patch_path: Option<&Path>) -> Result<Vec<u8>> {
+    let mut data: Vec<u8> = read_rom_file(path, entry)?;
+    if let Some(patch_path) = patch_path.map(Path::to_path_buf).or_else(|| patch::path_for_rom(path)) {
+        let patch_data: Vec<u8> = std::fs::read(&patch_path).map_err(|e| RomError(format!("Cannot read {}: {}", patch_path.display(), e)))?;
+        println!("Patching with {}", patch_path.display());
+        data = patch::apply(&data, &patch_data)?;
+    }
+    Ok(data)
+}
 
-    let rom: Rom = Rom::new(&data)?; 
-    let joypad1: Joypad = Joypad::new();
-    let joypad2: Joypad = Joypad::new();
-    let bus: Bus = Bus::new(rom, |ppu: &PPU, screen: &mut Screen| {
-        render::Renderer::render(ppu, &mut screen.frame);
-        let mut texture: Texture<'_> = screen.creator.create_texture_target(PixelFormatEnum::RGB24, 256, 240).expect("Cannot create texture !");
-        texture.update(None, &screen.frame.data, 256 * 3).unwrap();
- 
-        screen.canvas.copy(&texture, None, None).unwrap();
- 
-        screen.canvas.present();
-
-        for event in screen.event_pump.poll_iter() {
-            match event {
-              Event::Quit { .. }
-              | Event::KeyDown {
-                  keycode: Some(Keycode::Escape),
-                  ..
-              } => std::process::exit(0),
-              Event::KeyDown { keycode, .. } => {
-                if let Some(key) = screen.bindings_joypad1.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                    screen.joypad1.set_button_pressed_status(*key, true);
-                } 
-
-                if let Some(key) = screen.bindings_joypad2.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                    screen.joypad2.set_button_pressed_status(*key, true);
-                } 
-            },
-            Event::KeyUp { keycode, .. } => {
-                if let Some(key) = screen.bindings_joypad1.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                    screen.joypad1.set_button_pressed_status(*key, false);
+fn read_rom(path: &Path, entry: Option<&str>, patch_path: Option<&Path>, database: bool) -> Result<Rom> {
+    Ok(Rom::load(&read_patched_file(path, entry, patch_path)?, database)?)
+}
+
+// A .fds disk in the RAM adapter, the copy the game wrote to (game.fds.sav) is preferred to the original
+fn load_fds(disk_path: &Path, data: &[u8], bios: Option<&Path>) -> Result<Fds> {
+    let save_path: PathBuf = fds::save_path(disk_path);
+    let image: FdsImage = match save_path.is_file() {
+        true => {
+            println!("Loading the disk saved to {}", save_path.display());
+            FdsImage::parse(&std::fs::read(&save_path)?)?
+        }
+        false => FdsImage::parse(data)?,
+    };
+    let bios_path: PathBuf = fds::bios_path(disk_path, bios)?;
+    let bios: Vec<u8> = std::fs::read(&bios_path).map_err(|e| RomError(format!("Cannot read {}: {}", bios_path.display(), e)))?;
+    println!("Famicom Disk System: {} sides, F4 switches to the next one", image.sides.len());
+    Ok(Fds::new(&image, bios)?)
+}
+
+// nes_emul info <rom> [--json]: works on any iNES file, even those the emulator cannot run
+fn info(rom_path: &Path, entry: Option<&str>, json: bool) -> Result<()> {
+    let data: Vec<u8> = read_rom_file(rom_path, entry)?;
+    let info: RomInfo = RomInfo::new(&rom_path.display().to_string(), &data)?;
+    match json {
+        true => print!("{}", info.json()),
+        false => print!("{}", info.text()),
+    }
+    Ok(())
+}
+
+// nes_emul disasm <rom> [--syntax ca65|asm6] [--cdl <file>] [-o <output>]
+fn disasm(rom_path: &Path, entry: Option<&str>, syntax: Syntax, cdl_path: Option<&Path>, output: Option<&Path>) -> Result<()> {
+    let rom: Rom = read_rom(rom_path, entry, None, true)?;
+    let mut disassembler: Disassembler = Disassembler::for_rom(&rom, syntax);
+    if let Some(cdl_path) = cdl_path {
+        let cdl: CodeDataLog = CodeDataLog::load(cdl_path, rom.program_rom_size, rom.chr_rom.len())?;
+        disassembler = disassembler.with_data_mask(cdl.data_mask());
+    }
+    let listing: String = disassembler.listing();
+    match output {
+        Some(output) => std::fs::write(output, listing)?,
+        None => print!("{}", listing),
+    }
+    Ok(())
+}
+
+// nes_emul test <rom> [--frames <count>]: the exit code tells whether the test ROM passed
+fn test(rom_path: &Path, entry: Option<&str>, frames: usize) -> Result<()> {
+    let bus: Bus = Bus::new_headless(read_rom(rom_path, entry, None, true)?, gameloop, Joypad::new(), Joypad::new());
+    let mut cpu: CPU = CPU::new(bus);
+    cpu.reset();
+    let report: TestReport = testrom::run(&mut cpu, frames);
+    if !report.text.is_empty() {
+        println!("{}", report.text);
+    }
+    match report.status {
+        TestStatus::Passed => println!("Passed after {} frames", report.frames),
+        TestStatus::Failed(code) => println!("Failed with code {} after {} frames", code, report.frames),
+        TestStatus::Timeout => println!("No result after {} frames", report.frames),
+    }
+    if report.status != TestStatus::Passed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// nes_emul convert <input.fm2|bk2> <output.fm2|bk2>: FCEUX and BizHawk movies, by their extension
+fn convert(input: &Path, output: &Path) -> Result<()> {
+    let movie: Movie = Movie::load(input)?;
+    movie.save(output)?;
+    println!("{} frames written to {}", movie.frames.len(), output.display());
+    Ok(())
+}
+
+// Length of a rendered track without --seconds nor a NSFe time
+const DEFAULT_NSF_SECONDS: f64 = 120.0;
+// Audio queued ahead of the player, in seconds
+const NSF_BUFFER_SECONDS: f64 = 0.1;
+
+// nes_emul nsf <file> [--track <n>] [--wav <file> [--seconds <s>]]: plays the tracks, or renders one to a WAV file
+fn nsf(path: &Path, track: Option<u8>, wav_path: Option<&Path>, seconds: Option<f64>) -> Result<()> {
+    let data: Vec<u8> = std::fs::read(path).map_err(|e| RomError(format!("Cannot read {}: {}", path.display(), e)))?;
+    let nsf: Nsf = Nsf::parse(&data)?;
+    println!("{} - {} ({})", nsf.title, nsf.artist, nsf.copyright);
+    if nsf.expansion != 0 {
+        println!("Warning: the expansion sound chips (${:02x}) are not emulated, some voices will be missing", nsf.expansion);
+    }
+    let mut player: NsfPlayer = NsfPlayer::new(nsf, DEFAULT_SAMPLE_RATE)?;
+    if let Some(track) = track {
+        player.start_track(track - 1);
+    }
+
+    if let Some(wav_path) = wav_path {
+        let seconds: f64 = seconds
+            .or_else(|| player.nsf.track_time_ms(player.track).map(|ms| ms as f64 / 1000.0))
+            .unwrap_or(DEFAULT_NSF_SECONDS);
+        let samples: Vec<i16> = player.render(seconds);
+        wav::write_wav(wav_path, &samples, player.sample_rate())?;
+        println!("Track {}: {} seconds written to {}", player.track_name(), seconds, wav_path.display());
+        return Ok(());
+    }
+
+    let sdl_context = sdl2::init().map_err(AudioError)?;
+    let audio_subsystem = sdl_context.audio().map_err(AudioError)?;
+    let spec: AudioSpecDesired = AudioSpecDesired { freq: Some(player.sample_rate() as i32), channels: Some(1), samples: None };
+    let queue: AudioQueue<i16> = audio_subsystem.open_queue(None, &spec).map_err(AudioError)?;
+    queue.resume();
+    // Bytes of 16 bits samples
+    let buffer_size: u32 = (NSF_BUFFER_SECONDS * player.sample_rate() as f64) as u32 * 2;
+
+    println!("Commands: <number> plays that track, n next, p previous, q quit");
+    println!("Playing {}", player.track_name());
+    let commands: Receiver<String> = stdin_lines();
+    loop {
+        let track: Option<u8> = match commands.try_recv().as_ref().map(|line| line.trim()) {
+            Ok("q") | Err(mpsc::TryRecvError::Disconnected) => break,
+            Ok("n") => Some(player.track.saturating_add(1)),
+            Ok("p") => Some(player.track.saturating_sub(1)),
+            Ok(line) => match line.parse::<u8>() {
+                Ok(number) if number >= 1 => Some(number - 1),
+                _ => {
+                    println!("Unknown command {}", line);
+                    None
                 }
-                if let Some(key) = screen.bindings_joypad2.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                    screen.joypad2.set_button_pressed_status(*key, false);
-                } 
             },
-              _ => ()
+            Err(mpsc::TryRecvError::Empty) => None,
+        };
+        // A NSFe track moves on to the next one when its time is over
+        let over: bool = player.nsf.track_time_ms(player.track)
+            .is_some_and(|ms| player.played as u64 * 1000 >= ms as u64 * player.sample_rate() as u64);
+        let track: Option<u8> = track.or((over && player.track + 1 < player.nsf.songs).then_some(player.track + 1));
+        if let Some(track) = track {
+            queue.clear();
+            player.start_track(track);
+            println!("Playing {}", player.track_name());
+        }
+
+        if queue.size() < buffer_size && !over {
+            if !queue.queue(&player.play_frame()) {
+                return Err(AudioError(sdl2::get_error()).into());
+            }
+        } else {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+    }
+    Ok(())
+}
+
+fn stdin_lines() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in io::stdin().lines().map_while(|line| line.ok()) {
+            if sender.send(line).is_err() {
+                break;
             }
-         }
-        
-    }, joypad1, joypad2);
+        }
+    });
+    receiver
+}
+
+// Called at every frame: draws it, paces the window and reads the keyboard and the mouse
+fn gameloop(ppu: &PPU, screen: &mut Screen) {
+    render::Renderer::render(ppu, &mut screen.frame);
+    let display: &mut Display = match screen.display.as_mut() {
+        Some(display) => display,
+        None => return,
+    };
+    let mut texture: Texture<'_> = display.creator.create_texture_target(PixelFormatEnum::RGB24, 256, 240).expect("Cannot create texture !");
+    texture.update(None, &screen.frame.data, 256 * 3).expect("Cannot update texture !");
+
+    display.canvas.copy(&texture, None, None).expect("Cannot copy texture !");
+
+    display.canvas.present();
+
+    let events: Vec<Event> = display.event_pump.poll_iter().collect();
+    // The light gun, the paddle and the mouse follow the mouse
+    let mouse: MouseInput = display.mouse();
+    drop(texture);
+    display.wait_next_frame();
+    screen.update_viewers(ppu);
+    screen.update_mouse(&mouse);
+    for event in events {
+        match event {
+          Event::Window { window_id, win_event: WindowEvent::Close, .. } if !screen.viewers.is_empty() => screen.close_viewer(window_id),
+          Event::Quit { .. }
+          | Event::KeyDown {
+              keycode: Some(Keycode::Escape),
+              ..
+          } => {
+              // Let the trace, the CDL file and the movie be written before leaving
+              if FLUSH_ON_QUIT.load(Ordering::Relaxed) {
+                  QUIT.store(true, Ordering::Relaxed);
+              } else {
+                  std::process::exit(0)
+              }
+          }
+          Event::KeyDown { keycode: Some(Keycode::F4), .. } => SWITCH_DISK_SIDE.store(true, Ordering::Relaxed),
+          Event::KeyDown { keycode: Some(Keycode::F5), .. } => SAVE_STATE.store(true, Ordering::Relaxed),
+          Event::KeyDown { keycode: Some(Keycode::F7), .. } => LOAD_STATE.store(true, Ordering::Relaxed),
+          Event::KeyDown { keycode: Some(Keycode::F8), .. } => TOGGLE_READ_ONLY.store(true, Ordering::Relaxed),
+          Event::KeyDown { keycode: Some(keycode), .. } => screen.press_key(keycode, true),
+          Event::KeyUp { keycode: Some(keycode), .. } => screen.press_key(keycode, false),
+          _ => ()
+        }
+     }
+}
+
+// The command line wins over the input device given by the NES 2.0 header
+fn plug_controllers(screen: &mut Screen, input: Option<InputSetup>, device: ExpansionDevice) {
+    if let Some(setup) = input.or_else(|| InputSetup::from_expansion_device(device)) {
+        println!("{}", setup.help());
+        setup.plug(screen);
+    }
+}
+
+// nes_emul [run] <rom> [options], headless with --frames
+fn run(options: RunOptions) -> Result<()> {
+
+    // ================================== CPU initialization ========================================
+
+    let data: Vec<u8> = read_patched_file(&options.rom, options.entry.as_deref(), options.patch.as_deref())?;
+    let fds: Option<Fds> = FdsImage::detect(&data).then(|| load_fds(&options.rom, &data, options.bios.as_deref())).transpose()?;
+    let rom: Rom = match fds {
+        Some(_) => Rom::new_from_program_rom(vec![])?,
+        None => Rom::load(&data, options.database)?,
+    };
+    let expansion_device: ExpansionDevice = rom.expansion_device;
+    let mut bus: Bus = Bus::new_headless(rom, gameloop, Joypad::new(), Joypad::new());
+    if let Some(fds) = fds {
+        bus.attach_fds(fds);
+    }
+    plug_controllers(&mut bus.screen, options.input, expansion_device);
+    if options.frames.is_none() {
+        bus.screen.display = Some(Display::open(&options.display)?);
+    }
+
+    // The APU plays the sound of the game and of the disk, slowed down along with a 50Hz window
+    bus.apu = Some(Apu::new((DEFAULT_SAMPLE_RATE as f64 * options.display.frame_rate / DEFAULT_FRAME_RATE) as u32));
 
     let mut cpu: CPU = CPU::new(bus);
+    // A played movie uses its own power-on state
+    if let Some(power_on) = options.power_on {
+        cpu.bus.power_on(power_on);
+    }
     cpu.reset();
-    cpu.run();
 
+    for kind in options.viewers.iter() {
+        cpu.bus.screen.open_viewer(*kind)?;
+    }
+
+    // game.cht next to game.nes by default
+    let cheats_path: PathBuf = options.cheats.clone().unwrap_or_else(|| Cheats::path_for_rom(&options.rom));
+    if cheats_path.exists() {
+        cpu.bus.cheats = Cheats::load(&cheats_path)?;
+    }
+
+    // F5 saves and F7 loads game.ss0
+    let state_path: PathBuf = savestate::path_for_rom(&options.rom, 0);
+    if let Some(path) = options.state.as_ref() {
+        savestate::load_file(&mut cpu, path)?;
+    }
+
+    // F8 toggles read-only
+    let movie_path: Option<&PathBuf> = options.record.as_ref().or(options.play.as_ref());
+    if let Some(path) = options.record.as_ref() {
+        let name: String = options.rom.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        MovieSession::start_recording(&mut cpu, Movie::new(&name));
+        println!("Recording {}", path.display());
+    } else if let Some(path) = options.play.as_ref() {
+        MovieSession::start_playback(&mut cpu, Movie::load(path)?, !options.read_write)?;
+        println!("Playing {}", path.display());
+    }
+
+    if let Some(port) = options.gdb {
+        GdbServer::bind(port)?.serve(&mut cpu)?;
+    } else if options.debug {
+        Repl::new().run(&mut cpu, io::stdin().lock(), &mut io::stdout())?;
+    } else {
+        if let Some(path) = options.cdl.as_ref() {
+            // An existing log keeps accumulating
+            cpu.bus.cdl = match path.exists() {
+                true => Some(CodeDataLog::load(path, cpu.bus.prg_size(), cpu.bus.chr_size())?),
+                false => Some(CodeDataLog::new(cpu.bus.prg_size(), cpu.bus.chr_size())),
+            };
+        }
+        let mut tracer: Option<Tracer<BufWriter<File>>> = options.trace.as_ref().map(|(path, trace)| Tracer::create(path, trace.clone())).transpose()?;
+        let mut panel: Option<MemoryPanel> = options.memview.map(|region| {
+            let mut panel: MemoryPanel = MemoryPanel::new(region);
+            panel.color = true;
+            panel
+        });
+        let commands: Option<Receiver<String>> = panel.as_ref().map(|_| stdin_lines());
+        let mut frame: usize = cpu.bus.frames();
+        let last_frame: Option<usize> = options.frames.map(|frames| cpu.bus.frames() + frames);
+        let mut message: String = String::new();
+        FLUSH_ON_QUIT.store(true, Ordering::Relaxed);
+        cpu.run_with_callback(|cpu| {
+            if let Some(tracer) = tracer.as_mut() {
+                tracer.trace(cpu);
+            }
+            // The memory panel is redrawn once per frame
+            if let (Some(panel), Some(commands)) = (panel.as_mut(), commands.as_ref()) {
+                if cpu.bus.frames() != frame {
+                    frame = cpu.bus.frames();
+                    for command in commands.try_iter() {
+                        message = panel.execute(&mut cpu.bus, &command).unwrap_or_else(|e| e.to_string());
+                    }
+                    panel.refresh(&mut cpu.bus);
+                    print!("\x1b[H\x1b[2J{}\n{}\n> ", panel.render(&mut cpu.bus), message);
+                    io::stdout().flush().ok();
+                }
+            }
+            if SAVE_STATE.swap(false, Ordering::Relaxed) {
+                match savestate::save_file(cpu, &state_path) {
+                    Ok(()) => println!("State saved to {}", state_path.display()),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            if LOAD_STATE.swap(false, Ordering::Relaxed) {
+                match savestate::load_file(cpu, &state_path) {
+                    Ok(()) => println!("State loaded from {}", state_path.display()),
+                    Err(e) => println!("{}", e),
+                }
+                if let Some(movie) = cpu.bus.movie.as_ref() {
+                    println!("{}", movie.status());
+                }
+            }
+            if SWITCH_DISK_SIDE.swap(false, Ordering::Relaxed) {
+                if let Some(fds) = cpu.bus.fds.as_mut() {
+                    println!("Inserting {}", FdsImage::side_name(fds.switch_side()));
+                }
+            }
+            if TOGGLE_READ_ONLY.swap(false, Ordering::Relaxed) {
+                if let Some(movie) = cpu.bus.movie.as_mut() {
+                    movie.read_only = !movie.read_only;
+                    println!("{}", movie.status());
+                }
+            }
+            cpu.running &= !QUIT.load(Ordering::Relaxed) && last_frame.is_none_or(|last| cpu.bus.frames() < last);
+        }, false);
+        if let Some(tracer) = tracer {
+            tracer.finish()?;
+        }
+        if let (Some(path), Some(cdl)) = (options.cdl.as_ref(), cpu.bus.cdl.as_ref()) {
+            cdl.save(path)?;
+        }
+    }
+
+    // A recorded movie, or one played in read+write mode that may have been branched, also from the debugger
+    if let (Some(path), Some(session)) = (movie_path, cpu.bus.movie.as_ref()) {
+        if !session.read_only {
+            session.movie.save(path)?;
+            println!("Movie saved to {}: {}", path.display(), session.status());
+        }
+    }
+
+    if let Some(fds) = cpu.bus.fds.as_ref().filter(|fds| fds.modified) {
+        let path: PathBuf = fds::save_path(&options.rom);
+        std::fs::write(&path, fds.image().to_bytes())?;
+        println!("Disk saved to {}", path.display());
+    }
+
+    if let Some(path) = options.screenshot.as_ref() {
+        snapshot::write_png(path, Frame::WIDTH, Frame::HEIGHT, &cpu.bus.screen.frame.data)?;
+        println!("Screenshot saved to {}", path.display());
+    }
     Ok(())
+}
 
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::parse(&args)? {
+        Command::Help => print!("{}", cli::USAGE),
+        Command::Info { rom, entry, json } => info(&rom, entry.as_deref(), json)?,
+        Command::Disasm { rom, entry, syntax, cdl, output } => disasm(&rom, entry.as_deref(), syntax, cdl.as_deref(), output.as_deref())?,
+        Command::Test { rom, entry, frames } => test(&rom, entry.as_deref(), frames)?,
+        Command::Convert { input, output } => convert(&input, &output)?,
+        Command::Nsf { file, track, wav, seconds } => nsf(&file, track, wav.as_deref(), seconds)?,
+        Command::Run(options) => run(*options)?,
+    }
+    Ok(())
 }
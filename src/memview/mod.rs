@@ -0,0 +1,158 @@
+pub mod panel;
+pub mod ramsearch;
+mod test;
+
+use crate::bus::{Bus, PRG_RAM_START};
+use crate::error::{Error::DebuggerError, Error};
+use crate::mem::Mem;
+
+// Named views over the address spaces and memories of the console, for the memory viewer/hex editor.
+// Every access done here is side-effect free: reading $2002 doesn't clear the vblank flag, poking the
+// PRG or CHR ROM patches it in place
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Region {
+    // Internal RAM, $0000-$07FF
+    Ram,
+    // Cartridge RAM, $6000-$7FFF
+    PrgRam,
+    // The whole CPU address space as the CPU sees it
+    Cpu,
+    // PRG ROM, indexed by its offset in the ROM file
+    Prg,
+    // CHR ROM (or RAM)
+    Chr,
+    // The 2kB of VRAM holding the nametables
+    Nametables,
+    Oam,
+    Palette,
+    // The whole PPU address space, $0000-$3FFF
+    Ppu,
+}
+
+pub const REGIONS: [Region; 9] = [Region::Ram, Region::PrgRam, Region::Cpu, Region::Prg, Region::Chr, Region::Nametables, Region::Oam, Region::Palette, Region::Ppu];
+
+// An address whose value is written back every frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Freeze {
+    pub region: Region,
+    pub offset: usize,
+    pub value: u8,
+}
+
+impl Region {
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Ram => "ram",
+            Region::PrgRam => "prgram",
+            Region::Cpu => "cpu",
+            Region::Prg => "prg",
+            Region::Chr => "chr",
+            Region::Nametables => "nametables",
+            Region::Oam => "oam",
+            Region::Palette => "palette",
+            Region::Ppu => "ppu",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Region, Error> {
+        let name: String = name.to_ascii_lowercase();
+        match name.as_str() {
+            "vram" | "nt" => return Ok(Region::Nametables),
+            "pal" => return Ok(Region::Palette),
+            "sram" | "wram" => return Ok(Region::PrgRam),
+            _ => (),
+        }
+        REGIONS.iter().find(|region| region.name() == name).copied()
+            .ok_or_else(|| DebuggerError(format!("Unknown memory region {}, expected one of {}", name, REGIONS.map(Region::name).join(", "))))
+    }
+
+    pub fn size(self, bus: &Bus) -> usize {
+        match self {
+            Region::Ram => 0x800,
+            Region::PrgRam => 0x2000,
+            Region::Cpu => 0x10000,
+            Region::Prg => bus.prg_size(),
+            Region::Chr => bus.chr_size(),
+            Region::Nametables => 0x800,
+            Region::Oam => 0x100,
+            Region::Palette => 0x20,
+            Region::Ppu => 0x4000,
+        }
+    }
+}
+
+// CPU address of a PRG offset, the program ends at $FFFF
+fn prg_address(bus: &Bus, offset: usize) -> u16 {
+    (0x10000 - bus.prg_size() + offset) as u16
+}
+
+// Outside of the region, reads give 0
+pub fn peek(bus: &mut Bus, region: Region, offset: usize) -> u8 {
+    if offset >= region.size(bus) {
+        return 0;
+    }
+    match region {
+        Region::Ram | Region::Cpu => bus.mem_read_u8_no_fail(offset as u16, true),
+        Region::PrgRam => bus.mem_read_u8_no_fail(PRG_RAM_START + offset as u16, true),
+        Region::Prg => bus.mem_read_u8_no_fail(prg_address(bus, offset), true),
+        Region::Chr => bus.ppu.chr_rom[offset],
+        Region::Nametables => bus.ppu.vram[offset],
+        Region::Oam => bus.ppu.oam_data[offset],
+        Region::Palette => bus.ppu.palette_table[offset],
+        Region::Ppu => bus.ppu.peek(offset as u16),
+    }
+}
+
+// Outside of the region, or on CPU addresses that are neither RAM nor ROM, writes are ignored
+pub fn poke(bus: &mut Bus, region: Region, offset: usize, value: u8) {
+    if offset >= region.size(bus) {
+        return;
+    }
+    match region {
+        Region::Ram | Region::Cpu => bus.poke(offset as u16, value),
+        Region::PrgRam => bus.poke(PRG_RAM_START + offset as u16, value),
+        Region::Prg => bus.poke(prg_address(bus, offset), value),
+        Region::Chr => bus.ppu.chr_rom[offset] = value,
+        Region::Nametables => bus.ppu.vram[offset] = value,
+        Region::Oam => bus.ppu.oam_data[offset] = value,
+        Region::Palette => bus.ppu.palette_table[offset] = value,
+        Region::Ppu => bus.ppu.poke(offset as u16, value),
+    }
+}
+
+pub fn read(bus: &mut Bus, region: Region, start: usize, len: usize) -> Vec<u8> {
+    (start..start.saturating_add(len).min(region.size(bus))).map(|offset| peek(bus, region, offset)).collect()
+}
+
+// Offsets where the pattern starts, None in the pattern matches any byte
+pub fn search(bus: &mut Bus, region: Region, pattern: &[Option<u8>]) -> Vec<usize> {
+    if pattern.is_empty() {
+        return vec![];
+    }
+    let data: Vec<u8> = read(bus, region, 0, region.size(bus));
+    data.windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| window.iter().zip(pattern).all(|(byte, expected)| expected.is_none_or(|expected| expected == *byte)))
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+// Hex bytes separated by spaces, "??" for any byte: "A9 ?? 8D"
+pub fn parse_pattern(text: &str) -> Result<Vec<Option<u8>>, Error> {
+    text.split_whitespace().map(|byte| match byte {
+        "?" | "??" => Ok(None),
+        _ => Ok(Some(parse_byte(byte)?)),
+    }).collect()
+}
+
+// Hexadecimal by default, as in any hex editor
+pub fn parse_number(text: &str) -> Result<usize, Error> {
+    let digits: &str = text.trim_start_matches('$').trim_start_matches("0x");
+    usize::from_str_radix(digits, 16).map_err(|_| DebuggerError(format!("Invalid hexadecimal number {}", text)))
+}
+
+pub fn parse_byte(text: &str) -> Result<u8, Error> {
+    let value: usize = parse_number(text)?;
+    u8::try_from(value).map_err(|_| DebuggerError(format!("{} doesn't fit in a byte", text)))
+}
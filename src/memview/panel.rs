@@ -0,0 +1,166 @@
+use crate::bus::Bus;
+use crate::cheat;
+use crate::error::{Error::DebuggerError, Error};
+
+use super::ramsearch::{self, RamSearch};
+use super::{parse_byte, parse_number, parse_pattern, peek, poke, read, search, Region, REGIONS};
+
+const BYTES_PER_ROW: usize = 16;
+const DEFAULT_ROWS: usize = 16;
+// Matches listed by find, the others are only counted
+const MAX_LISTED_MATCHES: usize = 16;
+
+const CHANGED_COLOR: &str = "\x1b[1;31m";
+const FROZEN_COLOR: &str = "\x1b[36m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+pub const HELP: &str = "\
+Memory commands (offsets and values are hexadecimal):
+  regions                           list the memory regions and their sizes
+  region <name>                     show another region
+  goto <offset>                     scroll to an offset
+  poke <offset> <value>...          write bytes (side-effect free, ROMs included)
+  freeze <offset> [value]           keep a byte at a value (its current one by default)
+  unfreeze <offset>
+  freezes                           list the frozen bytes
+  find <pattern>                    search bytes, ?? matches anything, e.g. find A9 ?? 8D";
+
+// Hex view of a region, highlighting the bytes that changed since the previous refresh
+pub struct MemoryPanel {
+    pub region: Region,
+    pub offset: usize,
+    pub rows: usize,
+    // ANSI colors for the terminal, markers after the bytes otherwise (* changed, = frozen)
+    pub color: bool,
+    pub matches: Vec<usize>,
+    pub ram_search: RamSearch,
+    previous: Vec<u8>,
+    changed: Vec<bool>,
+}
+
+impl MemoryPanel {
+    pub fn new(region: Region) -> Self {
+        MemoryPanel { region, offset: 0, rows: DEFAULT_ROWS, color: false, matches: vec![], ram_search: RamSearch::default(), previous: vec![], changed: vec![] }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.offset = 0;
+        self.matches.clear();
+        self.previous.clear();
+        self.changed.clear();
+    }
+
+    // Takes a snapshot of the region, the bytes that differ from the previous one are highlighted
+    pub fn refresh(&mut self, bus: &mut Bus) {
+        let current: Vec<u8> = read(bus, self.region, 0, self.region.size(bus));
+        self.changed = match self.previous.len() == current.len() {
+            true => current.iter().zip(self.previous.iter()).map(|(now, before)| now != before).collect(),
+            false => vec![false; current.len()],
+        };
+        self.previous = current;
+    }
+
+    pub fn is_changed(&self, offset: usize) -> bool {
+        self.changed.get(offset).copied().unwrap_or(false)
+    }
+
+    pub fn render(&self, bus: &mut Bus) -> String {
+        let size: usize = self.region.size(bus);
+        let mut text: String = format!("{} ${:04X}-${:04X}, {} bytes\n", self.region.name(), 0, size.saturating_sub(1), size);
+        let start: usize = self.offset - self.offset % BYTES_PER_ROW;
+        for row in (start..size).step_by(BYTES_PER_ROW).take(self.rows) {
+            let bytes: Vec<u8> = read(bus, self.region, row, BYTES_PER_ROW);
+            let mut line: String = format!("{:04X}: ", row);
+            for (i, byte) in bytes.iter().enumerate() {
+                let frozen: bool = bus.is_frozen(self.region, row + i);
+                let changed: bool = self.is_changed(row + i);
+                match (self.color, frozen, changed) {
+                    (true, true, _) => line.push_str(&format!("{}{:02X}{} ", FROZEN_COLOR, byte, RESET_COLOR)),
+                    (true, false, true) => line.push_str(&format!("{}{:02X}{} ", CHANGED_COLOR, byte, RESET_COLOR)),
+                    (false, true, _) => line.push_str(&format!("{:02X}=", byte)),
+                    (false, false, true) => line.push_str(&format!("{:02X}*", byte)),
+                    _ => line.push_str(&format!("{:02X} ", byte)),
+                }
+            }
+            let ascii: String = bytes.iter().map(|b| if b.is_ascii_graphic() { *b as char } else { '.' }).collect();
+            line.push_str(&format!("{:width$}|{}|", "", ascii, width = 3 * (BYTES_PER_ROW - bytes.len())));
+            text.push_str(&line);
+            text.push('\n');
+        }
+        text
+    }
+
+    // Runs a command of HELP, returns what to show to the user
+    pub fn execute(&mut self, bus: &mut Bus, line: &str) -> Result<String, Error> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(self.render(bus)),
+            ["h" | "help"] => Ok(format!("{}\n{}\n{}", HELP, ramsearch::HELP, cheat::HELP)),
+            ["cheat", ..] => bus.cheats.execute(&words[1..].join(" ")),
+            ["rs", ..] => self.ram_search.execute(bus, &words[1..].join(" ")),
+            ["regions"] => Ok(REGIONS.iter().map(|region| format!("{:11} {} bytes\n", region.name(), region.size(bus))).collect()),
+            ["region", name] => {
+                self.set_region(Region::parse(name)?);
+                self.refresh(bus);
+                Ok(self.render(bus))
+            }
+            ["goto", offset] => {
+                self.offset = self.parse_offset(bus, offset)?;
+                Ok(self.render(bus))
+            }
+            ["poke", start, values @ ..] if !values.is_empty() => {
+                let start: usize = self.parse_offset(bus, start)?;
+                let values: Vec<u8> = values.iter().map(|value| parse_byte(value)).collect::<Result<_, _>>()?;
+                for (i, value) in values.iter().enumerate() {
+                    // A frozen byte keeps the new value
+                    if bus.is_frozen(self.region, start + i) {
+                        bus.freeze(self.region, start + i, *value);
+                    } else {
+                        poke(bus, self.region, start + i, *value);
+                    }
+                }
+                Ok(format!("Wrote {} bytes at {} ${:04X}", values.len(), self.region.name(), start))
+            }
+            ["freeze", offset] | ["freeze", offset, _] => {
+                let offset: usize = self.parse_offset(bus, offset)?;
+                let value: u8 = match words.get(2) {
+                    Some(value) => parse_byte(value)?,
+                    None => peek(bus, self.region, offset),
+                };
+                bus.freeze(self.region, offset, value);
+                Ok(format!("Froze {} ${:04X} to ${:02X}", self.region.name(), offset, value))
+            }
+            ["unfreeze", offset] => {
+                let offset: usize = self.parse_offset(bus, offset)?;
+                match bus.unfreeze(self.region, offset) {
+                    true => Ok(format!("Unfroze {} ${:04X}", self.region.name(), offset)),
+                    false => Err(DebuggerError(format!("{} ${:04X} isn't frozen", self.region.name(), offset))),
+                }
+            }
+            ["freezes"] => Ok(bus.freezes.iter().map(|freeze| format!("{} ${:04X} = ${:02X}\n", freeze.region.name(), freeze.offset, freeze.value)).collect()),
+            ["find", ..] => {
+                let pattern: Vec<Option<u8>> = parse_pattern(&words[1..].join(" "))?;
+                if pattern.is_empty() {
+                    return Err(DebuggerError(String::from("Usage: find <pattern>")));
+                }
+                self.matches = search(bus, self.region, &pattern);
+                if let Some(first) = self.matches.first() {
+                    self.offset = *first;
+                }
+                let listed: Vec<String> = self.matches.iter().take(MAX_LISTED_MATCHES).map(|offset| format!("${:04X}", offset)).collect();
+                let more: String = if self.matches.len() > MAX_LISTED_MATCHES { String::from(" ...") } else { String::new() };
+                Ok(format!("{} matches in {}: {}{}", self.matches.len(), self.region.name(), listed.join(" "), more))
+            }
+            _ => Err(DebuggerError(format!("Unknown memory command {}, try help", line.trim()))),
+        }
+    }
+
+    fn parse_offset(&self, bus: &Bus, text: &str) -> Result<usize, Error> {
+        let offset: usize = parse_number(text)?;
+        match offset < self.region.size(bus) {
+            true => Ok(offset),
+            false => Err(DebuggerError(format!("${:X} is outside of {}", offset, self.region.name()))),
+        }
+    }
+}
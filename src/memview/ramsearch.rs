@@ -0,0 +1,211 @@
+use crate::bus::Bus;
+use crate::error::{Error::DebuggerError, Error};
+
+use super::{peek, Region};
+
+// Candidates listed by default, the others are only counted
+const DEFAULT_LISTED: usize = 20;
+
+pub const HELP: &str = "\
+RAM search commands (values are decimal, or hexadecimal with $):
+  rs reset [1|2] [signed]           start over with every byte (or 16-bit word) of the RAM and PRG-RAM
+  rs <op> [value]                   keep the candidates whose value compares to the value, or to their
+                                    previous value when omitted; op is one of = != > < >= <=
+  rs list [count]                   candidates with their current and previous values
+  rs undo                           cancel the last filter";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueSize {
+    Byte,
+    // Little endian, as the 6502 stores 16-bit values
+    Word,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    GreaterOrEqual,
+    LessOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference {
+    // The value at the previous snapshot
+    Previous,
+    Value(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub region: Region,
+    pub offset: usize,
+    pub previous: i64,
+}
+
+// The classic cheat finder: snapshot the RAM, then narrow the addresses down by comparing them with
+// their previous values or with constants until only the interesting ones are left
+pub struct RamSearch {
+    pub regions: Vec<Region>,
+    pub size: ValueSize,
+    pub signed: bool,
+    candidates: Vec<Candidate>,
+    // Candidates before each filter, for undo
+    history: Vec<Vec<Candidate>>,
+}
+
+impl Comparison {
+    pub fn parse(text: &str) -> Result<Comparison, Error> {
+        match text {
+            "=" | "==" => Ok(Comparison::Equal),
+            "!=" | "<>" => Ok(Comparison::NotEqual),
+            ">" => Ok(Comparison::Greater),
+            "<" => Ok(Comparison::Less),
+            ">=" => Ok(Comparison::GreaterOrEqual),
+            "<=" => Ok(Comparison::LessOrEqual),
+            _ => Err(DebuggerError(format!("Unknown comparison {}, expected = != > < >= <=", text))),
+        }
+    }
+
+    pub fn matches(self, value: i64, reference: i64) -> bool {
+        match self {
+            Comparison::Equal => value == reference,
+            Comparison::NotEqual => value != reference,
+            Comparison::Greater => value > reference,
+            Comparison::Less => value < reference,
+            Comparison::GreaterOrEqual => value >= reference,
+            Comparison::LessOrEqual => value <= reference,
+        }
+    }
+}
+
+impl Default for RamSearch {
+    fn default() -> Self {
+        RamSearch::new(ValueSize::Byte, false)
+    }
+}
+
+impl RamSearch {
+    pub fn new(size: ValueSize, signed: bool) -> Self {
+        RamSearch { regions: vec![Region::Ram, Region::PrgRam], size, signed, candidates: vec![], history: vec![] }
+    }
+
+    pub fn value(&self, bus: &mut Bus, region: Region, offset: usize) -> i64 {
+        let low: u8 = peek(bus, region, offset);
+        match (self.size, self.signed) {
+            (ValueSize::Byte, false) => low as i64,
+            (ValueSize::Byte, true) => low as i8 as i64,
+            (ValueSize::Word, signed) => {
+                let word: u16 = u16::from_le_bytes([low, peek(bus, region, offset + 1)]);
+                if signed { word as i16 as i64 } else { word as i64 }
+            }
+        }
+    }
+
+    // Every address of the regions becomes a candidate again
+    pub fn reset(&mut self, bus: &mut Bus) {
+        let width: usize = if self.size == ValueSize::Word { 2 } else { 1 };
+        self.history.clear();
+        self.candidates = vec![];
+        for region in self.regions.clone() {
+            for offset in 0..=region.size(bus).saturating_sub(width) {
+                let previous: i64 = self.value(bus, region, offset);
+                self.candidates.push(Candidate { region, offset, previous });
+            }
+        }
+    }
+
+    // Keeps the matching candidates and takes a new snapshot of their values, returns how many are left
+    pub fn filter(&mut self, bus: &mut Bus, comparison: Comparison, reference: Reference) -> usize {
+        let mut kept: Vec<Candidate> = vec![];
+        for candidate in self.candidates.iter() {
+            let value: i64 = self.value(bus, candidate.region, candidate.offset);
+            let reference: i64 = match reference {
+                Reference::Previous => candidate.previous,
+                Reference::Value(value) => value,
+            };
+            if comparison.matches(value, reference) {
+                kept.push(Candidate { previous: value, ..*candidate });
+            }
+        }
+        self.history.push(std::mem::replace(&mut self.candidates, kept));
+        self.candidates.len()
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.history.pop() {
+            Some(candidates) => { self.candidates = candidates; true }
+            None => false,
+        }
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    // Runs a command of HELP (without the rs prefix), returns what to show to the user
+    pub fn execute(&mut self, bus: &mut Bus, line: &str) -> Result<String, Error> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["reset", options @ ..] => {
+                self.size = ValueSize::Byte;
+                self.signed = false;
+                for option in options {
+                    match *option {
+                        "1" => self.size = ValueSize::Byte,
+                        "2" => self.size = ValueSize::Word,
+                        "signed" => self.signed = true,
+                        "unsigned" => self.signed = false,
+                        other => return Err(DebuggerError(format!("Unknown option {}, expected 1, 2, signed or unsigned", other))),
+                    }
+                }
+                self.reset(bus);
+                Ok(format!("{} candidates", self.candidates.len()))
+            }
+            ["list"] | ["list", _] => {
+                let count: usize = match words.get(1) {
+                    Some(count) => count.parse().map_err(|_| DebuggerError(format!("Invalid count {}", count)))?,
+                    None => DEFAULT_LISTED,
+                };
+                let mut text: String = format!("{} candidates\n", self.candidates.len());
+                for candidate in self.candidates.iter().take(count) {
+                    let value: i64 = self.value(bus, candidate.region, candidate.offset);
+                    text.push_str(&format!("{} ${:04X}: {} (previous {})\n", candidate.region.name(), candidate.offset, value, candidate.previous));
+                }
+                Ok(text)
+            }
+            ["undo"] => match self.undo() {
+                true => Ok(format!("{} candidates", self.candidates.len())),
+                false => Err(DebuggerError(String::from("Nothing to undo"))),
+            },
+            [comparison] | [comparison, _] => {
+                let comparison: Comparison = Comparison::parse(comparison)?;
+                let reference: Reference = match words.get(1) {
+                    Some(value) => Reference::Value(parse_value(value)?),
+                    None => Reference::Previous,
+                };
+                if self.candidates.is_empty() && self.history.is_empty() {
+                    self.reset(bus);
+                }
+                Ok(format!("{} candidates", self.filter(bus, comparison, reference)))
+            }
+            _ => Err(DebuggerError(format!("Unknown RAM search command {}, try help", line.trim()))),
+        }
+    }
+}
+
+// Decimal, possibly negative, or hexadecimal with $ or 0x
+fn parse_value(text: &str) -> Result<i64, Error> {
+    let (negative, digits): (bool, &str) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value: Result<i64, _> = match digits.strip_prefix('$').or_else(|| digits.strip_prefix("0x")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    let value: i64 = value.map_err(|_| DebuggerError(format!("Invalid value {}", text)))?;
+    Ok(if negative { -value } else { value })
+}
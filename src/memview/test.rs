@@ -0,0 +1,137 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::asm;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+    use crate::rom::{Mirroring, Rom};
+
+    use super::super::panel::MemoryPanel;
+    use super::super::ramsearch::{Candidate, Comparison, RamSearch, Reference};
+    use super::super::*;
+
+    // Clears $10 once, then loops forever
+    fn load() -> CPU {
+        let program: Vec<u8> = asm::assemble("LDA #0\nSTA $10\nloop: JMP loop").unwrap().program_rom().unwrap();
+        let mut rom: Rom = Rom::new_from_program_rom(program).unwrap();
+        rom.chr_rom = (0..0x2000).map(|i| i as u8).collect();
+        rom.screen_mirroring = Mirroring::HORIZONTAL;
+        CPU::test_load_rom(rom)
+    }
+
+    #[test]
+    fn test_regions() {
+        let mut cpu: CPU = load();
+        let bus: &mut Bus = &mut cpu.bus;
+        assert_eq!(Region::parse("OAM").unwrap(), Region::Oam);
+        assert_eq!(Region::parse("vram").unwrap(), Region::Nametables);
+        assert_eq!(Region::parse("sram").unwrap(), Region::PrgRam);
+        assert!(Region::parse("rom").unwrap_err().to_string().contains("ram, prgram, cpu, prg"));
+        assert_eq!(REGIONS.map(|region| region.size(bus)), [0x800, 0x2000, 0x10000, 0x8000, 0x2000, 0x800, 0x100, 0x20, 0x4000]);
+
+        poke(bus, Region::Ram, 0x10, 0x42);
+        assert_eq!(peek(bus, Region::Cpu, 0x0810), 0x42);
+        poke(bus, Region::PrgRam, 0x10, 0x24);
+        assert_eq!(peek(bus, Region::Cpu, 0x6010), 0x24);
+        assert_eq!(peek(bus, Region::Prg, 0), 0xa9);
+        poke(bus, Region::Cpu, 0x8001, 0x07);
+        assert_eq!(peek(bus, Region::Prg, 1), 0x07);
+        assert_eq!(peek(bus, Region::Chr, 0x1234), 0x34);
+
+        // $3F10 mirrors $3F00, the second nametable mirrors the first one
+        poke(bus, Region::Ppu, 0x3f10, 0x0f);
+        poke(bus, Region::Ppu, 0x2405, 0x55);
+        assert_eq!(peek(bus, Region::Palette, 0), 0x0f);
+        assert_eq!(peek(bus, Region::Nametables, 0x005), 0x55);
+        assert_eq!(peek(bus, Region::Oam, 0x100), 0);
+
+        // Reading $2002 through the viewer keeps the vblank flag
+        bus.ppu.reg_status.set_vblank_status(true);
+        assert_eq!(peek(bus, Region::Cpu, 0x2002) & 0x80, 0x80);
+        assert!(bus.ppu.reg_status.is_in_vblank());
+    }
+
+    #[test]
+    fn test_search() {
+        let mut cpu: CPU = load();
+        assert_eq!(parse_pattern("A9 ?? $8D").unwrap(), vec![Some(0xa9), None, Some(0x8d)]);
+        assert!(parse_pattern("A9 100").is_err());
+        assert_eq!(search(&mut cpu.bus, Region::Prg, &parse_pattern("A9 ?? 85").unwrap()), vec![0]);
+        assert_eq!(search(&mut cpu.bus, Region::Chr, &parse_pattern("FE FF").unwrap()).len(), 32);
+        assert_eq!(search(&mut cpu.bus, Region::Chr, &[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut cpu: CPU = load();
+        cpu.bus.freeze(Region::Ram, 0x10, 0x42);
+        cpu.bus.freeze(Region::Ram, 0x10, 0x43);
+        assert_eq!(cpu.bus.freezes.len(), 1);
+        // The program clears $10, the freeze puts it back at the next frame
+        cpu.run_frame();
+        assert_eq!(peek(&mut cpu.bus, Region::Ram, 0x10), 0x43);
+        assert!(cpu.bus.unfreeze(Region::Ram, 0x10));
+        assert!(!cpu.bus.unfreeze(Region::Ram, 0x10));
+    }
+
+    #[test]
+    fn test_ram_search() {
+        let mut cpu: CPU = load();
+        let bus: &mut Bus = &mut cpu.bus;
+        let mut search: RamSearch = RamSearch::default();
+        search.reset(bus);
+        assert_eq!(search.candidates().len(), 0x800 + 0x2000);
+
+        bus.poke(0x0010, 3);
+        bus.poke(0x6020, 3);
+        assert_eq!(search.filter(bus, Comparison::Equal, Reference::Value(3)), 2);
+        // One life lost at $0010, the other one is something else
+        bus.poke(0x0010, 2);
+        bus.poke(0x6020, 5);
+        assert_eq!(search.filter(bus, Comparison::Less, Reference::Previous), 1);
+        assert_eq!(search.candidates(), [Candidate { region: Region::Ram, offset: 0x10, previous: 2 }]);
+        assert!(search.undo());
+        assert_eq!(search.candidates().len(), 2);
+
+        // Little endian words, signed
+        bus.poke(0x0030, 0xfe);
+        bus.poke(0x0031, 0xff);
+        assert_eq!(search.execute(bus, "reset 2 signed").unwrap(), format!("{} candidates", 0x7ff + 0x1fff));
+        assert_eq!(search.execute(bus, "= -2").unwrap(), "1 candidates");
+        assert_eq!(search.execute(bus, "list").unwrap(), "1 candidates\nram $0030: -2 (previous -2)\n");
+        assert_eq!(search.execute(bus, "reset").unwrap(), "10240 candidates");
+        assert_eq!(search.execute(bus, ">= $fe").unwrap(), "2 candidates");
+        assert!(search.execute(bus, "=~ 2").is_err());
+        assert!(search.execute(bus, "reset 4").is_err());
+    }
+
+    #[test]
+    fn test_panel() {
+        let mut cpu: CPU = load();
+        let mut panel: MemoryPanel = MemoryPanel::new(Region::Ram);
+        panel.rows = 2;
+        panel.refresh(&mut cpu.bus);
+        cpu.bus.poke(0x0003, 0x41);
+        panel.refresh(&mut cpu.bus);
+        assert_eq!(panel.execute(&mut cpu.bus, "freeze 5 7").unwrap(), "Froze ram $0005 to $07");
+        assert_eq!(panel.render(&mut cpu.bus), "\
+ram $0000-$07FF, 2048 bytes
+0000: 00 00 00 41*00 07=00 00 00 00 00 00 00 00 00 00 |...A............|
+0010: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 |................|
+");
+
+        assert_eq!(panel.execute(&mut cpu.bus, "poke 5 08 09").unwrap(), "Wrote 2 bytes at ram $0005");
+        assert_eq!(cpu.bus.freezes[0].value, 0x08);
+        assert_eq!(panel.execute(&mut cpu.bus, "freezes").unwrap(), "ram $0005 = $08\n");
+        assert!(panel.execute(&mut cpu.bus, "goto 800").is_err());
+
+        assert_eq!(panel.execute(&mut cpu.bus, "region chr").unwrap().lines().nth(1), Some("0000: 00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F |................|"));
+        assert_eq!(panel.execute(&mut cpu.bus, "find 30 ?? 32").unwrap(), "32 matches in chr: $0030 $0130 $0230 $0330 $0430 $0530 $0630 $0730 $0830 $0930 $0A30 $0B30 $0C30 $0D30 $0E30 $0F30 ...");
+        assert_eq!(panel.render(&mut cpu.bus).lines().nth(1), Some("0030: 30 31 32 33 34 35 36 37 38 39 3A 3B 3C 3D 3E 3F |0123456789:;<=>?|"));
+        assert!(panel.execute(&mut cpu.bus, "frobnicate").is_err());
+    }
+}
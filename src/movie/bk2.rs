@@ -0,0 +1,196 @@
+use crate::archive::{self, ZipEntry};
+use crate::error::{Error::MovieError, Error};
+use crate::input::JoypadButton;
+use crate::poweron::PowerOn;
+use crate::rom::hash;
+
+use super::{Movie, MovieFrame, COMMAND_HARD_RESET, COMMAND_SOFT_RESET};
+
+// BizHawk movies: a zip holding Header.txt ("Key Value" lines), Comments.txt and Input Log.txt:
+//   [Input]
+//   LogKey:#Reset|Power|#P1 Up|P1 Down|P1 Left|P1 Right|P1 Start|P1 Select|P1 B|P1 A|#P2 Up|...|
+//   |..|UDLRSsBA|UDLRSsBA|
+//   [/Input]
+// Each input line has one field per group of the LogKey, one character per button ('.' when released).
+// A movie starting from a save state has it in Core.bin, in the format of the core that made it,
+// so the states of BizHawk cannot be loaded here (nor ours there)
+
+pub const EXTENSION: &str = "bk2";
+
+const HEADER: &str = "Header.txt";
+const INPUT_LOG: &str = "Input Log.txt";
+const COMMENTS: &str = "Comments.txt";
+const SAVESTATE: &str = "Core.bin";
+
+const MOVIE_VERSION: &str = "BizHawk v2.0.0";
+
+// Gamepad buttons in the order of the log, with their mnemonics
+const JOYPAD_BUTTONS: [(&str, char, JoypadButton); 8] = [
+    ("Up", 'U', JoypadButton::UP),
+    ("Down", 'D', JoypadButton::DOWN),
+    ("Left", 'L', JoypadButton::LEFT),
+    ("Right", 'R', JoypadButton::RIGHT),
+    ("Start", 'S', JoypadButton::START),
+    ("Select", 's', JoypadButton::SELECT),
+    ("B", 'B', JoypadButton::BUTTON_B),
+    ("A", 'A', JoypadButton::BUTTON_A),
+];
+
+const CONSOLE_BUTTONS: [(&str, char, u8); 2] = [("Reset", 'r', COMMAND_SOFT_RESET), ("Power", 'P', COMMAND_HARD_RESET)];
+
+// What pressing a button of the LogKey does to a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Button {
+    Command(u8),
+    Joypad(usize, JoypadButton),
+}
+
+fn parse_button(name: &str) -> Result<Button, Error> {
+    if let Some((_, _, command)) = CONSOLE_BUTTONS.iter().find(|(console, _, _)| *console == name) {
+        return Ok(Button::Command(*command));
+    }
+    let port: usize = match name.get(..3) {
+        Some("P1 ") => 0,
+        Some("P2 ") => 1,
+        _ => return Err(MovieError(format!("Unsupported button {}", name))),
+    };
+    JOYPAD_BUTTONS.iter().find(|(button, _, _)| *button == &name[3..])
+        .map(|(_, _, button)| Button::Joypad(port, *button))
+        .ok_or_else(|| MovieError(format!("Unsupported button {}, only joypads are", name)))
+}
+
+// One group of buttons per field of the input lines
+fn parse_log_key(key: &str) -> Result<Vec<Vec<Button>>, Error> {
+    key.split('#')
+        .filter(|group| !group.is_empty())
+        .map(|group| group.split('|').filter(|name| !name.is_empty()).map(parse_button).collect())
+        .collect()
+}
+
+fn parse_frame(line: &str, groups: &[Vec<Button>]) -> Result<MovieFrame, Error> {
+    let fields: Vec<&str> = line.trim_matches('|').split('|').collect();
+    if fields.len() != groups.len() {
+        return Err(MovieError(format!("Invalid input line {}, expected {} fields", line, groups.len())));
+    }
+    let mut frame: MovieFrame = MovieFrame::new(JoypadButton::empty(), JoypadButton::empty());
+    for (field, group) in fields.iter().zip(groups) {
+        if field.chars().count() != group.len() {
+            return Err(MovieError(format!("Invalid input line {}, expected {} buttons in {}", line, group.len(), field)));
+        }
+        for (c, button) in field.chars().zip(group) {
+            match (c != '.' && c != ' ', button) {
+                (false, _) => (),
+                (true, Button::Command(command)) => frame.commands |= command,
+                (true, Button::Joypad(0, button)) => frame.joypad1 |= *button,
+                (true, Button::Joypad(_, button)) => frame.joypad2 |= *button,
+            }
+        }
+    }
+    Ok(frame)
+}
+
+fn parse_input_log(text: &str, movie: &mut Movie) -> Result<(), Error> {
+    let mut groups: Option<Vec<Vec<Button>>> = None;
+    for (number, line) in text.lines().enumerate() {
+        let error = |e: Error| MovieError(format!("{} line {}: {}", INPUT_LOG, number + 1, e));
+        let line: &str = line.trim_end_matches('\r');
+        if let Some(key) = line.strip_prefix("LogKey:") {
+            let key: Vec<Vec<Button>> = parse_log_key(key).map_err(error)?;
+            movie.ports[1] = key.iter().flatten().any(|button| matches!(button, Button::Joypad(1, _)));
+            groups = Some(key);
+        } else if line.starts_with('|') {
+            let groups: &[Vec<Button>] = groups.as_deref().ok_or_else(|| error(MovieError(String::from("Input before the LogKey"))))?;
+            movie.frames.push(parse_frame(line, groups).map_err(error)?);
+        }
+    }
+    Ok(())
+}
+
+pub fn parse(data: &[u8]) -> Result<Movie, Error> {
+    let entries: Vec<ZipEntry> = archive::read_zip(data)?;
+    let file = |name: &str| entries.iter().find(|entry| entry.name == name).map(|entry| String::from_utf8_lossy(&entry.data).into_owned());
+
+    let mut movie: Movie = Movie::new("");
+    let header: String = file(HEADER).ok_or_else(|| MovieError(format!("This is not a BizHawk movie, it has no {}", HEADER)))?;
+    let mut from_savestate: bool = false;
+    for line in header.lines() {
+        let (key, value): (&str, &str) = line.trim_end_matches('\r').split_once(' ').unwrap_or((line, ""));
+        match key {
+            "Platform" if value != "NES" => return Err(MovieError(format!("The movie is for the {}, not the NES", value))),
+            "GameName" => movie.rom_filename = String::from(value),
+            "SHA1" => {
+                let sha1: Vec<u8> = hash::from_hex(value).ok_or_else(|| MovieError(format!("Invalid SHA1 {}", value)))?;
+                movie.rom_sha1 = Some(sha1.try_into().map_err(|_| MovieError(format!("Invalid SHA1 {}", value)))?);
+            }
+            "rerecordCount" => movie.rerecord_count = value.parse().map_err(|_| MovieError(format!("Invalid rerecordCount {}", value)))?,
+            "StartsFromSavestate" => from_savestate = value.eq_ignore_ascii_case("true"),
+            "GUID" => movie.guid = String::from(value),
+            "PowerOn" => movie.power_on = Some(PowerOn::parse(value)?),
+            // MovieVersion, Author, emuVersion, Core, BoardName...
+            _ => (),
+        }
+    }
+    if let Some(comments) = file(COMMENTS) {
+        movie.comments = comments.lines().map(String::from).collect();
+    }
+    if from_savestate {
+        let state: &ZipEntry = entries.iter().find(|entry| entry.name == SAVESTATE)
+            .ok_or_else(|| MovieError(format!("The movie starts from a save state but has no {}", SAVESTATE)))?;
+        movie.savestate = Some(state.data.clone());
+    }
+    let input_log: String = file(INPUT_LOG).ok_or_else(|| MovieError(format!("The movie has no {}", INPUT_LOG)))?;
+    parse_input_log(&input_log, &mut movie)?;
+    Ok(movie)
+}
+
+fn input_log(movie: &Movie) -> String {
+    let mut key: String = String::from("LogKey:#");
+    for (name, _, _) in CONSOLE_BUTTONS.iter() {
+        key.push_str(&format!("{}|", name));
+    }
+    for port in (0..2).filter(|port| movie.ports[*port]) {
+        key.push('#');
+        for (name, _, _) in JOYPAD_BUTTONS.iter() {
+            key.push_str(&format!("P{} {}|", port + 1, name));
+        }
+    }
+
+    let mut text: String = format!("[Input]\n{}\n", key);
+    for frame in movie.frames.iter() {
+        let mut line: String = String::from("|");
+        line.extend(CONSOLE_BUTTONS.iter().map(|(_, c, command)| if frame.commands & command != 0 { *c } else { '.' }));
+        for (buttons, _) in [frame.joypad1, frame.joypad2].iter().zip(movie.ports).filter(|(_, plugged)| *plugged) {
+            line.push('|');
+            line.extend(JOYPAD_BUTTONS.iter().map(|(_, c, button)| if buttons.contains(*button) { *c } else { '.' }));
+        }
+        text.push_str(&line);
+        text.push_str("|\n");
+    }
+    text.push_str("[/Input]\n");
+    text
+}
+
+pub fn to_bytes(movie: &Movie) -> Vec<u8> {
+    let mut header: String = format!("MovieVersion {}\nPlatform NES\nGameName {}\n", MOVIE_VERSION, movie.rom_filename);
+    // Movies imported from FCEUX only know the MD5
+    if let Some(sha1) = movie.rom_sha1 {
+        header.push_str(&format!("SHA1 {}\n", hash::to_hex(&sha1).to_ascii_uppercase()));
+    }
+    header.push_str(&format!("rerecordCount {}\nGUID {}\n", movie.rerecord_count, movie.guid));
+    if let Some(power_on) = movie.power_on {
+        header.push_str(&format!("PowerOn {}\n", power_on.name()));
+    }
+    if movie.savestate.is_some() {
+        header.push_str("StartsFromSavestate True\n");
+    }
+
+    let mut entries: Vec<ZipEntry> = vec![
+        ZipEntry { name: String::from(HEADER), data: header.into_bytes() },
+        ZipEntry { name: String::from(COMMENTS), data: movie.comments.iter().map(|comment| format!("{}\n", comment)).collect::<String>().into_bytes() },
+        ZipEntry { name: String::from(INPUT_LOG), data: input_log(movie).into_bytes() },
+    ];
+    if let Some(state) = movie.savestate.as_ref() {
+        entries.push(ZipEntry { name: String::from(SAVESTATE), data: state.clone() });
+    }
+    archive::write_zip(&entries)
+}
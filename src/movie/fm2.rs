@@ -0,0 +1,142 @@
+use crate::error::{Error::MovieError, Error};
+use crate::input::JoypadButton;
+use crate::poweron::PowerOn;
+
+use super::{Movie, MovieFrame};
+
+// FCEUX text movies: "key value" header lines, then one line per frame
+//   |commands|RLDUTSBA|RLDUTSBA||
+// with the buttons of both ports, a '.' (or a space) for a released button
+
+const VERSION: u32 = 3;
+const EMU_VERSION: u32 = 1;
+// Devices of port0/port1 in the header
+const SI_NONE: u32 = 0;
+const SI_GAMEPAD: u32 = 1;
+
+const BUTTONS: &str = "RLDUTSBA";
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut text: String = String::new();
+    for chunk in data.chunks(3) {
+        let bits: u32 = chunk.iter().enumerate().fold(0, |bits, (i, byte)| bits | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => text.push(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => text.push('='),
+            }
+        }
+    }
+    text
+}
+
+pub fn base64_decode(text: &str) -> Result<Vec<u8>, Error> {
+    let digits: Vec<u32> = text.trim_end_matches('=').bytes()
+        .map(|c| BASE64.iter().position(|digit| *digit == c).map(|n| n as u32))
+        .collect::<Option<_>>()
+        .ok_or_else(|| MovieError(String::from("Invalid base64 data")))?;
+    let mut data: Vec<u8> = vec![];
+    for chunk in digits.chunks(4) {
+        let bits: u32 = chunk.iter().enumerate().fold(0, |bits, (i, digit)| bits | digit << (18 - 6 * i));
+        for i in 0..chunk.len().saturating_sub(1) {
+            data.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(data)
+}
+
+fn parse_buttons(text: &str) -> Result<JoypadButton, Error> {
+    if text.len() != BUTTONS.len() {
+        return Err(MovieError(format!("Invalid joypad input {}, expected 8 buttons like {}", text, BUTTONS)));
+    }
+    let bits: u8 = text.chars().enumerate()
+        .filter(|(_, c)| *c != '.' && *c != ' ')
+        .fold(0, |bits, (i, _)| bits | 0x80 >> i);
+    Ok(JoypadButton::from_bits_truncate(bits))
+}
+
+fn format_buttons(buttons: JoypadButton) -> String {
+    BUTTONS.chars().enumerate().map(|(i, c)| if buttons.bits() & 0x80 >> i != 0 { c } else { '.' }).collect()
+}
+
+fn parse_frame(line: &str, ports: [bool; 2]) -> Result<MovieFrame, Error> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 4 {
+        return Err(MovieError(format!("Invalid input line {}", line)));
+    }
+    let commands: u8 = fields[1].trim().parse().map_err(|_| MovieError(format!("Invalid commands in {}", line)))?;
+    let joypad = |i: usize| match ports[i] {
+        true => parse_buttons(fields[2 + i]),
+        false => Ok(JoypadButton::empty()),
+    };
+    Ok(MovieFrame { commands, joypad1: joypad(0)?, joypad2: joypad(1)? })
+}
+
+pub fn parse(text: &str) -> Result<Movie, Error> {
+    let mut movie: Movie = Movie::new("");
+    for (number, line) in text.lines().enumerate() {
+        let error = |e: Error| MovieError(format!("line {}: {}", number + 1, e));
+        let line: &str = line.trim_end_matches('\r');
+        if line.starts_with('|') {
+            movie.frames.push(parse_frame(line, movie.ports).map_err(error)?);
+            continue;
+        }
+        let (key, value): (&str, &str) = line.split_once(' ').unwrap_or((line, ""));
+        let number = || value.trim().parse::<u32>().map_err(|_| error(MovieError(format!("Invalid {} {}", key, value))));
+        match key {
+            "" => (),
+            "version" if number()? != VERSION => return Err(error(MovieError(format!("Unsupported FM2 version {}", value)))),
+            "rerecordCount" => movie.rerecord_count = number()?,
+            "romFilename" => movie.rom_filename = String::from(value),
+            "guid" => movie.guid = String::from(value),
+            "comment" => movie.comments.push(String::from(value)),
+            "romChecksum" => {
+                let bytes: Vec<u8> = base64_decode(value.trim_start_matches("base64:")).map_err(error)?;
+                movie.rom_md5 = Some(bytes.try_into().map_err(|_| error(MovieError(String::from("The ROM checksum should be a MD5"))))?);
+            }
+            "port0" | "port1" => {
+                let port: usize = if key == "port0" { 0 } else { 1 };
+                movie.ports[port] = match number()? {
+                    SI_NONE => false,
+                    SI_GAMEPAD => true,
+                    device => return Err(error(MovieError(format!("Unsupported device {} in {}, only joypads are", device, key)))),
+                };
+            }
+            "powerOn" => movie.power_on = Some(PowerOn::parse(value).map_err(error)?),
+            "savestate" => movie.savestate = Some(base64_decode(value.trim_start_matches("base64:")).map_err(error)?),
+            "fourscore" | "binary" | "palFlag" if number()? != 0 => return Err(error(MovieError(format!("{} movies are not supported", key)))),
+            // emuVersion, microphone, port2, FDS, NewPPU, subtitle...
+            _ => (),
+        }
+    }
+    Ok(movie)
+}
+
+pub fn to_text(movie: &Movie) -> String {
+    let port = |plugged: bool| if plugged { SI_GAMEPAD } else { SI_NONE };
+    let mut text: String = format!("version {}\nemuVersion {}\nrerecordCount {}\npalFlag 0\nromFilename {}\n",
+        VERSION, EMU_VERSION, movie.rerecord_count, movie.rom_filename);
+    // Movies imported from BizHawk only know the SHA-1
+    if let Some(md5) = movie.rom_md5 {
+        text.push_str(&format!("romChecksum base64:{}\n", base64_encode(&md5)));
+    }
+    text.push_str(&format!("guid {}\n", movie.guid));
+    text.push_str(&format!("fourscore 0\nmicrophone 0\nport0 {}\nport1 {}\nport2 0\nFDS 0\nNewPPU 0\n", port(movie.ports[0]), port(movie.ports[1])));
+    // Not an FCEUX key, FCEUX ignores it
+    if let Some(power_on) = movie.power_on {
+        text.push_str(&format!("powerOn {}\n", power_on.name()));
+    }
+    for comment in movie.comments.iter() {
+        text.push_str(&format!("comment {}\n", comment));
+    }
+    if let Some(state) = movie.savestate.as_ref() {
+        text.push_str(&format!("savestate base64:{}\n", base64_encode(state)));
+    }
+    for frame in movie.frames.iter() {
+        let joypad = |plugged: bool, buttons: JoypadButton| if plugged { format_buttons(buttons) } else { String::new() };
+        text.push_str(&format!("|{}|{}|{}||\n", frame.commands, joypad(movie.ports[0], frame.joypad1), joypad(movie.ports[1], frame.joypad2)));
+    }
+    text
+}
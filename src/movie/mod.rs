@@ -0,0 +1,216 @@
+pub mod bk2;
+pub mod fm2;
+mod test;
+
+use std::path::Path;
+
+use crate::cpu::CPU;
+use crate::error::{Error::MovieError, Error};
+use crate::input::{Joypad, JoypadButton};
+use crate::poweron::PowerOn;
+use crate::rom::hash;
+use crate::savestate;
+
+// Input movies: the buttons held on both joypads at every frame, from power-on or from a save state.
+// Playing one back gives the same frames as when it was recorded, which makes bug reports reproducible
+// and is the base of tool-assisted runs: save states are made along the way, and loading one in
+// read+write mode cuts the movie there and records a new branch (a rerecord).
+
+// Frame commands, as in FCEUX. They are kept in the files but not emulated
+pub const COMMAND_SOFT_RESET: u8 = 1;
+pub const COMMAND_HARD_RESET: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovieFrame {
+    pub commands: u8,
+    pub joypad1: JoypadButton,
+    pub joypad2: JoypadButton,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_filename: String,
+    // Checksums of the PRG and CHR ROM, FCEUX movies have the MD5 and BizHawk ones the SHA-1
+    pub rom_md5: Option<[u8; 16]>,
+    pub rom_sha1: Option<[u8; 20]>,
+    pub guid: String,
+    pub rerecord_count: u32,
+    pub comments: Vec<String>,
+    // Whether a joypad is plugged in each port
+    pub ports: [bool; 2],
+    // The movie starts from this save state, from power-on when there is none
+    pub savestate: Option<Vec<u8>>,
+    // Content of the memories at power-on, the one of the emulator is kept when unknown
+    pub power_on: Option<PowerOn>,
+    pub frames: Vec<MovieFrame>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+    // Played until its last frame, the joypads are back to the user
+    Finished,
+}
+
+// A movie attached to the bus, which calls next_frame at the start of every frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovieSession {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    // Read-only: loading a save state resumes the playback. Read+write: it starts recording from there
+    pub read_only: bool,
+    // Frames of the movie already played or recorded
+    pub frame: usize,
+}
+
+impl MovieFrame {
+    pub fn new(joypad1: JoypadButton, joypad2: JoypadButton) -> Self {
+        MovieFrame { commands: 0, joypad1, joypad2 }
+    }
+
+    pub fn to_bytes(self) -> [u8; 3] {
+        [self.commands, self.joypad1.bits(), self.joypad2.bits()]
+    }
+
+    pub fn from_bytes(bytes: [u8; 3]) -> Self {
+        MovieFrame { commands: bytes[0], joypad1: JoypadButton::from_bits_truncate(bytes[1]), joypad2: JoypadButton::from_bits_truncate(bytes[2]) }
+    }
+}
+
+// Random, in the usual 8-4-4-4-12 form
+fn new_guid() -> String {
+    let bytes: [u8; 16] = rand::random();
+    let hex: String = hash::to_hex(&bytes).to_ascii_uppercase();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+impl Movie {
+    pub fn new(rom_filename: &str) -> Self {
+        Movie {
+            rom_filename: String::from(rom_filename),
+            rom_md5: None,
+            rom_sha1: None,
+            guid: new_guid(),
+            rerecord_count: 0,
+            comments: vec![],
+            ports: [true, true],
+            savestate: None,
+            power_on: None,
+            frames: vec![],
+        }
+    }
+
+    fn is_bk2(path: &Path) -> bool {
+        path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(bk2::EXTENSION))
+    }
+
+    // BizHawk .bk2 or FCEUX .fm2 (any other extension), by the extension of the file
+    pub fn load(path: &Path) -> Result<Movie, Error> {
+        match Movie::is_bk2(path) {
+            true => bk2::parse(&std::fs::read(path)?),
+            false => fm2::parse(&std::fs::read_to_string(path)?),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        match Movie::is_bk2(path) {
+            true => std::fs::write(path, bk2::to_bytes(self))?,
+            false => std::fs::write(path, fm2::to_text(self))?,
+        }
+        Ok(())
+    }
+
+    // Movies without any checksum are played on any ROM
+    pub fn check_rom(&self, md5: [u8; 16], sha1: [u8; 20]) -> Result<(), Error> {
+        let other_rom = |checksum: String| MovieError(format!("The movie was recorded with another ROM ({}, checksum {})", self.rom_filename, checksum));
+        match (self.rom_md5, self.rom_sha1) {
+            (Some(expected), _) if expected != md5 => Err(other_rom(hash::to_hex(&expected))),
+            (_, Some(expected)) if expected != sha1 => Err(other_rom(hash::to_hex(&expected))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MovieSession {
+    // From power-on when the emulation has not started yet, from the current state otherwise
+    pub fn start_recording(cpu: &mut CPU, mut movie: Movie) {
+        cpu.bus.movie = None;
+        movie.rom_md5 = Some(cpu.bus.rom_md5());
+        movie.rom_sha1 = Some(cpu.bus.rom_sha1());
+        movie.power_on = Some(cpu.bus.power_on);
+        movie.savestate = (cpu.bus.cycles() != 0).then(|| savestate::save(cpu));
+        movie.frames.clear();
+        cpu.bus.movie = Some(MovieSession { movie, mode: MovieMode::Recording, read_only: false, frame: 0 });
+    }
+
+    // Loads the save state the movie starts from, if any
+    pub fn start_playback(cpu: &mut CPU, movie: Movie, read_only: bool) -> Result<(), Error> {
+        movie.check_rom(cpu.bus.rom_md5(), cpu.bus.rom_sha1())?;
+        cpu.bus.movie = None;
+        match movie.savestate.as_ref() {
+            Some(state) => savestate::load(cpu, state)?,
+            None if cpu.bus.cycles() != 0 => return Err(MovieError(String::from("The movie starts at power-on, it must be played before running the ROM"))),
+            None => if let Some(power_on) = movie.power_on {
+                cpu.bus.power_on(power_on);
+            },
+        }
+        cpu.bus.movie = Some(MovieSession { movie, mode: MovieMode::Playing, read_only, frame: 0 });
+        Ok(())
+    }
+
+    // Records the joypads or replaces them with the input of the movie
+    pub fn next_frame(&mut self, joypad1: &mut Joypad, joypad2: &mut Joypad) {
+        match self.mode {
+            MovieMode::Recording => {
+                self.movie.frames.truncate(self.frame);
+                let joypad2: JoypadButton = if self.movie.ports[1] { joypad2.buttons() } else { JoypadButton::empty() };
+                self.movie.frames.push(MovieFrame::new(joypad1.buttons(), joypad2));
+                self.frame += 1;
+            }
+            MovieMode::Playing => match self.movie.frames.get(self.frame) {
+                Some(frame) => {
+                    joypad1.set_buttons(frame.joypad1);
+                    joypad2.set_buttons(frame.joypad2);
+                    self.frame += 1;
+                }
+                None => self.mode = MovieMode::Finished,
+            },
+            MovieMode::Finished => (),
+        }
+    }
+
+    // Whether a save state holding these frames of input can be loaded during the movie.
+    // In read-only mode, the state must be on the timeline of the movie
+    pub fn check_state(&self, frames: Option<&[MovieFrame]>) -> Result<(), Error> {
+        let frames: &[MovieFrame] = frames.ok_or_else(|| MovieError(String::from("The save state was not made during this movie")))?;
+        let common: usize = frames.len().min(self.movie.frames.len());
+        if self.read_only && frames[..common] != self.movie.frames[..common] {
+            return Err(MovieError(String::from("The save state is not on the timeline of the movie")));
+        }
+        Ok(())
+    }
+
+    // Called once a save state with these frames of input has been loaded
+    pub fn state_loaded(&mut self, frames: Vec<MovieFrame>) {
+        self.frame = frames.len();
+        match self.read_only {
+            true => self.mode = if self.frame < self.movie.frames.len() { MovieMode::Playing } else { MovieMode::Finished },
+            false => {
+                self.movie.frames = frames;
+                self.movie.rerecord_count += 1;
+                self.mode = MovieMode::Recording;
+            }
+        }
+    }
+
+    pub fn status(&self) -> String {
+        let mode: &str = match self.mode {
+            MovieMode::Recording => "Recording",
+            MovieMode::Playing => "Playing",
+            MovieMode::Finished => "Finished",
+        };
+        format!("{} frame {}/{}, {} rerecords ({})", mode, self.frame, self.movie.frames.len(), self.movie.rerecord_count,
+            if self.read_only { "read-only" } else { "read+write" })
+    }
+}
@@ -0,0 +1,204 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::archive;
+    use crate::cpu::CPU;
+    use crate::input::JoypadButton;
+    use crate::mem::Mem;
+    use crate::rom::hash;
+    use crate::savestate;
+
+    use super::super::*;
+
+    // Adds the joypad 1 buttons to $01 at every NMI
+    const PROGRAM: &str = "
+        reset:  LDA #$80
+                STA $2000
+        loop:   JMP loop
+        nmi:    LDA #1
+                STA $4016
+                LDA #0
+                STA $4016
+                LDX #8
+        read:   LDA $4016
+                LSR A
+                ROL $00
+                DEX
+                BNE read
+                LDA $00
+                CLC
+                ADC $01
+                STA $01
+                RTI
+        .org $fffa
+                .word nmi, reset, reset
+    ";
+
+    fn new_cpu() -> CPU {
+        CPU::test_load(PROGRAM)
+    }
+
+    // Different buttons at every frame, as a player would press them
+    fn press(cpu: &mut CPU, frames: usize, seed: u8) {
+        for i in 0..frames {
+            let buttons: u8 = (cpu.bus.frames() as u8).wrapping_mul(37).wrapping_add(seed).rotate_left(i as u32 % 8);
+            cpu.bus.screen.joypad1.set_buttons(JoypadButton::from_bits_truncate(buttons));
+            cpu.run_frame();
+        }
+    }
+
+    // Runs without touching the joypads, the movie drives them
+    fn play(cpu: &mut CPU, frames: usize) -> u8 {
+        for _ in 0..frames {
+            cpu.run_frame();
+        }
+        cpu.mem_read_u8(0x01)
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(hash::to_hex(&hash::md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hash::to_hex(&hash::md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hash::to_hex(&hash::md5(&[b'a'; 100])), "36a92cc94a9e0fa21f625f8bfb007adf");
+        assert_eq!(hash::to_hex(&hash::sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hash::to_hex(&hash::sha1(&[b'a'; 100])), "7f9000257a4918d7072655ea468540cdcbd42e0c");
+        assert_eq!(hash::from_hex("00fF10"), Some(vec![0x00, 0xff, 0x10]));
+        assert_eq!(fm2::base64_encode(b"Man"), "TWFu");
+        assert_eq!(fm2::base64_encode(b"Ma"), "TWE=");
+        assert_eq!(fm2::base64_encode(b"M"), "TQ==");
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(fm2::base64_decode(&fm2::base64_encode(&data)).unwrap(), data);
+        assert!(fm2::base64_decode("T$==").is_err());
+    }
+
+    #[test]
+    fn test_fm2() {
+        let mut movie: Movie = Movie::new("game");
+        movie.rom_md5 = Some(hash::md5(b"game"));
+        movie.rerecord_count = 7;
+        movie.comments.push(String::from("author QA"));
+        movie.frames.push(MovieFrame::new(JoypadButton::RIGHT | JoypadButton::BUTTON_A, JoypadButton::empty()));
+        movie.frames.push(MovieFrame { commands: COMMAND_SOFT_RESET, joypad1: JoypadButton::empty(), joypad2: JoypadButton::START });
+        let text: String = fm2::to_text(&movie);
+        assert!(text.starts_with("version 3\n"));
+        assert!(text.contains("rerecordCount 7\n"));
+        assert!(text.contains(&format!("guid {}\n", movie.guid)));
+        assert!(text.ends_with("|0|R......A|........||\n|1|........|....T...||\n"));
+        assert_eq!(fm2::parse(&text).unwrap(), movie);
+
+        // As FCEUX writes them, with a single joypad
+        let text: &str = "version 3\nemuVersion 22020\nrerecordCount 2\nromFilename smb\nromChecksum base64:kKKg8aF6FD5Mk+yG1vCdbg==\nport0 1\nport1 0\n|0|..D..S..|||\n|0|    T  A|||\n";
+        let movie: Movie = fm2::parse(text).unwrap();
+        assert_eq!((movie.rerecord_count, movie.ports), (2, [true, false]));
+        assert_eq!(movie.frames[0].joypad1, JoypadButton::DOWN | JoypadButton::SELECT);
+        assert_eq!(movie.frames[1].joypad1, JoypadButton::START | JoypadButton::BUTTON_A);
+        assert!(fm2::parse("port0 2\n").is_err());
+        assert!(fm2::parse("romChecksum base64:kKKg8aF6FD5Mk+yG1vCdbg==\n|0|.....|........||\n").unwrap_err().to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_bk2() {
+        let mut movie: Movie = Movie::new("game");
+        movie.rom_sha1 = Some(hash::sha1(b"game"));
+        movie.rerecord_count = 3;
+        movie.comments.push(String::from("author QA"));
+        movie.frames.push(MovieFrame::new(JoypadButton::UP | JoypadButton::BUTTON_A, JoypadButton::SELECT));
+        movie.frames.push(MovieFrame { commands: COMMAND_HARD_RESET, joypad1: JoypadButton::empty(), joypad2: JoypadButton::empty() });
+        let bk2: Vec<u8> = bk2::to_bytes(&movie);
+        assert_eq!(bk2::parse(&bk2).unwrap(), movie);
+
+        let entries: Vec<archive::ZipEntry> = archive::read_zip(&bk2).unwrap();
+        let input_log: &archive::ZipEntry = entries.iter().find(|entry| entry.name == "Input Log.txt").unwrap();
+        assert!(String::from_utf8_lossy(&input_log.data).ends_with("|..|U......A|.....s..|\n|.P|........|........|\n[/Input]\n"));
+
+        // As BizHawk writes them, with a single joypad and the SHA-1 in uppercase
+        let header: &str = "MovieVersion BizHawk v2.0.0\r\nPlatform NES\r\nGameName smb\r\nSHA1 A9993E364706816ABA3E25717850C26C9CD0D89D\r\nrerecordCount 12\r\n";
+        let log: &str = "[Input]\r\nLogKey:#Reset|Power|#P1 Up|P1 Down|P1 Left|P1 Right|P1 Start|P1 Select|P1 B|P1 A|\r\n|..|...R...A|\r\n|r.|....S...|\r\n[/Input]\r\n";
+        let zip = |files: &[(&str, &str)]| archive::write_zip(&files.iter().map(|(name, data)| archive::ZipEntry { name: String::from(*name), data: data.as_bytes().to_vec() }).collect::<Vec<_>>());
+        let movie: Movie = bk2::parse(&zip(&[("Header.txt", header), ("Input Log.txt", log)])).unwrap();
+        assert_eq!((movie.rom_filename.as_str(), movie.rom_sha1, movie.rerecord_count, movie.ports), ("smb", Some(hash::sha1(b"abc")), 12, [true, false]));
+        assert_eq!(movie.frames, vec![
+            MovieFrame::new(JoypadButton::RIGHT | JoypadButton::BUTTON_A, JoypadButton::empty()),
+            MovieFrame { commands: COMMAND_SOFT_RESET, joypad1: JoypadButton::START, joypad2: JoypadButton::empty() },
+        ]);
+        // Converted to FCEUX, it has no MD5 but keeps its input
+        assert_eq!(fm2::parse(&fm2::to_text(&movie)).unwrap().frames, movie.frames);
+
+        assert!(bk2::parse(&zip(&[("Header.txt", "Platform SNES\n"), ("Input Log.txt", log)])).unwrap_err().to_string().contains("SNES"));
+        let zapper: &str = "[Input]\nLogKey:#P1 Fire|\n|.|\n";
+        assert!(bk2::parse(&zip(&[("Header.txt", header), ("Input Log.txt", zapper)])).unwrap_err().to_string().contains("P1 Fire"));
+        assert!(bk2::parse(&zip(&[("Input Log.txt", log)])).unwrap_err().to_string().contains("not a BizHawk movie"));
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        let mut cpu: CPU = new_cpu();
+        MovieSession::start_recording(&mut cpu, Movie::new("test"));
+        // The input of a frame is taken when it starts, the first one starts at the first vblank
+        press(&mut cpu, 30, 1);
+        let expected: u8 = cpu.mem_read_u8(0x01);
+        let session: MovieSession = cpu.bus.movie.take().unwrap();
+        assert_eq!((session.movie.frames.len(), session.movie.savestate.is_none()), (29, true));
+        let movie: Movie = fm2::parse(&fm2::to_text(&session.movie)).unwrap();
+
+        let mut cpu: CPU = new_cpu();
+        MovieSession::start_playback(&mut cpu, movie.clone(), true).unwrap();
+        assert_eq!(play(&mut cpu, 30), expected);
+        play(&mut cpu, 1);
+        assert_eq!(cpu.bus.movie.as_ref().unwrap().mode, MovieMode::Finished);
+
+        // Once the emulation runs, a movie can only start from a save state
+        assert!(MovieSession::start_playback(&mut cpu, movie.clone(), true).is_err());
+        MovieSession::start_recording(&mut cpu, Movie::new("test"));
+        press(&mut cpu, 10, 2);
+        let expected: u8 = cpu.mem_read_u8(0x01);
+        let movie: Movie = cpu.bus.movie.take().unwrap().movie;
+        assert!(movie.savestate.is_some());
+        press(&mut cpu, 5, 3);
+        MovieSession::start_playback(&mut cpu, movie, true).unwrap();
+        assert_eq!(play(&mut cpu, 10), expected);
+
+        let mut movie: Movie = Movie::new("test");
+        movie.rom_sha1 = Some([0; 20]);
+        assert!(MovieSession::start_playback(&mut new_cpu(), movie, true).unwrap_err().to_string().contains("another ROM"));
+    }
+
+    #[test]
+    fn test_rerecords() {
+        let mut cpu: CPU = new_cpu();
+        MovieSession::start_recording(&mut cpu, Movie::new("test"));
+        press(&mut cpu, 10, 1);
+        let state: Vec<u8> = savestate::save(&cpu);
+        press(&mut cpu, 10, 1);
+        let timeline: Vec<MovieFrame> = cpu.bus.movie.as_ref().unwrap().movie.frames.clone();
+
+        // Read+write: the movie is cut at the state and records a new branch
+        savestate::load(&mut cpu, &state).unwrap();
+        let session: &MovieSession = cpu.bus.movie.as_ref().unwrap();
+        assert_eq!((session.mode, session.frame, session.movie.frames.len(), session.movie.rerecord_count), (MovieMode::Recording, 9, 9, 1));
+        press(&mut cpu, 10, 2);
+        let branch: Vec<u8> = savestate::save(&cpu);
+        let expected: u8 = cpu.mem_read_u8(0x01);
+        assert_ne!(cpu.bus.movie.as_ref().unwrap().movie.frames, timeline);
+
+        // Read-only: loading a state on the timeline resumes the playback from there
+        cpu.bus.movie.as_mut().unwrap().read_only = true;
+        savestate::load(&mut cpu, &state).unwrap();
+        let session: &MovieSession = cpu.bus.movie.as_ref().unwrap();
+        assert_eq!((session.mode, session.frame, session.movie.frames.len(), session.movie.rerecord_count), (MovieMode::Playing, 9, 19, 1));
+        assert_eq!(play(&mut cpu, 10), expected);
+
+        // Not with a state from another branch
+        cpu.bus.movie.as_mut().unwrap().movie.frames = timeline;
+        assert!(savestate::load(&mut cpu, &branch).unwrap_err().to_string().contains("timeline"));
+
+        // Nor with a state made without a movie
+        let mut other: CPU = new_cpu();
+        press(&mut other, 3, 1);
+        assert!(savestate::load(&mut cpu, &savestate::save(&other)).unwrap_err().to_string().contains("not made during"));
+    }
+}
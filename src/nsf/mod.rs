@@ -0,0 +1,241 @@
+pub mod player;
+mod test;
+
+use crate::error::{Error::RomError, Error};
+
+// NES Sound Format: the music code and data of a game, with the addresses of its INIT and PLAY routines.
+//   NSF   a 128 bytes header followed by the data
+//   NSFe  chunks (INFO, DATA, BANK, RATE, auth, tlbl, time...) ended by NEND
+// https://www.nesdev.org/wiki/NSF and https://www.nesdev.org/wiki/NSFe
+
+const NSF_TAG: &[u8; 5] = b"NESM\x1a";
+const NSFE_TAG: &[u8; 4] = b"NSFE";
+const NSF_HEADER_SIZE: usize = 0x80;
+
+// Writing N to $5FF8 + i maps the 4kB bank N of the data at $8000 + i * $1000
+pub const BANK_REGISTERS_START: u16 = 0x5ff8;
+pub const BANK_REGISTERS_END: u16 = 0x5fff;
+const BANK_SIZE: usize = 0x1000;
+
+// Periods of the PLAY calls in microseconds when the file does not say
+pub const NTSC_PLAY_SPEED: u16 = 16639;
+pub const PAL_PLAY_SPEED: u16 = 19997;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nsf {
+    pub songs: u8,
+    // 0 is the first song
+    pub starting_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    // Periods of the PLAY calls in microseconds
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    // Initial banks, None when the data is simply loaded at the load address
+    pub banks: Option<[u8; 8]>,
+    pub pal: bool,
+    // Bits of the expansion sound chips (VRC6, VRC7, FDS, MMC5, N163, 5B), which are not emulated
+    pub expansion: u8,
+    pub data: Vec<u8>,
+    // NSFe only, per track
+    pub track_labels: Vec<String>,
+    pub track_times_ms: Vec<Option<u32>>,
+}
+
+// The $8000-$FFFF view of the data through the bank registers
+#[derive(Debug, Clone, PartialEq)]
+pub struct NsfMemory {
+    // Padded so that bank N starts at N * 4kB
+    data: Vec<u8>,
+    banks: [u8; 8],
+}
+
+impl NsfMemory {
+    pub fn read(&self, addr: u16) -> u8 {
+        let bank: usize = self.banks[((addr >> 12) & 7) as usize] as usize;
+        self.data.get(bank * BANK_SIZE + (addr as usize & (BANK_SIZE - 1))).copied().unwrap_or(0)
+    }
+
+    pub fn write_bank(&mut self, addr: u16, value: u8) {
+        self.banks[(addr - BANK_REGISTERS_START) as usize] = value;
+    }
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+// Null-terminated, or the whole field
+fn text(field: &[u8]) -> String {
+    let end: usize = field.iter().position(|byte| *byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).trim().to_string()
+}
+
+fn texts(data: &[u8]) -> Vec<String> {
+    let data: &[u8] = data.strip_suffix(&[0]).unwrap_or(data);
+    data.split(|byte| *byte == 0).map(text).collect()
+}
+
+impl Nsf {
+    pub fn parse(data: &[u8]) -> Result<Nsf, Error> {
+        let nsf: Nsf = match data {
+            _ if data.starts_with(NSF_TAG) => Nsf::parse_nsf(data)?,
+            _ if data.starts_with(NSFE_TAG) => Nsf::parse_nsfe(data)?,
+            _ => return Err(RomError(String::from("This is not a NSF nor a NSFe file"))),
+        };
+        if nsf.load_address < 0x8000 {
+            return Err(RomError(format!("Invalid load address ${:04x}, expected $8000 or more", nsf.load_address)));
+        }
+        if nsf.songs == 0 {
+            return Err(RomError(String::from("The file has no song")));
+        }
+        Ok(nsf)
+    }
+
+    fn parse_nsf(data: &[u8]) -> Result<Nsf, Error> {
+        if data.len() < NSF_HEADER_SIZE {
+            return Err(RomError(String::from("The NSF header is truncated")));
+        }
+        let banks: [u8; 8] = data[0x70..0x78].try_into().expect("8 bytes");
+        // NSF2 may store metadata after the program, whose length is then given
+        let program_size: usize = match data[5] {
+            2.. => data[0x7d] as usize | (data[0x7e] as usize) << 8 | (data[0x7f] as usize) << 16,
+            _ => 0,
+        };
+        let end: usize = match program_size {
+            0 => data.len(),
+            size => (NSF_HEADER_SIZE + size).min(data.len()),
+        };
+        Ok(Nsf {
+            songs: data[6],
+            starting_song: data[7].saturating_sub(1),
+            load_address: u16_at(data, 0x08),
+            init_address: u16_at(data, 0x0a),
+            play_address: u16_at(data, 0x0c),
+            title: text(&data[0x0e..0x2e]),
+            artist: text(&data[0x2e..0x4e]),
+            copyright: text(&data[0x4e..0x6e]),
+            ntsc_speed: u16_at(data, 0x6e),
+            pal_speed: u16_at(data, 0x78),
+            banks: banks.iter().any(|bank| *bank != 0).then_some(banks),
+            pal: data[0x7a] & 0b11 == 0b01,
+            expansion: data[0x7b],
+            data: data[NSF_HEADER_SIZE..end].to_vec(),
+            track_labels: vec![],
+            track_times_ms: vec![],
+        })
+    }
+
+    fn parse_nsfe(data: &[u8]) -> Result<Nsf, Error> {
+        let mut nsf: Option<Nsf> = None;
+        let mut program: Option<Vec<u8>> = None;
+        let mut pos: usize = NSFE_TAG.len();
+        loop {
+            let header: &[u8] = data.get(pos..pos + 8).ok_or_else(|| RomError(String::from("The NSFe file is truncated, NEND is missing")))?;
+            let size: usize = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let id: &[u8] = &header[4..8];
+            let chunk: &[u8] = data.get(pos + 8..pos + 8 + size)
+                .ok_or_else(|| RomError(format!("The NSFe chunk {} is truncated", String::from_utf8_lossy(id))))?;
+            pos += 8 + size;
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 8 {
+                        return Err(RomError(String::from("The NSFe INFO chunk is too short")));
+                    }
+                    nsf = Some(Nsf {
+                        songs: chunk.get(8).copied().unwrap_or(1),
+                        starting_song: chunk.get(9).copied().unwrap_or(0),
+                        load_address: u16_at(chunk, 0),
+                        init_address: u16_at(chunk, 2),
+                        play_address: u16_at(chunk, 4),
+                        title: String::new(),
+                        artist: String::new(),
+                        copyright: String::new(),
+                        ntsc_speed: NTSC_PLAY_SPEED,
+                        pal_speed: PAL_PLAY_SPEED,
+                        banks: None,
+                        pal: chunk[6] & 0b11 == 0b01,
+                        expansion: chunk[7],
+                        data: vec![],
+                        track_labels: vec![],
+                        track_times_ms: vec![],
+                    });
+                }
+                b"DATA" => program = Some(chunk.to_vec()),
+                b"NEND" => break,
+                _ => {
+                    // INFO comes first, the optional chunks complete it
+                    let nsf: &mut Nsf = nsf.as_mut().ok_or_else(|| RomError(String::from("The NSFe INFO chunk should come first")))?;
+                    match id {
+                        b"BANK" => {
+                            let mut banks: [u8; 8] = [0; 8];
+                            for (bank, value) in banks.iter_mut().zip(chunk) {
+                                *bank = *value;
+                            }
+                            nsf.banks = Some(banks);
+                        }
+                        b"RATE" if chunk.len() >= 2 => {
+                            nsf.ntsc_speed = u16_at(chunk, 0);
+                            if chunk.len() >= 4 {
+                                nsf.pal_speed = u16_at(chunk, 2);
+                            }
+                        }
+                        b"auth" => {
+                            let mut fields = texts(chunk).into_iter();
+                            nsf.title = fields.next().unwrap_or_default();
+                            nsf.artist = fields.next().unwrap_or_default();
+                            nsf.copyright = fields.next().unwrap_or_default();
+                        }
+                        b"tlbl" => nsf.track_labels = texts(chunk),
+                        b"time" => nsf.track_times_ms = chunk.chunks_exact(4)
+                            .map(|time| i32::from_le_bytes([time[0], time[1], time[2], time[3]]))
+                            .map(|time| u32::try_from(time).ok())
+                            .collect(),
+                        // An unknown chunk starting with a capital letter must be understood
+                        _ if id[0].is_ascii_uppercase() => {
+                            return Err(RomError(format!("Unsupported NSFe chunk {}", String::from_utf8_lossy(id))));
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+        let mut nsf: Nsf = nsf.ok_or_else(|| RomError(String::from("The NSFe INFO chunk is missing")))?;
+        nsf.data = program.ok_or_else(|| RomError(String::from("The NSFe DATA chunk is missing")))?;
+        Ok(nsf)
+    }
+
+    // Microseconds between two PLAY calls
+    pub fn play_speed(&self) -> u16 {
+        match (self.pal, self.pal_speed, self.ntsc_speed) {
+            (true, 0, _) => PAL_PLAY_SPEED,
+            (true, speed, _) => speed,
+            (false, _, 0) => NTSC_PLAY_SPEED,
+            (false, _, speed) => speed,
+        }
+    }
+
+    pub fn track_label(&self, track: u8) -> Option<&str> {
+        self.track_labels.get(track as usize).map(|label| label.as_str()).filter(|label| !label.is_empty())
+    }
+
+    pub fn track_time_ms(&self, track: u8) -> Option<u32> {
+        self.track_times_ms.get(track as usize).copied().flatten()
+    }
+
+    // The memory at power-on. Without bankswitching, the data is loaded at the load address;
+    // with it, the low 12 bits of the load address give where the data starts in the first bank
+    pub fn memory(&self) -> NsfMemory {
+        let (padding, banks): (usize, [u8; 8]) = match self.banks {
+            Some(banks) => (self.load_address as usize & (BANK_SIZE - 1), banks),
+            None => (self.load_address as usize - 0x8000, [0, 1, 2, 3, 4, 5, 6, 7]),
+        };
+        let mut data: Vec<u8> = vec![0; padding];
+        data.extend_from_slice(&self.data);
+        NsfMemory { data, banks }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::apu::{Apu, APU_FRAME_COUNTER_REGISTER, APU_REGISTERS_START, APU_REGISTERS_END, APU_STATUS_REGISTER, CPU_FREQUENCY};
+use crate::bus::{Bus, CPU_RAM_START, PRG_RAM_END, PRG_RAM_START};
+use crate::cpu::CPU;
+use crate::error::Error;
+use crate::input::Joypad;
+use crate::mem::Mem;
+use crate::ppu::PPU;
+use crate::rom::Rom;
+use crate::screen::Screen;
+
+use super::Nsf;
+
+// Plays the songs of a NSF with the CPU core: INIT once per song, then PLAY at the rate of the file.
+// The PPU is not emulated, the bus only maps the RAM, the APU and the banks of the NSF.
+
+// The routines are called as by a JSR from here, they are over when the PC comes back
+const RETURN_ADDRESS: u16 = 0x4100;
+// A routine that has not returned after that many PLAY periods is given up
+const ROUTINE_TIMEOUT_PERIODS: f64 = 60.0;
+
+fn no_display(_: &PPU, _: &mut Screen) {}
+
+pub struct NsfPlayer {
+    pub cpu: CPU,
+    pub nsf: Nsf,
+    // 0 is the first song
+    pub track: u8,
+    sample_rate: u32,
+    // CPU cycles between two PLAY calls, and how late the current period is
+    period: f64,
+    lag: f64,
+    // Samples played since the song started
+    pub played: usize,
+}
+
+impl NsfPlayer {
+    pub fn new(nsf: Nsf, sample_rate: u32) -> Result<NsfPlayer, Error> {
+        let mut bus: Bus = Bus::new_headless(Rom::new_from_program_rom(vec![])?, no_display, Joypad::new(), Joypad::new());
+        bus.nsf = Some(nsf.memory());
+        bus.apu = Some(Apu::new(sample_rate));
+        let mut player: NsfPlayer = NsfPlayer {
+            cpu: CPU::new(bus),
+            period: nsf.play_speed() as f64 * CPU_FREQUENCY / 1_000_000.0,
+            track: nsf.starting_song.min(nsf.songs - 1),
+            nsf,
+            sample_rate,
+            lag: 0.0,
+            played: 0,
+        };
+        player.start_track(player.track);
+        Ok(player)
+    }
+
+    // https://www.nesdev.org/wiki/NSF#Initializing_a_tune
+    pub fn start_track(&mut self, track: u8) {
+        self.track = track.min(self.nsf.songs - 1);
+        let bus: &mut Bus = &mut self.cpu.bus;
+        // The internal RAM is mirrored up to $1FFF
+        for addr in (CPU_RAM_START..0x0800).chain(PRG_RAM_START..=PRG_RAM_END) {
+            bus.mem_write_u8(addr, 0);
+        }
+        bus.nsf = Some(self.nsf.memory());
+        bus.apu = Some(Apu::new(self.sample_rate));
+        for addr in APU_REGISTERS_START..=APU_REGISTERS_END {
+            bus.mem_write_u8(addr, 0);
+        }
+        bus.mem_write_u8(APU_STATUS_REGISTER, 0x0f);
+        bus.mem_write_u8(APU_FRAME_COUNTER_REGISTER, 0x40);
+
+        self.cpu.reset();
+        self.cpu.reg_a = self.track;
+        self.cpu.reg_x = self.nsf.pal as u8;
+        self.cpu.call(self.nsf.init_address, RETURN_ADDRESS);
+        self.run_routine();
+        if let Some(apu) = self.cpu.bus.apu.as_mut() {
+            apu.samples.clear();
+        }
+        (self.lag, self.played) = (0.0, 0);
+    }
+
+    // Runs until the routine returns, the CPU cycles it took
+    fn run_routine(&mut self) -> usize {
+        let timeout: usize = (self.period * ROUTINE_TIMEOUT_PERIODS) as usize;
+        let mut cycles: usize = 0;
+        while self.cpu.reg_pc != RETURN_ADDRESS && self.cpu.running && cycles < timeout {
+            cycles += self.cpu.step();
+        }
+        cycles
+    }
+
+    // Calls PLAY and lets the APU run until the next call, returns the samples of that period
+    pub fn play_frame(&mut self) -> Vec<i16> {
+        self.cpu.running = true;
+        self.cpu.call(self.nsf.play_address, RETURN_ADDRESS);
+        let target: f64 = self.lag + self.period;
+        let busy: usize = self.run_routine();
+        let idle: usize = (target as usize).saturating_sub(busy);
+        self.cpu.bus.tick(idle);
+        self.lag = target - (busy + idle) as f64;
+        let samples: Vec<i16> = self.cpu.bus.apu.as_mut().map(Apu::take_samples).unwrap_or_default();
+        self.played += samples.len();
+        samples
+    }
+
+    // The current song for that long, used to render it to a WAV file
+    pub fn render(&mut self, seconds: f64) -> Vec<i16> {
+        let count: usize = (seconds * self.sample_rate as f64) as usize;
+        let mut samples: Vec<i16> = Vec::with_capacity(count);
+        while samples.len() < count {
+            samples.extend(self.play_frame());
+        }
+        samples.truncate(count);
+        samples
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // "3/12 Title" for the player
+    pub fn track_name(&self) -> String {
+        match self.nsf.track_label(self.track) {
+            Some(label) => format!("{}/{} {}", self.track + 1, self.nsf.songs, label),
+            None => format!("{}/{}", self.track + 1, self.nsf.songs),
+        }
+    }
+}
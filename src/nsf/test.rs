@@ -0,0 +1,126 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::asm::{assemble, Assembly};
+    use crate::mem::Mem;
+
+    use super::super::player::NsfPlayer;
+    use super::super::*;
+
+    // INIT keeps the song number and starts a square wave, PLAY counts its calls
+    const TUNE: &str = "
+        init:   STA $00
+                LDA #$BF
+                STA $4000
+                LDA #$FD
+                STA $4002
+                LDA #$08
+                STA $4003
+                RTS
+        play:   INC $01
+                RTS
+    ";
+
+    fn nsf_file(assembly: &Assembly, play: u16) -> Vec<u8> {
+        let mut data: Vec<u8> = b"NESM\x1a\x01\x03\x02".to_vec();
+        for address in [assembly.origin, assembly.origin, play] {
+            data.extend_from_slice(&address.to_le_bytes());
+        }
+        let mut title: [u8; 32] = [0; 32];
+        title[..4].copy_from_slice(b"Tune");
+        data.extend_from_slice(&title);
+        data.extend_from_slice(&[0; 64]);
+        data.extend_from_slice(&NTSC_PLAY_SPEED.to_le_bytes());
+        data.resize(0x80, 0);
+        data.extend_from_slice(&assembly.bytes);
+        data
+    }
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk: Vec<u8> = (data.len() as u32).to_le_bytes().to_vec();
+        chunk.extend_from_slice(id);
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    #[test]
+    fn test_parse_nsf() {
+        let assembly: Assembly = assemble(TUNE).unwrap();
+        let nsf: Nsf = Nsf::parse(&nsf_file(&assembly, 0x8010)).unwrap();
+        assert_eq!((nsf.songs, nsf.starting_song), (3, 1));
+        assert_eq!((nsf.load_address, nsf.init_address, nsf.play_address), (0x8000, 0x8000, 0x8010));
+        assert_eq!(nsf.title, "Tune");
+        assert_eq!((nsf.banks, nsf.pal, nsf.play_speed()), (None, false, NTSC_PLAY_SPEED));
+        assert_eq!(nsf.data, assembly.bytes);
+
+        assert!(Nsf::parse(b"NESM\x1a").unwrap_err().to_string().contains("truncated"));
+        assert!(Nsf::parse(b"NES\x1a").unwrap_err().to_string().contains("not a NSF"));
+    }
+
+    #[test]
+    fn test_parse_nsfe() {
+        let mut data: Vec<u8> = b"NSFE".to_vec();
+        data.extend(chunk(b"INFO", &[0x00, 0x80, 0x00, 0x80, 0x10, 0x80, 0x01, 0x00, 0x02, 0x01]));
+        data.extend(chunk(b"BANK", &[0, 1]));
+        data.extend(chunk(b"auth", b"Title\0Artist\0Copyright\0Ripper\0"));
+        data.extend(chunk(b"tlbl", b"Intro\0Level 1\0"));
+        data.extend(chunk(b"time", &[0x10, 0x27, 0, 0, 0xff, 0xff, 0xff, 0xff]));
+        data.extend(chunk(b"plst", &[1, 0]));
+        data.extend(chunk(b"DATA", &[0x60; 0x2000]));
+        data.extend(chunk(b"NEND", &[]));
+        let nsf: Nsf = Nsf::parse(&data).unwrap();
+        assert_eq!((nsf.songs, nsf.starting_song, nsf.pal), (2, 1, true));
+        assert_eq!((nsf.title.as_str(), nsf.artist.as_str(), nsf.copyright.as_str()), ("Title", "Artist", "Copyright"));
+        assert_eq!((nsf.track_label(1), nsf.track_time_ms(0), nsf.track_time_ms(1)), (Some("Level 1"), Some(10000), None));
+        assert_eq!((nsf.banks, nsf.play_speed()), (Some([0, 1, 0, 0, 0, 0, 0, 0]), PAL_PLAY_SPEED));
+
+        let mut unknown: Vec<u8> = data[..data.len() - 8].to_vec();
+        unknown.extend(chunk(b"VRC7", &[]));
+        assert!(Nsf::parse(&unknown).unwrap_err().to_string().contains("Unsupported NSFe chunk VRC7"));
+        assert!(Nsf::parse(&data[..data.len() - 8]).unwrap_err().to_string().contains("NEND is missing"));
+    }
+
+    #[test]
+    fn test_banks() {
+        let mut nsf: Nsf = Nsf::parse(&nsf_file(&assemble(TUNE).unwrap(), 0x8010)).unwrap();
+        nsf.data = (0..3).flat_map(|bank| vec![bank as u8; 0x1000]).collect();
+
+        // Without bankswitching the data is loaded at the load address
+        nsf.load_address = 0x9000;
+        let memory: NsfMemory = nsf.memory();
+        assert_eq!((memory.read(0x8fff), memory.read(0x9000), memory.read(0xb000), memory.read(0xc000)), (0, 0, 2, 0));
+
+        // With it, only the low 12 bits of the load address matter
+        nsf.load_address = 0x8800;
+        nsf.banks = Some([2, 1, 0, 0, 0, 0, 0, 0]);
+        let mut memory: NsfMemory = nsf.memory();
+        assert_eq!((memory.read(0x8000), memory.read(0x9000), memory.read(0xa7ff), memory.read(0xa800)), (1, 0, 0, 0));
+        memory.write_bank(BANK_REGISTERS_START + 7, 1);
+        assert_eq!((memory.read(0xf7ff), memory.read(0xf800)), (0, 1));
+        memory.write_bank(BANK_REGISTERS_START + 7, 2);
+        assert_eq!(memory.read(0xf000), 1);
+    }
+
+    #[test]
+    fn test_player() {
+        let assembly: Assembly = assemble(TUNE).unwrap();
+        let play: u16 = 0x8000 + assembly.bytes.iter().rposition(|byte| *byte == 0xe6).unwrap() as u16;
+        let nsf: Nsf = Nsf::parse(&nsf_file(&assembly, play)).unwrap();
+        let mut player: NsfPlayer = NsfPlayer::new(nsf, 44100).unwrap();
+        assert_eq!((player.track, player.cpu.mem_read_u8(0x00)), (1, 1));
+        assert_eq!(player.track_name(), "2/3");
+
+        let samples: Vec<i16> = player.render(0.1);
+        assert_eq!(samples.len(), 4410);
+        // 0.1 s is a little more than 6 periods of 16639 µs
+        assert_eq!(player.cpu.mem_read_u8(0x01), 7);
+        assert!(samples.iter().any(|sample| *sample > 1000));
+
+        player.start_track(9);
+        assert_eq!((player.track, player.cpu.mem_read_u8(0x00), player.cpu.mem_read_u8(0x01)), (2, 2, 0));
+    }
+}
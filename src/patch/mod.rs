@@ -0,0 +1,276 @@
+mod test;
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error::RomError, Error};
+use crate::rom::header::{CHR_ROM_PAGE_SIZE, HEADER_SIZE, PRG_ROM_PAGE_SIZE, TRAINER_SIZE};
+
+// Soft-patching: translations and hacks are distributed as patches applied to the whole file,
+// header included, before the ROM is parsed.
+//   IPS  records of (offset, bytes), no checksum
+//   UPS  XOR hunks, with the CRC32 of the source, the target and the patch
+//   BPS  copy and read actions, with the same three CRC32 as UPS
+// https://zerosoft.zophar.net/ips.php, https://www.romhacking.net/documents/392/ (UPS), https://www.romhacking.net/documents/746/ (BPS)
+
+const IPS_MAGIC: &[u8; 5] = b"PATCH";
+const IPS_EOF: &[u8; 3] = b"EOF";
+const UPS_MAGIC: &[u8; 4] = b"UPS1";
+const BPS_MAGIC: &[u8; 4] = b"BPS1";
+// Source, target and patch CRC32
+const FOOTER_SIZE: usize = 12;
+// Largest iNES file, checked before the target announced by a UPS or BPS patch is allocated
+const MAX_TARGET_SIZE: usize = HEADER_SIZE + TRAINER_SIZE + 0xff * PRG_ROM_PAGE_SIZE + 0xff * CHR_ROM_PAGE_SIZE;
+
+// Looked for next to the ROM in that order: game.nes -> game.ips
+pub const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+impl PatchFormat {
+    pub fn detect(patch: &[u8]) -> Option<PatchFormat> {
+        match patch {
+            _ if patch.starts_with(IPS_MAGIC) => Some(PatchFormat::Ips),
+            _ if patch.starts_with(UPS_MAGIC) => Some(PatchFormat::Ups),
+            _ if patch.starts_with(BPS_MAGIC) => Some(PatchFormat::Bps),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PatchFormat::Ips => "IPS",
+            PatchFormat::Ups => "UPS",
+            PatchFormat::Bps => "BPS",
+        }
+    }
+}
+
+// The first patch with the ROM's name, if any
+pub fn path_for_rom(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS.iter().map(|extension| rom_path.with_extension(extension)).find(|path| path.is_file())
+}
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    match PatchFormat::detect(patch) {
+        Some(PatchFormat::Ips) => apply_ips(source, patch),
+        Some(PatchFormat::Ups) => apply_ups(source, patch),
+        Some(PatchFormat::Bps) => apply_bps(source, patch),
+        None => Err(RomError(String::from("Unknown patch format, expected IPS, UPS or BPS"))),
+    }
+}
+
+// Reads the patch front to back, every read past the end is an error
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    format: PatchFormat,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], format: PatchFormat, start: usize) -> Reader<'a> {
+        Reader { data, pos: start, format }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        let bytes: &[u8] = self.data.get(self.pos..self.pos.saturating_add(count))
+            .ok_or_else(|| RomError(format!("The {} patch is truncated at offset {}", self.format.name(), self.pos)))?;
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn big_endian(&mut self, count: usize) -> Result<usize, Error> {
+        Ok(self.bytes(count)?.iter().fold(0, |value, byte| value << 8 | *byte as usize))
+    }
+
+    // UPS and BPS numbers: 7 bits per byte, the last one has bit 7 set, and each extra byte adds one
+    // so that every number has a single encoding
+    fn number(&mut self) -> Result<usize, Error> {
+        let (start, format): (usize, PatchFormat) = (self.pos, self.format);
+        let invalid = || RomError(format!("Invalid number at offset {} of the {} patch", start, format.name()));
+        let (mut value, mut shift): (usize, usize) = (0, 1);
+        loop {
+            let byte: u8 = self.byte()?;
+            value = (byte as usize & 0x7f).checked_mul(shift).and_then(|add| value.checked_add(add)).ok_or_else(invalid)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(invalid)?;
+            value = value.checked_add(shift).ok_or_else(invalid)?;
+        }
+    }
+}
+
+fn apply_ips(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut target: Vec<u8> = source.to_vec();
+    let mut reader: Reader = Reader::new(patch, PatchFormat::Ips, IPS_MAGIC.len());
+    loop {
+        if reader.data.get(reader.pos..reader.pos + 3) == Some(IPS_EOF) {
+            reader.pos += 3;
+            break;
+        }
+        let offset: usize = reader.big_endian(3)?;
+        let (size, value): (usize, Option<u8>) = match reader.big_endian(2)? {
+            // Run-length encoded record
+            0 => (reader.big_endian(2)?, Some(reader.byte()?)),
+            size => (size, None),
+        };
+        if target.len() < offset + size {
+            target.resize(offset + size, 0);
+        }
+        match value {
+            Some(value) => target[offset..offset + size].fill(value),
+            None => target[offset..offset + size].copy_from_slice(reader.bytes(size)?),
+        }
+    }
+    // An extension of Lunar IPS: the size of the patched file after "EOF"
+    match patch.len() - reader.pos {
+        0 => (),
+        3 => target.truncate(reader.big_endian(3)?),
+        extra => return Err(RomError(format!("{} unexpected bytes after the end of the IPS patch", extra))),
+    }
+    Ok(target)
+}
+
+// The patch's own CRC covers everything but itself, the source and target ones are returned
+fn check_footer(patch: &[u8], format: PatchFormat) -> Result<(u32, u32), Error> {
+    if patch.len() < 4 + FOOTER_SIZE {
+        return Err(RomError(format!("The {} patch is truncated", format.name())));
+    }
+    let crc = |pos: usize| u32::from_le_bytes([patch[pos], patch[pos + 1], patch[pos + 2], patch[pos + 3]]);
+    let end: usize = patch.len() - FOOTER_SIZE;
+    if crc32fast::hash(&patch[..patch.len() - 4]) != crc(end + 8) {
+        return Err(RomError(format!("The {} patch is corrupted (bad CRC)", format.name())));
+    }
+    Ok((crc(end), crc(end + 4)))
+}
+
+fn check_source(source: &[u8], expected_size: usize, expected_crc: u32, format: PatchFormat) -> Result<(), Error> {
+    if source.len() != expected_size || crc32fast::hash(source) != expected_crc {
+        return Err(RomError(format!(
+            "The {} patch was made for another ROM: it expects {} bytes with CRC32 {:08x}, this one has {} bytes with CRC32 {:08x}",
+            format.name(), expected_size, expected_crc, source.len(), crc32fast::hash(source),
+        )));
+    }
+    Ok(())
+}
+
+fn check_target_size(target_size: usize, format: PatchFormat) -> Result<(), Error> {
+    match target_size <= MAX_TARGET_SIZE {
+        true => Ok(()),
+        false => Err(RomError(format!("The {} patch makes a ROM of {} bytes, more than the {} of the largest iNES file", format.name(), target_size, MAX_TARGET_SIZE))),
+    }
+}
+
+fn check_target(target: &[u8], expected_crc: u32, format: PatchFormat) -> Result<(), Error> {
+    match crc32fast::hash(target) == expected_crc {
+        true => Ok(()),
+        false => Err(RomError(format!("The {} patch gave a wrong result (bad target CRC)", format.name()))),
+    }
+}
+
+fn apply_ups(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    let (source_crc, target_crc): (u32, u32) = check_footer(patch, PatchFormat::Ups)?;
+    let end: usize = patch.len() - FOOTER_SIZE;
+    let mut reader: Reader = Reader::new(&patch[..end], PatchFormat::Ups, UPS_MAGIC.len());
+    let source_size: usize = reader.number()?;
+    let target_size: usize = reader.number()?;
+    check_target_size(target_size, PatchFormat::Ups)?;
+    check_source(source, source_size, source_crc, PatchFormat::Ups)?;
+
+    let mut target: Vec<u8> = source.to_vec();
+    target.resize(target_size, 0);
+    // The XOR covers the source past the end of a smaller target, but nothing beyond
+    let limit: usize = source_size.max(target_size);
+    let past_end = || RomError(String::from("The UPS patch writes past the end of the target"));
+    let mut pos: usize = 0;
+    while reader.pos < end {
+        pos = pos.checked_add(reader.number()?).filter(|pos| *pos <= limit).ok_or_else(past_end)?;
+        // XOR until a zero byte, which skips one more byte
+        loop {
+            let byte: u8 = reader.byte()?;
+            if byte == 0 {
+                pos += 1;
+                break;
+            }
+            if pos >= limit {
+                return Err(past_end());
+            }
+            if pos < target_size {
+                target[pos] ^= byte;
+            }
+            pos += 1;
+        }
+    }
+    check_target(&target, target_crc, PatchFormat::Ups)?;
+    Ok(target)
+}
+
+fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    let (source_crc, target_crc): (u32, u32) = check_footer(patch, PatchFormat::Bps)?;
+    let end: usize = patch.len() - FOOTER_SIZE;
+    let mut reader: Reader = Reader::new(&patch[..end], PatchFormat::Bps, BPS_MAGIC.len());
+    let source_size: usize = reader.number()?;
+    let target_size: usize = reader.number()?;
+    check_target_size(target_size, PatchFormat::Bps)?;
+    let metadata_size: usize = reader.number()?;
+    reader.bytes(metadata_size)?;
+    check_source(source, source_size, source_crc, PatchFormat::Bps)?;
+
+    let out_of_bounds = |action: &str| RomError(format!("The BPS patch has an out of bounds {} action", action));
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset): (usize, usize) = (0, 0);
+    while reader.pos < end {
+        let action: usize = reader.number()?;
+        let length: usize = (action >> 2) + 1;
+        if length > target_size - target.len() {
+            return Err(RomError(String::from("The BPS patch writes past the end of the target")));
+        }
+        match action & 0b11 {
+            // SourceRead: the same bytes as the source
+            0 => {
+                let start: usize = target.len();
+                target.extend_from_slice(source.get(start..start + length).ok_or_else(|| out_of_bounds("SourceRead"))?);
+            }
+            // TargetRead: bytes from the patch
+            1 => target.extend_from_slice(reader.bytes(length)?),
+            // SourceCopy: bytes from anywhere in the source, the offset moves relatively
+            2 => {
+                source_offset = relative_offset(source_offset, reader.number()?).ok_or_else(|| out_of_bounds("SourceCopy"))?;
+                let source_end: usize = source_offset.checked_add(length).ok_or_else(|| out_of_bounds("SourceCopy"))?;
+                target.extend_from_slice(source.get(source_offset..source_end).ok_or_else(|| out_of_bounds("SourceCopy"))?);
+                source_offset = source_end;
+            }
+            // TargetCopy: bytes already written, one at a time since the ranges may overlap
+            _ => {
+                target_offset = relative_offset(target_offset, reader.number()?).filter(|offset| *offset < target.len())
+                    .ok_or_else(|| out_of_bounds("TargetCopy"))?;
+                for _ in 0..length {
+                    target.push(target[target_offset]);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err(RomError(format!("The BPS patch wrote {} bytes instead of {}", target.len(), target_size)));
+    }
+    check_target(&target, target_crc, PatchFormat::Bps)?;
+    Ok(target)
+}
+
+// Bit 0 is the sign, the other bits the distance
+fn relative_offset(offset: usize, data: usize) -> Option<usize> {
+    match data & 1 {
+        0 => offset.checked_add(data >> 1),
+        _ => offset.checked_sub(data >> 1),
+    }
+}
@@ -0,0 +1,142 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use super::super::*;
+
+    fn number(mut value: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![];
+        loop {
+            let low: u8 = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | low);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    // The three CRC32 that end UPS and BPS patches
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_numbers() {
+        for value in [0, 1, 0x7f, 0x80, 0x4000, 0x12345678] {
+            let bytes: Vec<u8> = number(value);
+            assert_eq!(Reader::new(&bytes, PatchFormat::Ups, 0).number().unwrap(), value);
+        }
+        assert!(Reader::new(&[0; 12], PatchFormat::Ups, 0).number().is_err());
+    }
+
+    #[test]
+    fn test_ips() {
+        let source: Vec<u8> = (0..16).collect();
+        let mut patch: Vec<u8> = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0, 0, 2, 0, 2, 0xaa, 0xbb]);
+        // Run of 3 bytes, past the end of the source
+        patch.extend_from_slice(&[0, 0, 15, 0, 0, 0, 3, 0xcc]);
+        patch.extend_from_slice(b"EOF");
+        let target: Vec<u8> = apply(&source, &patch).unwrap();
+        assert_eq!(&target[..5], &[0, 1, 0xaa, 0xbb, 4]);
+        assert_eq!(&target[14..], &[14, 0xcc, 0xcc, 0xcc]);
+
+        // Truncation extension
+        patch.extend_from_slice(&[0, 0, 4]);
+        assert_eq!(apply(&source, &patch).unwrap(), vec![0, 1, 0xaa, 0xbb]);
+        assert!(apply(&source, &patch[..patch.len() - 5]).unwrap_err().to_string().contains("IPS patch is truncated"));
+        assert!(apply(&source, b"NOT A PATCH").unwrap_err().to_string().contains("Unknown patch format"));
+    }
+
+    #[test]
+    fn test_ups() {
+        let source: Vec<u8> = (0..16).collect();
+        let mut expected: Vec<u8> = source.clone();
+        expected[3] = 0xff;
+        expected.push(0x42);
+
+        let mut patch: Vec<u8> = b"UPS1".to_vec();
+        patch.extend(number(16));
+        patch.extend(number(17));
+        patch.extend(number(3));
+        patch.extend_from_slice(&[3 ^ 0xff, 0]);
+        // The terminating zero left the position at 5, the new byte is at 16
+        patch.extend(number(11));
+        patch.extend_from_slice(&[0x42, 0]);
+        let patch: Vec<u8> = with_footer(patch, &source, &expected);
+        assert_eq!(apply(&source, &patch).unwrap(), expected);
+
+        assert!(apply(&source[1..], &patch).unwrap_err().to_string().contains("made for another ROM"));
+        let mut corrupted: Vec<u8> = patch.clone();
+        corrupted[8] ^= 1;
+        assert!(apply(&source, &corrupted).unwrap_err().to_string().contains("bad CRC"));
+
+        // A valid patch announcing a huge target
+        let mut huge: Vec<u8> = b"UPS1".to_vec();
+        huge.extend(number(16));
+        huge.extend(number(usize::MAX >> 8));
+        let huge: Vec<u8> = with_footer(huge, &source, &expected);
+        assert!(apply(&source, &huge).unwrap_err().to_string().contains("largest iNES file"));
+
+        // Hunks past the end of the source and target
+        for hunks in [vec![number(usize::MAX >> 1), vec![1, 0], number(usize::MAX >> 1), vec![1, 0]], vec![number(15), vec![1, 1, 1, 0]]] {
+            let mut far: Vec<u8> = b"UPS1".to_vec();
+            far.extend(number(16));
+            far.extend(number(17));
+            far.extend(hunks.concat());
+            let far: Vec<u8> = with_footer(far, &source, &expected);
+            assert!(apply(&source, &far).unwrap_err().to_string().contains("past the end of the target"));
+        }
+    }
+
+    #[test]
+    fn test_bps() {
+        let source: Vec<u8> = b"abcdefgh".to_vec();
+        let expected: Vec<u8> = b"abcXYXYXYfgh".to_vec();
+
+        let mut patch: Vec<u8> = b"BPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(expected.len()));
+        patch.extend(number(4));
+        patch.extend_from_slice(b"meta");
+        // SourceRead "abc", TargetRead "XY", TargetCopy "XYXY" from offset 3, SourceCopy "fgh" from offset 5
+        patch.extend(number((3 - 1) << 2));
+        patch.extend(number((2 - 1) << 2 | 1));
+        patch.extend_from_slice(b"XY");
+        patch.extend(number((4 - 1) << 2 | 3));
+        patch.extend(number(3 << 1));
+        patch.extend(number((3 - 1) << 2 | 2));
+        patch.extend(number(5 << 1));
+        let patch: Vec<u8> = with_footer(patch, &source, &expected);
+        assert_eq!(apply(&source, &patch).unwrap(), expected);
+
+        assert!(apply(b"abcdefgX", &patch).unwrap_err().to_string().contains("CRC32"));
+        assert!(apply(&source, &patch[..10]).unwrap_err().to_string().contains("truncated"));
+
+        // SourceCopy from far past the end of the source
+        let mut far: Vec<u8> = b"BPS1".to_vec();
+        far.extend(number(source.len()));
+        far.extend(number(expected.len()));
+        far.extend(number(0));
+        far.extend(number((3 - 1) << 2 | 2));
+        far.extend(number((usize::MAX >> 1) << 1));
+        let far: Vec<u8> = with_footer(far, &source, &expected);
+        assert!(apply(&source, &far).unwrap_err().to_string().contains("out of bounds SourceCopy"));
+
+        let mut huge: Vec<u8> = b"BPS1".to_vec();
+        huge.extend(number(source.len()));
+        huge.extend(number(1 << 40));
+        huge.extend(number(0));
+        let huge: Vec<u8> = with_footer(huge, &source, &expected);
+        assert!(apply(&source, &huge).unwrap_err().to_string().contains("largest iNES file"));
+    }
+}
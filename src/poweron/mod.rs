@@ -0,0 +1,86 @@
+mod test;
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+use crate::error::{Error::ConfigError, Error};
+
+// What the memories of the console hold at power-on. The real RAM comes up with garbage, some games
+// seed their random number generator from it, so the content has to be chosen (and recorded in save
+// states and movies) for runs to be reproducible
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerOn {
+    #[default]
+    Zeros,
+    // Every byte at $FF
+    Ones,
+    // 4 bytes at $00 then 4 at $FF, over and over (as FCEUX does)
+    Pattern,
+    // Seeded, so that the same seed always gives the same content. The rand version is pinned,
+    // which keeps StdRng on the same algorithm
+    Random(u64),
+}
+
+impl PowerOn {
+    // zeros, ff, pattern, random (with a new seed) or random:<seed>
+    pub fn parse(text: &str) -> Result<PowerOn, Error> {
+        match text.to_ascii_lowercase().as_str() {
+            "zeros" | "00" => Ok(PowerOn::Zeros),
+            "ones" | "ff" => Ok(PowerOn::Ones),
+            "pattern" => Ok(PowerOn::Pattern),
+            "random" => Ok(PowerOn::Random(rand::random())),
+            other => match other.strip_prefix("random:") {
+                Some(seed) => seed.parse().map(PowerOn::Random).map_err(|_| ConfigError(format!("Invalid seed {}", seed))),
+                None => Err(ConfigError(format!("Unknown power-on state {}, expected zeros, ff, pattern, random or random:<seed>", text))),
+            },
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            PowerOn::Zeros => String::from("zeros"),
+            PowerOn::Ones => String::from("ff"),
+            PowerOn::Pattern => String::from("pattern"),
+            PowerOn::Random(seed) => format!("random:{}", seed),
+        }
+    }
+
+    // Fills the memories one after the other, a random fill goes on with the same generator
+    pub fn fill(&self, memories: &mut [&mut [u8]]) {
+        let mut rng: StdRng = StdRng::seed_from_u64(if let PowerOn::Random(seed) = self { *seed } else { 0 });
+        for memory in memories.iter_mut() {
+            match self {
+                PowerOn::Zeros => memory.fill(0),
+                PowerOn::Ones => memory.fill(0xff),
+                PowerOn::Pattern => memory.iter_mut().enumerate().for_each(|(i, byte)| *byte = if i & 4 == 0 { 0 } else { 0xff }),
+                PowerOn::Random(_) => rng.fill_bytes(memory),
+            }
+        }
+    }
+
+    // Kind then seed, for save states
+    pub fn to_bytes(self) -> [u8; 9] {
+        let (kind, seed): (u8, u64) = match self {
+            PowerOn::Zeros => (0, 0),
+            PowerOn::Ones => (1, 0),
+            PowerOn::Pattern => (2, 0),
+            PowerOn::Random(seed) => (3, seed),
+        };
+        let mut bytes: [u8; 9] = [kind, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes[1..].copy_from_slice(&seed.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; 9]) -> Result<PowerOn, Error> {
+        let mut seed: [u8; 8] = [0; 8];
+        seed.copy_from_slice(&bytes[1..]);
+        match bytes[0] {
+            0 => Ok(PowerOn::Zeros),
+            1 => Ok(PowerOn::Ones),
+            2 => Ok(PowerOn::Pattern),
+            3 => Ok(PowerOn::Random(u64::from_le_bytes(seed))),
+            kind => Err(ConfigError(format!("Unknown power-on state {}", kind))),
+        }
+    }
+}
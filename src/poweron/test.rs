@@ -0,0 +1,108 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::cpu::CPU;
+    use crate::mem::Mem;
+    use crate::movie::{Movie, MovieSession};
+    use crate::savestate;
+
+    use super::super::*;
+
+    // Copies the uninitialized $10 to $00 at every frame
+    const PROGRAM: &str = "
+        reset:  LDA #$80
+                STA $2000
+        loop:   JMP loop
+        nmi:    LDA $10
+                STA $00
+                RTI
+        .org $fffa
+                .word nmi, reset, reset
+    ";
+
+    fn new_cpu() -> CPU {
+        CPU::test_load(PROGRAM)
+    }
+
+    #[test]
+    fn test_parse() {
+        for power_on in [PowerOn::Zeros, PowerOn::Ones, PowerOn::Pattern, PowerOn::Random(1234)] {
+            assert_eq!(PowerOn::parse(&power_on.name()).unwrap(), power_on);
+            assert_eq!(PowerOn::from_bytes(power_on.to_bytes()).unwrap(), power_on);
+        }
+        assert_eq!(PowerOn::parse("FF").unwrap(), PowerOn::Ones);
+        assert!(matches!(PowerOn::parse("random").unwrap(), PowerOn::Random(_)));
+        assert!(PowerOn::parse("random:x").unwrap_err().to_string().contains("Invalid seed x"));
+        assert!(PowerOn::parse("garbage").is_err());
+        assert!(PowerOn::from_bytes([4, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_fill() {
+        let (mut ram, mut vram): ([u8; 16], [u8; 8]) = ([0x55; 16], [0x55; 8]);
+        PowerOn::Ones.fill(&mut [&mut ram, &mut vram]);
+        assert_eq!((ram, vram), ([0xff; 16], [0xff; 8]));
+        PowerOn::Zeros.fill(&mut [&mut ram]);
+        assert_eq!(ram, [0; 16]);
+        PowerOn::Pattern.fill(&mut [&mut ram]);
+        assert_eq!(ram[..8], [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+
+        // The same seed always gives the same content, another one does not
+        let fill = |seed: u64| {
+            let (mut ram, mut vram): ([u8; 16], [u8; 8]) = ([0; 16], [0; 8]);
+            PowerOn::Random(seed).fill(&mut [&mut ram, &mut vram]);
+            (ram, vram)
+        };
+        assert_eq!(fill(1), fill(1));
+        assert_ne!(fill(1), fill(2));
+        assert_ne!(fill(1).0[..8], fill(1).1);
+    }
+
+    #[test]
+    fn test_bus_power_on() {
+        let mut cpu: CPU = new_cpu();
+        cpu.bus.power_on(PowerOn::Random(42));
+        assert!(cpu.bus.ppu.palette_table.iter().all(|color| *color < 0x40));
+        let mut other: CPU = new_cpu();
+        other.bus.power_on(PowerOn::Random(42));
+        assert_eq!(cpu.mem_read_u8(0x10), other.mem_read_u8(0x10));
+        assert_eq!((cpu.bus.ppu.vram, cpu.bus.ppu.oam_data), (other.bus.ppu.vram, other.bus.ppu.oam_data));
+
+        // Save states keep the setting without filling the memories again
+        cpu.reset();
+        cpu.run_frame();
+        let state: Vec<u8> = savestate::save(&cpu);
+        let mut loaded: CPU = new_cpu();
+        savestate::load(&mut loaded, &state).unwrap();
+        assert_eq!(loaded.bus.power_on, PowerOn::Random(42));
+        assert_eq!(loaded.mem_read_u8(0x00), cpu.mem_read_u8(0x00));
+    }
+
+    #[test]
+    fn test_movie_power_on() {
+        let mut cpu: CPU = new_cpu();
+        cpu.bus.power_on(PowerOn::Ones);
+        cpu.reset();
+        MovieSession::start_recording(&mut cpu, Movie::new("test"));
+        for _ in 0..3 {
+            cpu.run_frame();
+        }
+        assert_eq!(cpu.mem_read_u8(0x00), 0xff);
+        let movie: Movie = cpu.bus.movie.take().unwrap().movie;
+        assert_eq!(movie.power_on, Some(PowerOn::Ones));
+        assert!(movie.savestate.is_none());
+
+        // Played back on an emulator left at zeros
+        let mut other: CPU = new_cpu();
+        other.reset();
+        MovieSession::start_playback(&mut other, movie, true).unwrap();
+        for _ in 0..3 {
+            other.run_frame();
+        }
+        assert_eq!(other.mem_read_u8(0x00), 0xff);
+    }
+}
@@ -1,3 +1,6 @@
+use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 #[derive(Debug)]
 pub struct AddressingRegister {
     value: (u8, u8),
@@ -40,4 +43,17 @@ impl AddressingRegister {
     pub fn get(&self) -> u16 {
         (self.value.0 as u16) << 8 | (self.value.1 as u16)
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for AddressingRegister {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u16(self.get());
+        writer.bool(self.high_ptr);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.set(reader.u16()?);
+        self.high_ptr = reader.bool()?;
+        Ok(())
+    }
+}
@@ -4,7 +4,9 @@ pub mod statusregister;
 pub mod maskregister;
 pub mod scrollregister;
 
+use crate::error::Error;
 use crate::rom::Mirroring;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 
 use addressregister::AddressingRegister;
 use controlregister::ControlRegister;
@@ -35,6 +37,8 @@ const SCANLINE_DURATION_IN_PPU_CYCLES : usize = 341;
 #[derive(Debug)]
 pub struct PPU {
     pub chr_rom: Vec<u8>,
+    // The cartridge has CHR-RAM instead (the FDS RAM adapter), $2007 can write to it
+    pub chr_ram: bool,
     pub palette_table: [u8; 32],
     pub vram: [u8; 2048],
     pub oam_data: [u8; 256],
@@ -65,6 +69,7 @@ impl PPU {
     pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
         PPU {
             chr_rom,
+            chr_ram: false,
             palette_table: [0; 32],
             vram: [0; 2048],
             oam_data: [0; 256],
@@ -145,6 +150,11 @@ impl PPU {
     pub fn write_to_data(&mut self, value: u8) {
         let addr = self.reg_addr.get();
         match addr {
+            0..=0x1fff if self.chr_ram => {
+                if let Some(byte) = self.chr_rom.get_mut(addr as usize) {
+                    *byte = value;
+                }
+            }
             0..=0x1fff => {
                 println!("attempt to write to chr rom space {}", addr);
             }, 
@@ -220,6 +230,37 @@ impl PPU {
         }
     }
 
+    // What a read of $2007 would return, without touching the buffer nor the address
+    pub fn peek_data(&self) -> u8 {
+        let reg_addr: u16 = self.reg_addr.get();
+        match reg_addr {
+            PALETTE_START..=PALETTE_END => self.peek(reg_addr),
+            _ => self.internal_buffer,
+        }
+    }
+
+    // Side-effect-free read of the PPU address space (0x0000 to 0x3fff)
+    pub fn peek(&self, addr: u16) -> u8 {
+        let addr: u16 = addr & 0x3fff;
+        match addr {
+            CHR_ROM_START..=CHR_ROM_END => self.chr_rom.get(addr as usize).copied().unwrap_or(0),
+            VRAM_START..=FORBIDDEN_END => self.vram[self.mirror_vram_addr(addr) as usize],
+            0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => self.palette_table[(addr - 0x10 - PALETTE_START) as usize],
+            _ => self.palette_table[((addr - PALETTE_START) % 0x20) as usize],
+        }
+    }
+
+    // Side-effect-free write of the PPU address space, used to patch memory (even the CHR ROM)
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        let addr: u16 = addr & 0x3fff;
+        match addr {
+            CHR_ROM_START..=CHR_ROM_END => if let Some(byte) = self.chr_rom.get_mut(addr as usize) { *byte = value },
+            VRAM_START..=FORBIDDEN_END => self.vram[self.mirror_vram_addr(addr) as usize] = value,
+            0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => self.palette_table[(addr - 0x10 - PALETTE_START) as usize] = value,
+            _ => self.palette_table[((addr - PALETTE_START) % 0x20) as usize] = value,
+        }
+    }
+
     pub fn read_status(&mut self) -> u8 {
         let res: u8 = self.reg_status.snapshot();
         self.reg_status.reset_vblank_status();
@@ -243,4 +284,50 @@ impl PPU {
     pub fn poll_nmi_interrupt(&mut self) -> Option<()> {
         self.nmi_interrupt.take()
     }
-}
\ No newline at end of file
+}
+
+// The CHR ROM and the mirroring come from the cartridge, they are not saved
+impl SaveState for PPU {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.palette_table);
+        writer.bytes(&self.vram);
+        writer.bytes(&self.oam_data);
+        self.reg_addr.save_state(writer);
+        writer.u8(self.reg_control.bits());
+        writer.u8(self.reg_mask.bits());
+        writer.u8(self.reg_oam_addr);
+        writer.u8(self.reg_oam_data);
+        writer.u8(self.reg_status.bits());
+        self.reg_scroll.save_state(writer);
+        writer.bool(self.nmi_interrupt.is_some());
+        writer.u16(self.reg_v);
+        writer.u16(self.reg_t);
+        writer.u8(self.reg_x);
+        writer.bool(self.reg_w);
+        writer.u8(self.internal_buffer);
+        writer.usize(self.cycles);
+        writer.usize(self.scanline);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        reader.bytes(&mut self.palette_table)?;
+        reader.bytes(&mut self.vram)?;
+        reader.bytes(&mut self.oam_data)?;
+        self.reg_addr.load_state(reader)?;
+        self.reg_control = ControlRegister::from_bits_truncate(reader.u8()?);
+        self.reg_mask = MaskRegister::from_bits_truncate(reader.u8()?);
+        self.reg_oam_addr = reader.u8()?;
+        self.reg_oam_data = reader.u8()?;
+        self.reg_status = StatusRegister::from_bits_truncate(reader.u8()?);
+        self.reg_scroll.load_state(reader)?;
+        self.nmi_interrupt = reader.bool()?.then_some(());
+        self.reg_v = reader.u16()?;
+        self.reg_t = reader.u16()?;
+        self.reg_x = reader.u8()?;
+        self.reg_w = reader.bool()?;
+        self.internal_buffer = reader.u8()?;
+        self.cycles = reader.usize()?;
+        self.scanline = reader.usize()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,6 @@
+use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 #[derive(Debug)]
 
 pub struct ScrollRegister {
@@ -28,3 +31,18 @@ impl ScrollRegister {
         self.changing_y = false;
     }
 }
+
+impl SaveState for ScrollRegister {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.scroll_x);
+        writer.u8(self.scroll_y);
+        writer.bool(self.changing_y);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.scroll_x = reader.u8()?;
+        self.scroll_y = reader.u8()?;
+        self.changing_y = reader.bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::error::{Error::RomError, Error};
+
+use super::header::{Header, TvSystem};
+use super::Mirroring;
+
+// Known cartridges, to fix the headers of bad dumps. The format is described in gamedb.txt
+const DATABASE: &str = include_str!("gamedb.txt");
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameEntry {
+    // Of the PRG then CHR ROM
+    pub crc32: u32,
+    pub name: String,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub tv_system: TvSystem,
+    pub battery: bool,
+}
+
+fn parse_number<T: std::str::FromStr>(field: &str, line: usize) -> Result<T, Error> {
+    field.parse().map_err(|_| RomError(format!("Game database line {}: invalid number {}", line, field)))
+}
+
+fn parse_entry(text: &str, line: usize) -> Result<GameEntry, Error> {
+    let fields: Vec<&str> = text.split('\t').collect();
+    let [crc32, mapper, mirroring, prg_ram, prg_nvram, chr_ram, chr_nvram, region, battery, name] = fields.as_slice() else {
+        return Err(RomError(format!("Game database line {}: expected 10 fields, found {}", line, fields.len())));
+    };
+    let (mapper, submapper): (&str, &str) = mapper.split_once('.').unwrap_or((mapper, "0"));
+    Ok(GameEntry {
+        crc32: u32::from_str_radix(crc32, 16).map_err(|_| RomError(format!("Game database line {}: invalid CRC32 {}", line, crc32)))?,
+        name: name.to_string(),
+        mapper: parse_number(mapper, line)?,
+        submapper: parse_number(submapper, line)?,
+        mirroring: match *mirroring {
+            "h" => Mirroring::HORIZONTAL,
+            "v" => Mirroring::VERTICAL,
+            "4" => Mirroring::FOURSCREEN,
+            _ => return Err(RomError(format!("Game database line {}: invalid mirroring {}", line, mirroring))),
+        },
+        prg_ram_size: parse_number(prg_ram, line)?,
+        prg_nvram_size: parse_number(prg_nvram, line)?,
+        chr_ram_size: parse_number(chr_ram, line)?,
+        chr_nvram_size: parse_number(chr_nvram, line)?,
+        tv_system: match *region {
+            "ntsc" => TvSystem::Ntsc,
+            "pal" => TvSystem::Pal,
+            "multi" => TvSystem::MultiRegion,
+            "dendy" => TvSystem::Dendy,
+            _ => return Err(RomError(format!("Game database line {}: invalid region {}", line, region))),
+        },
+        battery: match *battery {
+            "0" => false,
+            "1" => true,
+            _ => return Err(RomError(format!("Game database line {}: invalid battery flag {}", line, battery))),
+        },
+    })
+}
+
+pub fn parse_database(text: &str) -> Result<Vec<GameEntry>, Error> {
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| parse_entry(line, index + 1))
+        .collect()
+}
+
+// The embedded database is parsed once and checked by the unit tests, an invalid line cannot reach a user
+pub fn lookup(crc32: u32) -> Option<&'static GameEntry> {
+    static ENTRIES: OnceLock<HashMap<u32, GameEntry>> = OnceLock::new();
+    ENTRIES.get_or_init(|| {
+        parse_database(DATABASE).unwrap_or_default().into_iter().map(|entry| (entry.crc32, entry)).collect()
+    }).get(&crc32)
+}
+
+impl GameEntry {
+    // Corrects the header, describing what differed
+    pub fn apply(&self, header: &mut Header) -> Vec<String> {
+        let mut changes: Vec<String> = vec![];
+        if (header.mapper, header.submapper) != (self.mapper, self.submapper) {
+            changes.push(format!("mapper {}.{} -> {}.{}", header.mapper, header.submapper, self.mapper, self.submapper));
+            (header.mapper, header.submapper) = (self.mapper, self.submapper);
+        }
+        if header.mirroring != self.mirroring {
+            changes.push(format!("mirroring {} -> {}", header.mirroring.name(), self.mirroring.name()));
+            header.mirroring = self.mirroring;
+        }
+        let sizes: [(&str, &mut usize, usize); 4] = [
+            ("PRG RAM", &mut header.prg_ram_size, self.prg_ram_size),
+            ("PRG NVRAM", &mut header.prg_nvram_size, self.prg_nvram_size),
+            ("CHR RAM", &mut header.chr_ram_size, self.chr_ram_size),
+            ("CHR NVRAM", &mut header.chr_nvram_size, self.chr_nvram_size),
+        ];
+        for (name, size, expected) in sizes {
+            if *size != expected {
+                changes.push(format!("{} {} -> {} bytes", name, size, expected));
+                *size = expected;
+            }
+        }
+        if header.tv_system != self.tv_system {
+            changes.push(format!("region {} -> {}", header.tv_system.name(), self.tv_system.name()));
+            header.tv_system = self.tv_system;
+        }
+        if header.battery != self.battery {
+            changes.push(format!("battery {} -> {}", header.battery, self.battery));
+            header.battery = self.battery;
+        }
+        changes
+    }
+}
@@ -0,0 +1,336 @@
+use crate::error::{Error::RomError, Error};
+
+use super::Mirroring;
+
+// The 16 bytes before the ROM data, in one of three flavors:
+//   archaic iNES  only bytes 4-6 are meaningful, the rest is often garbage ("DiskDude!")
+//   iNES          byte 7 adds the upper mapper nibble and the console type, 8 the PRG RAM, 9 the region
+//   NES 2.0       byte 7 bits 2-3 = 2, with 12 bits mappers, submappers, larger sizes and every RAM size
+// https://www.nesdev.org/wiki/INES and https://www.nesdev.org/wiki/NES_2.0
+
+pub const NES_TAG: [u8; 4] = [0x4e, 0x45, 0x53, 0x1a];
+pub const HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize = 512;
+pub const PRG_ROM_PAGE_SIZE: usize = 0x4000; // 16kB
+pub const CHR_ROM_PAGE_SIZE: usize = 0x2000; // 8kB
+const PRG_RAM_PAGE_SIZE: usize = 0x2000; // 8kB
+// PlayChoice-10 hint screen, after the CHR ROM
+const PLAYCHOICE_HINT_SIZE: usize = 0x2000;
+
+const DISK_DUDE: &[u8; 9] = b"DiskDude!";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Archaic,
+    INes,
+    Nes2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TvSystem {
+    Ntsc,
+    Pal,
+    // Works on both
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    Playchoice10,
+    // NES 2.0 byte 13: Famiclone with decimal mode, VT01... kept as a number
+    Extended(u8),
+}
+
+// NES 2.0 byte 15: what the game expects in the controller ports, the devices not emulated are
+// kept as a number
+// https://www.nesdev.org/wiki/NES_2.0#Default_Expansion_Device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpansionDevice {
+    Unspecified,
+    StandardControllers,
+    // NES Four Score or Satellite
+    FourScore,
+    // Famicom four players adapter in its simple mode
+    FamicomFourPlayers,
+    // Zapper in port 2
+    Zapper,
+    PowerPad,
+    FamilyTrainer,
+    // Vaus paddle of Arkanoid, in port 2 or in the expansion port
+    Arkanoid,
+    ArkanoidFamicom,
+    // SNES mouse in port 2
+    SnesMouse,
+    Other(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub format: Format,
+    pub mapper: u16,
+    pub submapper: u8,
+    // Sizes in bytes
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub battery: bool,
+    pub trainer: bool,
+    pub mirroring: Mirroring,
+    pub console: ConsoleType,
+    pub tv_system: TvSystem,
+    // NES 2.0 byte 14, ROMs stored after the CHR ROM
+    pub misc_roms: u8,
+    pub expansion_device: ExpansionDevice,
+    // Inconsistencies that don't prevent loading the ROM
+    pub warnings: Vec<String>,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Archaic => "archaic iNES",
+            Format::INes => "iNES",
+            Format::Nes2 => "NES 2.0",
+        }
+    }
+}
+
+impl TvSystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            TvSystem::Ntsc => "NTSC",
+            TvSystem::Pal => "PAL",
+            TvSystem::MultiRegion => "multi-region",
+            TvSystem::Dendy => "Dendy",
+        }
+    }
+}
+
+impl ConsoleType {
+    pub fn name(self) -> String {
+        match self {
+            ConsoleType::Nes => String::from("NES/Famicom"),
+            ConsoleType::VsSystem => String::from("Vs. System"),
+            ConsoleType::Playchoice10 => String::from("PlayChoice-10"),
+            ConsoleType::Extended(kind) => format!("extended console type {}", kind),
+        }
+    }
+}
+
+impl ExpansionDevice {
+    pub fn from_byte(value: u8) -> ExpansionDevice {
+        match value & 0x3f {
+            0x00 => ExpansionDevice::Unspecified,
+            0x01 => ExpansionDevice::StandardControllers,
+            0x02 => ExpansionDevice::FourScore,
+            0x03 => ExpansionDevice::FamicomFourPlayers,
+            0x08 => ExpansionDevice::Zapper,
+            // Sides A and B
+            0x0b | 0x0c => ExpansionDevice::PowerPad,
+            0x0d | 0x0e => ExpansionDevice::FamilyTrainer,
+            0x0f => ExpansionDevice::Arkanoid,
+            0x10 => ExpansionDevice::ArkanoidFamicom,
+            0x29 => ExpansionDevice::SnesMouse,
+            device => ExpansionDevice::Other(device),
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            ExpansionDevice::Unspecified => String::from("unspecified"),
+            ExpansionDevice::StandardControllers => String::from("standard controllers"),
+            ExpansionDevice::FourScore => String::from("NES Four Score"),
+            ExpansionDevice::FamicomFourPlayers => String::from("Famicom four players adapter"),
+            ExpansionDevice::Zapper => String::from("Zapper"),
+            ExpansionDevice::PowerPad => String::from("Power Pad"),
+            ExpansionDevice::FamilyTrainer => String::from("Family Trainer"),
+            ExpansionDevice::Arkanoid => String::from("Arkanoid Vaus"),
+            ExpansionDevice::ArkanoidFamicom => String::from("Arkanoid Vaus (Famicom)"),
+            ExpansionDevice::SnesMouse => String::from("SNES mouse"),
+            ExpansionDevice::Other(device) => format!("expansion device ${:02X}", device),
+        }
+    }
+}
+
+// NES 2.0 sizes: an MSB nibble of $F means the LSB is 2^E * (MM * 2 + 1), EEEEEEMM
+fn nes2_rom_size(lsb: u8, msb: u8, page_size: usize) -> Result<usize, Error> {
+    match msb {
+        0x0f => 1usize.checked_shl((lsb >> 2) as u32)
+            .and_then(|size| size.checked_mul((lsb & 0b11) as usize * 2 + 1))
+            .ok_or_else(|| RomError(format!("Invalid ROM size exponent {}", lsb >> 2))),
+        _ => Ok(((msb as usize) << 8 | lsb as usize) * page_size),
+    }
+}
+
+// NES 2.0 RAM sizes are 64 << shift bytes, 0 meaning none
+fn nes2_ram_size(shift: u8) -> usize {
+    match shift {
+        0 => 0,
+        shift => 64 << shift,
+    }
+}
+
+impl Header {
+    // Data[6]
+    // 76543210
+    // ||||||||
+    // |||||||+- Nametable arrangement: 1: vertical arrangement ("horizontal mirrored") (CIRAM A10 = PPU A11)
+    // |||||||                          0: horizontal arrangement ("vertically mirrored") (CIRAM A10 = PPU A10)
+    // ||||||+-- 1: Cartridge contains battery-backed PRG RAM ($6000-7FFF) or other persistent memory
+    // |||||+--- 1: 512-byte trainer at $7000-$71FF (stored before PRG data)
+    // ||||+---- 1: Alternative nametable layout
+    // ++++----- Lower part of mapper number
+
+    // Data[7]
+    // 76543210
+    // ||||||||
+    // |||||||+- VS Unisystem
+    // ||||||+-- PlayChoice-10 (8 KB of Hint Screen data stored after CHR data)
+    // ||||++--- If equal to 2, flags 8-15 are in NES 2.0 format
+    // ++++----- Upper part of mapper number
+    pub fn parse(data: &[u8]) -> Result<Header, Error> {
+        if data.len() < HEADER_SIZE || data[0..4] != NES_TAG {
+            return Err(RomError(String::from("This is not a iNES file")));
+        }
+        let (flags6, flags7): (u8, u8) = (data[6], data[7]);
+        let format: Format = match flags7 & 0b0000_1100 {
+            0b0000_1000 => Format::Nes2,
+            0b0000_0000 if data[12..16].iter().all(|byte| *byte == 0) => Format::INes,
+            _ => Format::Archaic,
+        };
+
+        let mut header: Header = Header {
+            format,
+            mapper: (flags6 >> 4) as u16,
+            submapper: 0,
+            prg_rom_size: data[4] as usize * PRG_ROM_PAGE_SIZE,
+            chr_rom_size: data[5] as usize * CHR_ROM_PAGE_SIZE,
+            prg_ram_size: PRG_RAM_PAGE_SIZE,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            battery: flags6 & 0b0000_0010 != 0,
+            trainer: flags6 & 0b0000_0100 != 0,
+            mirroring: match (flags6 & 0b0000_1000 != 0, flags6 & 0b0000_0001 != 0) {
+                (true, _) => Mirroring::FOURSCREEN,
+                (false, true) => Mirroring::VERTICAL,
+                (false, false) => Mirroring::HORIZONTAL,
+            },
+            console: ConsoleType::Nes,
+            tv_system: TvSystem::Ntsc,
+            misc_roms: 0,
+            expansion_device: ExpansionDevice::Unspecified,
+            warnings: vec![],
+        };
+
+        match format {
+            Format::Archaic => {
+                header.warnings.push(match &data[7..16] == DISK_DUDE {
+                    true => String::from("Bytes 7-15 hold \"DiskDude!\" (dirty header), the upper mapper nibble is ignored"),
+                    false => String::from("Bytes 7-15 are not zero (dirty or archaic header), the upper mapper nibble is ignored"),
+                });
+            }
+            Format::INes => {
+                header.mapper |= (flags7 & 0xf0) as u16;
+                header.console = match flags7 & 0b11 {
+                    0 => ConsoleType::Nes,
+                    1 => ConsoleType::VsSystem,
+                    2 => ConsoleType::Playchoice10,
+                    _ => {
+                        header.warnings.push(String::from("Both the Vs. System and PlayChoice-10 flags are set"));
+                        ConsoleType::VsSystem
+                    }
+                };
+                // 0 means 8kB for compatibility
+                header.prg_ram_size = data[8].max(1) as usize * PRG_RAM_PAGE_SIZE;
+                header.tv_system = if data[9] & 1 != 0 { TvSystem::Pal } else { TvSystem::Ntsc };
+                if data[9] & 0xfe != 0 || data[11] != 0 {
+                    header.warnings.push(String::from("Reserved bits of bytes 9-11 are set"));
+                }
+            }
+            Format::Nes2 => {
+                header.mapper |= (flags7 & 0xf0) as u16 | ((data[8] & 0x0f) as u16) << 8;
+                header.submapper = data[8] >> 4;
+                header.prg_rom_size = nes2_rom_size(data[4], data[9] & 0x0f, PRG_ROM_PAGE_SIZE)?;
+                header.chr_rom_size = nes2_rom_size(data[5], data[9] >> 4, CHR_ROM_PAGE_SIZE)?;
+                header.prg_ram_size = nes2_ram_size(data[10] & 0x0f);
+                header.prg_nvram_size = nes2_ram_size(data[10] >> 4);
+                header.chr_ram_size = nes2_ram_size(data[11] & 0x0f);
+                header.chr_nvram_size = nes2_ram_size(data[11] >> 4);
+                header.console = match flags7 & 0b11 {
+                    0 => ConsoleType::Nes,
+                    1 => ConsoleType::VsSystem,
+                    2 => ConsoleType::Playchoice10,
+                    _ => ConsoleType::Extended(data[13] & 0x0f),
+                };
+                header.tv_system = match data[12] & 0b11 {
+                    0 => TvSystem::Ntsc,
+                    1 => TvSystem::Pal,
+                    2 => TvSystem::MultiRegion,
+                    _ => TvSystem::Dendy,
+                };
+                header.misc_roms = data[14] & 0b11;
+                header.expansion_device = ExpansionDevice::from_byte(data[15]);
+                if header.battery && header.prg_nvram_size == 0 && header.chr_nvram_size == 0 {
+                    header.warnings.push(String::from("The battery flag is set but there is no battery-backed RAM"));
+                }
+            }
+        }
+        // Before NES 2.0, the battery makes the PRG RAM saved and no CHR ROM means 8kB of CHR RAM
+        if format != Format::Nes2 {
+            if header.battery {
+                header.prg_nvram_size = header.prg_ram_size;
+                header.prg_ram_size = 0;
+            }
+            if header.chr_rom_size == 0 {
+                header.chr_ram_size = CHR_ROM_PAGE_SIZE;
+            }
+        }
+
+        if header.prg_rom_size == 0 {
+            header.warnings.push(String::from("The header announces no PRG ROM"));
+        }
+        let expected: usize = header.file_size()?;
+        if data.len() < expected {
+            header.warnings.push(format!("The file is truncated: {} bytes instead of {}", data.len(), expected));
+        } else if data.len() > expected && header.misc_roms == 0 {
+            header.warnings.push(format!("{} unexpected bytes after the ROM data, {} expected", data.len() - expected, expected));
+        }
+        Ok(header)
+    }
+
+    // Where the PRG ROM starts in the file
+    pub fn prg_rom_start(&self) -> usize {
+        HEADER_SIZE + if self.trainer { TRAINER_SIZE } else { 0 }
+    }
+
+    pub fn chr_rom_start(&self) -> usize {
+        self.prg_rom_start().saturating_add(self.prg_rom_size)
+    }
+
+    // The PRG then CHR ROM, or what a truncated file holds of them
+    pub fn rom_data<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        let start: usize = self.prg_rom_start().min(data.len());
+        let end: usize = self.chr_rom_start().saturating_add(self.chr_rom_size).min(data.len());
+        &data[start..end]
+    }
+
+    // Size of a file holding what the header announces (NES 2.0 miscellaneous ROMs excepted), an
+    // error when the NES 2.0 exponent sizes add up past what a file can hold
+    pub fn file_size(&self) -> Result<usize, Error> {
+        let hint_screen: usize = match (self.format, self.console) {
+            (Format::INes, ConsoleType::Playchoice10) => PLAYCHOICE_HINT_SIZE,
+            _ => 0,
+        };
+        self.prg_rom_start().checked_add(self.prg_rom_size)
+            .and_then(|size| size.checked_add(self.chr_rom_size))
+            .and_then(|size| size.checked_add(hint_screen))
+            .ok_or_else(|| RomError(format!("Invalid ROM sizes: {} bytes of PRG ROM and {} of CHR ROM", self.prg_rom_size, self.chr_rom_size)))
+    }
+}
@@ -0,0 +1,228 @@
+use crate::error::Error;
+
+use super::gamedb::{self, GameEntry};
+use super::hash;
+use super::header::Header;
+
+// What "nes_emul info" reports about a dump, as text or as JSON
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub file: String,
+    pub file_size: usize,
+    // As found in the file, the database corrections are listed apart
+    pub header: Header,
+    pub database: Option<GameEntry>,
+    // Checksums of the PRG then CHR ROM, without the header and trainer, as the databases list them
+    pub rom_crc32: u32,
+    pub rom_sha1: [u8; 20],
+    pub file_crc32: u32,
+    pub file_sha1: [u8; 20],
+}
+
+// https://www.nesdev.org/wiki/Mapper, the boards most dumps use
+pub fn mapper_name(mapper: u16) -> &'static str {
+    match mapper {
+        0 => "NROM",
+        1 => "MMC1 (SxROM)",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3 (TxROM)",
+        5 => "MMC5 (ExROM)",
+        7 => "AxROM",
+        9 => "MMC2 (PxROM)",
+        10 => "MMC4 (FxROM)",
+        11 => "Color Dreams",
+        13 => "CPROM",
+        16 => "Bandai FCG",
+        18 => "Jaleco SS88006",
+        19 => "Namco 163",
+        21 | 23 | 25 => "Konami VRC2/VRC4",
+        22 => "Konami VRC2a",
+        24 | 26 => "Konami VRC6",
+        28 => "Action 53",
+        30 => "UNROM 512",
+        34 => "BNROM / NINA-001",
+        48 => "Taito TC0690",
+        64 => "Tengen RAMBO-1",
+        65 => "Irem H3001",
+        66 => "GxROM",
+        67 => "Sunsoft-3",
+        68 => "Sunsoft-4",
+        69 => "Sunsoft FME-7",
+        70 => "Bandai 74161",
+        71 => "Camerica BF909x",
+        73 => "Konami VRC3",
+        75 => "Konami VRC1",
+        76 => "Namco 109 (NAMCOT-3446)",
+        79 => "NINA-03/NINA-06",
+        85 => "Konami VRC7",
+        87 => "Jaleco JF-xx",
+        94 => "UN1ROM",
+        105 => "NES-EVENT",
+        118 => "TxSROM",
+        119 => "TQROM",
+        140 => "Jaleco JF-11/JF-14",
+        180 => "UNROM (Crazy Climber)",
+        185 => "CNROM with copy protection",
+        206 => "Namco 118 (DxROM)",
+        210 => "Namco 175/340",
+        228 => "Action 52",
+        232 => "Camerica Quattro",
+        _ => "unknown",
+    }
+}
+
+// https://www.nesdev.org/wiki/NES_2.0_submappers
+pub fn submapper_name(mapper: u16, submapper: u8) -> Option<&'static str> {
+    match (mapper, submapper) {
+        (_, 0) => None,
+        (1, 5) => Some("SEROM/SHROM/SH1ROM"),
+        (1, 6) => Some("2ME"),
+        (2 | 3 | 7 | 34, 1) => Some("no bus conflicts"),
+        (2 | 3 | 7 | 34, 2) => Some("AND bus conflicts"),
+        (4, 1) => Some("MMC6"),
+        (4, 3) => Some("MC-ACC"),
+        (4, 4) => Some("MMC3A"),
+        (16, 4) => Some("FCG-1/2"),
+        (16, 5) => Some("LZ93D50"),
+        (19, 1) => Some("Namco 163 without expansion sound"),
+        (19, 2) => Some("Namco 163 with N163 sound"),
+        (21 | 23 | 25, 1) => Some("VRC4a/VRC4b/VRC4e"),
+        (21 | 23 | 25, 2) => Some("VRC4c/VRC4d/VRC4f"),
+        (21 | 23 | 25, 3) => Some("VRC2"),
+        (71, 1) => Some("Fire Hawk"),
+        (78, 1) => Some("Cosmo Carrier"),
+        (78, 3) => Some("Holy Diver"),
+        _ => Some("unknown"),
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut json: String = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn json_list(texts: &[String]) -> String {
+    format!("[{}]", texts.iter().map(|text| json_string(text)).collect::<Vec<String>>().join(", "))
+}
+
+// 24 kB, 512 bytes or none
+fn size_name(size: usize) -> String {
+    match size {
+        0 => String::from("none"),
+        size if size % 1024 == 0 => format!("{} kB", size / 1024),
+        size => format!("{} bytes", size),
+    }
+}
+
+impl RomInfo {
+    pub fn new(file: &str, data: &[u8]) -> Result<RomInfo, Error> {
+        let header: Header = Header::parse(data)?;
+        let rom: &[u8] = header.rom_data(data);
+        let rom_crc32: u32 = crc32fast::hash(rom);
+        Ok(RomInfo {
+            file: String::from(file),
+            file_size: data.len(),
+            database: gamedb::lookup(rom_crc32).cloned(),
+            header,
+            rom_crc32,
+            rom_sha1: hash::sha1(rom),
+            file_crc32: crc32fast::hash(data),
+            file_sha1: hash::sha1(data),
+        })
+    }
+
+    fn mapper_text(&self) -> String {
+        let (mapper, submapper): (u16, u8) = (self.header.mapper, self.header.submapper);
+        match submapper_name(mapper, submapper) {
+            Some(name) => format!("{} ({}), submapper {} ({})", mapper, mapper_name(mapper), submapper, name),
+            None => format!("{} ({})", mapper, mapper_name(mapper)),
+        }
+    }
+
+    // What the database would change in the header
+    pub fn corrections(&self) -> Vec<String> {
+        match self.database.as_ref() {
+            Some(entry) => entry.apply(&mut self.header.clone()),
+            None => vec![],
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let header: &Header = &self.header;
+        let mut lines: Vec<String> = vec![
+            format!("File:       {} ({} bytes)", self.file, self.file_size),
+            format!("Format:     {}", header.format.name()),
+            format!("Mapper:     {}", self.mapper_text()),
+            format!("PRG ROM:    {}", size_name(header.prg_rom_size)),
+            format!("CHR ROM:    {}", size_name(header.chr_rom_size)),
+            format!("PRG RAM:    {}, battery-backed {}", size_name(header.prg_ram_size), size_name(header.prg_nvram_size)),
+            format!("CHR RAM:    {}, battery-backed {}", size_name(header.chr_ram_size), size_name(header.chr_nvram_size)),
+            format!("Battery:    {}", if header.battery { "yes" } else { "no" }),
+            format!("Trainer:    {}", if header.trainer { "yes" } else { "no" }),
+            format!("Mirroring:  {}", header.mirroring.name()),
+            format!("Region:     {}", header.tv_system.name()),
+            format!("Console:    {}", header.console.name()),
+            format!("Input:      {}", header.expansion_device.name()),
+            format!("ROM CRC32:  {:08x}", self.rom_crc32),
+            format!("ROM SHA-1:  {}", hash::to_hex(&self.rom_sha1)),
+            format!("File CRC32: {:08x}", self.file_crc32),
+            format!("File SHA-1: {}", hash::to_hex(&self.file_sha1)),
+        ];
+        if let Some(entry) = self.database.as_ref() {
+            lines.push(format!("Database:   {}", entry.name));
+            for correction in self.corrections() {
+                lines.push(format!("Correction: {}", correction));
+            }
+        }
+        for warning in header.warnings.iter() {
+            lines.push(format!("Warning:    {}", warning));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    pub fn json(&self) -> String {
+        let header: &Header = &self.header;
+        let fields: Vec<(&str, String)> = vec![
+            ("file", json_string(&self.file)),
+            ("file_size", self.file_size.to_string()),
+            ("format", json_string(header.format.name())),
+            ("mapper", header.mapper.to_string()),
+            ("mapper_name", json_string(mapper_name(header.mapper))),
+            ("submapper", header.submapper.to_string()),
+            ("submapper_name", submapper_name(header.mapper, header.submapper).map(json_string).unwrap_or_else(|| String::from("null"))),
+            ("prg_rom_size", header.prg_rom_size.to_string()),
+            ("chr_rom_size", header.chr_rom_size.to_string()),
+            ("prg_ram_size", header.prg_ram_size.to_string()),
+            ("prg_nvram_size", header.prg_nvram_size.to_string()),
+            ("chr_ram_size", header.chr_ram_size.to_string()),
+            ("chr_nvram_size", header.chr_nvram_size.to_string()),
+            ("battery", header.battery.to_string()),
+            ("trainer", header.trainer.to_string()),
+            ("mirroring", json_string(header.mirroring.name())),
+            ("region", json_string(header.tv_system.name())),
+            ("console", json_string(&header.console.name())),
+            ("expansion_device", json_string(&header.expansion_device.name())),
+            ("rom_crc32", json_string(&format!("{:08x}", self.rom_crc32))),
+            ("rom_sha1", json_string(&hash::to_hex(&self.rom_sha1))),
+            ("file_crc32", json_string(&format!("{:08x}", self.file_crc32))),
+            ("file_sha1", json_string(&hash::to_hex(&self.file_sha1))),
+            ("database", self.database.as_ref().map(|entry| json_string(&entry.name)).unwrap_or_else(|| String::from("null"))),
+            ("corrections", json_list(&self.corrections())),
+            ("warnings", json_list(&header.warnings)),
+        ];
+        let body: Vec<String> = fields.iter().map(|(name, value)| format!("  {}: {}", json_string(name), value)).collect();
+        format!("{{\n{}\n}}\n", body.join(",\n"))
+    }
+}
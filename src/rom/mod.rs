@@ -1,100 +1,118 @@
+mod test;
+
+pub mod gamedb;
+pub mod hash;
+pub mod header;
+pub mod info;
+pub mod unif;
+
 use crate::error::{Error::RomError, Error};
 
-const NES_TAG: [u8; 4] = [0x4e, 0x45, 0x53, 0x1a];
-const PROG_ROM_PAGE_SIZE: usize = 0x4000; // 16kB
-const CHR_ROM_PAGE_SIZE: usize = 0x2000; // 8kB
+use header::{ExpansionDevice, Header};
+use unif::Unif;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mirroring {
     VERTICAL,
     HORIZONTAL,
     FOURSCREEN,
 }
 
+impl Mirroring {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mirroring::VERTICAL => "vertical",
+            Mirroring::HORIZONTAL => "horizontal",
+            Mirroring::FOURSCREEN => "four-screen",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Rom {
     pub program_rom: [u8; 0x8000],
+    // Size of the program actually present, it ends at the top of program_rom
+    pub program_rom_size: usize,
     pub chr_rom: Vec<u8>,
     pub mapper: u8,
     pub screen_mirroring: Mirroring,
+    // From the NES 2.0 header, picks what is plugged in the controller ports
+    pub expansion_device: ExpansionDevice,
 }
 
 impl Rom {
     pub fn new(data: &Vec<u8>) -> Result<Self, Error> {
-        // Data[0..3] => NES^Z
-        // Data[4]    => Program ROM Size
-        // Data[5]    => Chr ROM Size
-        // Data[6]    => Control Byte
-        // Data[7]    => Control Byte
-        // Data[8]    => Program RAM Size
-        // Data[9]    => TV System (Ignored here)
-        // Data[10]    => TV System, Program RAM Presence (Ignored Here)
-
-        // Data[6]
-        // 76543210
-        // ||||||||
-        // |||||||+- Nametable arrangement: 1: vertical arrangement ("horizontal mirrored") (CIRAM A10 = PPU A11)
-        // |||||||                          0: horizontal arrangement ("vertically mirrored") (CIRAM A10 = PPU A10)
-        // ||||||+-- 1: Cartridge contains battery-backed PRG RAM ($6000-7FFF) or other persistent memory
-        // |||||+--- 1: 512-byte trainer at $7000-$71FF (stored before PRG data)
-        // ||||+---- 1: Alternative nametable layout
-        // ++++----- Lower part of mapper number
-
-        // Data[7]
-        // 76543210
-        // ||||||||
-        // |||||||+- VS Unisystem
-        // ||||||+-- PlayChoice-10 (8 KB of Hint Screen data stored after CHR data)
-        // ||||++--- If equal to 2, flags 8-15 are in NES 2.0 format
-        // ++++----- Upper part of mapper number
-
-
-        // ==================== Verification of rom format ====================
-
-        if &data[0..=3] != NES_TAG {
-            return Err(RomError(String::from("This is not a iNES file")))
-        }
-
+        Rom::load(data, true)
+    }
 
-        let ines_version: u8 = (data[7] >> 2) & 0b11;
-        if ines_version != 0b00 {
-            return Err(RomError(String::from("This version of NES is not supported!")))
+    // With database, a header found in gamedb.txt is corrected. UNIF files are recognized by their tag
+    pub fn load(data: &[u8], database: bool) -> Result<Self, Error> {
+        if Unif::detect(data) {
+            return Rom::from_unif(&Unif::parse(data)?);
+        }
+        // The bytes of the header are described in header.rs
+        let mut header: Header = Header::parse(data)?;
+        if database {
+            if let Some(entry) = gamedb::lookup(crc32fast::hash(header.rom_data(data))) {
+                let changes: Vec<String> = entry.apply(&mut header);
+                if !changes.is_empty() {
+                    println!("Header corrected from the game database ({}): {}", entry.name, changes.join(", "));
+                }
+            }
+        }
+        if header.mapper > 0xff {
+            return Err(RomError(format!("The mapper {} is not supported", header.mapper)));
         }
 
         // ==================== Extraction of data ======================
 
-        let mapper: u8 = (data[7] & 0b1111_0000) | (data[6] >> 4);
-
-        let four_screen: bool = data[6] & 0b0000_1000 != 0;
-        let vertical_mirroring: bool = data[6] & 0b0000_0001 != 0;
-        let screen_mirroring: Mirroring = match (four_screen, vertical_mirroring) {
-            (true, _) => Mirroring::FOURSCREEN,
-            (false, true) => Mirroring::VERTICAL,
-            (false, false) => Mirroring::HORIZONTAL,
-        };
+        let mapper: u8 = header.mapper as u8;
+        let screen_mirroring: Mirroring = header.mirroring;
 
-        if data[4] > 2 {
+        if header.prg_rom_size > 0x8000 {
             return Err(RomError(String::from("Program Rom is too big !")));
         }
 
-        let program_rom_size: usize = data[4] as usize * PROG_ROM_PAGE_SIZE;
-        let chr_rom_size: usize = data[5] as usize * CHR_ROM_PAGE_SIZE;
-
-        let skip_trainer: bool = data[6] & 0b0000_0100 != 0;
-
-        let program_rom_start: usize = 16 + if skip_trainer { 512 } else { 0 };
-        let chr_rom_start: usize = program_rom_start + program_rom_size;
+        let program_rom_start: usize = header.prg_rom_start();
+        let chr_rom_start: usize = header.chr_rom_start();
+        let chr_rom_end: usize = chr_rom_start.saturating_add(header.chr_rom_size);
+        if data.len() < chr_rom_end {
+            return Err(RomError(format!("The file is truncated, {} bytes instead of {}", data.len(), chr_rom_end)));
+        }
+        let program: &[u8] = &data[program_rom_start..program_rom_start + header.prg_rom_size];
+        let mut rom: Rom = Rom::from_parts(program, &data[chr_rom_start..chr_rom_end], mapper, screen_mirroring)?;
+        rom.expansion_device = header.expansion_device;
+        Ok(rom)
+    }
 
+    // The board name gives the mapper. Only NROM boards run, the others (and the mirroring left to
+    // the mapper) are refused instead of running as NROM
+    pub fn from_unif(unif: &Unif) -> Result<Self, Error> {
+        let mapper: u8 = unif.mapper()?;
+        if mapper != 0 {
+            return Err(RomError(format!("The UNIF board {} uses the mapper {}, which is not implemented (only NROM boards run)", unif.board, mapper)));
+        }
+        let screen_mirroring: Mirroring = unif.mirroring.ok_or_else(|| {
+            RomError(format!("The UNIF board {} leaves the mirroring to the mapper (MIRR 5), which is not implemented", unif.board))
+        })?;
+        Rom::from_parts(&unif.prg_rom, &unif.chr_rom, mapper, screen_mirroring)
+    }
 
+    fn from_parts(program: &[u8], chr_rom: &[u8], mapper: u8, screen_mirroring: Mirroring) -> Result<Self, Error> {
+        if program.len() > 0x8000 {
+            return Err(RomError(String::from("Program Rom is too big !")));
+        }
+        let program_rom_size: usize = program.len();
         let mut program_rom: [u8; 0x8000] = [0; 0x8000];
-        program_rom[0x8000-program_rom_size..].copy_from_slice(&data[program_rom_start..(program_rom_start+program_rom_size)]);
+        program_rom[0x8000-program_rom_size..].copy_from_slice(program);
 
-        
         Ok(Rom{
             program_rom,
-            chr_rom: data[chr_rom_start..(chr_rom_start+chr_rom_size)].to_vec(),
+            program_rom_size,
+            chr_rom: chr_rom.to_vec(),
             mapper,
-            screen_mirroring
+            screen_mirroring,
+            expansion_device: ExpansionDevice::Unspecified,
         })
     }
 
@@ -108,10 +126,29 @@ impl Rom {
         Ok(
             Rom {
                 program_rom,
+                program_rom_size: 0x8000,
                 chr_rom: vec![],
                 mapper: 0,
-                screen_mirroring: Mirroring::FOURSCREEN
+                screen_mirroring: Mirroring::FOURSCREEN,
+                expansion_device: ExpansionDevice::Unspecified,
             }
         )
     }
+
+    // The PRG then CHR ROM, without the header
+    fn data(&self) -> Vec<u8> {
+        let mut data: Vec<u8> = self.program_rom[0x8000 - self.program_rom_size..].to_vec();
+        data.extend_from_slice(&self.chr_rom);
+        data
+    }
+
+    // The checksum FCEUX writes in its movies
+    pub fn md5(&self) -> [u8; 16] {
+        hash::md5(&self.data())
+    }
+
+    // The checksum BizHawk writes in its movies
+    pub fn sha1(&self) -> [u8; 20] {
+        hash::sha1(&self.data())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use super::super::gamedb::{self, GameEntry};
+    use super::super::header::*;
+    use super::super::info::RomInfo;
+    use super::super::unif::{self, Unif};
+    use super::super::*;
+
+    // A header followed by the PRG and CHR ROMs it announces
+    fn ines(header: [u8; 16]) -> Vec<u8> {
+        let mut data: Vec<u8> = header.to_vec();
+        data.resize(HEADER_SIZE + header[4] as usize * PRG_ROM_PAGE_SIZE + header[5] as usize * CHR_ROM_PAGE_SIZE, 0xea);
+        data
+    }
+
+    #[test]
+    fn test_ines_header() {
+        let data: Vec<u8> = ines([0x4e, 0x45, 0x53, 0x1a, 2, 1, 0x13, 0x40, 0, 1, 0, 0, 0, 0, 0, 0]);
+        let header: Header = Header::parse(&data).unwrap();
+        assert_eq!(header.format, Format::INes);
+        assert_eq!(header.mapper, 0x41);
+        assert_eq!((header.prg_rom_size, header.chr_rom_size), (0x8000, 0x2000));
+        assert_eq!((header.prg_ram_size, header.prg_nvram_size, header.chr_ram_size), (0, 0x2000, 0));
+        assert!(header.battery && !header.trainer);
+        assert_eq!(header.mirroring, Mirroring::VERTICAL);
+        assert_eq!(header.tv_system, TvSystem::Pal);
+        assert!(header.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_archaic_header() {
+        let mut header: [u8; 16] = [0x4e, 0x45, 0x53, 0x1a, 1, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        header[7..16].copy_from_slice(b"DiskDude!");
+        let header: Header = Header::parse(&ines(header)).unwrap();
+        assert_eq!(header.format, Format::Archaic);
+        // The "D" would make it mapper 0x41
+        assert_eq!(header.mapper, 1);
+        assert_eq!(header.chr_ram_size, 0x2000);
+        assert!(header.warnings[0].contains("DiskDude!"));
+    }
+
+    #[test]
+    fn test_nes2_header() {
+        let data: Vec<u8> = ines([0x4e, 0x45, 0x53, 0x1a, 8, 0, 0x42, 0x08, 0x14, 0, 0x70, 0x07, 2, 0, 0, 0]);
+        let header: Header = Header::parse(&data).unwrap();
+        assert_eq!(header.format, Format::Nes2);
+        assert_eq!((header.mapper, header.submapper), (0x404, 1));
+        assert_eq!(header.prg_rom_size, 8 * PRG_ROM_PAGE_SIZE);
+        assert_eq!((header.prg_ram_size, header.prg_nvram_size), (0, 0x2000));
+        assert_eq!((header.chr_ram_size, header.chr_nvram_size), (0x2000, 0));
+        assert_eq!(header.tv_system, TvSystem::MultiRegion);
+        assert_eq!(header.expansion_device, ExpansionDevice::Unspecified);
+        assert!(header.warnings.is_empty());
+
+        // NES 2.0 ROMs load, with the input device the game expects
+        let mut data: Vec<u8> = ines([0x4e, 0x45, 0x53, 0x1a, 1, 1, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0x02]);
+        assert_eq!(Rom::new(&data).unwrap().expansion_device, ExpansionDevice::FourScore);
+        data[15] = 0x2a;
+        assert_eq!(Header::parse(&data).unwrap().expansion_device, ExpansionDevice::Other(0x2a));
+        assert!(RomInfo::new("game.nes", &data).unwrap().text().contains("Input:      expansion device $2A"));
+        data[8] = 0x01;
+        assert!(Rom::new(&data).unwrap_err().to_string().contains("mapper 256"));
+    }
+
+    #[test]
+    fn test_size_warnings() {
+        let mut data: Vec<u8> = ines([0x4e, 0x45, 0x53, 0x1a, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.push(0);
+        assert!(Header::parse(&data).unwrap().warnings[0].contains("1 unexpected bytes"));
+        data.truncate(0x5000);
+        assert!(Header::parse(&data).unwrap().warnings[0].contains("truncated"));
+        assert!(Rom::new(&data).is_err());
+        assert!(Header::parse(b"NES").is_err());
+
+        // 2^63 bytes of PRG and of CHR ROM, the sum doesn't fit
+        let data: Vec<u8> = vec![0x4e, 0x45, 0x53, 0x1a, 0xfc, 0xfc, 0, 0x08, 0, 0xff, 0, 0, 0, 0, 0, 0];
+        assert!(Header::parse(&data).unwrap_err().to_string().contains("Invalid ROM sizes"));
+        assert!(Rom::new(&data).is_err());
+        assert!(RomInfo::new("game.nes", &data).is_err());
+    }
+
+    #[test]
+    fn test_rom_info() {
+        let data: Vec<u8> = ines([0x4e, 0x45, 0x53, 0x1a, 1, 1, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let info: RomInfo = RomInfo::new("game \"1\".nes", &data).unwrap();
+        assert_eq!(info.rom_crc32, crc32fast::hash(&data[HEADER_SIZE..]));
+        assert_eq!(info.file_sha1, hash::sha1(&data));
+        let text: String = info.text();
+        assert!(text.contains("Format:     iNES"));
+        assert!(text.contains("Mapper:     0 (NROM)"));
+        assert!(text.contains("PRG ROM:    16 kB"));
+        assert!(text.contains("Mirroring:  vertical"));
+        let json: String = info.json();
+        assert!(json.starts_with("{\n  \"file\": \"game \\\"1\\\".nes\",\n"));
+        assert!(json.contains("\"submapper_name\": null,"));
+        assert!(json.contains("\"warnings\": []\n}"));
+    }
+
+    #[test]
+    fn test_game_database() {
+        let entries: Vec<GameEntry> = gamedb::parse_database(include_str!("gamedb.txt")).unwrap();
+        assert!(!entries.is_empty());
+        assert_eq!(gamedb::lookup(entries[0].crc32), Some(&entries[0]));
+        assert_eq!(gamedb::lookup(0xdeadbeef), None);
+        assert!(gamedb::parse_database("3337ec46\t0\tv\n").unwrap_err().to_string().contains("line 1: expected 10 fields"));
+        assert!(gamedb::parse_database("# comment\n3337ec46\t0\tx\t0\t0\t0\t0\tntsc\t0\tGame").unwrap_err().to_string().contains("line 2: invalid mirroring x"));
+
+        let entry: GameEntry = gamedb::parse_database("12345678\t4.1\th\t0\t1024\t0\t0\tpal\t1\tGame").unwrap().remove(0);
+        assert_eq!((entry.mapper, entry.submapper, entry.mirroring), (4, 1, Mirroring::HORIZONTAL));
+        let mut header: Header = Header::parse(&ines([0x4e, 0x45, 0x53, 0x1a, 1, 1, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0])).unwrap();
+        let changes: Vec<String> = entry.apply(&mut header);
+        assert_eq!(changes, vec![
+            "mapper 1.0 -> 4.1", "mirroring vertical -> horizontal", "PRG RAM 8192 -> 0 bytes",
+            "PRG NVRAM 0 -> 1024 bytes", "region NTSC -> PAL", "battery false -> true",
+        ]);
+        assert_eq!((header.mapper, header.prg_nvram_size, header.tv_system), (4, 1024, TvSystem::Pal));
+        assert!(entry.apply(&mut header).is_empty());
+    }
+
+    fn unif_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk: Vec<u8> = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    #[test]
+    fn test_unif() {
+        let mut data: Vec<u8> = b"UNIF\x07\0\0\0".to_vec();
+        data.resize(32, 0);
+        data.extend(unif_chunk(b"MAPR", b"NES-NROM-256\0"));
+        data.extend(unif_chunk(b"PRG1", &[2; 0x4000]));
+        data.extend(unif_chunk(b"PRG0", &[1; 0x4000]));
+        data.extend(unif_chunk(b"CHR0", &[3; 0x2000]));
+        data.extend(unif_chunk(b"NAME", b"Game\0"));
+        data.extend(unif_chunk(b"MIRR", &[1]));
+        data.extend(unif_chunk(b"BATR", &[1]));
+        data.extend(unif_chunk(b"TVCI", &[2]));
+        let parsed: Unif = Unif::parse(&data).unwrap();
+        assert_eq!((parsed.revision, parsed.board.as_str(), parsed.mapper().unwrap()), (7, "NES-NROM-256", 0));
+        assert_eq!((parsed.mirroring, parsed.battery, parsed.tv_system), (Some(Mirroring::VERTICAL), true, TvSystem::MultiRegion));
+
+        // The same Rom as the iNES path
+        let rom: Rom = Rom::load(&data, true).unwrap();
+        assert_eq!((rom.program_rom_size, rom.program_rom[0], rom.program_rom[0x4000]), (0x8000, 1, 2));
+        assert_eq!((rom.chr_rom.len(), rom.mapper, rom.screen_mirroring), (0x2000, 0, Mirroring::VERTICAL));
+
+        assert_eq!(unif::board_mapper("HVC-SNROM"), Some(1));
+        assert_eq!(unif::board_mapper("unl-sachen-8259a"), Some(141));
+        assert_eq!(unif::board_mapper("NES-XYZROM"), None);
+
+        let mut unknown: Vec<u8> = data.clone();
+        unknown[40..52].copy_from_slice(b"NES-XYZROM\0\0");
+        assert!(Rom::load(&unknown, true).unwrap_err().to_string().contains("Unknown UNIF board NES-XYZROM"));
+
+        // Known boards whose mapper isn't implemented, and the mirroring set by the mapper
+        let mut mapped: Vec<u8> = data.clone();
+        mapped[40..52].copy_from_slice(b"NES-SNROM\0\0\0");
+        assert!(Rom::load(&mapped, true).unwrap_err().to_string().contains("NES-SNROM uses the mapper 1, which is not implemented"));
+        let mut mapper_mirroring: Vec<u8> = data.clone();
+        let mirr: usize = mapper_mirroring.windows(4).position(|id| id == b"MIRR").unwrap();
+        mapper_mirroring[mirr + 8] = 5;
+        assert!(Rom::load(&mapper_mirroring, true).unwrap_err().to_string().contains("MIRR 5"));
+        assert!(Unif::parse(&data[..data.len() - 1]).unwrap_err().to_string().contains("TVCI is truncated"));
+        assert!(Unif::parse(&data[..32]).unwrap_err().to_string().contains("MAPR chunk is missing"));
+    }
+}
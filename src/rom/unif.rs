@@ -0,0 +1,127 @@
+use crate::error::{Error::RomError, Error};
+
+use super::header::TvSystem;
+use super::Mirroring;
+
+// UNIF: a 32 bytes header ("UNIF", revision) followed by chunks of a 4 letters ID, a 32 bits size and
+// the data. The cartridge is described by its board name instead of a mapper number:
+//   MAPR       board name, null-terminated
+//   PRG0-PRGF  program ROM, concatenated in that order
+//   CHR0-CHRF  character ROM, concatenated in that order
+//   MIRR       0 horizontal, 1 vertical, 2/3 single-screen, 4 four-screen, 5 set by the mapper
+//   BATR       battery-backed RAM
+//   TVCI       0 NTSC, 1 PAL, 2 both
+// https://www.nesdev.org/wiki/UNIF
+
+pub const UNIF_TAG: &[u8; 4] = b"UNIF";
+const UNIF_HEADER_SIZE: usize = 32;
+
+// Boards of licensed games, without their NES-/HVC- prefix, and unlicensed ones with their full name
+const BOARDS: [(&str, u8); 52] = [
+    ("NROM", 0), ("NROM-128", 0), ("NROM-256", 0), ("RROM", 0), ("RROM-128", 0),
+    ("SAROM", 1), ("SBROM", 1), ("SCROM", 1), ("SEROM", 1), ("SFROM", 1), ("SGROM", 1), ("SHROM", 1),
+    ("SJROM", 1), ("SKROM", 1), ("SLROM", 1), ("SL1ROM", 1), ("SNROM", 1), ("SOROM", 1), ("SUROM", 1), ("SXROM", 1),
+    ("UNROM", 2), ("UOROM", 2),
+    ("CNROM", 3),
+    ("TBROM", 4), ("TEROM", 4), ("TFROM", 4), ("TGROM", 4), ("TKROM", 4), ("TLROM", 4), ("TL1ROM", 4),
+    ("TR1ROM", 4), ("TSROM", 4), ("TVROM", 4),
+    ("EKROM", 5), ("ELROM", 5), ("ETROM", 5), ("EWROM", 5),
+    ("AMROM", 7), ("ANROM", 7), ("AOROM", 7),
+    ("PNROM", 9),
+    ("FJROM", 10), ("FKROM", 10),
+    ("GNROM", 66), ("MHROM", 66),
+    ("UNL-SA-NROM", 143), ("UNL-SA-72007", 145), ("UNL-SA-72008", 133),
+    ("UNL-Sachen-8259A", 141), ("UNL-Sachen-8259B", 138), ("UNL-Sachen-8259C", 139), ("UNL-Sachen-8259D", 137),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unif {
+    pub revision: u32,
+    pub board: String,
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    // None when the mapper sets it
+    pub mirroring: Option<Mirroring>,
+    pub battery: bool,
+    pub tv_system: TvSystem,
+}
+
+// The iNES mapper number of a board: "NES-SNROM" -> 1, "UNL-Sachen-8259A" -> 141
+pub fn board_mapper(board: &str) -> Option<u8> {
+    let find = |name: &str| BOARDS.iter().find(|(board, _)| board.eq_ignore_ascii_case(name)).map(|(_, mapper)| *mapper);
+    find(board).or_else(|| board.split_once('-').and_then(|(_, name)| find(name)))
+}
+
+impl Unif {
+    pub fn detect(data: &[u8]) -> bool {
+        data.starts_with(UNIF_TAG)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Unif, Error> {
+        if !Unif::detect(data) || data.len() < UNIF_HEADER_SIZE {
+            return Err(RomError(String::from("This is not a UNIF file")));
+        }
+        let mut unif: Unif = Unif {
+            revision: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            board: String::new(),
+            prg_rom: vec![],
+            chr_rom: vec![],
+            mirroring: Some(Mirroring::HORIZONTAL),
+            battery: false,
+            tv_system: TvSystem::Ntsc,
+        };
+        // The ROM chunks may come in any order
+        let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+        let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+        let mut pos: usize = UNIF_HEADER_SIZE;
+        while pos < data.len() {
+            let header: &[u8] = data.get(pos..pos + 8).ok_or_else(|| RomError(String::from("The UNIF file is truncated")))?;
+            let id: &[u8] = &header[..4];
+            let size: usize = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            let chunk: &[u8] = data.get(pos + 8..(pos + 8).saturating_add(size))
+                .ok_or_else(|| RomError(format!("The UNIF chunk {} is truncated", String::from_utf8_lossy(id))))?;
+            pos += 8 + size;
+            // PRG0-PRGF and CHR0-CHRF end with a hexadecimal digit
+            let bank: Option<usize> = (id[3] as char).to_digit(16).map(|bank| bank as usize);
+            match (&id[..3], bank) {
+                (b"PRG", Some(bank)) => prg_chunks[bank] = Some(chunk),
+                (b"CHR", Some(bank)) => chr_chunks[bank] = Some(chunk),
+                _ => match id {
+                    b"MAPR" => {
+                        let end: usize = chunk.iter().position(|byte| *byte == 0).unwrap_or(chunk.len());
+                        unif.board = String::from_utf8_lossy(&chunk[..end]).trim().to_string();
+                    }
+                    b"MIRR" => unif.mirroring = match chunk.first() {
+                        Some(0) => Some(Mirroring::HORIZONTAL),
+                        Some(1) => Some(Mirroring::VERTICAL),
+                        Some(4) => Some(Mirroring::FOURSCREEN),
+                        Some(5) => None,
+                        Some(2 | 3) => return Err(RomError(String::from("Single-screen mirroring is not supported"))),
+                        _ => return Err(RomError(String::from("Invalid UNIF MIRR chunk"))),
+                    },
+                    b"BATR" => unif.battery = chunk.first().is_none_or(|battery| *battery != 0),
+                    b"TVCI" => unif.tv_system = match chunk.first() {
+                        Some(1) => TvSystem::Pal,
+                        Some(2) => TvSystem::MultiRegion,
+                        _ => TvSystem::Ntsc,
+                    },
+                    // Names, dumper, controllers, checksums...
+                    _ => (),
+                },
+            }
+        }
+        if unif.board.is_empty() {
+            return Err(RomError(String::from("The UNIF MAPR chunk is missing")));
+        }
+        unif.prg_rom = prg_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+        unif.chr_rom = chr_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+        if unif.prg_rom.is_empty() {
+            return Err(RomError(String::from("The UNIF file has no PRG chunk")));
+        }
+        Ok(unif)
+    }
+
+    pub fn mapper(&self) -> Result<u8, Error> {
+        board_mapper(&self.board).ok_or_else(|| RomError(format!("Unknown UNIF board {}", self.board)))
+    }
+}
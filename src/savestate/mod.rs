@@ -0,0 +1,221 @@
+mod test;
+
+use std::path::{Path, PathBuf};
+
+use crate::cpu::CPU;
+use crate::error::{Error::StateError, Error};
+use crate::movie::MovieFrame;
+
+// Save states: everything the emulated console holds (CPU registers, RAMs, PPU registers and
+// memories, joypads, the frame being drawn) so that running from a loaded state gives exactly the
+// same frames as running from the moment it was saved.
+// What belongs to the user rather than to the console (cheats, freezes, logs) is left untouched.
+
+const MAGIC: &[u8; 8] = b"NESSTATE";
+const VERSION: u8 = 3;
+
+// game.nes -> game.ss0 ... game.ss9
+pub const STATE_FILE_EXTENSION: &str = "ss";
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+// Implemented by every part of the console with some state, each one knows its private fields
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error>;
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        StateWriter::new()
+    }
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { data: vec![] }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    // Fixed size memories, the reader knows their length
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    // Variable size data, prefixed with its length
+    pub fn vec(&mut self, bytes: &[u8]) {
+        self.usize(bytes.len());
+        self.bytes(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes: &[u8] = self.data.get(self.pos..self.pos.saturating_add(len))
+            .ok_or_else(|| StateError(String::from("The save state is truncated")))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        let bytes: &[u8] = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        let mut bytes: [u8; 8] = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.u64()?).map_err(|_| StateError(String::from("Invalid size in the save state")))
+    }
+
+    pub fn bytes(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        bytes.copy_from_slice(self.take(bytes.len())?);
+        Ok(())
+    }
+
+    pub fn vec(&mut self) -> Result<Vec<u8>, Error> {
+        let len: usize = self.usize()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+// Where the movie being recorded or played was when the state was saved, see MovieSession::state_loaded
+fn save_movie(cpu: &CPU, writer: &mut StateWriter) {
+    match cpu.bus.movie.as_ref() {
+        Some(session) => {
+            writer.bool(true);
+            writer.usize(session.frame);
+            for frame in session.movie.frames.iter().take(session.frame) {
+                writer.bytes(&frame.to_bytes());
+            }
+        }
+        None => writer.bool(false),
+    }
+}
+
+fn load_movie(reader: &mut StateReader) -> Result<Option<Vec<MovieFrame>>, Error> {
+    if !reader.bool()? {
+        return Ok(None);
+    }
+    let len: usize = reader.usize()?;
+    let mut frames: Vec<MovieFrame> = vec![];
+    for _ in 0..len {
+        let mut bytes: [u8; 3] = [0; 3];
+        reader.bytes(&mut bytes)?;
+        frames.push(MovieFrame::from_bytes(bytes));
+    }
+    Ok(Some(frames))
+}
+
+pub fn save(cpu: &CPU) -> Vec<u8> {
+    let mut writer: StateWriter = StateWriter::new();
+    writer.bytes(MAGIC);
+    writer.u8(VERSION);
+    writer.bytes(&cpu.bus.rom_md5());
+    save_movie(cpu, &mut writer);
+    cpu.save_state(&mut writer);
+    writer.finish()
+}
+
+// A state that cannot be loaded leaves the console as it was
+pub fn load(cpu: &mut CPU, data: &[u8]) -> Result<(), Error> {
+    let mut reader: StateReader = StateReader::new(data);
+    let mut magic: [u8; 8] = [0; 8];
+    reader.bytes(&mut magic).map_err(|_| StateError(String::from("This is not a save state")))?;
+    if &magic != MAGIC {
+        return Err(StateError(String::from("This is not a save state")));
+    }
+    let version: u8 = reader.u8()?;
+    if version != VERSION {
+        return Err(StateError(format!("Unsupported save state version {}, expected {}", version, VERSION)));
+    }
+    let mut md5: [u8; 16] = [0; 16];
+    reader.bytes(&mut md5)?;
+    if md5 != cpu.bus.rom_md5() {
+        return Err(StateError(String::from("The save state was made with another ROM")));
+    }
+    let movie_frames: Option<Vec<MovieFrame>> = load_movie(&mut reader)?;
+    if let Some(session) = cpu.bus.movie.as_ref() {
+        session.check_state(movie_frames.as_deref())?;
+    }
+
+    let backup: Vec<u8> = save(cpu);
+    let result: Result<(), Error> = cpu.load_state(&mut reader).and_then(|_| match reader.is_empty() {
+        true => Ok(()),
+        false => Err(StateError(String::from("Unexpected data at the end of the save state"))),
+    });
+    if let Err(e) = result {
+        let mut reader: StateReader = StateReader::new(&backup);
+        reader.take(MAGIC.len() + 1 + 16)?;
+        load_movie(&mut reader)?;
+        cpu.load_state(&mut reader)?;
+        return Err(e);
+    }
+    if let (Some(session), Some(frames)) = (cpu.bus.movie.as_mut(), movie_frames) {
+        session.state_loaded(frames);
+    }
+    Ok(())
+}
+
+pub fn save_file(cpu: &CPU, path: &Path) -> Result<(), Error> {
+    std::fs::write(path, save(cpu))?;
+    Ok(())
+}
+
+pub fn load_file(cpu: &mut CPU, path: &Path) -> Result<(), Error> {
+    load(cpu, &std::fs::read(path)?)
+}
+
+pub fn path_for_rom(rom_path: &Path, slot: u8) -> PathBuf {
+    rom_path.with_extension(format!("{}{}", STATE_FILE_EXTENSION, slot))
+}
@@ -0,0 +1,96 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::cpu::CPU;
+    use crate::input::{InputSetup, JoypadButton};
+    use crate::mem::Mem;
+
+    use super::super::*;
+
+    // Adds the joypad 1 buttons to $01 at every NMI, counts the frames in $02
+    const PROGRAM: &str = "
+        reset:  LDA #$80
+                STA $2000
+        loop:   JMP loop
+        nmi:    LDA #1
+                STA $4016
+                LDA #0
+                STA $4016
+                LDX #8
+        read:   LDA $4016
+                LSR A
+                ROL $00
+                DEX
+                BNE read
+                LDA $00
+                CLC
+                ADC $01
+                STA $01
+                INC $02
+                RTI
+        .org $fffa
+                .word nmi, reset, reset
+    ";
+
+
+    // RAM and screen after some frames, pressing A on every other frame
+    fn run(cpu: &mut CPU, frames: usize) -> (u8, u8, u64) {
+        for _ in 0..frames {
+            let buttons: JoypadButton = if cpu.bus.frames().is_multiple_of(2) { JoypadButton::BUTTON_A } else { JoypadButton::empty() };
+            cpu.bus.screen.joypad1.set_buttons(buttons);
+            cpu.run_frame();
+        }
+        (cpu.mem_read_u8(0x01), cpu.mem_read_u8(0x02), cpu.bus.screen.frame.hash())
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut cpu: CPU = CPU::test_load(PROGRAM);
+        run(&mut cpu, 10);
+        let state: Vec<u8> = save(&cpu);
+        let (pc, frames): (u16, usize) = (cpu.reg_pc, cpu.bus.frames());
+        let expected: (u8, u8, u64) = run(&mut cpu, 10);
+
+        load(&mut cpu, &state).unwrap();
+        assert_eq!((cpu.reg_pc, cpu.bus.frames()), (pc, frames));
+        assert_eq!(run(&mut cpu, 10), expected);
+
+        // In another emulator of the same ROM
+        let mut other: CPU = CPU::test_load(PROGRAM);
+        load(&mut other, &state).unwrap();
+        assert_eq!(run(&mut other, 10), expected);
+    }
+
+    #[test]
+    fn test_invalid_states() {
+        let mut cpu: CPU = CPU::test_load(PROGRAM);
+        run(&mut cpu, 5);
+        let state: Vec<u8> = save(&cpu);
+        let before: Vec<u8> = save(&cpu);
+
+        assert!(load(&mut cpu, b"not a state").unwrap_err().to_string().contains("not a save state"));
+        let truncated: Result<(), Error> = load(&mut cpu, &state[..state.len() - 100]);
+        assert!(truncated.unwrap_err().to_string().contains("truncated"));
+        assert_eq!(save(&cpu), before);
+
+        let mut other: CPU = CPU::test_load(&PROGRAM.replace("INC $02", "INC $03"));
+        assert!(load(&mut other, &state).unwrap_err().to_string().contains("another ROM"));
+
+        // Another input setup, whose device states have another layout
+        let mut zapper: CPU = CPU::test_load(PROGRAM);
+        InputSetup::Zapper.plug(&mut zapper.bus.screen);
+        let before: Vec<u8> = save(&zapper);
+        assert!(load(&mut zapper, &state).unwrap_err().to_string().contains("made with the joypads input setup, not zapper"));
+        assert_eq!(save(&zapper), before);
+        assert!(load(&mut cpu, &before).unwrap_err().to_string().contains("zapper input setup, not joypads"));
+    }
+
+    #[test]
+    fn test_path_for_rom() {
+        assert_eq!(path_for_rom(Path::new("roms/game.nes"), 3), PathBuf::from("roms/game.ss3"));
+    }
+}
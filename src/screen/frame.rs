@@ -1,10 +1,14 @@
+use crate::error::Error;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+#[derive(Clone)]
 pub struct Frame {
     pub data: Vec<u8>,
 }
 
 impl Frame {
-    const WIDTH: usize = 256;
-    const HEIGHT: usize = 240;
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 240;
 
     pub fn new() -> Self {
         Frame {
@@ -21,4 +25,29 @@ impl Frame {
         }
     }
 
-}
\ No newline at end of file
+    pub fn get_pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let base: usize = 3*y*Frame::WIDTH + 3*x;
+        (self.data[base], self.data[base+1], self.data[base+2])
+    }
+
+    // FNV-1a over the RGB data, stable across runs and platforms (unlike std's DefaultHasher)
+    pub fn hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in self.data.iter() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+
+}
+
+impl SaveState for Frame {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.data);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        reader.bytes(&mut self.data)
+    }
+}
@@ -1,39 +1,100 @@
 pub mod palette;
 pub mod frame;
 pub mod render;
+pub mod snapshot;
+pub mod viewer;
+mod test;
 
 use std::collections::HashMap;
-
-use anyhow::Error;
+use std::time::{Duration, Instant};
 
 use frame::Frame;
 use sdl2::{Sdl, VideoSubsystem, EventPump};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::render::{Canvas, TextureCreator};
-use sdl2::video::{WindowContext, Window};
+use sdl2::video::{WindowBuilder, WindowContext, Window};
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::{MouseState, MouseUtil, RelativeMouseState};
 
-use crate::input::{Joypad, JoypadButton};
+use crate::apu::DEFAULT_SAMPLE_RATE;
+use crate::error::{Error::ImageError, Error};
+use crate::input::{InputContext, InputDevice, InputSetup, Joypad, JoypadButton, MouseInput, StandardController};
+use crate::ppu::PPU;
 
+use viewer::{ViewerKind, ViewerWindow};
 
-const SCALE_FACTOR: u16 = 3;
 
-pub struct Screen {
+pub const DEFAULT_SCALE: u32 = 3;
+// NTSC consoles draw 60.0988 frames per second
+pub const DEFAULT_FRAME_RATE: f64 = 60.0988;
+// Sound waiting for the sound card, in bytes of 16 bits samples. Past it the samples of a frame are
+// dropped, the window and the sound card never run exactly at the same pace
+const AUDIO_BUFFER_SIZE: u32 = DEFAULT_SAMPLE_RATE / 10 * 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayOptions {
+    // Size of a NES pixel on the screen
+    pub scale: u32,
+    pub fullscreen: bool,
+    // Frames per second, the window doesn't wait for the vertical sync of the monitor
+    pub frame_rate: f64,
+}
+
+// The SDL side of the screen, absent when running headless (tests, golden frames...)
+pub struct Display {
     pub canvas: Canvas<Window>,
     pub event_pump: EventPump,
     pub creator: TextureCreator<WindowContext>,
+    pub video: VideoSubsystem,
+    pub mouse: MouseUtil,
+    // None when there is no sound card, the game runs silently
+    audio: Option<AudioQueue<i16>>,
+    frame_duration: Duration,
+    next_frame: Instant,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions { scale: DEFAULT_SCALE, fullscreen: false, frame_rate: DEFAULT_FRAME_RATE }
+    }
+}
+
+pub struct Screen {
+    pub display: Option<Display>,
     pub frame: Frame,
 
     pub joypad1: Joypad,
     pub joypad2: Joypad,
+    // Only read through a four players adapter
+    pub joypad3: Joypad,
+    pub joypad4: Joypad,
 
     pub bindings_joypad1: HashMap<Keycode, JoypadButton>,
-    pub bindings_joypad2: HashMap<Keycode, JoypadButton>
+    pub bindings_joypad2: HashMap<Keycode, JoypadButton>,
+    pub bindings_joypad3: HashMap<Keycode, JoypadButton>,
+    pub bindings_joypad4: HashMap<Keycode, JoypadButton>,
+
+    // What $4016 and $4017 read through: the joypads 1 and 2 by default, and the Famicom
+    // expansion port, empty by default
+    pub port1: Box<dyn InputDevice>,
+    pub port2: Box<dyn InputDevice>,
+    pub expansion: Option<Box<dyn InputDevice>>,
+    // What was plugged in them, see InputSetup::plug
+    pub input_setup: InputSetup,
+
+    // Auxiliary windows with the PPU viewers
+    pub viewers: Vec<ViewerWindow>,
 }
 
 impl Screen {
 
     pub fn new(joypad1: Joypad, joypad2: Joypad) -> Self {
+        let mut screen: Screen = Screen::new_headless(joypad1, joypad2);
+        screen.display = Some(Display::open(&DisplayOptions::default()).expect("Cannot open the window !"));
+        screen
+    }
 
+    pub fn new_headless(joypad1: Joypad, joypad2: Joypad) -> Self {
         let mut bindings_joypad1: HashMap<Keycode, JoypadButton> = HashMap::new(); 
         bindings_joypad1.insert(Keycode::Down, JoypadButton::DOWN);
         bindings_joypad1.insert(Keycode::Up, JoypadButton::UP);
@@ -54,32 +115,233 @@ impl Screen {
         bindings_joypad2.insert(Keycode::D, JoypadButton::BUTTON_A);
         bindings_joypad2.insert(Keycode::E, JoypadButton::BUTTON_B);
 
-        let sdl_context: Sdl = sdl2::init().map_err(Error::msg).expect("Cannot init SDL !");
-        let video_subsystem: VideoSubsystem = sdl_context.video().map_err(Error::msg).expect("Cannot init VideoSubSystem !");
-        let window: Window = video_subsystem
-            .window("NES Emulator", (256*SCALE_FACTOR) as u32, (256*SCALE_FACTOR) as u32)
-            .position_centered()
-            .build()
-            .expect("Cannot create window !");
+        let mut bindings_joypad3: HashMap<Keycode, JoypadButton> = HashMap::new();
+        bindings_joypad3.insert(Keycode::T, JoypadButton::UP);
+        bindings_joypad3.insert(Keycode::G, JoypadButton::DOWN);
+        bindings_joypad3.insert(Keycode::H, JoypadButton::RIGHT);
+        bindings_joypad3.insert(Keycode::F, JoypadButton::LEFT);
+        bindings_joypad3.insert(Keycode::V, JoypadButton::SELECT);
+        bindings_joypad3.insert(Keycode::N, JoypadButton::START);
+        bindings_joypad3.insert(Keycode::X, JoypadButton::BUTTON_A);
+        bindings_joypad3.insert(Keycode::Z, JoypadButton::BUTTON_B);
+
+        let mut bindings_joypad4: HashMap<Keycode, JoypadButton> = HashMap::new();
+        bindings_joypad4.insert(Keycode::Kp8, JoypadButton::UP);
+        bindings_joypad4.insert(Keycode::Kp5, JoypadButton::DOWN);
+        bindings_joypad4.insert(Keycode::Kp6, JoypadButton::RIGHT);
+        bindings_joypad4.insert(Keycode::Kp4, JoypadButton::LEFT);
+        bindings_joypad4.insert(Keycode::Kp7, JoypadButton::SELECT);
+        bindings_joypad4.insert(Keycode::Kp9, JoypadButton::START);
+        bindings_joypad4.insert(Keycode::Kp1, JoypadButton::BUTTON_A);
+        bindings_joypad4.insert(Keycode::Kp2, JoypadButton::BUTTON_B);
+
+        Screen {
+            display: None,
+            frame: Frame::new(),
+            joypad1,
+            joypad2,
+            joypad3: Joypad::new(),
+            joypad4: Joypad::new(),
+            bindings_joypad1,
+            bindings_joypad2,
+            bindings_joypad3,
+            bindings_joypad4,
+            port1: Box::new(StandardController::new(0)),
+            port2: Box::new(StandardController::new(1)),
+            expansion: None,
+            input_setup: InputSetup::Joypads,
+            viewers: vec![],
+        }
+    }
+
+    // A key of the keyboard pressed or released, for every joypad it is bound to
+    pub fn press_key(&mut self, keycode: Keycode, pressed: bool) {
+        let joypads: [(&HashMap<Keycode, JoypadButton>, &mut Joypad); 4] = [
+            (&self.bindings_joypad1, &mut self.joypad1),
+            (&self.bindings_joypad2, &mut self.joypad2),
+            (&self.bindings_joypad3, &mut self.joypad3),
+            (&self.bindings_joypad4, &mut self.joypad4),
+        ];
+        for (bindings, joypad) in joypads {
+            if let Some(button) = bindings.get(&keycode) {
+                joypad.set_button_pressed_status(*button, pressed);
+            }
+        }
+        for device in self.devices() {
+            device.press_key(keycode, pressed);
+        }
+    }
 
-        let mut canvas: Canvas<Window> = window.into_canvas().present_vsync().build().expect("Cannot build canvas !");
-        let event_pump: EventPump = sdl_context.event_pump().expect("Cannot create EventPump !");
+    pub fn update_mouse(&mut self, mouse: &MouseInput) {
+        for device in self.devices() {
+            device.update_mouse(mouse);
+        }
+    }
 
-        canvas.set_scale(SCALE_FACTOR as f32, SCALE_FACTOR as f32).expect("Cannot scale canvas !");
+    // Port 1, port 2 and the expansion port when something is plugged in it
+    pub fn devices(&mut self) -> Vec<&mut Box<dyn InputDevice>> {
+        let mut devices: Vec<&mut Box<dyn InputDevice>> = vec![&mut self.port1, &mut self.port2];
+        devices.extend(self.expansion.as_mut());
+        devices
+    }
+
+    // The first device of that type, to drive it without a window
+    pub fn device_mut<T: InputDevice + 'static>(&mut self) -> Option<&mut T> {
+        self.devices().into_iter().find_map(|device| device.as_any_mut().downcast_mut::<T>())
+    }
+
+    // Register 0 is $4016, read through port 1 and the expansion port, 1 is $4017 through port 2
+    pub fn read_port(&mut self, register: usize, scanline: usize, dot: usize) -> u8 {
+        let mut context: InputContext = InputContext {
+            joypads: [&mut self.joypad1, &mut self.joypad2, &mut self.joypad3, &mut self.joypad4],
+            frame: &self.frame,
+            scanline,
+            dot,
+        };
+        let port: &mut Box<dyn InputDevice> = if register == 0 { &mut self.port1 } else { &mut self.port2 };
+        let mut value: u8 = port.read(register, &mut context);
+        if let Some(expansion) = self.expansion.as_mut() {
+            value |= expansion.read(register, &mut context);
+        }
+        value
+    }
+
+    // Without side effects
+    pub fn peek_port(&mut self, register: usize, scanline: usize, dot: usize) -> u8 {
+        let context: InputContext = InputContext {
+            joypads: [&mut self.joypad1, &mut self.joypad2, &mut self.joypad3, &mut self.joypad4],
+            frame: &self.frame,
+            scanline,
+            dot,
+        };
+        let port: &dyn InputDevice = if register == 0 { self.port1.as_ref() } else { self.port2.as_ref() };
+        let expansion: u8 = self.expansion.as_ref().map_or(0, |expansion| expansion.peek(register, &context));
+        port.peek(register, &context) | expansion
+    }
+
+    // $4016 writes go to every port
+    pub fn write_ports(&mut self, value: u8) {
+        let mut context: InputContext = InputContext {
+            joypads: [&mut self.joypad1, &mut self.joypad2, &mut self.joypad3, &mut self.joypad4],
+            frame: &self.frame,
+            scanline: 0,
+            dot: 0,
+        };
+        self.port1.write(value, &mut context);
+        self.port2.write(value, &mut context);
+        if let Some(expansion) = self.expansion.as_mut() {
+            expansion.write(value, &mut context);
+        }
+    }
+
+    // Does nothing when running headless
+    pub fn open_viewer(&mut self, kind: ViewerKind) -> Result<(), crate::error::Error> {
+        if let Some(display) = self.display.as_ref() {
+            self.viewers.push(ViewerWindow::open(display, kind)?);
+        }
+        Ok(())
+    }
+
+    pub fn update_viewers(&mut self, ppu: &PPU) {
+        for viewer in self.viewers.iter_mut() {
+            viewer.update(ppu);
+        }
+    }
+
+    pub fn close_viewer(&mut self, window_id: u32) {
+        self.viewers.retain(|viewer| viewer.window_id() != window_id);
+    }
+}
+
+// The frame fills the window, keeping its aspect ratio with black bars around it
+pub fn window_to_frame((width, height): (u32, u32), x: i32, y: i32) -> Option<(usize, usize)> {
+    let scale: f64 = (width as f64 / Frame::WIDTH as f64).min(height as f64 / Frame::HEIGHT as f64);
+    if scale <= 0.0 {
+        return None;
+    }
+    let left: f64 = (width as f64 - Frame::WIDTH as f64 * scale) / 2.0;
+    let top: f64 = (height as f64 - Frame::HEIGHT as f64 * scale) / 2.0;
+    let frame_x: f64 = (x as f64 - left) / scale;
+    let frame_y: f64 = (y as f64 - top) / scale;
+    let inside: bool = (0.0..Frame::WIDTH as f64).contains(&frame_x) && (0.0..Frame::HEIGHT as f64).contains(&frame_y);
+    inside.then_some((frame_x as usize, frame_y as usize))
+}
+
+impl Display {
+
+    pub fn open(options: &DisplayOptions) -> Result<Self, Error> {
+        let sdl_context: Sdl = sdl2::init().map_err(ImageError)?;
+        let video_subsystem: VideoSubsystem = sdl_context.video().map_err(ImageError)?;
+        let mut window: WindowBuilder = video_subsystem.window("NES Emulator", Frame::WIDTH as u32 * options.scale, Frame::HEIGHT as u32 * options.scale);
+        if options.fullscreen {
+            window.fullscreen_desktop();
+        } else {
+            window.position_centered();
+        }
+
+        let mut canvas: Canvas<Window> = window.build()?.into_canvas().build().map_err(|e| ImageError(e.to_string()))?;
+        let event_pump: EventPump = sdl_context.event_pump().map_err(ImageError)?;
+        let mouse: MouseUtil = sdl_context.mouse();
+
+        // In fullscreen, the frame is stretched over the whole screen with black bars
+        if options.fullscreen {
+            canvas.set_logical_size(Frame::WIDTH as u32, Frame::HEIGHT as u32).map_err(|e| ImageError(e.to_string()))?;
+        } else {
+            canvas.set_scale(options.scale as f32, options.scale as f32).map_err(ImageError)?;
+        }
 
         let creator: TextureCreator<WindowContext> = canvas.texture_creator();
-        let frame: Frame = Frame::new();
 
-        Screen {
-            canvas: canvas,
-            event_pump: event_pump,
-            creator: creator,
-            frame: frame,
-            joypad1: joypad1,
-            joypad2: joypad2,
-            bindings_joypad1: bindings_joypad1,
-            bindings_joypad2: bindings_joypad2
+        let spec: AudioSpecDesired = AudioSpecDesired { freq: Some(DEFAULT_SAMPLE_RATE as i32), channels: Some(1), samples: None };
+        let audio: Option<AudioQueue<i16>> = sdl_context.audio().and_then(|audio| audio.open_queue(None, &spec)).ok();
+        if let Some(queue) = audio.as_ref() {
+            queue.resume();
+        }
+
+        Ok(Display {
+            canvas,
+            event_pump,
+            creator,
+            video: video_subsystem,
+            mouse,
+            audio,
+            frame_duration: Duration::from_secs_f64(1.0 / options.frame_rate),
+            next_frame: Instant::now(),
+        })
+    }
+
+    // Once per frame, the movement is counted since the previous call. The position is None when the
+    // mouse is away from the window or over the black bars
+    pub fn mouse(&self) -> MouseInput {
+        let window: &Window = self.canvas.window();
+        let relative: RelativeMouseState = self.event_pump.relative_mouse_state();
+        if self.mouse.focused_window_id() != Some(window.id()) {
+            return MouseInput::default();
+        }
+        let state: MouseState = self.event_pump.mouse_state();
+        MouseInput {
+            position: window_to_frame(window.size(), state.x(), state.y()),
+            motion: (relative.x(), relative.y()),
+            left: state.left(),
+            right: state.right(),
+        }
+    }
+
+    // The samples of the APU for a frame
+    pub fn queue_samples(&mut self, samples: &[i16]) {
+        if let Some(queue) = self.audio.as_ref().filter(|queue| queue.size() < AUDIO_BUFFER_SIZE) {
+            queue.queue(samples);
         }
-        
     }
-}
\ No newline at end of file
+
+    // Sleeps until the next frame is due, a late frame is not caught up
+    pub fn wait_next_frame(&mut self) {
+        let now: Instant = Instant::now();
+        if self.next_frame > now {
+            std::thread::sleep(self.next_frame - now);
+            self.next_frame += self.frame_duration;
+        } else {
+            self.next_frame = now + self.frame_duration;
+        }
+    }
+}
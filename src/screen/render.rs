@@ -28,7 +28,8 @@ pub struct Renderer {
 
 impl Renderer {
 
-    pub fn forward_name_table(ppu: &PPU, frame: &mut Frame, name_table: &[u8], viewport: Rect, shift_x: isize, shift_y: isize, x: usize, y: usize) {
+    // Returns the CHR address of the tile when some of it was drawn in the viewport
+    pub fn forward_name_table(ppu: &PPU, frame: &mut Frame, name_table: &[u8], viewport: Rect, shift_x: isize, shift_y: isize, x: usize, y: usize) -> Option<usize> {
         let bank: usize = ppu.reg_control.bg_pattern_addr() as usize;
         let attribute_table: &[u8] = &name_table[0x03c0..0x0400];
     
@@ -40,6 +41,7 @@ impl Renderer {
     
         let tile: &[u8] = &ppu.chr_rom[(bank + (tile_index*0x10) as usize)..(bank+ ((tile_index+1)*0x10) as usize)];
         let palette: [u8; 4] = Renderer::bg_palette(ppu, attribute_table, tile_x, tile_y);
+        let mut drawn: bool = false;
     
         for offset_in_tile_y in 0..=7 {
             let upper: u8 = tile[offset_in_tile_y];
@@ -57,13 +59,21 @@ impl Renderer {
                 let pixel_y: usize = 8*tile_y + offset_in_tile_y; // y
                 if pixel_x >= viewport.x1 && pixel_x < viewport.x2 && pixel_y >= viewport.y1 && pixel_y < viewport.y2 {
                     frame.set_pixel((shift_x + pixel_x as isize) as usize, (shift_y + pixel_y as isize) as usize, rgb);
+                    drawn = true;
                 }
             }
         }
+        drawn.then_some(bank + tile_index as usize * 0x10)
     }
     
 
-    pub fn forward(ppu: &PPU, frame: &mut Frame, ppu_cycles_to_forward: usize) {
+    // Returns the CHR address of the background tiles fetched, when the background is shown
+    pub fn forward(ppu: &PPU, frame: &mut Frame, ppu_cycles_to_forward: usize) -> Vec<usize> {
+        let mut fetches: Vec<usize> = vec![];
+        // Program-only roms (like the ones built by the unit tests) have nothing to draw
+        if ppu.chr_rom.is_empty() {
+            return fetches;
+        }
 
         let scroll_x: usize = ppu.reg_scroll.scroll_x as usize;
         let scroll_y: usize = ppu.reg_scroll.scroll_y as usize;
@@ -93,24 +103,51 @@ impl Renderer {
             if x % 8 != 0 { continue; }
             if y % 8 != 0 { continue; }
 
-            Renderer::forward_name_table(ppu, frame, main_nametable, 
+            fetches.extend(Renderer::forward_name_table(ppu, frame, main_nametable, 
                 Rect::new(scroll_x, scroll_y, 256, 240),
-                -(scroll_x as isize), -(scroll_y as isize), x, y);
+                -(scroll_x as isize), -(scroll_y as isize), x, y));
         
             if scroll_x > 0 {
-                Renderer::forward_name_table(ppu, frame, second_nametable, 
+                fetches.extend(Renderer::forward_name_table(ppu, frame, second_nametable, 
                     Rect::new(0, 0, scroll_x, 240),
-                    (256-scroll_x) as isize, 0, x, y);    
+                    (256-scroll_x) as isize, 0, x, y));    
             } else if scroll_y > 0 {
-                Renderer::forward_name_table(ppu, frame, second_nametable, 
+                fetches.extend(Renderer::forward_name_table(ppu, frame, second_nametable, 
                     Rect::new(0, 0, 256, scroll_y),
-                    0, (240 - scroll_y) as isize, x, y);    
+                    0, (240 - scroll_y) as isize, x, y));    
             }
         }
+        if !ppu.reg_mask.show_bg() {
+            fetches.clear();
+        }
+        fetches
+    }
+
+    // CHR address of the pattern tiles fetched for the sprites on screen, when they are shown. 8x16
+    // sprites take their bank from bit 0 of the tile index, and use the tile and the next one
+    pub fn sprite_fetches(ppu: &PPU) -> Vec<usize> {
+        if ppu.chr_rom.is_empty() || !ppu.reg_mask.show_sprites() {
+            return vec![];
+        }
+        let mut fetches: Vec<usize> = vec![];
+        for sprite in ppu.oam_data.chunks(4).filter(|sprite| sprite[0] < 0xef) {
+            let tile_index: usize = sprite[1] as usize;
+            match ppu.reg_control.sprite_size() {
+                16 => {
+                    let tile: usize = (tile_index & 1) * 0x1000 + (tile_index & 0xfe) * 0x10;
+                    fetches.extend([tile, tile + 0x10]);
+                }
+                _ => fetches.push(ppu.reg_control.sprite_pattern_addr() as usize + tile_index * 0x10),
+            }
+        }
+        fetches
     }
     
     pub fn render(ppu: &PPU, frame: &mut Frame) {
-        
+        if ppu.chr_rom.is_empty() {
+            return;
+        }
+
         // Draw sprites
         for i in (0..ppu.oam_data.len()).step_by(4).rev() {
             let tile_index: u16 = ppu.oam_data[i+1] as u16;
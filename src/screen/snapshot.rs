@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use crate::error::{Error::ImageError, Error};
+
+use super::frame::Frame;
+
+// Writes an RGB24 buffer (3 bytes per pixel, row after row) to a png file
+pub fn write_png(path: &Path, width: usize, height: usize, rgb: &[u8]) -> Result<(), Error> {
+    if rgb.len() != width * height * 3 {
+        return Err(ImageError(format!("Expected {} bytes for a {}x{} image, got {}", width * height * 3, width, height, rgb.len())));
+    }
+    let file: File = File::create(path)?;
+    let mut encoder: png::Encoder<BufWriter<File>> = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| ImageError(e.to_string()))?;
+    writer.write_image_data(rgb).map_err(|e| ImageError(e.to_string()))?;
+    Ok(())
+}
+
+// Reads a png file back as (width, height, RGB24 data), alpha is dropped
+pub fn read_png(path: &Path) -> Result<(usize, usize, Vec<u8>), Error> {
+    let file: File = File::open(path)?;
+    let mut decoder: png::Decoder<BufReader<File>> = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| ImageError(e.to_string()))?;
+    let mut buffer: Vec<u8> = vec![0; reader.output_buffer_size()];
+    let info: png::OutputInfo = reader.next_frame(&mut buffer).map_err(|e| ImageError(e.to_string()))?;
+    buffer.truncate(info.buffer_size());
+
+    let (width, height): (usize, usize) = (info.width as usize, info.height as usize);
+    let rgb: Vec<u8> = match info.color_type {
+        png::ColorType::Rgb => buffer,
+        png::ColorType::Rgba => buffer.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|g| [*g, *g, *g]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|p| [p[0], p[0], p[0]]).collect(),
+        png::ColorType::Indexed => return Err(ImageError(String::from("Indexed png should have been expanded"))),
+    };
+    Ok((width, height, rgb))
+}
+
+impl Frame {
+    pub fn save_png(&self, path: &Path) -> Result<(), Error> {
+        write_png(path, Frame::WIDTH, Frame::HEIGHT, &self.data)
+    }
+
+    pub fn load_png(path: &Path) -> Result<Self, Error> {
+        let (width, height, data): (usize, usize, Vec<u8>) = read_png(path)?;
+        if width != Frame::WIDTH || height != Frame::HEIGHT {
+            return Err(ImageError(format!("{} is {}x{}, a frame is {}x{}", path.display(), width, height, Frame::WIDTH, Frame::HEIGHT)));
+        }
+        Ok(Frame { data })
+    }
+}
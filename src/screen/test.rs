@@ -0,0 +1,112 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::ppu::PPU;
+    use crate::rom::Mirroring;
+
+    use super::super::palette::SYSTEM_PALLETE;
+    use super::super::snapshot::read_png;
+    use super::super::viewer::*;
+    use super::super::window_to_frame;
+
+    // Tile 1 is a vertical bar of color 1 on its left column, tile 2 is filled with color 3
+    fn ppu() -> PPU {
+        let mut chr: Vec<u8> = vec![0; 0x2000];
+        for row in 0..8 {
+            chr[0x10 + row] = 0x80;
+            chr[0x20 + row] = 0xff;
+            chr[0x28 + row] = 0xff;
+            chr[0x1010 + row] = 0x80;
+        }
+        let mut ppu: PPU = PPU::new(chr, Mirroring::VERTICAL);
+        ppu.palette_table = std::array::from_fn(|i| i as u8);
+        ppu
+    }
+
+    #[test]
+    fn test_pattern_tables() {
+        let ppu: PPU = ppu();
+        let image: Image = pattern_tables(&ppu, 5);
+        assert_eq!((image.width, image.height), (256, 128));
+        assert_eq!(image.get_pixel(8, 0), SYSTEM_PALLETE[0x15]);
+        assert_eq!(image.get_pixel(9, 0), SYSTEM_PALLETE[0x00]);
+        assert_eq!(image.get_pixel(16, 7), SYSTEM_PALLETE[0x17]);
+        assert_eq!(image.get_pixel(128 + 8, 3), SYSTEM_PALLETE[0x15]);
+        assert_eq!(palette_colors(&ppu, 2), [SYSTEM_PALLETE[0], SYSTEM_PALLETE[9], SYSTEM_PALLETE[10], SYSTEM_PALLETE[11]]);
+    }
+
+    #[test]
+    fn test_nametables() {
+        let mut ppu: PPU = ppu();
+        // Tile 2 at the top left of $2400, with the attribute picking the palette 3
+        ppu.poke(0x2402, 2);
+        ppu.poke(0x27c0, 0b1100);
+        ppu.reg_scroll.write(16);
+        ppu.reg_scroll.write(8);
+        let image: Image = nametables(&ppu);
+        assert_eq!((image.width, image.height), (512, 480));
+        assert_eq!(image.get_pixel(256 + 16 + 3, 4), SYSTEM_PALLETE[0x0f]);
+        // Vertical mirroring: $2C00 is $2400
+        assert_eq!(image.get_pixel(256 + 16 + 3, 240 + 4), SYSTEM_PALLETE[0x0f]);
+        assert_eq!(image.get_pixel(3, 4), SYSTEM_PALLETE[0]);
+
+        // The scroll window starts at (16, 8) and wraps at the right
+        let outline: (u8, u8, u8) = (0xff, 0x00, 0xff);
+        assert_eq!(image.get_pixel(16, 8), outline);
+        assert_eq!(image.get_pixel(16 + 255, 8 + 100), outline);
+        assert_eq!(image.get_pixel(16 + 128, 8 + 239), outline);
+        assert_ne!(image.get_pixel(15, 8), outline);
+    }
+
+    #[test]
+    fn test_oam_and_palette() {
+        let mut ppu: PPU = ppu();
+        // Sprite 0: tile 1, palette 6, flipped horizontally; sprite 1 is hidden
+        ppu.oam_data[..8].copy_from_slice(&[10, 1, 0x42, 20, 0xf0, 0, 0x20, 0]);
+        let image: Image = oam(&ppu);
+        assert_eq!((image.width, image.height), (128, 192));
+        assert_eq!(image.get_pixel(4 + 7, 4), SYSTEM_PALLETE[0x19]);
+        assert_eq!(image.get_pixel(4, 4), (0x20, 0x20, 0x20));
+        assert_eq!(image.get_pixel(16, 0), (0x50, 0x10, 0x10));
+        let table: String = oam_table(&ppu);
+        assert_eq!(table.lines().next(), Some(" 0: X: 20 Y: 10 tile:$01 palette:6 front flip-h"));
+        assert_eq!(table.lines().nth(1), Some(" 1: X:  0 Y:240 tile:$00 palette:4 behind hidden"));
+
+        let image: Image = palette(&ppu);
+        assert_eq!((image.width, image.height), (256, 32));
+        assert_eq!(image.get_pixel(3 * 16 + 5, 16 + 5), SYSTEM_PALLETE[0x13]);
+    }
+
+    #[test]
+    fn test_export() {
+        assert_eq!(ViewerKind::parse("pattern:3").unwrap(), ViewerKind::PatternTables(3));
+        assert!(ViewerKind::parse("pattern:8").is_err());
+        assert!(ViewerKind::parse("sprites").is_err());
+
+        let dir = std::env::temp_dir().join(format!("nes_emul_viewers_{}", std::process::id()));
+        export_all(&ppu(), &dir, 1).unwrap();
+        for kind in ViewerKind::ALL {
+            let (width, height, _) = read_png(&dir.join(format!("{}.png", kind.name()))).unwrap();
+            assert_eq!((width, height), kind.size());
+        }
+        assert_eq!(std::fs::read_to_string(dir.join("oam.txt")).unwrap().lines().count(), 64);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_window_to_frame() {
+        // Scale 3 window, then a 16:9 fullscreen with black bars on the sides
+        assert_eq!(window_to_frame((768, 720), 0, 0), Some((0, 0)));
+        assert_eq!(window_to_frame((768, 720), 767, 719), Some((255, 239)));
+        assert_eq!(window_to_frame((768, 720), 216, 312), Some((72, 104)));
+        assert_eq!(window_to_frame((768, 720), 768, 10), None);
+        assert_eq!(window_to_frame((1920, 1080), 100, 540), None);
+        assert_eq!(window_to_frame((1920, 1080), 384, 0), Some((0, 0)));
+        assert_eq!(window_to_frame((1920, 1080), 960, 540), Some((128, 120)));
+        assert_eq!(window_to_frame((0, 0), 0, 0), None);
+    }
+}
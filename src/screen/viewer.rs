@@ -0,0 +1,297 @@
+use std::path::Path;
+
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+use crate::error::{Error::ImageError, Error};
+use crate::ppu::PPU;
+
+use super::palette::SYSTEM_PALLETE;
+use super::snapshot::write_png;
+use super::Display;
+
+// Debug views of the PPU memories: pattern tables, nametables, sprites and palette, rendered straight
+// from the PPU state (nothing here is what the game shows, only what it has in memory)
+
+const VIEWER_SCALE: u32 = 2;
+const SCROLL_OUTLINE_COLOR: (u8, u8, u8) = (0xff, 0x00, 0xff);
+const OAM_CELL_BACKGROUND: (u8, u8, u8) = (0x20, 0x20, 0x20);
+// Sprites below the screen (Y >= $EF) are hidden
+const OAM_HIDDEN_BACKGROUND: (u8, u8, u8) = (0x50, 0x10, 0x10);
+const OAM_CELL_WIDTH: usize = 16;
+const OAM_CELL_HEIGHT: usize = 24;
+const PALETTE_SWATCH_SIZE: usize = 16;
+
+// An RGB24 image of any size, a Frame is always 256x240
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image { width, height, data: vec![0; width * height * 3] }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+        if x < self.width && y < self.height {
+            let base: usize = 3 * (y * self.width + x);
+            self.data[base..base + 3].copy_from_slice(&[rgb.0, rgb.1, rgb.2]);
+        }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let base: usize = 3 * (y * self.width + x);
+        (self.data[base], self.data[base + 1], self.data[base + 2])
+    }
+
+    pub fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, rgb: (u8, u8, u8)) {
+        for j in y..y + height {
+            for i in x..x + width {
+                self.set_pixel(i, j, rgb);
+            }
+        }
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), Error> {
+        write_png(path, self.width, self.height, &self.data)
+    }
+}
+
+// The 4 colors of a palette, 0 to 3 for the background and 4 to 7 for the sprites
+pub fn palette_colors(ppu: &PPU, palette: usize) -> [(u8, u8, u8); 4] {
+    let color = |i: usize| SYSTEM_PALLETE[(ppu.palette_table[i] & 0x3f) as usize];
+    let start: usize = (palette % 8) * 4;
+    [color(0), color(start + 1), color(start + 2), color(start + 3)]
+}
+
+// Draws the 8x8 tile at a CHR address, color 0 is skipped when transparent
+#[allow(clippy::too_many_arguments)]
+fn draw_tile(image: &mut Image, ppu: &PPU, tile_addr: usize, x: usize, y: usize, colors: &[(u8, u8, u8); 4], transparent: bool, flip: (bool, bool)) {
+    let byte = |i: usize| ppu.chr_rom.get(tile_addr + i).copied().unwrap_or(0);
+    for row in 0..8 {
+        let (upper, lower): (u8, u8) = (byte(row), byte(row + 8));
+        for column in 0..8 {
+            let value: usize = ((((lower >> (7 - column)) & 1) << 1) | ((upper >> (7 - column)) & 1)) as usize;
+            if value == 0 && transparent {
+                continue;
+            }
+            let pixel_x: usize = if flip.0 { 7 - column } else { column };
+            let pixel_y: usize = if flip.1 { 7 - row } else { row };
+            image.set_pixel(x + pixel_x, y + pixel_y, colors[value]);
+        }
+    }
+}
+
+// One pattern table ($0000 or $1000) as 16x16 tiles, 128x128 pixels
+pub fn pattern_table(ppu: &PPU, table: usize, palette: usize) -> Image {
+    let mut image: Image = Image::new(128, 128);
+    let colors: [(u8, u8, u8); 4] = palette_colors(ppu, palette);
+    for tile in 0..256 {
+        draw_tile(&mut image, ppu, (table & 1) * 0x1000 + tile * 0x10, (tile % 16) * 8, (tile / 16) * 8, &colors, false, (false, false));
+    }
+    image
+}
+
+// Both pattern tables side by side, 256x128 pixels
+pub fn pattern_tables(ppu: &PPU, palette: usize) -> Image {
+    let mut image: Image = Image::new(256, 128);
+    for table in 0..2 {
+        let half: Image = pattern_table(ppu, table, palette);
+        for y in 0..128 {
+            for x in 0..128 {
+                image.set_pixel(table * 128 + x, y, half.get_pixel(x, y));
+            }
+        }
+    }
+    image
+}
+
+// The 4 nametables of $2000-$2FFF (mirrors included) as 512x480 pixels, with the part the scroll
+// registers show outlined
+pub fn nametables(ppu: &PPU) -> Image {
+    let mut image: Image = Image::new(512, 480);
+    let bank: usize = ppu.reg_control.bg_pattern_addr() as usize;
+    for name_table in 0..4 {
+        let base: u16 = 0x2000 + name_table as u16 * 0x400;
+        let (origin_x, origin_y): (usize, usize) = ((name_table % 2) * 256, (name_table / 2) * 240);
+        for row in 0..30 {
+            for column in 0..32 {
+                let tile: u8 = ppu.peek(base + (row * 32 + column) as u16);
+                let attribute: u8 = ppu.peek(base + 0x3c0 + ((row / 4) * 8 + column / 4) as u16);
+                let shift: usize = ((row % 4) / 2) * 4 + ((column % 4) / 2) * 2;
+                let colors: [(u8, u8, u8); 4] = palette_colors(ppu, ((attribute >> shift) & 0b11) as usize);
+                draw_tile(&mut image, ppu, bank + tile as usize * 0x10, origin_x + column * 8, origin_y + row * 8, &colors, false, (false, false));
+            }
+        }
+    }
+
+    // The visible 256x240 window, wrapping around the 4 nametables
+    let name_table: usize = ((ppu.reg_control.nametable_addr() - 0x2000) / 0x400) as usize;
+    let start_x: usize = (name_table % 2) * 256 + ppu.reg_scroll.scroll_x as usize;
+    let start_y: usize = (name_table / 2) * 240 + ppu.reg_scroll.scroll_y as usize;
+    for i in 0..256 {
+        image.set_pixel((start_x + i) % 512, start_y % 480, SCROLL_OUTLINE_COLOR);
+        image.set_pixel((start_x + i) % 512, (start_y + 239) % 480, SCROLL_OUTLINE_COLOR);
+    }
+    for j in 0..240 {
+        image.set_pixel(start_x % 512, (start_y + j) % 480, SCROLL_OUTLINE_COLOR);
+        image.set_pixel((start_x + 255) % 512, (start_y + j) % 480, SCROLL_OUTLINE_COLOR);
+    }
+    image
+}
+
+// The 64 sprites as an 8x8 grid, with their palette and flips applied (8x16 sprites included)
+pub fn oam(ppu: &PPU) -> Image {
+    let mut image: Image = Image::new(8 * OAM_CELL_WIDTH, 8 * OAM_CELL_HEIGHT);
+    let tall: bool = ppu.reg_control.sprite_size() == 16;
+    for (i, sprite) in ppu.oam_data.chunks(4).enumerate() {
+        let (cell_x, cell_y): (usize, usize) = ((i % 8) * OAM_CELL_WIDTH, (i / 8) * OAM_CELL_HEIGHT);
+        let background: (u8, u8, u8) = if sprite[0] >= 0xef { OAM_HIDDEN_BACKGROUND } else { OAM_CELL_BACKGROUND };
+        image.fill(cell_x, cell_y, OAM_CELL_WIDTH - 1, OAM_CELL_HEIGHT - 1, background);
+
+        let colors: [(u8, u8, u8); 4] = palette_colors(ppu, 4 + (sprite[2] & 0b11) as usize);
+        let flip: (bool, bool) = (sprite[2] & 0x40 != 0, sprite[2] & 0x80 != 0);
+        let (x, y): (usize, usize) = (cell_x + 4, cell_y + 4);
+        match tall {
+            false => draw_tile(&mut image, ppu, ppu.reg_control.sprite_pattern_addr() as usize + sprite[1] as usize * 0x10, x, y, &colors, true, flip),
+            true => {
+                let top: usize = (sprite[1] as usize & 1) * 0x1000 + (sprite[1] as usize & 0xfe) * 0x10;
+                let (first, second): (usize, usize) = if flip.1 { (top + 0x10, top) } else { (top, top + 0x10) };
+                draw_tile(&mut image, ppu, first, x, y, &colors, true, flip);
+                draw_tile(&mut image, ppu, second, x, y + 8, &colors, true, flip);
+            }
+        }
+    }
+    image
+}
+
+// The attributes of the 64 sprites, one per line
+pub fn oam_table(ppu: &PPU) -> String {
+    ppu.oam_data.chunks(4).enumerate().map(|(i, sprite)| {
+        format!("{:2}: X:{:3} Y:{:3} tile:${:02X} palette:{} {}{}{}{}\n", i, sprite[3], sprite[0], sprite[1], 4 + (sprite[2] & 0b11),
+            if sprite[2] & 0x20 != 0 { "behind" } else { "front" },
+            if sprite[2] & 0x40 != 0 { " flip-h" } else { "" },
+            if sprite[2] & 0x80 != 0 { " flip-v" } else { "" },
+            if sprite[0] >= 0xef { " hidden" } else { "" })
+    }).collect()
+}
+
+// The 32 entries of the palette RAM, background on the first row and sprites on the second one
+pub fn palette(ppu: &PPU) -> Image {
+    let mut image: Image = Image::new(16 * PALETTE_SWATCH_SIZE, 2 * PALETTE_SWATCH_SIZE);
+    for (i, color) in ppu.palette_table.iter().enumerate() {
+        let rgb: (u8, u8, u8) = SYSTEM_PALLETE[(color & 0x3f) as usize];
+        image.fill((i % 16) * PALETTE_SWATCH_SIZE, (i / 16) * PALETTE_SWATCH_SIZE, PALETTE_SWATCH_SIZE, PALETTE_SWATCH_SIZE, rgb);
+    }
+    image
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewerKind {
+    // With the palette used to color the tiles
+    PatternTables(usize),
+    Nametables,
+    Oam,
+    Palette,
+}
+
+impl ViewerKind {
+    pub const ALL: [ViewerKind; 4] = [ViewerKind::PatternTables(0), ViewerKind::Nametables, ViewerKind::Oam, ViewerKind::Palette];
+
+    // pattern[:<palette>], nametables, oam or palette
+    pub fn parse(text: &str) -> Result<ViewerKind, Error> {
+        let (name, palette): (&str, Option<&str>) = match text.split_once(':') {
+            Some((name, palette)) => (name, Some(palette)),
+            None => (text, None),
+        };
+        match (name, palette) {
+            ("pattern", None) => Ok(ViewerKind::PatternTables(0)),
+            ("pattern", Some(palette)) => match palette.parse::<usize>() {
+                Ok(palette) if palette < 8 => Ok(ViewerKind::PatternTables(palette)),
+                _ => Err(ImageError(format!("Invalid palette {}, expected 0 to 7", palette))),
+            },
+            ("nametables", None) => Ok(ViewerKind::Nametables),
+            ("oam", None) => Ok(ViewerKind::Oam),
+            ("palette", None) => Ok(ViewerKind::Palette),
+            _ => Err(ImageError(format!("Unknown viewer {}, expected pattern[:<palette>], nametables, oam or palette", text))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ViewerKind::PatternTables(_) => "pattern",
+            ViewerKind::Nametables => "nametables",
+            ViewerKind::Oam => "oam",
+            ViewerKind::Palette => "palette",
+        }
+    }
+
+    // Size of the rendered image
+    pub fn size(self) -> (usize, usize) {
+        match self {
+            ViewerKind::PatternTables(_) => (256, 128),
+            ViewerKind::Nametables => (512, 480),
+            ViewerKind::Oam => (8 * OAM_CELL_WIDTH, 8 * OAM_CELL_HEIGHT),
+            ViewerKind::Palette => (16 * PALETTE_SWATCH_SIZE, 2 * PALETTE_SWATCH_SIZE),
+        }
+    }
+
+    pub fn render(self, ppu: &PPU) -> Image {
+        match self {
+            ViewerKind::PatternTables(palette) => pattern_tables(ppu, palette),
+            ViewerKind::Nametables => nametables(ppu),
+            ViewerKind::Oam => oam(ppu),
+            ViewerKind::Palette => palette(ppu),
+        }
+    }
+}
+
+// Writes <dir>/pattern.png, nametables.png, oam.png and palette.png, plus oam.txt with the attributes
+pub fn export_all(ppu: &PPU, dir: &Path, pattern_palette: usize) -> Result<(), Error> {
+    std::fs::create_dir_all(dir)?;
+    for kind in ViewerKind::ALL {
+        let kind: ViewerKind = match kind {
+            ViewerKind::PatternTables(_) => ViewerKind::PatternTables(pattern_palette),
+            kind => kind,
+        };
+        kind.render(ppu).save_png(&dir.join(format!("{}.png", kind.name())))?;
+    }
+    std::fs::write(dir.join("oam.txt"), oam_table(ppu))?;
+    Ok(())
+}
+
+// An auxiliary SDL window showing one of the views, refreshed every frame
+pub struct ViewerWindow {
+    pub kind: ViewerKind,
+    pub canvas: Canvas<Window>,
+    pub creator: TextureCreator<WindowContext>,
+}
+
+impl ViewerWindow {
+    pub fn open(display: &Display, kind: ViewerKind) -> Result<Self, Error> {
+        let (width, height): (usize, usize) = kind.size();
+        let window: Window = display.video
+            .window(&format!("NES Emulator - {}", kind.name()), width as u32 * VIEWER_SCALE, height as u32 * VIEWER_SCALE)
+            .build()?;
+        let mut canvas: Canvas<Window> = window.into_canvas().build().map_err(|e| ImageError(e.to_string()))?;
+        canvas.set_scale(VIEWER_SCALE as f32, VIEWER_SCALE as f32).map_err(ImageError)?;
+        let creator: TextureCreator<WindowContext> = canvas.texture_creator();
+        Ok(ViewerWindow { kind, canvas, creator })
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    pub fn update(&mut self, ppu: &PPU) {
+        let image: Image = self.kind.render(ppu);
+        let mut texture: Texture<'_> = self.creator.create_texture_target(PixelFormatEnum::RGB24, image.width as u32, image.height as u32).expect("Cannot create texture !");
+        texture.update(None, &image.data, image.width * 3).unwrap();
+        self.canvas.copy(&texture, None, None).unwrap();
+        self.canvas.present();
+    }
+}
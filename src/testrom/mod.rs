@@ -0,0 +1,75 @@
+mod test;
+
+use crate::cpu::CPU;
+use crate::memview::{self, Region};
+
+// Test ROMs following blargg's protocol report through the cartridge RAM:
+//   $6000        status: $80 while running, $81 when the console must be reset, the result code once done
+//   $6001-$6003  DE B0 61, telling that the other bytes are meaningful
+//   $6004-       the text shown on screen, zero-terminated
+// A result code of 0 means that every test passed.
+
+pub const DEFAULT_FRAMES: usize = 60 * 60;
+
+const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET: u8 = 0x81;
+// The ROM expects the reset at least 100ms after asking for it
+const RESET_DELAY: usize = 6;
+const TEXT_SIZE: usize = 0x1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestStatus {
+    Passed,
+    Failed(u8),
+    // Still running after the last frame, or never wrote the signature
+    Timeout,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestReport {
+    pub status: TestStatus,
+    pub text: String,
+    pub frames: usize,
+}
+
+fn peek(cpu: &mut CPU, offset: usize) -> u8 {
+    memview::peek(&mut cpu.bus, Region::PrgRam, offset)
+}
+
+fn has_signature(cpu: &mut CPU) -> bool {
+    (0..SIGNATURE.len()).all(|i| peek(cpu, 1 + i) == SIGNATURE[i])
+}
+
+fn text(cpu: &mut CPU) -> String {
+    let bytes: Vec<u8> = (4..TEXT_SIZE).map(|offset| peek(cpu, offset)).take_while(|byte| *byte != 0).collect();
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+// Runs the ROM until it reports a result or for max_frames
+pub fn run(cpu: &mut CPU, max_frames: usize) -> TestReport {
+    let mut reset_at: Option<usize> = None;
+    for frame in 0..max_frames {
+        cpu.run_frame();
+        if !has_signature(cpu) {
+            continue;
+        }
+        match peek(cpu, 0) {
+            STATUS_RUNNING => (),
+            STATUS_RESET => match reset_at {
+                Some(at) if frame >= at => {
+                    cpu.reset();
+                    reset_at = None;
+                }
+                Some(_) => (),
+                None => reset_at = Some(frame + RESET_DELAY),
+            },
+            code => {
+                let status: TestStatus = if code == 0 { TestStatus::Passed } else { TestStatus::Failed(code) };
+                return TestReport { status, text: text(cpu), frames: frame + 1 };
+            }
+        }
+    }
+    let text: String = if has_signature(cpu) { text(cpu) } else { String::new() };
+    TestReport { status: TestStatus::Timeout, text, frames: max_frames }
+}
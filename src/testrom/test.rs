@@ -0,0 +1,83 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::asm;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+    use crate::input::Joypad;
+    use crate::rom::Rom;
+
+    use super::super::*;
+
+    // Writes the signature and "OK", sets the FIRST status (asking for a reset with $81),
+    // then the RESULT after 5 frames
+    const PROGRAM: &str = "
+        reset:  LDX #0
+        copy:   LDA message,X
+                STA $6001,X
+                INX
+                CPX #8
+                BNE copy
+                LDA $01
+                BNE start
+                INC $01
+                LDA #FIRST
+                STA $6000
+                CMP #$81
+                BEQ loop
+        start:  LDA #$80
+                STA $6000
+                STA $2000
+        loop:   JMP loop
+        nmi:    INC $00
+                LDA $00
+                CMP #5
+                BNE done
+                LDA #RESULT
+                STA $6000
+        done:   RTI
+        message: .byte $de, $b0, $61, $4f, $4b, $0a, $00, $00
+        .org $fffa
+                .word nmi, reset, reset
+    ";
+
+    fn new_cpu(first: u8, result: u8) -> CPU {
+        let program: String = PROGRAM.replace("FIRST", &format!("${:02X}", first)).replace("RESULT", &format!("${:02X}", result));
+        CPU::test_load(&program)
+    }
+
+    #[test]
+    fn test_results() {
+        let report: TestReport = run(&mut new_cpu(0x80, 0x00), 100);
+        assert_eq!((report.status, report.text.as_str()), (TestStatus::Passed, "OK"));
+        assert!(report.frames < 10);
+
+        assert_eq!(run(&mut new_cpu(0x80, 0x03), 100).status, TestStatus::Failed(3));
+
+        let report: TestReport = run(&mut new_cpu(0x80, 0x80), 20);
+        assert_eq!((report.status, report.text.as_str(), report.frames), (TestStatus::Timeout, "OK", 20));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut cpu: CPU = new_cpu(0x81, 0x00);
+        let report: TestReport = run(&mut cpu, 100);
+        assert_eq!(report.status, TestStatus::Passed);
+        assert!(report.frames > RESET_DELAY);
+    }
+
+    #[test]
+    fn test_no_signature() {
+        // A result in $6000 means nothing without the signature
+        let program: &str = "reset: LDA #0\n STA $6000\n loop: JMP loop\n .org $fffa\n .word reset, reset, reset";
+        let rom: Rom = Rom::new_from_program_rom(asm::assemble(program).unwrap().program_rom().unwrap()).unwrap();
+        let mut cpu: CPU = CPU::new(Bus::new_headless(rom, |_, _| {}, Joypad::new(), Joypad::new()));
+        cpu.set_program_base(0x8000).unwrap();
+        cpu.reset();
+        assert_eq!(run(&mut cpu, 5), TestReport { status: TestStatus::Timeout, text: String::new(), frames: 5 });
+    }
+}
@@ -0,0 +1,257 @@
+mod test;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::cpu::opcode::{AddressingMode, Opcode, OPCODES};
+use crate::cpu::{CPU, CPUFlag};
+use crate::error::{Error::CpuError, Error};
+use crate::mem::Mem;
+
+// Streaming execution trace, one line per instruction written before it executes
+//
+// Everything is read with side-effect-free peeks, so tracing never changes the emulation. Lines
+// can be restricted to PC ranges, start when the PC first reaches an address and stop after a
+// number of lines.
+
+// The reset sequence takes 7 cycles that the bus doesn't count, nestest logs start at CYC:7
+pub const RESET_CYCLES: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    // C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+    Nestest,
+    // c7          $C000:4C F5 C5  JMP $C5F5                   A:00 X:00 Y:00 S:FD P:nvUbdIzc
+    Fceux,
+    // C000  $4C $F5 $C5  JMP $C5F5                A:00 X:00 Y:00 S:FD P:nvUbdIzc V:0   H:21  Cyc:7
+    Mesen,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceOptions {
+    pub format: TraceFormat,
+    // PPU scanline and dot
+    pub ppu: bool,
+    // CPU cycles since the power on
+    pub cycles: bool,
+    // Only the instructions whose address is in one of the (inclusive) ranges, all if empty
+    pub ranges: Vec<(u16, u16)>,
+    pub start_pc: Option<u16>,
+    pub max_lines: Option<usize>,
+}
+
+pub struct Tracer<W: Write> {
+    pub options: TraceOptions,
+    output: W,
+    started: bool,
+    lines: usize,
+    error: Option<std::io::Error>,
+}
+
+impl TraceFormat {
+    pub fn parse(name: &str) -> Result<Self, Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "nestest" => Ok(TraceFormat::Nestest),
+            "fceux" => Ok(TraceFormat::Fceux),
+            "mesen" => Ok(TraceFormat::Mesen),
+            other => Err(CpuError(format!("Unknown trace format {}, expected nestest, fceux or mesen", other))),
+        }
+    }
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        TraceOptions { format: TraceFormat::Nestest, ppu: true, cycles: true, ranges: vec![], start_pc: None, max_lines: None }
+    }
+}
+
+impl Tracer<BufWriter<File>> {
+    pub fn create(path: &Path, options: TraceOptions) -> Result<Self, Error> {
+        Ok(Tracer::new(BufWriter::new(File::create(path)?), options))
+    }
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(output: W, options: TraceOptions) -> Self {
+        let started: bool = options.start_pc.is_none();
+        Tracer { options, output, started, lines: 0, error: None }
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    // No more line will be written (limit reached or the output failed)
+    pub fn is_done(&self) -> bool {
+        self.error.is_some() || self.options.max_lines.is_some_and(|max| self.lines >= max)
+    }
+
+    // To call before each instruction, the first error is kept for finish()
+    pub fn trace(&mut self, cpu: &mut CPU) {
+        if self.is_done() {
+            return;
+        }
+        let pc: u16 = cpu.reg_pc;
+        if !self.started {
+            if Some(pc) != self.options.start_pc {
+                return;
+            }
+            self.started = true;
+        }
+        if !self.options.ranges.is_empty() && !self.options.ranges.iter().any(|(start, end)| (*start..=*end).contains(&pc)) {
+            return;
+        }
+        let line: String = format_line(cpu, &self.options);
+        match writeln!(self.output, "{}", line) {
+            Ok(()) => self.lines += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    pub fn finish(mut self) -> Result<W, Error> {
+        if let Some(e) = self.error.take() {
+            return Err(e.into());
+        }
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+// ===================================================================
+// =========================== Formatting ============================
+// ===================================================================
+
+fn peek(cpu: &mut CPU, addr: u16) -> u8 {
+    cpu.mem_read_u8_no_fail(addr, true)
+}
+
+fn peek_u16(cpu: &mut CPU, low: u16, high: u16) -> u16 {
+    (peek(cpu, high) as u16) << 8 | peek(cpu, low) as u16
+}
+
+// Address the instruction will access, computed the way the CPU does (zero page wrap, JMP bug)
+fn effective_address(cpu: &mut CPU, mode: AddressingMode, operand: u16) -> Option<u16> {
+    match mode {
+        AddressingMode::ZeroPage | AddressingMode::Absolute => Some(operand),
+        AddressingMode::ZeroPageX => Some((operand as u8).wrapping_add(cpu.reg_x) as u16),
+        AddressingMode::ZeroPageY => Some((operand as u8).wrapping_add(cpu.reg_y) as u16),
+        AddressingMode::AbsoluteX => Some(operand.wrapping_add(cpu.reg_x as u16)),
+        AddressingMode::AbsoluteY => Some(operand.wrapping_add(cpu.reg_y as u16)),
+        AddressingMode::IndirectX => {
+            let pointer: u8 = (operand as u8).wrapping_add(cpu.reg_x);
+            Some(peek_u16(cpu, pointer as u16, pointer.wrapping_add(1) as u16))
+        }
+        AddressingMode::IndirectY => {
+            let pointer: u8 = operand as u8;
+            Some(peek_u16(cpu, pointer as u16, pointer.wrapping_add(1) as u16).wrapping_add(cpu.reg_y as u16))
+        }
+        AddressingMode::Indirect => Some(peek_u16(cpu, operand, (operand & 0xff00) | (operand as u8).wrapping_add(1) as u16)),
+        _ => None,
+    }
+}
+
+fn flags(cpu: &CPU) -> String {
+    [
+        (CPUFlag::Negative, 'n'), (CPUFlag::Overflow, 'v'), (CPUFlag::Break2, 'u'), (CPUFlag::Break, 'b'),
+        (CPUFlag::Decimal, 'd'), (CPUFlag::InterruptDisabled, 'i'), (CPUFlag::Zero, 'z'), (CPUFlag::Carry, 'c'),
+    ].into_iter().map(|(flag, name)| if cpu.get_flag(flag) { name.to_ascii_uppercase() } else { name }).collect()
+}
+
+// Instruction and operand, with the accessed address and value in the style of the format
+fn disassembly(cpu: &mut CPU, opcode: &Opcode, operand: u16, format: TraceFormat) -> String {
+    let pc: u16 = cpu.reg_pc;
+    let jump: bool = opcode.name == "JMP" || opcode.name == "JSR";
+    let addr: Option<u16> = effective_address(cpu, opcode.address_mode, operand);
+    let value: u8 = addr.map(|addr| peek(cpu, addr)).unwrap_or(0);
+    let addr: u16 = addr.unwrap_or(0);
+    let (byte, word): (u8, u16) = (operand as u8, operand);
+
+    let text: String = match (format, opcode.address_mode) {
+        (_, AddressingMode::Immediate) => format!("#${:02X}", byte),
+        (_, AddressingMode::Accumulator) => String::from("A"),
+        (_, AddressingMode::Relative) => format!("${:04X}", pc.wrapping_add(2).wrapping_add(byte as i8 as u16)),
+        (_, AddressingMode::Absolute) if jump => format!("${:04X}", word),
+        (_, AddressingMode::Implied) | (_, AddressingMode::NoneAddressing) => String::new(),
+
+        (TraceFormat::Nestest, AddressingMode::ZeroPage) => format!("${:02X} = {:02X}", byte, value),
+        (TraceFormat::Nestest, AddressingMode::Absolute) => format!("${:04X} = {:02X}", word, value),
+        (TraceFormat::Nestest, AddressingMode::ZeroPageX) => format!("${:02X},X @ {:02X} = {:02X}", byte, addr, value),
+        (TraceFormat::Nestest, AddressingMode::ZeroPageY) => format!("${:02X},Y @ {:02X} = {:02X}", byte, addr, value),
+        (TraceFormat::Nestest, AddressingMode::AbsoluteX) => format!("${:04X},X @ {:04X} = {:02X}", word, addr, value),
+        (TraceFormat::Nestest, AddressingMode::AbsoluteY) => format!("${:04X},Y @ {:04X} = {:02X}", word, addr, value),
+        (TraceFormat::Nestest, AddressingMode::Indirect) => format!("(${:04X}) = {:04X}", word, addr),
+        (TraceFormat::Nestest, AddressingMode::IndirectX) => {
+            format!("(${:02X},X) @ {:02X} = {:04X} = {:02X}", byte, byte.wrapping_add(cpu.reg_x), addr, value)
+        }
+        (TraceFormat::Nestest, AddressingMode::IndirectY) => {
+            format!("(${:02X}),Y = {:04X} @ {:04X} = {:02X}", byte, addr.wrapping_sub(cpu.reg_y as u16), addr, value)
+        }
+
+        (TraceFormat::Fceux, AddressingMode::ZeroPage) => format!("${:02X} = #${:02X}", byte, value),
+        (TraceFormat::Fceux, AddressingMode::Absolute) => format!("${:04X} = #${:02X}", word, value),
+        (TraceFormat::Fceux, AddressingMode::ZeroPageX) => format!("${:02X},X @ ${:04X} = #${:02X}", byte, addr, value),
+        (TraceFormat::Fceux, AddressingMode::ZeroPageY) => format!("${:02X},Y @ ${:04X} = #${:02X}", byte, addr, value),
+        (TraceFormat::Fceux, AddressingMode::AbsoluteX) => format!("${:04X},X @ ${:04X} = #${:02X}", word, addr, value),
+        (TraceFormat::Fceux, AddressingMode::AbsoluteY) => format!("${:04X},Y @ ${:04X} = #${:02X}", word, addr, value),
+        (TraceFormat::Fceux, AddressingMode::Indirect) => format!("(${:04X}) = ${:04X}", word, addr),
+        (TraceFormat::Fceux, AddressingMode::IndirectX) => format!("(${:02X},X) @ ${:04X} = #${:02X}", byte, addr, value),
+        (TraceFormat::Fceux, AddressingMode::IndirectY) => format!("(${:02X}),Y @ ${:04X} = #${:02X}", byte, addr, value),
+
+        (TraceFormat::Mesen, AddressingMode::ZeroPage) => format!("${:02X} = ${:02X}", byte, value),
+        (TraceFormat::Mesen, AddressingMode::Absolute) => format!("${:04X} = ${:02X}", word, value),
+        (TraceFormat::Mesen, AddressingMode::ZeroPageX) => format!("${:02X},X [${:04X}] = ${:02X}", byte, addr, value),
+        (TraceFormat::Mesen, AddressingMode::ZeroPageY) => format!("${:02X},Y [${:04X}] = ${:02X}", byte, addr, value),
+        (TraceFormat::Mesen, AddressingMode::AbsoluteX) => format!("${:04X},X [${:04X}] = ${:02X}", word, addr, value),
+        (TraceFormat::Mesen, AddressingMode::AbsoluteY) => format!("${:04X},Y [${:04X}] = ${:02X}", word, addr, value),
+        (TraceFormat::Mesen, AddressingMode::Indirect) => format!("(${:04X}) [${:04X}]", word, addr),
+        (TraceFormat::Mesen, AddressingMode::IndirectX) => format!("(${:02X},X) [${:04X}] = ${:02X}", byte, addr, value),
+        (TraceFormat::Mesen, AddressingMode::IndirectY) => format!("(${:02X}),Y [${:04X}] = ${:02X}", byte, addr, value),
+    };
+    let marker: &str = match (format, opcode.official) {
+        (TraceFormat::Nestest, true) => " ",
+        (TraceFormat::Nestest, false) => "*",
+        _ => "",
+    };
+    format!("{}{} {}", marker, opcode.name, text).trim_end().to_string()
+}
+
+// The line of the instruction at PC, without executing it
+pub fn format_line(cpu: &mut CPU, options: &TraceOptions) -> String {
+    let pc: u16 = cpu.reg_pc;
+    let opcode_num: u8 = peek(cpu, pc);
+    let opcode: Opcode = OPCODES[opcode_num as usize];
+    let bytes: Vec<u8> = (0..opcode.inst_size.max(1) as u16).map(|i| peek(cpu, pc.wrapping_add(i))).collect();
+    let operand: u16 = match bytes.len() {
+        2 => bytes[1] as u16,
+        3 => (bytes[2] as u16) << 8 | bytes[1] as u16,
+        _ => 0,
+    };
+    let instruction: String = disassembly(cpu, &opcode, operand, options.format);
+    let cycles: usize = cpu.bus.cycles() + RESET_CYCLES;
+    let (scanline, dot): (usize, usize) = (cpu.bus.ppu.scanline, cpu.bus.ppu.cycles);
+    let hex = |separator: &str, prefix: &str| bytes.iter().map(|b| format!("{}{:02X}", prefix, b)).collect::<Vec<String>>().join(separator);
+
+    let mut line: String = match options.format {
+        TraceFormat::Nestest => format!("{:04X}  {:8} {:32} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            pc, hex(" ", ""), instruction, cpu.reg_a, cpu.reg_x, cpu.reg_y, cpu.status, cpu.reg_sp),
+        TraceFormat::Fceux => format!("{}${:04X}:{:9} {:28}A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{}",
+            if options.cycles { format!("c{:<11}", cycles) } else { String::new() },
+            pc, hex(" ", ""), instruction, cpu.reg_a, cpu.reg_x, cpu.reg_y, cpu.reg_sp, flags(cpu)),
+        TraceFormat::Mesen => format!("{:04X}  {:12} {:30} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{}",
+            pc, hex(" ", "$"), instruction, cpu.reg_a, cpu.reg_x, cpu.reg_y, cpu.reg_sp, flags(cpu)),
+    };
+    match (options.format, options.ppu) {
+        (TraceFormat::Mesen, true) => line.push_str(&format!(" V:{:<3} H:{:<3}", scanline, dot)),
+        (_, true) => line.push_str(&format!(" PPU:{:3},{:3}", scanline, dot)),
+        _ => (),
+    }
+    match (options.format, options.cycles) {
+        (TraceFormat::Nestest, true) => line.push_str(&format!(" CYC:{}", cycles)),
+        (TraceFormat::Mesen, true) => line.push_str(&format!(" Cyc:{}", cycles)),
+        _ => (),
+    }
+    // The padding of the last field is not kept
+    line.truncate(line.trim_end().len());
+    line
+}
@@ -0,0 +1,83 @@
+// ==================================================================================================
+// ============================================ UNIT TESTS ==========================================
+// ==================================================================================================
+
+
+#[cfg(test)]
+mod test {
+    use crate::cpu::CPU;
+
+    use super::super::*;
+
+    // Reads the registers with side effects (PPU status and data, joypad) in a loop
+    const PROGRAM: &str = "
+                LDA #$20
+                STA $2006
+                LDX #0
+                STX $2006
+                LDA #$03
+                STA $11
+        loop:   LDA $2002
+                LDA $2007
+                LDA $4016
+                STA $0300,X
+                LDA ($10),Y
+                INX
+                CPX #20
+                BNE loop
+                BRK
+    ";
+
+    fn load() -> CPU {
+        CPU::test_load(PROGRAM)
+    }
+
+    fn trace(options: TraceOptions) -> Vec<String> {
+        let mut cpu: CPU = load();
+        let mut tracer: Tracer<Vec<u8>> = Tracer::new(vec![], options);
+        cpu.run_with_callback(|cpu| tracer.trace(cpu), false);
+        String::from_utf8(tracer.finish().unwrap()).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_formats() {
+        let lines: Vec<String> = trace(TraceOptions::default());
+        assert_eq!(lines.len(), 6 + 20 * 8 + 1);
+        assert_eq!(lines[0], "8000  A9 20     LDA #$20                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7");
+        assert_eq!(lines[6], "800E  AD 02 20  LDA $2002 = 00                  A:03 X:00 Y:00 P:24 SP:FD PPU:  0, 72 CYC:24");
+        assert!(lines[10].starts_with("801A  B1 10     LDA ($10),Y = 0300 @ 0300 = 00  A:00"));
+
+        let options: TraceOptions = TraceOptions { format: TraceFormat::Fceux, ppu: false, ..TraceOptions::default() };
+        let lines: Vec<String> = trace(options);
+        assert_eq!(lines[0], "c7          $8000:A9 20     LDA #$20                    A:00 X:00 Y:00 S:FD P:nvUbdIzc");
+        assert!(lines[9].contains("STA $0300,X @ $0300 = #$00"));
+
+        let options: TraceOptions = TraceOptions { format: TraceFormat::Mesen, cycles: false, ..TraceOptions::default() };
+        let lines: Vec<String> = trace(options);
+        assert_eq!(lines[1], "8002  $8D $06 $20  STA $2006 = $00                A:20 X:00 Y:00 S:FD P:nvUbdIzc V:0   H:27");
+        assert!(lines[10].contains("LDA ($10),Y [$0300] = $00"));
+    }
+
+    #[test]
+    fn test_filters() {
+        // Starts at the loop, only its first two instructions, 5 lines
+        let options: TraceOptions = TraceOptions { ranges: vec![(0x800e, 0x8013)], start_pc: Some(0x800e), max_lines: Some(5), ..TraceOptions::default() };
+        let lines: Vec<String> = trace(options);
+        let addresses: Vec<&str> = lines.iter().map(|line| &line[..4]).collect();
+        assert_eq!(addresses, ["800E", "8011", "800E", "8011", "800E"]);
+    }
+
+    #[test]
+    fn test_no_side_effects() {
+        let mut plain: CPU = load();
+        plain.run();
+        let mut traced: CPU = load();
+        let mut tracer: Tracer<std::io::Sink> = Tracer::new(std::io::sink(), TraceOptions::default());
+        traced.run_with_callback(|cpu| tracer.trace(cpu), false);
+
+        assert_eq!(tracer.lines(), 6 + 20 * 8 + 1);
+        assert_eq!((plain.reg_a, plain.reg_x, plain.reg_pc, plain.status), (traced.reg_a, traced.reg_x, traced.reg_pc, traced.status));
+        assert_eq!(plain.bus.cycles(), traced.bus.cycles());
+        assert_eq!(plain.bus.ppu.reg_addr.get(), traced.bus.ppu.reg_addr.get());
+    }
+}
@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+use nes_emul::golden::{self, GoldenCase, GoldenResult};
+
+// Runs every rom of $NES_GOLDEN_DIR (rom_examples/golden by default) and compares its last frame
+// with the stored <rom>.png. Set NES_GOLDEN_BLESS=1 to (re)generate the references.
+#[test]
+fn golden_frames() {
+    let dir: PathBuf = golden::golden_dir();
+    let cases: Vec<GoldenCase> = golden::discover(&dir).expect("Cannot read the golden directory");
+    if cases.is_empty() {
+        println!("No rom found in {}, skipping golden frames", dir.display());
+        return;
+    }
+    let bless: bool = std::env::var(golden::GOLDEN_BLESS_VAR).is_ok();
+
+    let mut failures: Vec<String> = vec![];
+    for case in cases.iter() {
+        match case.check(bless) {
+            Ok(GoldenResult::Match) => println!("{}: ok", case.name),
+            Ok(GoldenResult::MissingReference) if bless => println!("{}: reference written", case.name),
+            Ok(GoldenResult::MissingReference) => failures.push(format!("{}: no reference, see {}", case.name, case.actual_path().display())),
+            Ok(GoldenResult::Mismatch { .. }) if bless => println!("{}: reference updated", case.name),
+            Ok(GoldenResult::Mismatch { differing_pixels }) => failures.push(format!("{}: {} pixels differ, see {}", case.name, differing_pixels, case.diff_path().display())),
+            Err(e) => failures.push(format!("{}: {}", case.name, e)),
+        }
+    }
+    assert!(failures.is_empty(), "Golden frames failed:\n{}", failures.join("\n"));
+}